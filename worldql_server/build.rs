@@ -11,7 +11,7 @@ fn main() {
 }
 
 fn commit_hash() -> Result<String, Box<dyn Error>> {
-    let output = Command::new("git").args(&["rev-parse", "HEAD"]).output()?;
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output()?;
     let hash = String::from_utf8(output.stdout)?;
 
     Ok(hash)
@@ -0,0 +1,203 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{Extension, Path, TypedHeader};
+use axum::headers::authorization::Bearer;
+use axum::headers::Authorization;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{AddExtensionLayer, Json, Router};
+use color_eyre::Result;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::database::{DatabaseClient, DatabaseError, WorldStats};
+use crate::server::{ServerStats, StatsHandle};
+use crate::subscriptions::ThreadWorldMap;
+use crate::utils::constant_time_eq;
+
+type Auth = Option<TypedHeader<Authorization<Bearer>>>;
+
+/// Everything the admin endpoints can see, shared between requests.
+struct AdminState {
+    stats: StatsHandle,
+    world_map: ThreadWorldMap,
+    /// A dedicated connection, so slow admin queries never hold up the database workers
+    database_client: Mutex<DatabaseClient>,
+    auth_token: Option<String>,
+}
+
+/// Serve the admin HTTP API on `addr` until `token` is cancelled.
+///
+/// Requests must send `auth_token` as a bearer token if it is set. Endpoints:
+/// - `GET /stats`: [`ServerStats`] snapshot
+/// - `GET /worlds`: worlds with subscriptions, and how many each has
+/// - `GET /worlds/:name/stats`: [`WorldStats`] of a stored world
+/// - `DELETE /worlds/:name`: delete every stored record of a world
+/// - `GET /metrics`: metrics in the Prometheus text exposition format
+pub async fn start_admin_server(
+    stats: StatsHandle,
+    world_map: ThreadWorldMap,
+    database_client: DatabaseClient,
+    addr: SocketAddr,
+    auth_token: Option<String>,
+    token: CancellationToken,
+) -> Result<()> {
+    info!("Admin HTTP Server listening on {}", addr);
+
+    let state = Arc::new(AdminState {
+        stats,
+        world_map,
+        database_client: Mutex::new(database_client),
+        auth_token,
+    });
+
+    let app = Router::new()
+        .route("/stats", get(get_stats))
+        .route("/worlds", get(get_worlds))
+        .route("/worlds/:name", axum::routing::delete(delete_world))
+        .route("/worlds/:name/stats", get(get_world_stats))
+        .route("/metrics", get(get_metrics))
+        .layer(AddExtensionLayer::new(state));
+
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async move { token.cancelled().await })
+        .await?;
+
+    Ok(())
+}
+
+// region: Errors
+#[derive(Debug, Error)]
+enum AdminError {
+    #[error("missing or invalid auth token")]
+    Unauthorized,
+
+    #[error(transparent)]
+    DatabaseError(#[from] DatabaseError),
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match &self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::DatabaseError(DatabaseError::InvalidWorldName { .. }) => StatusCode::BAD_REQUEST,
+            Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
+
+fn authorize(state: &AdminState, authorization: Auth) -> Result<(), AdminError> {
+    match (&state.auth_token, authorization) {
+        // No auth token requested, always allow
+        (None, _) => Ok(()),
+
+        // Auth token requested and given
+        (Some(token), Some(TypedHeader(Authorization(bearer))))
+            if constant_time_eq(token.as_bytes(), bearer.token().as_bytes()) =>
+        {
+            Ok(())
+        }
+
+        // Auth token requested but missing or wrong
+        (Some(_), _) => Err(AdminError::Unauthorized),
+    }
+}
+// endregion
+
+// region: Handlers
+async fn get_stats(
+    Extension(state): Extension<Arc<AdminState>>,
+    authorization: Auth,
+) -> Result<Json<ServerStats>, AdminError> {
+    authorize(&state, authorization)?;
+
+    Ok(Json(state.stats.stats().await))
+}
+
+#[derive(Debug, Serialize)]
+struct WorldSummary {
+    name: String,
+    subscriptions: usize,
+}
+
+async fn get_worlds(
+    Extension(state): Extension<Arc<AdminState>>,
+    authorization: Auth,
+) -> Result<Json<Vec<WorldSummary>>, AdminError> {
+    authorize(&state, authorization)?;
+
+    let mut worlds = {
+        let world_map = state.world_map.read().await;
+        world_map
+            .subscription_counts()
+            .map(|(name, subscriptions)| WorldSummary {
+                name: name.to_string(),
+                subscriptions,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    worlds.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(worlds))
+}
+
+async fn get_world_stats(
+    Extension(state): Extension<Arc<AdminState>>,
+    Path(world_name): Path<String>,
+    authorization: Auth,
+) -> Result<Json<WorldStats>, AdminError> {
+    authorize(&state, authorization)?;
+
+    let mut database_client = state.database_client.lock().await;
+    let stats = database_client.world_stats(&world_name).await?;
+
+    Ok(Json(stats))
+}
+
+#[derive(Debug, Serialize)]
+struct DroppedWorld {
+    tables_dropped: u32,
+}
+
+/// Database workers forget their cached lookups for the world before handling their next
+/// message, see [`DatabaseClient::drop_world`].
+async fn delete_world(
+    Extension(state): Extension<Arc<AdminState>>,
+    Path(world_name): Path<String>,
+    authorization: Auth,
+) -> Result<Json<DroppedWorld>, AdminError> {
+    authorize(&state, authorization)?;
+
+    let mut database_client = state.database_client.lock().await;
+    let tables_dropped = database_client.drop_world(&world_name).await?;
+    info!(
+        "admin dropped world \"{}\" with {} tables",
+        world_name, tables_dropped
+    );
+
+    Ok(Json(DroppedWorld { tables_dropped }))
+}
+
+/// Serve metrics in the Prometheus text exposition format.
+async fn get_metrics(
+    Extension(state): Extension<Arc<AdminState>>,
+    authorization: Auth,
+) -> Result<impl IntoResponse, AdminError> {
+    authorize(&state, authorization)?;
+
+    let mut headers = HeaderMap::new();
+    let content_type = HeaderValue::from_static("text/plain; version=0.0.4");
+    headers.insert(CONTENT_TYPE, content_type);
+
+    Ok((headers, crate::metrics::render()))
+}
+// endregion
@@ -1,10 +1,21 @@
 use std::net::IpAddr;
+#[cfg(feature = "admin")]
+use std::net::SocketAddr;
 use std::num::ParseIntError;
+#[cfg(feature = "zeromq")]
+use std::path::PathBuf;
 
 use clap::{AppSettings, Parser};
 use once_cell::sync::Lazy;
 use thiserror::Error;
 use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::database::{IndexSpec, PartitionStrategy};
+use crate::subscriptions::WorldPolicy;
+#[cfg(feature = "zeromq")]
+use crate::transport::{OverflowPolicy, UuidPolicy};
+use crate::utils::{Charset, PayloadLogging};
 
 static VERSION: Lazy<String> = Lazy::new(|| {
     let mut version = format!("v{}", env!("CARGO_PKG_VERSION"));
@@ -24,12 +35,65 @@ pub struct Args {
     #[clap(short = 'p', long = "psql", env = "WQL_POSTGRES_CONNECTION_STRING")]
     pub psql_conn: String,
 
+    /// PostgreSQL connection string for a read-only replica to serve record reads
+    ///
+    /// All queries use the primary if not set or if the replica is unreachable
+    #[clap(long = "psql-replica", env = "WQL_POSTGRES_REPLICA_CONNECTION_STRING")]
+    pub psql_replica_conn: Option<String>,
+
     /// Side length of subscription region cubes
     ///
     /// A value of 0 is invalid
     #[clap(long, default_value = "16", env = "WQL_SUBSCRIPTION_REGION_CUBE_SIZE", parse(try_from_str = parse_non_zero_16))]
     pub sub_region_size: u16,
 
+    /// Maximum number of subscription regions a single peer can join in each world
+    ///
+    /// Unlimited if not set, a value of 0 is invalid
+    #[clap(long, env = "WQL_SUBSCRIPTION_MAX_PER_PEER", parse(try_from_str = parse_non_zero_sized))]
+    pub sub_max_per_peer: Option<usize>,
+
+    /// Which worlds peers can create by subscribing to them, one of: open, allowlist
+    #[clap(long, default_value = "open", env = "WQL_WORLD_POLICY")]
+    pub world_policy: WorldPolicy,
+
+    /// Comma separated list of worlds peers can use with `--world-policy allowlist`
+    #[clap(long, env = "WQL_WORLD_ALLOWLIST", use_delimiter = true)]
+    pub world_allowlist: Vec<String>,
+
+    /// Maximum number of worlds that can have subscriptions at once
+    ///
+    /// Unlimited if not set, a value of 0 is invalid
+    #[clap(long, env = "WQL_MAX_WORLDS", parse(try_from_str = parse_non_zero_sized))]
+    pub max_worlds: Option<usize>,
+
+    /// Time to keep a disconnected peer's subscriptions, so they are restored if it
+    /// reconnects with the same UUID in time (seconds)
+    ///
+    /// Subscriptions are removed as soon as a peer disconnects if not set
+    #[clap(long, env = "WQL_SUBSCRIPTION_DISCONNECT_GRACE_SECS")]
+    pub sub_disconnect_grace_secs: Option<u64>,
+
+    /// Maximum number of incoming messages queued for processing
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "65536", env = "WQL_MSG_QUEUE_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub msg_queue_size: usize,
+
+    /// Number of messages waiting in a queue above which a warning is logged
+    #[clap(long, default_value = "8192", env = "WQL_QUEUE_HIGH_WATER", parse(try_from_str = parse_non_zero_sized))]
+    pub queue_high_water: usize,
+
+    /// Time between samples of message queue depths (seconds)
+    #[clap(long, default_value = "5", env = "WQL_QUEUE_SAMPLE_SECS", parse(try_from_str = parse_non_zero_32))]
+    pub queue_sample_secs: u32,
+
+    /// Number of recent global messages kept per world for peers to replay
+    ///
+    /// Set to 0 to disable history
+    #[clap(long, default_value = "0", env = "WQL_GLOBAL_HISTORY_SIZE")]
+    pub global_history_size: usize,
+
     /// TODO: Add arg docs
     ///
     /// A value of 0 is invalid
@@ -59,6 +123,111 @@ pub struct Args {
     /// Set to 0 to disable cache eviction
     #[clap(long, default_value = "1024", env = "WQL_DB_CACHE_SIZE", parse(try_from_str = parse_non_zero_sized))]
     pub db_cache_size: usize,
+
+    /// Number of database workers, each with its own caches
+    ///
+    /// Messages for a world are always handled by the same worker
+    #[clap(long, default_value = "1", env = "WQL_DB_WORKERS", parse(try_from_str = parse_non_zero_sized))]
+    pub db_workers: usize,
+
+    /// Maximum number of PostgreSQL connections shared by the database workers
+    ///
+    /// The read replica, if set, has its own pool of the same size
+    #[clap(long, default_value = "16", env = "WQL_DB_POOL_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub db_pool_size: usize,
+
+    /// Consecutive failures to reach the database after which database messages are
+    /// skipped, until the database is reachable again
+    #[clap(long, default_value = "5", env = "WQL_DB_BREAKER_THRESHOLD", parse(try_from_str = parse_non_zero_32))]
+    pub db_breaker_threshold: u32,
+
+    /// Time between checks of whether an unreachable database has recovered (seconds)
+    #[clap(long, default_value = "5", env = "WQL_DB_BREAKER_COOLDOWN_SECS")]
+    pub db_breaker_cooldown_secs: u64,
+
+    /// Maximum size in bytes of the data or flex of a single record
+    ///
+    /// Unlimited if not set, a value of 0 is invalid
+    #[clap(long, env = "WQL_DB_MAX_RECORD_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub db_max_record_size: Option<usize>,
+
+    /// Maximum length of sanitized world names in bytes
+    ///
+    /// PostgreSQL truncates identifiers longer than 63 bytes. A value of 0 is invalid
+    #[clap(long, default_value = "63", env = "WQL_WORLD_NAME_MAX_LENGTH", parse(try_from_str = parse_non_zero_sized))]
+    pub world_name_max_length: usize,
+
+    /// Letters and digits allowed in world names, one of: ascii, unicode
+    #[clap(long, default_value = "ascii", env = "WQL_WORLD_NAME_CHARSET")]
+    pub world_name_charset: Charset,
+
+    /// Lowercase world names, so worlds differing only by case are the same world
+    #[clap(long, env = "WQL_WORLD_NAME_CASE_FOLD")]
+    pub world_name_case_fold: bool,
+
+    /// How regions are grouped into database tables, one of: fixed-grid, hilbert, single-table
+    ///
+    /// Must not change once a database has records
+    #[clap(long, default_value = "fixed-grid", env = "WQL_DB_PARTITION_STRATEGY")]
+    pub db_partition_strategy: PartitionStrategy,
+
+    /// Indexes built on new world tables, one of: region, region-position, gist
+    ///
+    /// Tables keep the indexes they were created with
+    #[clap(long, default_value = "region", env = "WQL_DB_INDEX")]
+    pub db_index: IndexSpec,
+
+    /// Comma separated list of worlds to pre-populate the lookup caches for on startup
+    #[clap(long, env = "WQL_DB_WARM_WORLDS", use_delimiter = true)]
+    pub db_warm_worlds: Vec<String>,
+
+    /// Only let peers update or delete the records they created
+    #[clap(long, env = "WQL_DB_RECORD_OWNERSHIP")]
+    pub db_record_ownership: bool,
+
+    /// Comma separated list of peer UUIDs allowed to update or delete any record, when
+    /// --db-record-ownership is set
+    #[clap(long, env = "WQL_DB_PRIVILEGED_PEERS", use_delimiter = true)]
+    pub db_privileged_peers: Vec<Uuid>,
+
+    /// Maximum number of attempts for database writes that fail with transient errors
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "3", env = "WQL_DB_RETRY_ATTEMPTS", parse(try_from_str = parse_non_zero_32))]
+    pub db_retry_attempts: u32,
+
+    /// Base delay between database write retries (milliseconds)
+    ///
+    /// Doubles after every failed attempt
+    #[clap(long, default_value = "50", env = "WQL_DB_RETRY_DELAY_MS")]
+    pub db_retry_delay_ms: u64,
+
+    /// Buffer created records for up to this long before inserting them in one batch
+    /// (milliseconds)
+    ///
+    /// Records are inserted immediately if not set. Buffered records are lost if the
+    /// server crashes before they are flushed
+    #[clap(long, env = "WQL_DB_WRITE_BUFFER_MS")]
+    pub db_write_buffer_ms: Option<u64>,
+
+    /// Number of buffered records that triggers a flush before `--db-write-buffer-ms`
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "1024", env = "WQL_DB_WRITE_BUFFER_RECORDS", parse(try_from_str = parse_non_zero_sized))]
+    pub db_write_buffer_records: usize,
+
+    /// Cache the records of each region read for this long (milliseconds)
+    ///
+    /// Disabled if not set. Writes from other servers sharing the database are not seen
+    /// until cached regions expire
+    #[clap(long, env = "WQL_DB_RECORD_CACHE_MS")]
+    pub db_record_cache_ms: Option<u64>,
+
+    /// Maximum number of regions held in the record cache
+    ///
+    /// A value of 0 is invalid
+    #[clap(long, default_value = "1024", env = "WQL_DB_RECORD_CACHE_SIZE", parse(try_from_str = parse_non_zero_sized))]
+    pub db_record_cache_size: usize,
     // endregion
 
     // region: HTTP
@@ -78,6 +247,21 @@ pub struct Args {
     pub http_auth_token: Option<String>,
     // endregion
 
+    // region: Admin
+    /// Address to serve the admin HTTP API on, disabled if not set
+    #[cfg(feature = "admin")]
+    #[clap(long, env = "WQL_ADMIN_ADDR")]
+    pub admin_addr: Option<SocketAddr>,
+
+    /// Bearer token required by the admin HTTP API
+    ///
+    /// Only optional if the admin address is a loopback address, anyone who can reach it
+    /// can use the API if not set
+    #[cfg(feature = "admin")]
+    #[clap(long, env = "WQL_ADMIN_AUTH_TOKEN")]
+    pub admin_auth_token: Option<String>,
+    // endregion
+
     // region: WebSocket
     /// WebSocket server host
     #[cfg(feature = "websocket")]
@@ -117,6 +301,108 @@ pub struct Args {
     #[cfg(feature = "zeromq")]
     #[clap(short = 'T', long, default_value = "25", env = "WQL_ZMQ_TIMEOUT_SECS", parse(try_from_str = parse_zmq_timeout_secs))]
     pub zmq_timeout_secs: u8,
+
+    /// Maximum number of messages per second accepted from each ZeroMQ peer
+    ///
+    /// Unlimited if not set, a value of 0 is invalid
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_RATE_LIMIT", parse(try_from_str = parse_non_zero_32))]
+    pub zmq_rate_limit: Option<u32>,
+
+    /// Maximum size of a single incoming ZeroMQ message, before and after decompression (bytes)
+    ///
+    /// Larger messages are dropped before deserialization, a value of 0 is invalid
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "1048576", env = "WQL_ZMQ_MAX_MESSAGE_BYTES", parse(try_from_str = parse_non_zero_sized))]
+    pub zmq_max_message_bytes: usize,
+
+    /// What to do with incoming ZeroMQ messages when the processing queue is full
+    ///
+    /// One of `block`, `drop-oldest` or `drop-newest`. Blocking stalls ingestion for every peer
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "block", env = "WQL_ZMQ_OVERFLOW_POLICY")]
+    pub zmq_overflow_policy: OverflowPolicy,
+
+    /// Window in milliseconds to buffer outgoing ZeroMQ messages for, sending each peer's
+    /// messages as one multipart frame
+    ///
+    /// Only applies to peers that advertise the `multipart` capability during their handshake.
+    /// Disabled if not set, a value of 0 is invalid
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_COALESCE_MS", parse(try_from_str = parse_non_zero_32))]
+    pub zmq_coalesce_ms: Option<u32>,
+
+    /// Shared secret ZeroMQ peers must send as `token=<secret>` in their handshake
+    ///
+    /// Handshakes are not authenticated if not set
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_AUTH_SECRET")]
+    pub zmq_auth_secret: Option<String>,
+
+    /// How UUIDs sent in ZeroMQ handshakes are treated, one of: trust, assign, v1, v2, v3,
+    /// v4, v5
+    ///
+    /// `assign` gives every handshake a new UUID, returned as `uuid=` in the handshake ack.
+    /// `v1` to `v5` reject UUIDs of any other version, and UUIDs of connected peers
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "trust", env = "WQL_ZMQ_UUID_POLICY")]
+    pub zmq_uuid_policy: UuidPolicy,
+
+    /// Maximum number of connected peers, new ZeroMQ peers are rejected once reached
+    ///
+    /// Peers are not limited if not set
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_MAX_PEERS")]
+    pub max_peers: Option<usize>,
+
+    /// Time to wait for a ZeroMQ peer's reply socket to accept the handshake response
+    /// before the handshake is abandoned (seconds)
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "5", env = "WQL_ZMQ_HANDSHAKE_TIMEOUT_SECS")]
+    pub zmq_handshake_timeout_secs: u64,
+
+    /// Time to ignore handshakes from an address after failed authentication (seconds)
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_AUTH_COOLDOWN_SECS")]
+    pub zmq_auth_cooldown_secs: Option<u64>,
+
+    /// Number of invalid ZeroMQ messages an address may send within
+    /// --zmq-invalid-window-secs before it is blacklisted
+    ///
+    /// Invalid messages are never blacklisted if not set, a value of 0 is invalid
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_INVALID_THRESHOLD", parse(try_from_str = parse_non_zero_32))]
+    pub zmq_invalid_threshold: Option<u32>,
+
+    /// Window over which invalid ZeroMQ messages are counted (seconds)
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "10", env = "WQL_ZMQ_INVALID_WINDOW_SECS")]
+    pub zmq_invalid_window_secs: u64,
+
+    /// Time to ignore an address after it sends too many invalid ZeroMQ messages (seconds)
+    #[cfg(feature = "zeromq")]
+    #[clap(long, default_value = "60", env = "WQL_ZMQ_INVALID_COOLDOWN_SECS")]
+    pub zmq_invalid_cooldown_secs: u64,
+
+    /// File to append ZeroMQ messages that fail decoding to, for diagnosing malformed clients
+    ///
+    /// Invalid messages are silently dropped if not set
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_DEAD_LETTER_FILE")]
+    pub zmq_dead_letter_file: Option<PathBuf>,
+
+    /// Server secret key for encrypting ZeroMQ traffic with CurveZMQ, as a Z85 key or
+    /// ZeroMQ certificate file
+    ///
+    /// Requires --zmq-curve-client-keys-file
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_CURVE_SECRET_KEY_FILE")]
+    pub zmq_curve_secret_key_file: Option<PathBuf>,
+
+    /// Z85 public keys of clients allowed to connect when CurveZMQ is enabled, one per line
+    #[cfg(feature = "zeromq")]
+    #[clap(long, env = "WQL_ZMQ_CURVE_CLIENT_KEYS_FILE")]
+    pub zmq_curve_client_keys_file: Option<PathBuf>,
     // endregion
 
     // region: Other Flags
@@ -125,6 +411,14 @@ pub struct Args {
     /// eg: -vvv for very verbose logs
     #[clap(short, long, parse(from_occurrences))]
     pub verbose: u8,
+
+    /// How much of each handled message to log at trace level, one of: off, header, full
+    ///
+    /// `header` logs the instruction, sender, world and position, `full` also dumps
+    /// `data` and `flex` as hex. Defaults to off, or full for debug builds with the
+    /// `trace_packets` feature
+    #[clap(long, env = "WQL_PAYLOAD_LOGGING")]
+    pub payload_logging: Option<PayloadLogging>,
     // endregion
 }
 // endregion
@@ -190,6 +484,10 @@ impl Args {
             warn!("Subscription region sizes less than 10 might impact lookup performance")
         }
 
+        if self.world_policy == WorldPolicy::Allowlist && self.world_allowlist.is_empty() {
+            warn!("--world-policy is allowlist but --world-allowlist is empty, peers can't subscribe to any world")
+        }
+
         // TODO: Better error messages
         let mod_x = self.db_table_size % u32::from(self.db_region_x_size);
         if mod_x != 0 {
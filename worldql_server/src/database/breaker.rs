@@ -0,0 +1,253 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use super::retry::is_transient;
+use super::DatabaseClient;
+
+// region: BreakerState Enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// The database is reachable, every operation is attempted
+    Closed,
+    /// The database is unreachable, operations are skipped until the cooldown ends
+    Open,
+    /// The cooldown has ended and a single probe is checking if the database recovered
+    HalfOpen,
+}
+// endregion
+
+// region: CircuitBreaker Struct
+/// Tracks whether the database is reachable, so database messages can be skipped while
+/// it is down instead of failing one by one.
+///
+/// Cheaply cloneable, every clone shares the same state. Shared by all database workers,
+/// since they all connect to the same server.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker(Arc<Mutex<BreakerInner>>);
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    /// Consecutive failed operations while closed
+    failures: u32,
+    /// Failures that open the breaker
+    threshold: u32,
+    cooldown: Duration,
+    opened_at: Instant,
+    /// Messages skipped while the breaker was not closed
+    skipped: u64,
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker which opens after `threshold` consecutive failures, and
+    /// probes the database every `cooldown` while open.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self(Arc::new(Mutex::new(BreakerInner {
+            state: BreakerState::Closed,
+            failures: 0,
+            threshold: threshold.max(1),
+            cooldown,
+            opened_at: Instant::now(),
+            skipped: 0,
+        })))
+    }
+
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, BreakerInner> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[inline]
+    pub fn state(&self) -> BreakerState {
+        self.lock().state
+    }
+
+    /// Take a point-in-time snapshot of the breaker.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn snapshot(&self) -> BreakerStats {
+        let inner = self.lock();
+        BreakerStats {
+            state: inner.state,
+            skipped_messages: inner.skipped,
+        }
+    }
+
+    /// Returns `true` if the caller should probe the database.
+    ///
+    /// Once the cooldown of an open breaker ends, only the first caller is allowed to
+    /// probe until it reports the result.
+    pub(super) fn try_probe(&self) -> bool {
+        let mut inner = self.lock();
+        match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::HalfOpen => false,
+            BreakerState::Open => {
+                if inner.opened_at.elapsed() < inner.cooldown {
+                    return false;
+                }
+
+                inner.state = BreakerState::HalfOpen;
+                true
+            }
+        }
+    }
+
+    /// Record an operation that reached the database.
+    pub(super) fn record_success(&self) {
+        let mut inner = self.lock();
+        if inner.state != BreakerState::Closed {
+            info!(
+                "database is reachable again, {} messages skipped since startup",
+                inner.skipped
+            );
+        }
+
+        inner.state = BreakerState::Closed;
+        inner.failures = 0;
+    }
+
+    /// Record an operation that failed because the database could not be reached.
+    pub(super) fn record_failure(&self) {
+        let mut inner = self.lock();
+        inner.failures = inner.failures.saturating_add(1);
+
+        let trip = match inner.state {
+            BreakerState::Closed => inner.failures >= inner.threshold,
+            BreakerState::HalfOpen | BreakerState::Open => true,
+        };
+
+        if trip {
+            if inner.state == BreakerState::Closed {
+                warn!(
+                    "database unreachable after {} failures, skipping database messages",
+                    inner.failures
+                );
+            }
+
+            inner.state = BreakerState::Open;
+            inner.opened_at = Instant::now();
+        }
+    }
+
+    /// Count a message skipped because the database is unreachable.
+    pub(super) fn record_skipped(&self) {
+        let mut inner = self.lock();
+        inner.skipped = inner.skipped.saturating_add(1);
+    }
+}
+// endregion
+
+// region: BreakerStats Struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub struct BreakerStats {
+    pub state: BreakerState,
+    /// Database messages skipped since startup while the database was unreachable
+    pub skipped_messages: u64,
+}
+// endregion
+
+// region: DatabaseClient
+impl DatabaseClient {
+    /// Skip database messages while the database is unreachable, according to `breaker`.
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.breaker = Some(breaker);
+    }
+
+    /// Returns `false` if the next database message should be skipped, as the database
+    /// is unreachable.
+    ///
+    /// Probes the database once the breaker's cooldown ends. Always `true` without a
+    /// circuit breaker.
+    pub async fn is_available(&mut self) -> bool {
+        let breaker = match &self.breaker {
+            Some(breaker) => breaker.clone(),
+            None => return true,
+        };
+
+        if breaker.state() == BreakerState::Closed {
+            return true;
+        }
+
+        if !breaker.try_probe() {
+            breaker.record_skipped();
+            return false;
+        }
+
+        match self.probe().await {
+            Ok(_) => {
+                breaker.record_success();
+                true
+            }
+
+            Err(error) => {
+                debug!("database probe failed: {}", error);
+                breaker.record_failure();
+                breaker.record_skipped();
+
+                false
+            }
+        }
+    }
+
+    /// Check the primary responds. The pool replaces closed connections, so this reconnects
+    /// if needed.
+    async fn probe(&self) -> Result<(), tokio_postgres::Error> {
+        self.client().await?.simple_query("SELECT 1").await?;
+        Ok(())
+    }
+
+    /// Record a query that reached the primary.
+    #[inline]
+    pub(super) fn record_reachable(&self) {
+        if let Some(breaker) = &self.breaker {
+            breaker.record_success();
+        }
+    }
+
+    /// Record a failed query, which only counts towards the breaker if the primary could
+    /// not be reached.
+    #[inline]
+    pub(super) fn record_error(&self, error: &tokio_postgres::Error) {
+        if let Some(breaker) = &self.breaker {
+            if error.is_closed() || is_transient(error) {
+                breaker.record_failure();
+            }
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::ZERO);
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // Only a single probe is allowed once the cooldown ends
+        assert!(breaker.try_probe());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(!breaker.try_probe());
+
+        // A failed probe opens the breaker again straight away
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        assert!(breaker.try_probe());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+}
+// endregion
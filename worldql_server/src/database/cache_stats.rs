@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+// region: CacheCounters Struct
+/// Hit and miss counters for the [`super::DatabaseClient`] lookup caches.
+#[derive(Debug, Default)]
+pub(super) struct CacheCounters {
+    table_hits: AtomicU64,
+    table_misses: AtomicU64,
+    region_hits: AtomicU64,
+    region_misses: AtomicU64,
+}
+
+impl CacheCounters {
+    #[inline]
+    pub(super) fn table_hit(&self) {
+        self.table_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn table_miss(&self) {
+        self.table_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn region_hit(&self) {
+        self.region_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn region_miss(&self) {
+        self.region_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Take a point-in-time snapshot of all counters.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub(super) fn snapshot(&self) -> CacheStats {
+        CacheStats {
+            table_hits: self.table_hits.load(Ordering::Relaxed),
+            table_misses: self.table_misses.load(Ordering::Relaxed),
+            region_hits: self.region_hits.load(Ordering::Relaxed),
+            region_misses: self.region_misses.load(Ordering::Relaxed),
+        }
+    }
+}
+// endregion
+
+// region: CacheStatsHandle Struct
+/// Cheaply cloneable handle for reading [`CacheStats`] from outside the thread which owns
+/// the [`super::DatabaseClient`].
+#[derive(Debug, Default, Clone)]
+pub struct CacheStatsHandle(pub(super) Arc<CacheCounters>);
+
+impl CacheStatsHandle {
+    /// Take a point-in-time snapshot of all counters.
+    #[inline]
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn snapshot(&self) -> CacheStats {
+        self.0.snapshot()
+    }
+}
+// endregion
+
+// region: CacheKind Enum
+/// Identifies one of the [`super::DatabaseClient`] lookup caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    /// Caches `table_suffix` values
+    Table,
+    /// Caches `region_id` values
+    Region,
+}
+// endregion
+
+// region: CacheStats Struct
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub struct CacheStats {
+    pub table_hits: u64,
+    pub table_misses: u64,
+    pub region_hits: u64,
+    pub region_misses: u64,
+}
+
+impl CacheStats {
+    /// Ratio of `table_suffix` lookups served from cache, between `0.0` and `1.0`.
+    #[cfg(test)]
+    #[inline]
+    pub fn table_hit_ratio(&self) -> f64 {
+        hit_ratio(self.table_hits, self.table_misses)
+    }
+
+    /// Ratio of `region_id` lookups served from cache, between `0.0` and `1.0`.
+    #[cfg(test)]
+    #[inline]
+    pub fn region_hit_ratio(&self) -> f64 {
+        hit_ratio(self.region_hits, self.region_misses)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::cast_precision_loss)]
+fn hit_ratio(hits: u64, misses: u64) -> f64 {
+    let total = hits + misses;
+    if total == 0 {
+        return 0.0;
+    }
+
+    hits as f64 / total as f64
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::float_cmp)]
+
+    use super::*;
+
+    #[test]
+    fn hit_ratios() {
+        let counters = CacheCounters::default();
+
+        // No lookups yet
+        let stats = counters.snapshot();
+        assert_eq!(stats.table_hit_ratio(), 0.0);
+        assert_eq!(stats.region_hit_ratio(), 0.0);
+
+        counters.table_miss();
+        counters.table_hit();
+        counters.table_hit();
+        counters.table_hit();
+        counters.region_miss();
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.table_hits, 3);
+        assert_eq!(stats.table_misses, 1);
+        assert_eq!(stats.table_hit_ratio(), 0.75);
+        assert_eq!(stats.region_hit_ratio(), 0.0);
+    }
+}
+// endregion
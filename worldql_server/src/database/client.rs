@@ -1,43 +1,134 @@
-use ahash::AHashMap;
+#[cfg(test)]
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(test)]
+use std::time::SystemTime;
+
+use ahash::{AHashMap, AHashSet};
+use bytes::Bytes;
 use chrono::prelude::*;
 use color_eyre::Result;
+use deadpool_postgres::{Object, Pool, PoolError};
+#[cfg(test)]
+use futures_util::stream::{self, Stream, StreamExt};
 use lru::LruCache;
 use thiserror::Error;
+#[cfg(test)]
+use tokio::sync::Semaphore;
 use tokio_postgres::error::SqlState;
 use tokio_postgres::types::ToSql;
-use tokio_postgres::Client;
+use tokio_postgres::{Row, Statement, Transaction};
+use tracing::{debug, warn};
 use uuid::Uuid;
 
+use super::breaker::CircuitBreaker;
+#[cfg(test)]
+use super::cache_stats::CacheStats;
+use super::cache_stats::{CacheCounters, CacheKind, CacheStatsHandle};
+#[cfg(test)]
+use super::flex_filter::FlexFilter;
+use super::index_spec::IndexSpec;
+use super::partition::PartitionStrategy;
+use super::record_cache::RecordCache;
+use super::region_records::RegionRecords;
+use super::retry::{is_transient, RetryPolicy};
+use super::table_locks::TableLocks;
+use super::world_drop::WorldDrops;
 use super::world_region::WorldRegion;
-use super::{query_create_world_schema, query_delete_duplictes, query_delete_record};
-use crate::database::{
-    query_create_world, query_create_world_index, query_insert_record, query_insert_record_many,
-    query_select_records, query_select_records_after,
+use super::write_buffer::WriteBuffer;
+use super::{
+    global_table_name, query_create_world, query_create_world_global, query_create_world_index,
+    query_create_world_schema, query_delete_global_records_by_uuid,
+    query_delete_owned_global_records, query_delete_owned_record, query_delete_record,
+    query_insert_global_record, query_insert_moved_record, query_insert_record_many,
+    query_select_denied, query_select_global_denied, query_select_global_records,
+    query_select_records, query_select_records_after, query_select_records_in_box,
+    query_take_record, query_update_record_position, query_upgrade_world,
+    query_upgrade_world_global, table_name, QUERY_LOOKUP_TABLE_SUFFIXES_IN_BOX,
+    QUERY_TABLE_COMMENT, RECORD_TABLE_VERSION, TABLE_VERSION_PREFIX,
 };
+#[cfg(test)]
+use super::{
+    query_delete_records_by_uuid, query_insert_record, query_select_global_records_by_uuid,
+    query_select_nearest_records, query_select_records_by_uuid, query_select_records_filtered,
+    query_select_records_in_radius, query_select_records_paged, QUERY_LOOKUP_WORLD_REGIONS,
+};
+use crate::metrics::DB_DURATION;
 use crate::structures::{Record, Vector3};
-use crate::utils::{sanitize_world_name, SanitizeError};
+use crate::utils::{sanitize_world_name_with, SanitizeConfig, SanitizeError};
+
+/// Called with the world name and minimum `(x, y, z)` coordinates of each region
+/// evicted from a lookup cache.
+pub type EvictionHook = Box<dyn Fn(CacheKind, &str, (i64, i64, i64)) + Send + Sync>;
 
 pub struct DatabaseClient {
-    pub(super) client: Client,
+    /// Shared with every client of the same server, each connection caches the statements
+    /// prepared on it
+    pool: Pool,
     pub(super) table_cache: LruCache<WorldRegion, i32>,
     pub(super) region_cache: LruCache<WorldRegion, i32>,
+    /// Serves record reads when set, see [`DatabaseClient::with_read_replica`]
+    replica: Option<Pool>,
+    pub(super) cache_counters: Arc<CacheCounters>,
+    /// Shared with every client writing to the same database, see [`Self::with_table_locks`]
+    pub(super) table_locks: Arc<TableLocks>,
+    /// Shared with every client of the same server, see [`Self::with_world_drops`]
+    pub(super) world_drops: WorldDrops,
+    pub(super) eviction_hook: Option<EvictionHook>,
+    retry_policy: RetryPolicy,
 
     region_x_size: u16,
     region_y_size: u16,
     region_z_size: u16,
+    region_size_overrides: AHashMap<String, (u16, u16, u16)>,
     table_size: u32,
+    partition_strategy: PartitionStrategy,
+    index_spec: IndexSpec,
+
+    max_record_size: Option<usize>,
+    max_record_size_overrides: AHashMap<String, Option<usize>>,
+    sanitize_config: SanitizeConfig,
+    /// Inserts are made immediately if not set
+    pub(super) write_buffer: Option<WriteBuffer>,
+    /// Region reads always query the database if not set
+    pub(super) record_cache: Option<RecordCache>,
+    /// Database messages are always handled if not set
+    pub(super) breaker: Option<CircuitBreaker>,
+    /// Peers allowed to modify any record, ownership is only enforced if set
+    pub(super) privileged_peers: Option<AHashSet<Uuid>>,
 }
 
-pub type DedupeData = (Uuid, NaiveDateTime, String, Vector3);
+/// Maximum number of queries [`DatabaseClient::get_records_in_regions`] runs at once.
+#[cfg(test)]
+pub const MAX_CONCURRENT_REGION_QUERIES: usize = 16;
+
+/// Maximum number of tables a single [`DatabaseClient::get_records_in_box`] can query.
+pub const MAX_BOX_QUERY_TABLES: usize = 64;
+
+type TableMap = AHashMap<(String, i32), Vec<(i32, Record)>>;
+type GlobalMap = AHashMap<String, Vec<Record>>;
+type RecordRow = (
+    i32,
+    Vector3,
+    Uuid,
+    Option<String>,
+    Option<Vec<u8>>,
+    Option<Uuid>,
+);
+type GlobalRow = (Uuid, Option<String>, Option<Vec<u8>>, Option<Uuid>);
 
 impl DatabaseClient {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        client: Client,
+        pool: Pool,
         region_x_size: u16,
         region_y_size: u16,
         region_z_size: u16,
         table_size: u32,
         cache_size: usize,
+        retry_policy: RetryPolicy,
+        partition_strategy: PartitionStrategy,
     ) -> Self {
         let (table_cache, region_cache) = if cache_size == 0 {
             (LruCache::unbounded(), LruCache::unbounded())
@@ -46,128 +137,701 @@ impl DatabaseClient {
         };
 
         Self {
-            client,
+            pool,
             table_cache,
             region_cache,
+            replica: None,
+            cache_counters: Arc::default(),
+            table_locks: Arc::default(),
+            world_drops: WorldDrops::default(),
+            eviction_hook: None,
+            retry_policy,
 
             region_x_size,
             region_y_size,
             region_z_size,
+            region_size_overrides: AHashMap::new(),
             table_size,
+            partition_strategy,
+            index_spec: IndexSpec::default(),
+
+            max_record_size: None,
+            max_record_size_overrides: AHashMap::new(),
+            sanitize_config: SanitizeConfig::default(),
+            write_buffer: None,
+            record_cache: None,
+            breaker: None,
+            privileged_peers: None,
         }
     }
 
+    /// Route record reads to a read-only replica, while writes and region lookups stay on
+    /// the primary so newly allocated regions are always visible.
+    ///
+    /// Reads fall back to the primary once the replica can't be reached. Records written
+    /// to the primary may not be readable until the replica catches up.
+    pub fn with_read_replica(mut self, pool: Pool) -> Self {
+        self.replica = Some(pool);
+        self
+    }
+
+    /// Returns a connection to the primary from the pool, waiting for one to be returned
+    /// if they are all in use.
+    #[inline]
+    pub(super) async fn client(&self) -> Result<Object, tokio_postgres::Error> {
+        pooled_client(&self.pool).await
+    }
+
+    /// Count lookup cache hits and misses in `handle`, so clients used by several
+    /// database workers report combined [`CacheStats`].
+    pub fn with_cache_stats(mut self, handle: &CacheStatsHandle) -> Self {
+        self.cache_counters = handle.0.clone();
+        self
+    }
+
+    /// Share table creation locks with other clients, so their reads wait for tables this
+    /// client is creating instead of finding them missing.
+    pub fn with_table_locks(mut self, table_locks: Arc<TableLocks>) -> Self {
+        self.table_locks = table_locks;
+        self
+    }
+
+    /// Returns the table creation locks of this client, see [`Self::with_table_locks`].
+    pub fn table_locks(&self) -> Arc<TableLocks> {
+        self.table_locks.clone()
+    }
+
+    /// Share dropped worlds with other clients, so they forget their cached lookups for
+    /// worlds this client drops, see [`Self::drop_world`].
+    pub fn with_world_drops(mut self, world_drops: WorldDrops) -> Self {
+        self.world_drops = world_drops;
+        self
+    }
+
+    /// Returns the dropped worlds of this client, see [`Self::with_world_drops`].
+    pub fn world_drops(&self) -> WorldDrops {
+        self.world_drops.clone()
+    }
+
     // region: Getters
+    #[cfg(test)]
     #[inline]
     pub(super) fn region_x_size(&self) -> u16 {
         self.region_x_size
     }
 
+    #[cfg(test)]
     #[inline]
     pub(super) fn region_y_size(&self) -> u16 {
         self.region_y_size
     }
 
+    #[cfg(test)]
     #[inline]
     pub(super) fn region_z_size(&self) -> u16 {
         self.region_z_size
     }
 
+    /// Returns the `(x, y, z)` region sizes for a sanitized world name, falling back to
+    /// the global defaults if the world has no override.
+    #[inline]
+    pub(super) fn region_size(&self, world_name: &str) -> (u16, u16, u16) {
+        match self.region_size_overrides.get(world_name) {
+            Some(sizes) => *sizes,
+            None => (self.region_x_size, self.region_y_size, self.region_z_size),
+        }
+    }
+
     #[inline]
     pub(super) fn table_size(&self) -> u32 {
         self.table_size
     }
+
+    #[inline]
+    pub(super) fn partition_strategy(&self) -> PartitionStrategy {
+        self.partition_strategy
+    }
+
+    /// Returns a snapshot of the lookup cache hit and miss counters.
+    #[cfg(test)]
+    #[inline]
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache_counters.snapshot()
+    }
+
+    /// Returns a handle for reading cache stats once the client has been moved to
+    /// another thread.
+    #[inline]
+    pub fn cache_stats_handle(&self) -> CacheStatsHandle {
+        CacheStatsHandle(self.cache_counters.clone())
+    }
+    // endregion
+
+    // region: Setters
+    /// Set a hook which is called whenever a region is evicted from a lookup cache.
+    ///
+    /// Useful for tracking which regions age out, eg: for metrics.
+    #[cfg(test)]
+    pub fn set_eviction_hook(
+        &mut self,
+        hook: impl Fn(CacheKind, &str, (i64, i64, i64)) + Send + Sync + 'static,
+    ) {
+        self.eviction_hook = Some(Box::new(hook));
+    }
+
+    /// Override the region sizes used for a single world.
+    ///
+    /// Cached region IDs for the world are invalidated. Regions already allocated in the
+    /// database keep their original bounds, so this should be set before any records are
+    /// written to the world.
+    ///
+    /// Returns [`DatabaseError::InvalidRegionSize`] if any size doesn't evenly divide the
+    /// table size.
+    #[cfg(test)]
+    pub fn set_region_size(
+        &mut self,
+        world_name: &str,
+        x: u16,
+        y: u16,
+        z: u16,
+    ) -> Result<(), DatabaseError> {
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        // Regions must evenly divide tables
+        let table_size = self.table_size;
+        let is_valid = [x, y, z]
+            .iter()
+            .all(|size| *size != 0 && table_size % u32::from(*size) == 0);
+
+        if !is_valid {
+            return Err(DatabaseError::InvalidRegionSize {
+                world_name,
+                size: (x, y, z),
+                table_size,
+            });
+        }
+
+        // Invalidate cached region IDs for this world
+        let stale = self
+            .region_cache
+            .iter()
+            .filter(|(region, _)| region.world_name() == &world_name)
+            .map(|(region, _)| region.clone())
+            .collect::<Vec<_>>();
+
+        for region in stale {
+            self.region_cache.pop(&region);
+        }
+
+        self.region_size_overrides.insert(world_name, (x, y, z));
+        Ok(())
+    }
+
+    /// Sets the rules world names are sanitized with, the strict defaults are used if unset.
+    ///
+    /// Worlds already written under a name that sanitizes differently keep their old schema,
+    /// so this should be set before any records are written.
+    #[inline]
+    pub fn set_sanitize_config(&mut self, config: SanitizeConfig) {
+        self.sanitize_config = config;
+    }
+
+    #[inline]
+    pub(super) fn sanitize_world_name(&self, world_name: &str) -> Result<String, SanitizeError> {
+        sanitize_world_name_with(world_name, &self.sanitize_config)
+    }
+
+    /// Sets the indexes built on world tables created from now on.
+    #[inline]
+    pub fn set_index_spec(&mut self, spec: IndexSpec) {
+        self.index_spec = spec;
+    }
+
+    /// Sets the maximum size in bytes of a record's `data` or `flex`, unlimited if `None`.
+    ///
+    /// Records with a larger field are rejected with [`DatabaseError::RecordTooLarge`].
+    #[inline]
+    pub fn set_max_record_size(&mut self, limit: Option<usize>) {
+        self.max_record_size = limit;
+    }
+
+    /// Override the maximum record size used for a single world.
+    ///
+    /// See [`Self::set_max_record_size`].
+    #[cfg(test)]
+    pub fn set_world_max_record_size(&mut self, world_name: &str, limit: Option<usize>) {
+        match self.sanitize_world_name(world_name) {
+            Ok(world_name) => {
+                self.max_record_size_overrides.insert(world_name, limit);
+            }
+
+            Err(error) => warn!("Cannot set max record size for world: {}", error),
+        }
+    }
+
+    /// Returns an error if either field of `record` is larger than the limit for its world.
+    ///
+    /// `world_name` must already be sanitized.
+    fn check_record_size(&self, world_name: &str, record: &Record) -> Result<(), DatabaseError> {
+        let limit = match self.max_record_size_overrides.get(world_name) {
+            Some(limit) => *limit,
+            None => self.max_record_size,
+        };
+
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let sizes = [
+            ("data", record.data.as_ref().map_or(0, String::len)),
+            ("flex", record.flex.as_ref().map_or(0, Bytes::len)),
+        ];
+
+        match sizes.iter().find(|(_, size)| *size > limit) {
+            None => Ok(()),
+            Some((field, size)) => Err(DatabaseError::RecordTooLarge {
+                record: record.uuid,
+                field,
+                size: *size,
+                limit,
+            }),
+        }
+    }
     // endregion
 
     // region: Methods
     /// Insert many [`Record`] structs into the database.
     ///
     /// Batches records that map to the same table into a single `INSERT` operation.
+    /// Records whose [`Uuid`] is already stored in their table are skipped and reported as
+    /// [`DatabaseError::DuplicateRecord`], see [`Self::upsert_records`] to overwrite them.
+    #[cfg(test)]
+    #[inline]
     pub async fn insert_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
-        // Early return for no records
-        if records.is_empty() {
-            return vec![];
-        }
+        self.write_records(records, false, None).await.errors
+    }
 
-        type HashKey = (String, i32);
-        type HashValue = Vec<(i32, Record)>;
-        let mut table_map: AHashMap<HashKey, HashValue> = AHashMap::new();
+    /// Insert many [`Record`] structs into the database, returning how many rows were
+    /// inserted alongside any errors.
+    ///
+    /// Behaves exactly like [`Self::insert_records`].
+    #[cfg(test)]
+    #[inline]
+    pub async fn insert_records_counted(&mut self, records: Vec<Record>) -> InsertReport {
+        self.write_records(records, false, None).await
+    }
 
-        // Divide up records into table insertion operations
-        let len = records.len();
-        let mut errors = Vec::with_capacity(len);
+    /// Check which [`Record`] structs [`Self::insert_records`] would reject, without
+    /// inserting any of them.
+    ///
+    /// Records go through the same world name sanitization and size limits as an insert,
+    /// and records already stored are reported as [`DatabaseError::DuplicateRecord`].
+    /// Nothing is written, regions and tables are only looked up if already allocated.
+    ///
+    /// Records written by others after the check may still be reported by the insert.
+    #[cfg(test)]
+    #[inline]
+    pub async fn validate_records(&mut self, records: &[Record]) -> Vec<DatabaseError> {
+        self.check_records(records, None).await
+    }
+
+    /// Check which [`Record`] structs [`Self::upsert_records_as`] would reject, without
+    /// writing any of them.
+    ///
+    /// Like [`Self::validate_records`], but records owned by another peer are reported as
+    /// [`DatabaseError::PermissionDenied`] instead of duplicates.
+    #[cfg(test)]
+    #[inline]
+    pub async fn validate_records_as(
+        &mut self,
+        owner: Uuid,
+        records: &[Record],
+    ) -> Vec<DatabaseError> {
+        self.check_records(records, Some(owner)).await
+    }
+
+    #[cfg(test)]
+    async fn check_records(
+        &mut self,
+        records: &[Record],
+        owner: Option<Uuid>,
+    ) -> Vec<DatabaseError> {
+        let _timer = DB_DURATION.start_timer("validate_records");
+        let guard = owner.filter(|owner| self.enforces_ownership(owner));
+        let table_size = i64::from(self.table_size());
+        let mut errors = vec![];
+
+        // Records that may already be stored by table, global tables have no suffix
+        let mut tables: AHashMap<(String, Option<i32>), Vec<Uuid>> = AHashMap::new();
+        let mut seen = AHashSet::new();
         for record in records {
-            // TODO: Handle records without position
-            let position = record.position.unwrap();
-            let world_name = match sanitize_world_name(&record.world_name) {
+            let world_name = match self.sanitize_world_name(&record.world_name) {
                 Ok(world_name) => world_name,
                 Err(error) => {
-                    errors.push(error.into());
+                    let error = DatabaseError::invalid_world_name(
+                        &record.world_name,
+                        Some(record.uuid),
+                        error,
+                    );
+
+                    errors.push(error);
                     continue;
                 }
             };
 
-            // Lookup navigation IDs for this record
-            let (table_suffix, region_id) = match self.lookup_ids(&world_name, &position).await {
-                Ok(result) => result,
+            if let Err(error) = self.check_record_size(&world_name, record) {
+                errors.push(error);
+                continue;
+            }
+
+            let region = record
+                .position
+                .map(|position| self.world_region(&world_name, &position));
+
+            // An insert skips all but the first record with the same Uuid in a table
+            let bounds = region
+                .as_ref()
+                .map(|region| self.partition_strategy().table_bounds(region, table_size));
+
+            if owner.is_none() && !seen.insert((world_name.clone(), bounds, record.uuid)) {
+                errors.push(DatabaseError::DuplicateRecord {
+                    record: record.uuid,
+                });
+
+                continue;
+            }
+
+            // Tables that were never allocated hold no records
+            let table_suffix = match region {
+                None => None,
+                Some(region) => match self.find_table_suffix(&region).await {
+                    Ok(Some(table_suffix)) => Some(table_suffix),
+                    Ok(None) => continue,
+                    Err(error) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                },
+            };
+
+            tables
+                .entry((world_name, table_suffix))
+                .or_default()
+                .push(record.uuid);
+        }
+
+        // Upserts only fail on records owned by another peer
+        if owner.is_some() && guard.is_none() {
+            return errors;
+        }
+
+        for ((world_name, table_suffix), uuids) in tables {
+            let (query, params): (String, Vec<&(dyn ToSql + Sync)>) = match (table_suffix, &guard) {
+                (Some(table_suffix), None) => (
+                    query_select_records_by_uuid(&world_name, table_suffix),
+                    vec![&uuids],
+                ),
+                (None, None) => (
+                    query_select_global_records_by_uuid(&world_name),
+                    vec![&uuids],
+                ),
+                (Some(table_suffix), Some(owner)) => (
+                    query_select_denied(&world_name, table_suffix),
+                    vec![&uuids, owner],
+                ),
+                (None, Some(owner)) => {
+                    (query_select_global_denied(&world_name), vec![&uuids, owner])
+                }
+            };
+
+            let rows = match self.query_cached(&query, &params).await {
+                Ok(rows) => rows,
+                Err(error) if is_undefined_table(&error) => continue,
                 Err(error) => {
                     errors.push(error.into());
                     continue;
                 }
             };
 
-            // Get or create Vec for this table_suffix
-            let filtered_records = table_map
-                .entry((world_name, table_suffix))
-                .or_insert_with(|| Vec::with_capacity(len));
+            errors.extend(rows.iter().map(|row| match guard {
+                Some(peer) => DatabaseError::PermissionDenied {
+                    record: row.get("uuid"),
+                    peer,
+                },
+                None => DatabaseError::DuplicateRecord {
+                    record: row.get("uuid"),
+                },
+            }));
+        }
+
+        errors
+    }
+
+    /// Insert or update many [`Record`] structs in the database.
+    ///
+    /// Records with a [`Uuid`] that already exists in the target table are overwritten
+    /// rather than duplicated. Uses the same batching as [`Self::insert_records`].
+    #[cfg(test)]
+    #[inline]
+    pub async fn upsert_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
+        self.write_records(records, true, None).await.errors
+    }
+
+    /// Insert or update many [`Record`] structs on behalf of the peer `owner`.
+    ///
+    /// New records are owned by `owner`. If ownership is enforced, records owned by
+    /// another peer are left unchanged and reported as [`DatabaseError::PermissionDenied`],
+    /// see [`Self::set_record_ownership`].
+    #[inline]
+    pub async fn upsert_records_as(
+        &mut self,
+        owner: Uuid,
+        records: Vec<Record>,
+    ) -> Vec<DatabaseError> {
+        self.write_records(records, true, Some(owner)).await.errors
+    }
+
+    /// Insert many [`Record`] structs into the database as a single transaction.
+    ///
+    /// Either every record is inserted or none are. Tables created as part of the insert
+    /// are also rolled back if any later insert fails.
+    #[cfg(test)]
+    pub async fn insert_records_atomic(
+        &mut self,
+        records: Vec<Record>,
+    ) -> Result<(), DatabaseError> {
+        let _timer = DB_DURATION.start_timer("insert_records_atomic");
+        // Early return for no records
+        if records.is_empty() {
+            return Ok(());
+        }
 
-            filtered_records.push((region_id, record));
+        // Abort before touching any tables if a record can't be placed
+        let (table_map, global_map, mut errors) = self.group_records(records).await;
+        if !errors.is_empty() {
+            return Err(errors.swap_remove(0));
         }
 
-        for ((world_name, table_suffix), records) in table_map {
-            // Destructure and map records
-            let records = records
-                .into_iter()
-                .map(|(region_id, record)| {
-                    (
-                        region_id,
-                        record.position.unwrap(),
-                        record.uuid,
-                        record.data,
-                        record.flex.map(|b| b.to_vec()),
-                    )
-                })
-                .collect::<Vec<_>>();
+        for (world_name, table_suffix) in table_map.keys() {
+            self.invalidate_cached_table(world_name, *table_suffix);
+        }
 
-            // Construct params array
-            let count = records.len();
-            let params = {
-                let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        // Dropping the transaction without committing rolls it back
+        // Tables created here only become visible on commit, so their locks are held until then
+        let mut creating = vec![];
+        let mut client = self.client().await?;
+        let mut transaction = client.transaction().await?;
+        for ((world_name, table_suffix), records) in table_map {
+            let rows = into_record_rows(records, false, None);
+            let params = record_row_params(&rows);
+            let uuids = rows.iter().map(|row| row.2).collect::<Vec<_>>();
+            let query =
+                query_insert_record_many(&world_name, table_suffix, rows.len(), false, false);
+
+            // Use a savepoint so a missing table doesn't abort the whole transaction
+            let savepoint = transaction.savepoint("insert_records").await?;
+            let error = match savepoint.query(&query, &params).await {
+                Ok(written) => {
+                    savepoint.commit().await?;
+                    reject_duplicates(&uuids, &written)?;
+                    continue;
+                }
+                Err(error) => error,
+            };
 
-                for (region_id, position, uuid, data, flex) in &records {
-                    params.push(region_id);
-                    params.push(position.x());
-                    params.push(position.y());
-                    params.push(position.z());
-                    params.push(uuid);
-                    params.push(data);
-                    params.push(flex);
+            // Tables created by older versions may be missing newer columns or indexes
+            if is_outdated_layout(&error) {
+                savepoint.rollback().await?;
+                let table = table_name(&world_name, table_suffix);
+                let upgrade = query_upgrade_world(&world_name, table_suffix);
+                if !upgrade_table(&transaction, &table, &upgrade).await? {
+                    return Err(error.into());
                 }
 
-                params
+                let written = transaction.query(&query, &params).await?;
+                reject_duplicates(&uuids, &written)?;
+                continue;
+            }
+
+            // Check for undefined table error, if not then re-throw
+            if !is_undefined_table(&error) {
+                return Err(error.into());
+            }
+
+            savepoint.rollback().await?;
+            creating.push(
+                self.table_locks
+                    .lock_create(&world_name, table_suffix)
+                    .await,
+            );
+
+            // Create schema, table and indexes inside the same transaction
+            transaction
+                .execute(&query_create_world_schema(&world_name), &[])
+                .await?;
+
+            transaction
+                .execute(&query_create_world(&world_name, table_suffix), &[])
+                .await?;
+
+            transaction
+                .batch_execute(&query_create_world_index(
+                    &world_name,
+                    table_suffix,
+                    self.index_spec,
+                ))
+                .await?;
+
+            // Retry insertion
+            let written = transaction.query(&query, &params).await?;
+            reject_duplicates(&uuids, &written)?;
+        }
+
+        for (world_name, records) in global_map {
+            let rows = into_global_rows(records, false, None);
+            let params = global_row_params(&rows);
+            let uuids = rows.iter().map(|row| row.0).collect::<Vec<_>>();
+            let query = query_insert_global_record(&world_name, rows.len(), false, false);
+
+            let savepoint = transaction.savepoint("insert_global_records").await?;
+            let error = match savepoint.query(&query, &params).await {
+                Ok(written) => {
+                    savepoint.commit().await?;
+                    reject_duplicates(&uuids, &written)?;
+                    continue;
+                }
+                Err(error) => error,
             };
 
+            if is_outdated_layout(&error) {
+                savepoint.rollback().await?;
+                let table = global_table_name(&world_name);
+                let upgrade = query_upgrade_world_global(&world_name);
+                if !upgrade_table(&transaction, &table, &upgrade).await? {
+                    return Err(error.into());
+                }
+
+                let written = transaction.query(&query, &params).await?;
+                reject_duplicates(&uuids, &written)?;
+                continue;
+            }
+
+            if !is_undefined_table(&error) {
+                return Err(error.into());
+            }
+
+            savepoint.rollback().await?;
+
+            // Create schema and global table inside the same transaction
+            transaction
+                .execute(&query_create_world_schema(&world_name), &[])
+                .await?;
+
+            transaction
+                .batch_execute(&query_create_world_global(&world_name))
+                .await?;
+
+            // Retry insertion
+            let written = transaction.query(&query, &params).await?;
+            reject_duplicates(&uuids, &written)?;
+        }
+
+        transaction.commit().await?;
+        drop(creating);
+
+        Ok(())
+    }
+
+    /// Insert or upsert records, owned by `owner` if set.
+    pub(super) async fn write_records(
+        &mut self,
+        records: Vec<Record>,
+        upsert: bool,
+        owner: Option<Uuid>,
+    ) -> InsertReport {
+        let _timer = DB_DURATION.start_timer(match upsert {
+            true => "upsert_records",
+            false => "insert_records",
+        });
+
+        // Early return for no records
+        if records.is_empty() {
+            return InsertReport::default();
+        }
+
+        // Only upserts can overwrite another peer's records
+        let guard = owner.filter(|owner| upsert && self.enforces_ownership(owner));
+
+        let mut inserted = 0;
+        let (table_map, global_map, mut errors) = self.group_records(records).await;
+        for ((world_name, table_suffix), records) in table_map {
+            // Upserts can also move records between regions of the same table
+            self.invalidate_cached_table(&world_name, table_suffix);
+
+            let rows = into_record_rows(records, upsert, owner);
+            let params = record_row_params(&rows);
+            let uuids = rows.iter().map(|row| row.2).collect::<Vec<_>>();
+
             // Build a bulk insertion query and execute
-            let query = query_insert_record_many(&world_name, table_suffix, count);
-            let result = self.client.execute(&query, &params).await;
+            let query = query_insert_record_many(
+                &world_name,
+                table_suffix,
+                rows.len(),
+                upsert,
+                guard.is_some(),
+            );
+            let result = self
+                .execute_insert(&query, &params, &uuids, upsert, &mut errors)
+                .await;
 
             // Insertion completed without errors, exit early
-            if result.is_ok() {
+            let error = match result {
+                Ok(written) => {
+                    inserted += written;
+
+                    // Rows left out by the ownership guard weren't written
+                    if let Some(owner) = guard.filter(|_| written < rows.len() as u64) {
+                        let query = query_select_denied(&world_name, table_suffix);
+                        errors.extend(self.denied_records(&query, uuids, owner).await);
+                    }
+
+                    continue;
+                }
+                Err(error) => error,
+            };
+
+            // Tables created by older versions may be missing newer columns or indexes
+            if is_outdated_layout(&error) {
+                let table = table_name(&world_name, table_suffix);
+                let upgrade = query_upgrade_world(&world_name, table_suffix);
+                match self.upgrade_table(&table, &upgrade).await {
+                    Ok(true) => (),
+                    Ok(false) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                    Err(error) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                }
+
+                let result = self
+                    .execute_insert(&query, &params, &uuids, upsert, &mut errors)
+                    .await;
+
+                match result {
+                    Ok(rows) => inserted += rows,
+                    Err(error) => errors.push(error.into()),
+                }
+
                 continue;
             }
 
             // Handle SQL errors
-            let error = result.unwrap_err();
             let db_error = error.as_db_error();
 
             // If error isn't a database error, re-throw
@@ -183,83 +847,591 @@ impl DatabaseClient {
                 continue;
             }
 
-            // Create schema for world
-            let result = self
-                .client
+            // Readers wait for the table to be created rather than reporting it missing
+            let _creating = self
+                .table_locks
+                .lock_create(&world_name, table_suffix)
+                .await;
+
+            // Create schema for world, another insert may be racing to create the same
+            // table so duplicates are not errors
+            let client = match self.client().await {
+                Ok(client) => client,
+                Err(error) => {
+                    errors.push(error.into());
+                    continue;
+                }
+            };
+
+            let result = client
                 .execute(&query_create_world_schema(&world_name), &[])
                 .await;
 
-            if let Err(error) = result {
+            if let Err(error) = ignore_duplicate(result) {
                 errors.push(error.into());
                 continue;
             }
 
             // Create table for world region
-            let result = self
-                .client
+            let result = client
                 .execute(&query_create_world(&world_name, table_suffix), &[])
                 .await;
 
-            if let Err(error) = result {
+            if let Err(error) = ignore_duplicate(result) {
                 errors.push(error.into());
                 continue;
             }
 
-            // Create index for new table
-            let result = self
-                .client
-                .execute(&query_create_world_index(&world_name, table_suffix), &[])
+            // Create indexes for new table
+            let result = client
+                .batch_execute(&query_create_world_index(
+                    &world_name,
+                    table_suffix,
+                    self.index_spec,
+                ))
                 .await;
 
-            if let Err(error) = result {
+            if let Err(error) = ignore_duplicate(result) {
                 errors.push(error.into());
                 continue;
             }
 
+            // Return the connection before retrying, so a pool of one can't deadlock
+            drop(client);
+
             // Retry insertion
-            let result = self.client.execute(&query, &params[..]).await;
-            if let Err(error) = result {
-                errors.push(error.into());
-                continue;
+            let result = self
+                .execute_insert(&query, &params, &uuids, upsert, &mut errors)
+                .await;
+
+            match result {
+                Ok(rows) => inserted += rows,
+                Err(error) => errors.push(error.into()),
             }
         }
 
-        errors
-    }
+        for (world_name, records) in global_map {
+            let uuids = records.iter().map(|record| record.uuid).collect::<Vec<_>>();
+            let result = self
+                .write_global_records(&world_name, records, upsert, owner, &mut errors)
+                .await;
 
-    /// Insert a single [`Record`] into the database.
-    #[deprecated = "use insert_records() instead"]
-    pub async fn insert_record(&mut self, record: &Record) -> Result<(), DatabaseError> {
-        // TODO: Handle records without position
-        let position = record.position.unwrap();
-        let world_name = sanitize_world_name(&record.world_name)?;
+            let written = match result {
+                Ok(written) => written,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
 
-        let (table_suffix, region_id) = self.lookup_ids(&world_name, &position).await?;
-        let query = query_insert_record(&world_name, table_suffix);
+            inserted += written;
+            if let Some(owner) = guard.filter(|_| written < uuids.len() as u64) {
+                let query = query_select_global_denied(&world_name);
+                errors.extend(self.denied_records(&query, uuids, owner).await);
+            }
+        }
+
+        InsertReport { inserted, errors }
+    }
 
+    /// Insert records without a position into the world's global table.
+    ///
+    /// `world_name` must already be sanitized. Returns the number of rows written, records
+    /// skipped by a plain insert are added to `errors`.
+    async fn write_global_records(
+        &mut self,
+        world_name: &str,
+        records: Vec<Record>,
+        upsert: bool,
+        owner: Option<Uuid>,
+        errors: &mut Vec<DatabaseError>,
+    ) -> Result<u64, DatabaseError> {
+        let guarded = owner.map_or(false, |owner| upsert && self.enforces_ownership(&owner));
+        let rows = into_global_rows(records, upsert, owner);
+        let params = global_row_params(&rows);
+        let uuids = rows.iter().map(|row| row.0).collect::<Vec<_>>();
+
+        // Build a bulk insertion query and execute
+        let query = query_insert_global_record(world_name, rows.len(), upsert, guarded);
         let result = self
-            .client
-            .execute(
-                &query,
-                &[
-                    &region_id,
-                    position.x(),
-                    position.y(),
-                    position.z(),
-                    &record.uuid,
-                    &record.data,
-                    &record.flex.as_ref().map(|b| b.to_vec()),
-                ],
-            )
+            .execute_insert(&query, &params, &uuids, upsert, errors)
             .await;
 
+        let error = match result {
+            Ok(rows) => return Ok(rows),
+            Err(error) => error,
+        };
+
+        // Tables created by older versions may be missing newer columns or indexes
+        if is_outdated_layout(&error) {
+            let table = global_table_name(world_name);
+            let upgrade = query_upgrade_world_global(world_name);
+            if !self.upgrade_table(&table, &upgrade).await? {
+                return Err(error.into());
+            }
+
+            let rows = self
+                .execute_insert(&query, &params, &uuids, upsert, errors)
+                .await?;
+
+            return Ok(rows);
+        }
+
+        // Check for undefined table error, if not then re-throw
+        if !is_undefined_table(&error) {
+            return Err(error.into());
+        }
+
+        // Create schema for world
+        let result = self
+            .client()
+            .await?
+            .execute(&query_create_world_schema(world_name), &[])
+            .await;
+
+        ignore_duplicate(result)?;
+
+        // Create global table and indexes for world
+        let result = self
+            .client()
+            .await?
+            .batch_execute(&query_create_world_global(world_name))
+            .await;
+
+        ignore_duplicate(result)?;
+
+        // Retry insertion
+        let rows = self
+            .execute_insert(&query, &params, &uuids, upsert, errors)
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Run a bulk insertion query for the records `uuids`, returning how many were written.
+    ///
+    /// Plain inserts skip records whose [`Uuid`] is already stored, each of those is added
+    /// to `errors` as a [`DatabaseError::DuplicateRecord`].
+    async fn execute_insert(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+        uuids: &[Uuid],
+        upsert: bool,
+        errors: &mut Vec<DatabaseError>,
+    ) -> Result<u64, tokio_postgres::Error> {
+        if upsert {
+            return self.execute_with_retry(query, params).await;
+        }
+
+        let rows = self.query_with_retry(query, params).await?;
+        errors.extend(duplicate_records(uuids, &rows));
+
+        Ok(rows.len() as u64)
+    }
+
+    /// Upgrade `table` if it was created by an older version, see [`upgrade_table`].
+    async fn upgrade_table(
+        &mut self,
+        table: &str,
+        upgrade: &str,
+    ) -> Result<bool, tokio_postgres::Error> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        let upgraded = upgrade_table(&transaction, table, upgrade).await?;
+        transaction.commit().await?;
+
+        Ok(upgraded)
+    }
+
+    /// Returns a pooled connection to the primary and a prepared [`Statement`] for the
+    /// query, which must be run on that connection.
+    ///
+    /// Statements are prepared only once per connection. Statements for missing tables fail
+    /// to prepare, so are never cached.
+    #[inline]
+    async fn prepare_cached(
+        &self,
+        query: &str,
+    ) -> Result<(Object, Statement), tokio_postgres::Error> {
+        prepare_pooled(&self.pool, query).await
+    }
+
+    /// Returns the pool to use for record reads, the replica if there is one.
+    #[cfg(test)]
+    fn read_pool(&self) -> &Pool {
+        self.replica.as_ref().unwrap_or(&self.pool)
+    }
+
+    /// Like [`Self::prepare_cached`] on the connection used for record reads.
+    ///
+    /// A replica that can't be reached is dropped, so reads fall back to the primary.
+    async fn prepare_read(
+        &mut self,
+        query: &str,
+    ) -> Result<(Object, Statement), tokio_postgres::Error> {
+        if let Some(replica) = &self.replica {
+            match prepare_pooled(replica, query).await {
+                Err(error) if is_connection_error(&error) => {
+                    warn!("read replica unreachable, reading from primary: {}", error);
+                    self.replica = None;
+                }
+
+                result => return result,
+            }
+        }
+
+        self.prepare_cached(query).await
+    }
+
+    /// Run a read-only query on the replica if there is one, otherwise on the primary.
+    ///
+    /// Reads are idempotent, so transient errors are always retried according to the
+    /// [`RetryPolicy`].
+    async fn query_read(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.query_read_once(query, params).await {
+                Ok(rows) => return Ok(rows),
+                Err(error) => error,
+            };
+
+            attempt += 1;
+            match self.retry_delay(attempt, &error) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(error),
+            }
+        }
+    }
+
+    /// Like [`Self::query_read`] for a query on a single record table.
+    ///
+    /// If the table is missing but being created, waits for it to be created and queries
+    /// it again, so reads racing the first write to a table don't report it missing.
+    async fn query_read_table(
+        &mut self,
+        world_name: &str,
+        table_suffix: i32,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        match self.query_read(query, params).await {
+            Err(error) if is_undefined_table(&error) => {
+                if !self
+                    .table_locks
+                    .wait_created(world_name, table_suffix)
+                    .await
+                {
+                    return Err(error);
+                }
+
+                self.query_read(query, params).await
+            }
+
+            result => result,
+        }
+    }
+
+    async fn query_read_once(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let (client, statement) = self.prepare_read(query).await?;
+        let is_replica = self.replica.is_some();
+
+        match client.query(&statement, params).await {
+            // Retry on the primary if the replica went away mid-query
+            Err(error) if is_replica && is_connection_error(&error) => {
+                warn!("read replica unreachable, reading from primary: {}", error);
+                self.replica = None;
+
+                self.query_cached(query, params).await
+            }
+
+            result => result,
+        }
+    }
+
+    /// Execute a query using a cached prepared [`Statement`].
+    async fn execute_cached(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, tokio_postgres::Error> {
+        let (client, statement) = self.prepare_cached(query).await?;
+        client.execute(&statement, params).await
+    }
+
+    /// Run a query using a cached prepared [`Statement`], returning the resulting rows.
+    pub(super) async fn query_cached(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let result = match self.prepare_cached(query).await {
+            Ok((client, statement)) => client.query(&statement, params).await,
+            Err(error) => Err(error),
+        };
+
+        match &result {
+            Ok(_) => self.record_reachable(),
+            Err(error) => self.record_error(error),
+        }
+
+        result
+    }
+
+    /// Execute a statement, retrying transient errors according to the [`RetryPolicy`].
+    async fn execute_with_retry(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, tokio_postgres::Error> {
+        let mut attempt = 0;
+        loop {
+            let error = match self.execute_cached(query, params).await {
+                Ok(rows) => {
+                    self.record_reachable();
+                    return Ok(rows);
+                }
+
+                Err(error) => error,
+            };
+
+            attempt += 1;
+            match self.retry_delay(attempt, &error) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => {
+                    self.record_error(&error);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Run a statement returning rows, retrying transient errors according to the
+    /// [`RetryPolicy`].
+    async fn query_with_retry(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error> {
+        let mut attempt = 0;
+        loop {
+            let result = match self.prepare_cached(query).await {
+                Ok((client, statement)) => client.query(&statement, params).await,
+                Err(error) => Err(error),
+            };
+
+            let error = match result {
+                Ok(rows) => {
+                    self.record_reachable();
+                    return Ok(rows);
+                }
+
+                Err(error) => error,
+            };
+
+            attempt += 1;
+            match self.retry_delay(attempt, &error) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => {
+                    self.record_error(&error);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    /// Returns how long to wait before retrying a query that has failed `attempt` times
+    /// with `error`, or `None` if it shouldn't be retried.
+    fn retry_delay(&self, attempt: u32, error: &tokio_postgres::Error) -> Option<Duration> {
+        if attempt >= self.retry_policy.max_attempts() || !is_transient(error) {
+            return None;
+        }
+
+        let delay = self.retry_policy.backoff(attempt - 1);
+        warn!(
+            "transient database error, retrying in {:?} (attempt {}/{}): {}",
+            delay,
+            attempt + 1,
+            self.retry_policy.max_attempts(),
+            error
+        );
+
+        Some(delay)
+    }
+
+    /// Divide up records into table insertion operations, keyed by world name and
+    /// `table_suffix`.
+    ///
+    /// Records without a position are grouped separately by world name.
+    async fn group_records(
+        &mut self,
+        records: Vec<Record>,
+    ) -> (TableMap, GlobalMap, Vec<DatabaseError>) {
+        let mut table_map: TableMap = AHashMap::new();
+        let mut global_map: GlobalMap = AHashMap::new();
+        let mut spatial_map: AHashMap<String, Vec<Record>> = AHashMap::new();
+
+        let len = records.len();
+        let mut errors = Vec::with_capacity(len);
+        for record in records {
+            let world_name = match self.sanitize_world_name(&record.world_name) {
+                Ok(world_name) => world_name,
+                Err(error) => {
+                    let error = DatabaseError::invalid_world_name(
+                        &record.world_name,
+                        Some(record.uuid),
+                        error,
+                    );
+
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            if let Err(error) = self.check_record_size(&world_name, &record) {
+                errors.push(error);
+                continue;
+            }
+
+            // Records that aren't spatially anchored go to the global table
+            match record.position {
+                Some(_) => spatial_map.entry(world_name).or_default().push(record),
+                None => global_map.entry(world_name).or_default().push(record),
+            }
+        }
+
+        for (world_name, records) in spatial_map {
+            // Lookup navigation IDs for every record in the world at once
+            let positions = records
+                .iter()
+                .filter_map(|record| record.position)
+                .collect::<Vec<_>>();
+
+            let ids = match self
+                .lookup_sanitized_ids_batch(&world_name, &positions)
+                .await
+            {
+                Ok(ids) => ids.into_iter().map(Ok).collect::<Vec<_>>(),
+
+                // Fall back to individual lookups, so each record gets its own error
+                Err(error) => {
+                    debug!("batch lookup failed, retrying individually: {}", error);
+
+                    let mut ids = Vec::with_capacity(positions.len());
+                    for position in &positions {
+                        ids.push(self.lookup_ids(&world_name, position).await);
+                    }
+
+                    ids
+                }
+            };
+
+            for (record, ids) in records.into_iter().zip(ids) {
+                let (table_suffix, region_id) = match ids {
+                    Ok(result) => result,
+                    Err(error) => {
+                        errors.push(error.into());
+                        continue;
+                    }
+                };
+
+                // Get or create Vec for this table_suffix
+                let filtered_records = table_map
+                    .entry((world_name.clone(), table_suffix))
+                    .or_insert_with(|| Vec::with_capacity(len));
+
+                filtered_records.push((region_id, record));
+            }
+        }
+
+        (table_map, global_map, errors)
+    }
+
+    /// Insert a single [`Record`] into the database.
+    #[cfg(test)]
+    #[deprecated = "use insert_records() instead"]
+    pub async fn insert_record(&mut self, record: &Record) -> Result<(), DatabaseError> {
+        let _timer = DB_DURATION.start_timer("insert_record");
+        let world_name = self
+            .sanitize_world_name(&record.world_name)
+            .map_err(|error| {
+                DatabaseError::invalid_world_name(&record.world_name, Some(record.uuid), error)
+            })?;
+        self.check_record_size(&world_name, record)?;
+
+        let position = match record.position {
+            Some(position) => position,
+            None => {
+                let mut errors = vec![];
+                self.write_global_records(
+                    &world_name,
+                    vec![record.clone()],
+                    false,
+                    None,
+                    &mut errors,
+                )
+                .await?;
+
+                return match errors.pop() {
+                    Some(error) => Err(error),
+                    None => Ok(()),
+                };
+            }
+        };
+
+        let (table_suffix, region_id) = self.lookup_ids(&world_name, &position).await?;
+        self.invalidate_cached_table(&world_name, table_suffix);
+
+        let query = query_insert_record(&world_name, table_suffix, false);
+
+        let flex = record.flex.as_ref().map(|b| b.to_vec());
+        let params: [&(dyn ToSql + Sync); 7] = [
+            &region_id,
+            position.x(),
+            position.y(),
+            position.z(),
+            &record.uuid,
+            &record.data,
+            &flex,
+        ];
+
+        // Nothing is written if the record is already stored
+        let inserted = |written: u64| match written {
+            0 => Err(DatabaseError::DuplicateRecord {
+                record: record.uuid,
+            }),
+            _ => Ok(()),
+        };
+
+        let result = self.execute_with_retry(&query, &params).await;
+
         // Insertion completed without errors, exit early
-        if result.is_ok() {
-            return Ok(());
+        let error = match result {
+            Ok(written) => return inserted(written),
+            Err(error) => error,
+        };
+
+        // Tables created by older versions may be missing newer columns or indexes
+        if is_outdated_layout(&error) {
+            let table = table_name(&world_name, table_suffix);
+            let upgrade = query_upgrade_world(&world_name, table_suffix);
+            if !self.upgrade_table(&table, &upgrade).await? {
+                return Err(error.into());
+            }
+
+            let written = self.execute_with_retry(&query, &params).await?;
+            return inserted(written);
         }
 
         // Handle SQL Error
-        let error = result.unwrap_err();
         let db_error = error.as_db_error();
 
         // If error isn't a database error, re-throw
@@ -273,151 +1445,2428 @@ impl DatabaseClient {
             return Err(DatabaseError::PostgresError(error));
         }
 
+        // Readers wait for the table to be created rather than reporting it missing
+        let _creating = self
+            .table_locks
+            .lock_create(&world_name, table_suffix)
+            .await;
+
         // Create schema for world
-        self.client
+        let client = self.client().await?;
+        client
             .execute(&query_create_world_schema(&world_name), &[])
             .await?;
 
         // Create table for world region
-        self.client
+        client
             .execute(&query_create_world(&world_name, table_suffix), &[])
             .await?;
 
-        // Create index for new table
-        self.client
-            .execute(&query_create_world_index(&world_name, table_suffix), &[])
+        // Create indexes for new table
+        client
+            .batch_execute(&query_create_world_index(
+                &world_name,
+                table_suffix,
+                self.index_spec,
+            ))
             .await?;
 
-        // Retry insertion
-        self.client
-            .execute(
-                &query,
-                &[
-                    &region_id,
-                    position.x(),
-                    position.y(),
-                    position.z(),
-                    &record.uuid,
-                    &record.data,
-                    &record.flex.as_ref().map(|b| b.to_vec()),
-                ],
-            )
-            .await?;
+        // Return the connection before retrying, so a pool of one can't deadlock
+        drop(client);
 
-        Ok(())
+        // Retry insertion
+        let written = self.execute_with_retry(&query, &params).await?;
+        inserted(written)
     }
 
     /// Returns a [`Vec`] containing all records found within the region represented
     /// by `point_inside_region`
+    ///
+    /// Reads without `after` are served from the record cache when enabled, see
+    /// [`Self::set_record_cache`].
+    #[cfg(test)]
     pub async fn get_records_in_region(
         &mut self,
         world_name: &str,
         point_inside_region: Vector3,
         after: Option<NaiveDateTime>,
     ) -> Result<Vec<(NaiveDateTime, Record)>> {
-        let (table_suffix, region_id) = self.lookup_ids(world_name, &point_inside_region).await?;
+        let records = self
+            .get_region_records(world_name, point_inside_region, after)
+            .await?;
+
+        Ok(records.into_records())
+    }
+
+    /// Like [`Self::get_records_in_region`], but tells apart regions that have never been
+    /// written to from regions without matching records.
+    ///
+    /// Tables span several regions, so a region whose neighbours have been written to is
+    /// reported as [`RegionRecords::Empty`].
+    pub async fn get_region_records(
+        &mut self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        after: Option<NaiveDateTime>,
+    ) -> Result<RegionRecords> {
+        let _timer = DB_DURATION.start_timer("get_records_in_region");
+
+        // Sanitize first, region size overrides are keyed by sanitized name
+        let sanitized = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let (table_suffix, region_id) = self.lookup_ids(&sanitized, &point_inside_region).await?;
+
+        let cacheable = after.is_none() && self.record_cache.is_some();
+        if cacheable {
+            if let Some(records) = self
+                .record_cache
+                .as_mut()
+                .and_then(|cache| cache.get(&sanitized, region_id))
+            {
+                return Ok(records);
+            }
+        }
 
         let result = match after {
             // Send all results
             None => {
-                let query = query_select_records(world_name, table_suffix);
-                self.client.query(&query, &[&region_id]).await
+                let query = query_select_records(&sanitized, table_suffix);
+                self.query_read_table(&sanitized, table_suffix, &query, &[&region_id])
+                    .await
             }
 
             // Send only results after time
             Some(after) => {
-                let query = query_select_records_after(world_name, table_suffix);
-                self.client.query(&query, &[&region_id, &after]).await
+                let query = query_select_records_after(&sanitized, table_suffix);
+                self.query_read_table(&sanitized, table_suffix, &query, &[&region_id, &after])
+                    .await
             }
         };
 
-        // Check for undefined table error and early return no records
-        if let Err(error) = result {
-            match error.as_db_error() {
-                None => return Err(error.into()),
-                Some(db_error) => {
-                    // Early return
-                    if *db_error.code() == SqlState::UNDEFINED_TABLE {
-                        return Ok(vec![]);
-                    }
+        let records = match result {
+            Ok(rows) => RegionRecords::from_rows(
+                rows.into_iter()
+                    .map(|row| {
+                        let timestamp: NaiveDateTime = row.get("last_modified");
+                        let record = Record::from_postgres_row(row, world_name);
+
+                        (timestamp, record)
+                    })
+                    .collect(),
+            ),
+
+            // Writes create the region's table, so a missing table means it was never written
+            Err(error) if is_undefined_table(&error) => RegionRecords::NeverWritten,
+            Err(error) => return Err(error.into()),
+        };
 
-                    // Different error, re-throw
-                    return Err(error.into());
-                }
+        if cacheable {
+            if let Some(cache) = &mut self.record_cache {
+                cache.put(&sanitized, table_suffix, region_id, records.clone());
             }
         }
 
-        let records = result
-            .unwrap()
-            .into_iter()
-            .map(|row| {
-                let timestamp: NaiveDateTime = row.get("last_modified");
-                let record = Record::from_postgres_row(row, world_name);
-
-                (timestamp, record)
-            })
-            .collect::<Vec<_>>();
-
         Ok(records)
     }
 
-    /// Delete many [`Record`] structs at once.
-    pub async fn delete_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
-        let mut errors = vec![];
+    /// Returns the records of a world without a position, which live in its global table.
+    ///
+    /// Only records modified after `after` are returned, if set. Worlds whose global table
+    /// was never created are reported as [`RegionRecords::NeverWritten`].
+    pub async fn get_global_records(
+        &mut self,
+        world_name: &str,
+        after: Option<NaiveDateTime>,
+    ) -> Result<RegionRecords, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("get_global_records");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        let query = query_select_global_records(&world_name);
+        let rows = match self.query_read(&query, &[&after]).await {
+            Ok(rows) => rows,
+            Err(error) if is_undefined_table(&error) => return Ok(RegionRecords::NeverWritten),
+            Err(error) => return Err(error.into()),
+        };
 
-        for record in records {
-            // TODO: Handle records without position
-            let position = record.position.unwrap();
-            let world_name = match sanitize_world_name(&record.world_name) {
-                Ok(world_name) => world_name,
-                Err(error) => {
-                    errors.push(error.into());
-                    continue;
-                }
-            };
+        Ok(RegionRecords::from_rows(
+            rows.into_iter()
+                .map(|row| {
+                    let timestamp: NaiveDateTime = row.get("last_modified");
+                    (
+                        timestamp,
+                        Record::from_postgres_global_row(row, &world_name),
+                    )
+                })
+                .collect(),
+        ))
+    }
 
-            let (table_suffix, region_id) = match self.lookup_ids(&world_name, &position).await {
-                Ok(result) => result,
-                Err(error) => {
-                    errors.push(error.into());
-                    continue;
-                }
-            };
+    /// Returns a [`Vec`] containing records within the region represented by
+    /// `point_inside_region` that were inserted or updated after `since`.
+    ///
+    /// Lets peers re-sync a region after a brief disconnect without refetching every record.
+    #[cfg(test)]
+    pub async fn get_records_in_region_since(
+        &mut self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        since: SystemTime,
+    ) -> Result<Vec<(NaiveDateTime, Record)>> {
+        // Timestamps are stored as UTC without a time zone
+        let after = DateTime::<Utc>::from(since).naive_utc();
 
-            let query = query_delete_record(&world_name, table_suffix);
-            let result = self
-                .client
-                .execute(&query, &[&region_id, &record.uuid])
-                .await;
+        self.get_records_in_region(world_name, point_inside_region, Some(after))
+            .await
+    }
 
-            if let Err(error) = result {
-                errors.push(error.into())
-            }
+    /// Returns the records matching `uuids` without knowing their position, with the time
+    /// each was last modified.
+    ///
+    /// Records are partitioned by position, so every table allocated for the world is
+    /// queried, plus the world's global table for records without a position. Each query
+    /// goes through the table's unique `uuid` index, which [`Self::ensure_schema`] adds to
+    /// tables created before it existed, so the cost grows with the number of tables in
+    /// the world rather than the number of records. Prefer [`Self::get_records_in_region`]
+    /// when positions are known.
+    ///
+    /// Records are returned in no particular order and missing UUIDs are skipped. Each
+    /// table holds a single version of a record, but upserts that move a record to
+    /// another table can leave an older version behind, so a record may be returned more
+    /// than once.
+    #[cfg(test)]
+    pub async fn get_records_by_uuids(
+        &mut self,
+        world_name: &str,
+        uuids: &[Uuid],
+    ) -> Result<Vec<(NaiveDateTime, Record)>, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("get_records_by_uuids");
+        // Early return for no records
+        if uuids.is_empty() {
+            return Ok(vec![]);
         }
 
-        errors
-    }
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        let table_suffixes = self.lookup_world_table_suffixes(&world_name).await?;
 
-    /// Delete duplicate records based on [`Uuid`] and last modified [`NaiveDateTime`]
-    pub async fn dedupe_records(&mut self, ops: Vec<DedupeData>) -> Result<(), DatabaseError> {
-        // TODO: Run concurrently
-        for (uuid, timestamp, world_name, position) in ops {
-            let (table_suffix, _) = self.lookup_ids(&world_name, &position).await?;
-            let query = query_delete_duplictes(&world_name, table_suffix);
+        let mut records = Vec::with_capacity(uuids.len());
+        for table_suffix in table_suffixes {
+            let query = query_select_records_by_uuid(&world_name, table_suffix);
+            let rows = match self.query_read(&query, &[&uuids]).await {
+                Ok(rows) => rows,
+                // Table was never created, nothing to read
+                Err(error) if is_undefined_table(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
 
-            self.client.execute(&query, &[&uuid, &timestamp]).await?;
+            records.extend(rows.into_iter().map(|row| {
+                let timestamp: NaiveDateTime = row.get("last_modified");
+                (timestamp, Record::from_postgres_row(row, &world_name))
+            }));
         }
 
-        Ok(())
-    }
-    // endregion
-}
+        // Records without a position live in the global table
+        let query = query_select_global_records_by_uuid(&world_name);
+        let rows = match self.query_read(&query, &[&uuids]).await {
+            Ok(rows) => rows,
+            Err(error) if is_undefined_table(&error) => vec![],
+            Err(error) => return Err(error.into()),
+        };
 
-#[derive(Debug, Error)]
+        records.extend(rows.into_iter().map(|row| {
+            let timestamp: NaiveDateTime = row.get("last_modified");
+            (
+                timestamp,
+                Record::from_postgres_global_row(row, &world_name),
+            )
+        }));
+
+        Ok(records)
+    }
+
+    /// Returns a [`Vec`] containing records found within the region represented by
+    /// `point_inside_region` whose `flex` data matches `filter`.
+    #[cfg(test)]
+    pub async fn get_records_in_region_filtered(
+        &mut self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        filter: &FlexFilter,
+    ) -> Result<Vec<(NaiveDateTime, Record)>> {
+        let _timer = DB_DURATION.start_timer("get_records_in_region_filtered");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let (table_suffix, region_id) = self.lookup_ids(&world_name, &point_inside_region).await?;
+
+        let query = query_select_records_filtered(&world_name, table_suffix);
+        let include_null = filter.includes_null();
+        let prefix = filter.prefix_bytes();
+
+        let params: [&(dyn ToSql + Sync); 3] = [&region_id, &include_null, &prefix];
+        let rows = match self
+            .query_read_table(&world_name, table_suffix, &query, &params)
+            .await
+        {
+            Ok(rows) => rows,
+
+            // Regions without a table have no records
+            Err(error) if is_undefined_table(&error) => return Ok(vec![]),
+            Err(error) => return Err(error.into()),
+        };
+
+        let records = rows
+            .into_iter()
+            .map(|row| {
+                let timestamp: NaiveDateTime = row.get("last_modified");
+                let record = Record::from_postgres_row(row, &world_name);
+
+                (timestamp, record)
+            })
+            .collect::<Vec<_>>();
+
+        Ok(records)
+    }
+
+    /// Returns all records found within the regions represented by each of `points`,
+    /// deduplicated by [`Uuid`].
+    ///
+    /// Each distinct region is queried once, with up to [`MAX_CONCURRENT_REGION_QUERIES`]
+    /// queries in flight at a time.
+    #[cfg(test)]
+    pub async fn get_records_in_regions(
+        &mut self,
+        world_name: &str,
+        points: Vec<Vector3>,
+    ) -> Result<Vec<Record>> {
+        let _timer = DB_DURATION.start_timer("get_records_in_regions");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        // Resolve each region once, many points usually share a region
+        let regions = points
+            .iter()
+            .map(|point| self.world_region(&world_name, point))
+            .collect::<AHashSet<_>>();
+
+        let mut ids = AHashSet::with_capacity(regions.len());
+        for region in &regions {
+            ids.insert(self.lookup_region_ids(region).await?);
+        }
+
+        let queries = ids
+            .into_iter()
+            .map(|(table_suffix, region_id)| {
+                (query_select_records(&world_name, table_suffix), region_id)
+            })
+            .collect::<Vec<_>>();
+
+        // Each query runs on its own pooled connection
+        let pool = self.read_pool();
+        let semaphore = Semaphore::new(MAX_CONCURRENT_REGION_QUERIES);
+        let semaphore = &semaphore;
+
+        let futures = queries.iter().map(|(query, region_id)| async move {
+            let _permit = semaphore.acquire().await;
+            let (client, statement) = prepare_pooled(pool, query).await?;
+            client.query(&statement, &[region_id]).await
+        });
+
+        let mut seen = AHashSet::new();
+        let mut records = vec![];
+        for result in futures_util::future::join_all(futures).await {
+            let rows = match result {
+                Ok(rows) => rows,
+
+                // Regions without a table have no records
+                Err(error) if is_undefined_table(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            for row in rows {
+                let record = Record::from_postgres_row(row, &world_name);
+                if seen.insert(record.uuid) {
+                    records.push(record);
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Returns a single page of records found within the region represented by
+    /// `point_inside_region`, ordered by [`Uuid`].
+    ///
+    /// The returned [`bool`] is `true` if more records remain after this page.
+    #[cfg(test)]
+    pub async fn get_records_in_region_paged(
+        &mut self,
+        world_name: &str,
+        point_inside_region: Vector3,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Vec<(NaiveDateTime, Record)>, bool)> {
+        let _timer = DB_DURATION.start_timer("get_records_in_region_paged");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let (table_suffix, region_id) = self.lookup_ids(&world_name, &point_inside_region).await?;
+
+        // Fetch one extra row to determine if more rows remain
+        let query = query_select_records_paged(&world_name, table_suffix);
+        let fetch_limit = i64::from(limit) + 1;
+        let offset = i64::from(offset);
+
+        let result = self
+            .query_read_table(
+                &world_name,
+                table_suffix,
+                &query,
+                &[&region_id, &fetch_limit, &offset],
+            )
+            .await;
+
+        // Check for undefined table error and early return no records
+        if let Err(error) = result {
+            match error.as_db_error() {
+                None => return Err(error.into()),
+                Some(db_error) => {
+                    // Early return
+                    if *db_error.code() == SqlState::UNDEFINED_TABLE {
+                        return Ok((vec![], false));
+                    }
+
+                    // Different error, re-throw
+                    return Err(error.into());
+                }
+            }
+        }
+
+        let mut rows = result.unwrap();
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+
+        let records = rows
+            .into_iter()
+            .map(|row| {
+                let timestamp: NaiveDateTime = row.get("last_modified");
+                let record = Record::from_postgres_row(row, &world_name);
+
+                (timestamp, record)
+            })
+            .collect::<Vec<_>>();
+
+        Ok((records, has_more))
+    }
+
+    /// Returns a [`Stream`] of records found within the region represented by
+    /// `point_inside_region`.
+    ///
+    /// Rows are converted as they arrive rather than collected first, and the stream only
+    /// reads more rows from the connection as it is polled, so slow consumers apply
+    /// backpressure. The stream is empty if the region's table doesn't exist.
+    #[cfg(test)]
+    pub async fn get_records_in_region_stream(
+        &mut self,
+        world_name: &str,
+        point_inside_region: Vector3,
+    ) -> Result<impl Stream<Item = Result<Record>>> {
+        let _timer = DB_DURATION.start_timer("get_records_in_region_stream");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let (table_suffix, region_id) = self.lookup_ids(&world_name, &point_inside_region).await?;
+
+        let query = query_select_records(&world_name, table_suffix);
+        let result = match self.prepare_read(&query).await {
+            Ok((client, statement)) => client
+                .query_raw(&statement, [&region_id])
+                .await
+                .map(|rows| (client, rows)),
+            Err(error) => Err(error),
+        };
+
+        // Check for undefined table error and early return no records
+        let (client, rows) = match result {
+            Ok(result) => result,
+            Err(error) if is_undefined_table(&error) => {
+                return Ok(stream::empty().left_stream());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        // Keep the connection out of the pool until the stream is dropped
+        let records = rows.map(move |row| {
+            let _client = &client;
+            match row {
+                Ok(row) => Ok(Record::from_postgres_row(row, &world_name)),
+                Err(error) => Err(error.into()),
+            }
+        });
+
+        Ok(records.right_stream())
+    }
+
+    /// Returns a [`Vec`] containing all records within `radius` of `center`.
+    ///
+    /// Every region overlapping the bounding box of the sphere is queried, and the results
+    /// are merged keeping only the latest version of each [`Uuid`].
+    #[cfg(test)]
+    pub async fn get_records_in_radius(
+        &mut self,
+        world_name: &str,
+        center: Vector3,
+        radius: f64,
+    ) -> Result<Vec<(NaiveDateTime, Record)>> {
+        let _timer = DB_DURATION.start_timer("get_records_in_radius");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        // Early return for invalid radius
+        if radius.is_nan() || radius < 0.0 {
+            return Ok(vec![]);
+        }
+
+        let extent = Vector3::new(radius, radius, radius);
+        let regions =
+            self.world_regions_in_bounds(&world_name, &(center - extent), &(center + extent));
+
+        let radius_squared = radius * radius;
+        let mut merged: AHashMap<Uuid, (NaiveDateTime, Record)> = AHashMap::new();
+
+        for world_region in regions {
+            let (table_suffix, region_id) = self.lookup_region_ids(&world_region).await?;
+
+            let query = query_select_records_in_radius(&world_name, table_suffix);
+            let result = self
+                .query_read(
+                    &query,
+                    &[
+                        &region_id,
+                        center.x(),
+                        center.y(),
+                        center.z(),
+                        &radius_squared,
+                    ],
+                )
+                .await;
+
+            let rows = match result {
+                Ok(rows) => rows,
+                Err(error) => match error.as_db_error() {
+                    // Table doesn't exist yet, skip region
+                    Some(db_error) if *db_error.code() == SqlState::UNDEFINED_TABLE => continue,
+
+                    // Different error, re-throw
+                    _ => return Err(error.into()),
+                },
+            };
+
+            for row in rows {
+                let timestamp: NaiveDateTime = row.get("last_modified");
+                let record = Record::from_postgres_row(row, &world_name);
+
+                // Only keep the latest version of each record
+                match merged.get(&record.uuid) {
+                    Some((existing_ts, _)) if *existing_ts > timestamp => (),
+                    _ => {
+                        merged.insert(record.uuid, (timestamp, record));
+                    }
+                }
+            }
+        }
+
+        Ok(merged.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// Returns a [`Vec`] containing all records inside the axis-aligned box with opposite
+    /// corners `min` and `max`, including its faces.
+    ///
+    /// Only tables that already exist are queried, with a single lookup, so nothing is
+    /// allocated for empty parts of the box. Each table is queried once, however many
+    /// regions of it overlap the box, and the results are merged keeping only the latest
+    /// version of each [`Uuid`]. Boxes overlapping more than [`MAX_BOX_QUERY_TABLES`] tables
+    /// return [`DatabaseError::TooManyTables`] without querying any of them.
+    pub async fn get_records_in_box(
+        &mut self,
+        world_name: &str,
+        min: Vector3,
+        max: Vector3,
+    ) -> Result<Vec<(NaiveDateTime, Record)>, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("get_records_in_box");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        // Accept corners in either order, boxes with a NaN coordinate contain nothing
+        let axes = [(min.x(), max.x()), (min.y(), max.y()), (min.z(), max.z())];
+        if axes.iter().any(|(a, b)| a.is_nan() || b.is_nan()) {
+            return Ok(vec![]);
+        }
+
+        let [(min_x, max_x), (min_y, max_y), (min_z, max_z)] =
+            axes.map(|(a, b)| (a.min(*b), a.max(*b)));
+        let bounds: [&(dyn ToSql + Sync); 6] = [&min_x, &max_x, &min_y, &max_y, &min_z, &max_z];
+
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&world_name];
+        params.extend_from_slice(&bounds);
+
+        let rows = self
+            .client()
+            .await?
+            .query(QUERY_LOOKUP_TABLE_SUFFIXES_IN_BOX, &params)
+            .await?;
+
+        if rows.len() > MAX_BOX_QUERY_TABLES {
+            return Err(DatabaseError::TooManyTables {
+                tables: rows.len(),
+                limit: MAX_BOX_QUERY_TABLES,
+            });
+        }
+
+        let mut merged: AHashMap<Uuid, (NaiveDateTime, Record)> = AHashMap::new();
+        for row in rows {
+            let table_suffix: i32 = row.try_get("table_suffix")?;
+            let query = query_select_records_in_box(&world_name, table_suffix);
+            let rows = match self.query_read(&query, &bounds).await {
+                Ok(rows) => rows,
+
+                // Table doesn't exist yet, skip it
+                Err(error) if is_undefined_table(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            for row in rows {
+                let timestamp: NaiveDateTime = row.get("last_modified");
+                let record = Record::from_postgres_row(row, &world_name);
+
+                // Only keep the latest version of each record
+                match merged.get(&record.uuid) {
+                    Some((existing_ts, _)) if *existing_ts > timestamp => (),
+                    _ => {
+                        merged.insert(record.uuid, (timestamp, record));
+                    }
+                }
+            }
+        }
+
+        Ok(merged.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// Returns up to `n` records closest to `center`, sorted by ascending distance.
+    ///
+    /// Regions are searched nearest first, stopping once no remaining region could hold a
+    /// record closer than the furthest of the `n` found so far, or every region in the
+    /// world has been searched.
+    #[cfg(test)]
+    pub async fn get_nearest_records(
+        &mut self,
+        world_name: &str,
+        center: Vector3,
+        n: usize,
+    ) -> Result<Vec<Record>> {
+        let _timer = DB_DURATION.start_timer("get_nearest_records");
+        if n == 0 {
+            return Ok(vec![]);
+        }
+
+        let sanitized = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let sizes = self.region_size(&sanitized);
+
+        let rows = self
+            .client()
+            .await?
+            .query(QUERY_LOOKUP_WORLD_REGIONS, &[&sanitized, &i64::MAX])
+            .await?;
+
+        let mut regions = Vec::with_capacity(rows.len());
+        for row in rows {
+            // Regions without a table have no records
+            let table_suffix: Option<i32> = row.try_get("table_suffix")?;
+            let table_suffix = match table_suffix {
+                Some(table_suffix) => table_suffix,
+                None => continue,
+            };
+
+            let region = WorldRegion::from_min(
+                &sanitized,
+                row.try_get("min_x")?,
+                row.try_get("min_y")?,
+                row.try_get("min_z")?,
+            );
+
+            let distance = region.distance_squared(&center, sizes);
+            let region_id: i32 = row.try_get("region_id")?;
+            regions.push((distance, table_suffix, region_id));
+        }
+
+        regions.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+        let limit = i64::try_from(n).unwrap_or(i64::MAX);
+        let mut nearest: Vec<(f64, Record)> = Vec::with_capacity(n);
+        for (region_distance, table_suffix, region_id) in regions {
+            // Every remaining region is at least this far away
+            if nearest.len() == n && nearest[n - 1].0 <= region_distance {
+                break;
+            }
+
+            let query = query_select_nearest_records(&sanitized, table_suffix);
+            let params: [&(dyn ToSql + Sync); 5] =
+                [&region_id, center.x(), center.y(), center.z(), &limit];
+
+            let rows = match self.query_read(&query, &params).await {
+                Ok(rows) => rows,
+                Err(error) if is_undefined_table(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            for row in rows {
+                let record = Record::from_postgres_row(row, world_name);
+                let distance = match &record.position {
+                    Some(position) => position.distance_squared(&center),
+                    None => continue,
+                };
+
+                nearest.push((distance, record));
+            }
+
+            nearest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            nearest.truncate(n);
+        }
+
+        Ok(nearest.into_iter().map(|(_, record)| record).collect())
+    }
+
+    /// Delete many [`Record`] structs at once.
+    #[cfg(test)]
+    #[inline]
+    pub async fn delete_records(&mut self, records: Vec<Record>) -> Vec<DatabaseError> {
+        self.remove_records(records, None).await
+    }
+
+    /// Delete many [`Record`] structs on behalf of the peer `owner`.
+    ///
+    /// If ownership is enforced, records owned by another peer are left in place and
+    /// reported as [`DatabaseError::PermissionDenied`], see [`Self::set_record_ownership`].
+    #[inline]
+    pub async fn delete_records_as(
+        &mut self,
+        owner: Uuid,
+        records: Vec<Record>,
+    ) -> Vec<DatabaseError> {
+        self.remove_records(records, Some(owner)).await
+    }
+
+    async fn remove_records(
+        &mut self,
+        records: Vec<Record>,
+        owner: Option<Uuid>,
+    ) -> Vec<DatabaseError> {
+        let _timer = DB_DURATION.start_timer("delete_records");
+        let guard = owner.filter(|owner| self.enforces_ownership(owner));
+        let mut errors = vec![];
+
+        for record in records {
+            let world_name = match self.sanitize_world_name(&record.world_name) {
+                Ok(world_name) => world_name,
+                Err(error) => {
+                    let error = DatabaseError::invalid_world_name(
+                        &record.world_name,
+                        Some(record.uuid),
+                        error,
+                    );
+
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            // Records without a position live in the global table
+            let position = match record.position {
+                Some(position) => position,
+                None => {
+                    let uuids = vec![record.uuid];
+                    let result = match guard {
+                        None => {
+                            let query = query_delete_global_records_by_uuid(&world_name);
+                            self.execute_cached(&query, &[&uuids]).await
+                        }
+
+                        Some(owner) => {
+                            let query = query_delete_owned_global_records(&world_name);
+                            self.execute_cached(&query, &[&uuids, &owner]).await
+                        }
+                    };
+
+                    match (result, guard) {
+                        // Nothing deleted, either missing or owned by another peer
+                        (Ok(0), Some(owner)) => {
+                            let query = query_select_global_denied(&world_name);
+                            errors.extend(self.denied_records(&query, uuids, owner).await);
+                        }
+
+                        (Err(error), _) if !is_undefined_table(&error) => errors.push(error.into()),
+
+                        _ => (),
+                    }
+
+                    continue;
+                }
+            };
+
+            let (table_suffix, region_id) = match self.lookup_ids(&world_name, &position).await {
+                Ok(result) => result,
+                Err(error) => {
+                    errors.push(error.into());
+                    continue;
+                }
+            };
+
+            self.invalidate_cached_table(&world_name, table_suffix);
+
+            let result = match guard {
+                None => {
+                    let query = query_delete_record(&world_name, table_suffix);
+                    self.execute_cached(&query, &[&region_id, &record.uuid])
+                        .await
+                }
+
+                Some(owner) => {
+                    let query = query_delete_owned_record(&world_name, table_suffix);
+                    self.execute_cached(&query, &[&region_id, &record.uuid, &owner])
+                        .await
+                }
+            };
+
+            match (result, guard) {
+                // Nothing deleted, either missing or owned by another peer
+                (Ok(0), Some(owner)) => {
+                    let query = query_select_denied(&world_name, table_suffix);
+                    let denied = self.denied_records(&query, vec![record.uuid], owner).await;
+                    errors.extend(denied);
+                }
+
+                (Err(error), _) => errors.push(error.into()),
+                _ => (),
+            }
+        }
+
+        errors
+    }
+
+    /// Delete many records by [`Uuid`] without knowing their position.
+    ///
+    /// Every table allocated for the world is searched, so prefer [`Self::delete_records`]
+    /// when positions are known. Tables that have not been created yet are skipped.
+    #[cfg(test)]
+    pub async fn delete_records_by_uuid(
+        &mut self,
+        world_name: &str,
+        uuids: Vec<Uuid>,
+    ) -> Vec<DatabaseError> {
+        let _timer = DB_DURATION.start_timer("delete_records_by_uuid");
+        // Both the positioned and global tables are named after the sanitized world
+        let world_name = match self.sanitize_world_name(world_name) {
+            Ok(world_name) => world_name,
+            Err(error) => return vec![DatabaseError::invalid_world_name(world_name, None, error)],
+        };
+
+        // Early return for no records
+        if uuids.is_empty() {
+            return vec![];
+        }
+
+        self.invalidate_cached_world(&world_name);
+
+        // The global table is still searched if the positioned tables can't be listed
+        let mut errors = vec![];
+        let table_suffixes = match self.lookup_world_table_suffixes(&world_name).await {
+            Ok(suffixes) => suffixes,
+            Err(error) => {
+                errors.push(error.into());
+                vec![]
+            }
+        };
+
+        for table_suffix in table_suffixes {
+            let query = query_delete_records_by_uuid(&world_name, table_suffix);
+            let result = self.execute_cached(&query, &[&uuids]).await;
+
+            // Deletion completed without errors
+            let error = match result {
+                Ok(_) => continue,
+                Err(error) => error,
+            };
+
+            // Table was never created, nothing to delete
+            if let Some(db_error) = error.as_db_error() {
+                if *db_error.code() == SqlState::UNDEFINED_TABLE {
+                    continue;
+                }
+            }
+
+            errors.push(error.into());
+        }
+
+        // Records without a position live in the global table
+        let query = query_delete_global_records_by_uuid(&world_name);
+        let result = self.execute_cached(&query, &[&uuids]).await;
+
+        match result {
+            Err(error) if !is_undefined_table(&error) => errors.push(error.into()),
+            _ => (),
+        }
+
+        errors
+    }
+
+    /// Move an existing record to `position`, leaving its `data` and `flex` untouched.
+    ///
+    /// Records that move into another table are deleted from their old table and inserted
+    /// into the new one in a single transaction. Every table allocated for the world may be
+    /// searched, and [`DatabaseError::RecordNotFound`] is returned if no table holds the record.
+    #[inline]
+    pub async fn update_record_position(
+        &mut self,
+        world_name: &str,
+        uuid: Uuid,
+        position: Vector3,
+    ) -> Result<(), DatabaseError> {
+        self.move_record(world_name, uuid, position, None).await
+    }
+
+    /// Move an existing record to `position` on behalf of the peer `owner`.
+    ///
+    /// If ownership is enforced, records owned by another peer are left in place and
+    /// [`DatabaseError::PermissionDenied`] is returned, see [`Self::set_record_ownership`].
+    #[inline]
+    pub async fn update_record_position_as(
+        &mut self,
+        owner: Uuid,
+        world_name: &str,
+        uuid: Uuid,
+        position: Vector3,
+    ) -> Result<(), DatabaseError> {
+        self.move_record(world_name, uuid, position, Some(owner))
+            .await
+    }
+
+    async fn move_record(
+        &mut self,
+        world_name: &str,
+        uuid: Uuid,
+        position: Vector3,
+        owner: Option<Uuid>,
+    ) -> Result<(), DatabaseError> {
+        let _timer = DB_DURATION.start_timer("update_record_position");
+        let guard = owner.filter(|owner| self.enforces_ownership(owner));
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, Some(uuid), error))?;
+
+        let (table_suffix, region_id) = self.lookup_ids(&world_name, &position).await?;
+        let table_suffixes = self.lookup_world_table_suffixes(&world_name).await?;
+
+        // The record may be moved out of any table
+        self.invalidate_cached_world(&world_name);
+
+        // Dropping the transaction without committing rolls it back
+        let mut client = self.client().await?;
+        let mut transaction = client.transaction().await?;
+
+        // Most updates stay within the same table
+        let query = query_update_record_position(&world_name, table_suffix, guard.is_some());
+        let mut params: Vec<&(dyn ToSql + Sync)> =
+            vec![&region_id, position.x(), position.y(), position.z(), &uuid];
+
+        if let Some(owner) = &guard {
+            params.push(owner);
+        }
+
+        let savepoint = transaction.savepoint("update_position").await?;
+        match savepoint.execute(&query, &params).await {
+            Ok(0) => {
+                savepoint.rollback().await?;
+
+                // Nothing updated, either held by another table or owned by another peer
+                if let Some(peer) = guard {
+                    let query = query_select_denied(&world_name, table_suffix);
+                    let denied = transaction.query(&query, &[&vec![uuid], &peer]).await?;
+                    if !denied.is_empty() {
+                        return Err(DatabaseError::PermissionDenied { record: uuid, peer });
+                    }
+                }
+            }
+
+            Ok(_) => {
+                savepoint.commit().await?;
+                transaction.commit().await?;
+
+                return Ok(());
+            }
+
+            Err(error) if is_undefined_table(&error) => savepoint.rollback().await?,
+            Err(error) => return Err(error.into()),
+        }
+
+        // Otherwise take the record out of whichever table holds it
+        let mut taken = None;
+        for suffix in table_suffixes.into_iter().filter(|s| *s != table_suffix) {
+            let query = query_take_record(&world_name, suffix);
+
+            let savepoint = transaction.savepoint("take_record").await?;
+            match savepoint.query_opt(&query, &[&uuid]).await {
+                Ok(Some(row)) => {
+                    savepoint.commit().await?;
+                    taken = Some(row);
+                    break;
+                }
+
+                Ok(None) => savepoint.rollback().await?,
+                Err(error) if is_undefined_table(&error) => savepoint.rollback().await?,
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        let row = taken.ok_or(DatabaseError::RecordNotFound { record: uuid })?;
+        let created_at: NaiveDateTime = row.get("created_at");
+        let data: Option<String> = row.get("data");
+        let flex: Option<Vec<u8>> = row.get("flex");
+        let record_owner: Option<Uuid> = row.get("owner_uuid");
+
+        // Dropping the transaction puts the record back
+        if let (Some(peer), Some(record_owner)) = (guard, record_owner) {
+            if peer != record_owner {
+                return Err(DatabaseError::PermissionDenied { record: uuid, peer });
+            }
+        }
+
+        let query = query_insert_moved_record(&world_name, table_suffix);
+        let params: [&(dyn ToSql + Sync); 9] = [
+            &created_at,
+            &region_id,
+            position.x(),
+            position.y(),
+            position.z(),
+            &uuid,
+            &data,
+            &flex,
+            &record_owner,
+        ];
+
+        // Nothing is written if the new table already holds the record
+        let duplicate = DatabaseError::DuplicateRecord { record: uuid };
+
+        // Create the new table inside the same transaction if needed
+        let savepoint = transaction.savepoint("insert_moved").await?;
+        let _creating = match savepoint.execute(&query, &params).await {
+            Ok(0) => return Err(duplicate),
+            Ok(_) => {
+                savepoint.commit().await?;
+                None
+            }
+
+            // Tables created by older versions may be missing newer columns or indexes
+            Err(error) if is_outdated_layout(&error) => {
+                savepoint.rollback().await?;
+                let table = table_name(&world_name, table_suffix);
+                let upgrade = query_upgrade_world(&world_name, table_suffix);
+                if !upgrade_table(&transaction, &table, &upgrade).await? {
+                    return Err(error.into());
+                }
+
+                if transaction.execute(&query, &params).await? == 0 {
+                    return Err(duplicate);
+                }
+
+                None
+            }
+
+            Err(error) if is_undefined_table(&error) => {
+                savepoint.rollback().await?;
+                let creating = self
+                    .table_locks
+                    .lock_create(&world_name, table_suffix)
+                    .await;
+
+                transaction
+                    .execute(&query_create_world_schema(&world_name), &[])
+                    .await?;
+
+                transaction
+                    .execute(&query_create_world(&world_name, table_suffix), &[])
+                    .await?;
+
+                transaction
+                    .batch_execute(&query_create_world_index(
+                        &world_name,
+                        table_suffix,
+                        self.index_spec,
+                    ))
+                    .await?;
+
+                transaction.execute(&query, &params).await?;
+                Some(creating)
+            }
+
+            Err(error) => return Err(error.into()),
+        };
+
+        transaction.commit().await?;
+        Ok(())
+    }
+    // endregion
+}
+
+// region: Insert Helpers
+/// Destructure and map records into owned query parameter rows.
+fn into_record_rows(
+    mut records: Vec<(i32, Record)>,
+    upsert: bool,
+    owner: Option<Uuid>,
+) -> Vec<RecordRow> {
+    if upsert {
+        retain_latest(&mut records, |(_, record)| record.uuid);
+    }
+
+    records
+        .into_iter()
+        .map(|(region_id, record)| {
+            (
+                region_id,
+                record.position.unwrap(),
+                record.uuid,
+                record.data,
+                record.flex.map(|b| b.to_vec()),
+                owner,
+            )
+        })
+        .collect()
+}
+
+/// Destructure and map position-less records into owned query parameter rows.
+fn into_global_rows(mut records: Vec<Record>, upsert: bool, owner: Option<Uuid>) -> Vec<GlobalRow> {
+    if upsert {
+        retain_latest(&mut records, |record| record.uuid);
+    }
+
+    records
+        .into_iter()
+        .map(|record| {
+            (
+                record.uuid,
+                record.data,
+                record.flex.map(|b| b.to_vec()),
+                owner,
+            )
+        })
+        .collect()
+}
+
+/// Returns a [`DatabaseError::DuplicateRecord`] for each record out of `uuids` that a
+/// plain insert skipped, given the `rows` it returned.
+fn duplicate_records(uuids: &[Uuid], rows: &[Row]) -> Vec<DatabaseError> {
+    let mut written = rows
+        .iter()
+        .map(|row| row.get::<_, Uuid>("uuid"))
+        .collect::<AHashSet<_>>();
+
+    // The same record can be sent twice in one insert, only the first is written
+    uuids
+        .iter()
+        .filter(|uuid| !written.remove(*uuid))
+        .map(|uuid| DatabaseError::DuplicateRecord { record: *uuid })
+        .collect()
+}
+
+/// Fails with the first record a plain insert skipped, see [`duplicate_records`].
+#[cfg(test)]
+fn reject_duplicates(uuids: &[Uuid], rows: &[Row]) -> Result<(), DatabaseError> {
+    match duplicate_records(uuids, rows).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// A single upsert cannot affect the same row twice, keep only the latest of each [`Uuid`].
+fn retain_latest<T>(items: &mut Vec<T>, uuid: impl Fn(&T) -> Uuid) {
+    let mut seen = AHashSet::with_capacity(items.len());
+    items.reverse();
+    items.retain(|item| seen.insert(uuid(item)));
+    items.reverse();
+}
+
+/// Construct a flat params array for a bulk insertion query.
+fn record_row_params(rows: &[RecordRow]) -> Vec<&(dyn ToSql + Sync)> {
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(rows.len() * 8);
+
+    for (region_id, position, uuid, data, flex, owner) in rows {
+        params.push(region_id);
+        params.push(position.x());
+        params.push(position.y());
+        params.push(position.z());
+        params.push(uuid);
+        params.push(data);
+        params.push(flex);
+        params.push(owner);
+    }
+
+    params
+}
+
+/// Construct a flat params array for a bulk global insertion query.
+fn global_row_params(rows: &[GlobalRow]) -> Vec<&(dyn ToSql + Sync)> {
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(rows.len() * 4);
+
+    for (uuid, data, flex, owner) in rows {
+        params.push(uuid);
+        params.push(data);
+        params.push(flex);
+        params.push(owner);
+    }
+
+    params
+}
+
+/// Returns a connection from `pool`, waiting for one to be returned if they are all in use.
+///
+/// # Panics
+/// Pools are built without timeouts or hooks and never closed, so only connecting can fail.
+async fn pooled_client(pool: &Pool) -> Result<Object, tokio_postgres::Error> {
+    match pool.get().await {
+        Ok(client) => Ok(client),
+        Err(PoolError::Backend(error)) => Err(error),
+        Err(error) => unreachable!("unexpected connection pool error: {}", error),
+    }
+}
+
+/// Returns a connection from `pool` and a prepared [`Statement`] for the query on it,
+/// preparing it only if that connection hasn't already.
+async fn prepare_pooled(
+    pool: &Pool,
+    query: &str,
+) -> Result<(Object, Statement), tokio_postgres::Error> {
+    let client = pooled_client(pool).await?;
+    let statement = client.prepare_cached(query).await?;
+
+    Ok((client, statement))
+}
+
+/// Returns `true` if `error` was caused by the connection rather than the query.
+#[inline]
+fn is_connection_error(error: &tokio_postgres::Error) -> bool {
+    error.is_closed() || error.as_db_error().is_none()
+}
+
+#[inline]
+pub(super) fn is_undefined_table(error: &tokio_postgres::Error) -> bool {
+    match error.as_db_error() {
+        None => false,
+        Some(db_error) => *db_error.code() == SqlState::UNDEFINED_TABLE,
+    }
+}
+
+/// Returns whether the error could come from a table created by an older version.
+///
+/// Older tables may be missing newer columns, or the unique `uuid` index that
+/// `ON CONFLICT (uuid)` needs.
+#[inline]
+fn is_outdated_layout(error: &tokio_postgres::Error) -> bool {
+    match error.as_db_error() {
+        None => false,
+        Some(db_error) => matches!(
+            *db_error.code(),
+            SqlState::UNDEFINED_COLUMN | SqlState::INVALID_COLUMN_REFERENCE
+        ),
+    }
+}
+
+/// Parse the layout version from a record table's comment, see [`RECORD_TABLE_VERSION`].
+fn parse_table_version(comment: Option<&str>) -> i32 {
+    comment
+        .and_then(|comment| comment.strip_prefix(TABLE_VERSION_PREFIX))
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Upgrade `table` with `upgrade` if it was created by an older version, returning
+/// whether it was upgraded.
+///
+/// Tables are only upgraded once a write to them fails, so servers of both versions
+/// can share a database during a rolling upgrade.
+async fn upgrade_table(
+    transaction: &Transaction<'_>,
+    table: &str,
+    upgrade: &str,
+) -> Result<bool, tokio_postgres::Error> {
+    let row = transaction
+        .query_one(QUERY_TABLE_COMMENT, &[&table])
+        .await?;
+    let version = parse_table_version(row.get(0));
+    if version >= RECORD_TABLE_VERSION {
+        return Ok(false);
+    }
+
+    debug!("upgrading table {} from version {}", table, version);
+
+    // Another server may be upgrading the same table
+    ignore_duplicate(transaction.batch_execute(upgrade).await)?;
+    Ok(true)
+}
+
+/// Treat errors from losing a race to create the same schema, table, index or column as
+/// success.
+///
+/// `IF NOT EXISTS` isn't atomic, so concurrent creations can still fail with a duplicate
+/// error, or a unique violation on the system catalogs.
+fn ignore_duplicate<T>(
+    result: Result<T, tokio_postgres::Error>,
+) -> Result<(), tokio_postgres::Error> {
+    let error = match result {
+        Ok(_) => return Ok(()),
+        Err(error) => error,
+    };
+
+    let duplicate = error.as_db_error().map_or(false, |db_error| {
+        matches!(
+            *db_error.code(),
+            SqlState::DUPLICATE_SCHEMA
+                | SqlState::DUPLICATE_TABLE
+                | SqlState::DUPLICATE_OBJECT
+                | SqlState::DUPLICATE_COLUMN
+                | SqlState::UNIQUE_VIOLATION
+        )
+    });
+
+    match duplicate {
+        true => Ok(()),
+        false => Err(error),
+    }
+}
+// endregion
+
+/// Outcome of a bulk insert, see [`DatabaseClient::insert_records_counted`].
+#[derive(Debug, Default)]
+pub struct InsertReport {
+    /// Total number of rows written across every table.
+    pub inserted: u64,
+    pub errors: Vec<DatabaseError>,
+}
+
+#[derive(Debug, Error)]
 pub enum DatabaseError {
-    #[error("world name error: {0}")]
-    InvalidWorldName(#[from] SanitizeError),
+    #[error("invalid world name \"{world_name}\"{}: {source}", for_record(.record))]
+    InvalidWorldName {
+        world_name: String,
+        /// UUID of the record with the invalid world name, if any
+        record: Option<Uuid>,
+        #[source]
+        source: SanitizeError,
+    },
+
+    #[error("record {record} {field} is {size} bytes, above the limit of {limit}")]
+    RecordTooLarge {
+        record: Uuid,
+        field: &'static str,
+        size: usize,
+        limit: usize,
+    },
+
+    #[error("record {record} not found")]
+    RecordNotFound { record: Uuid },
+
+    #[error("record {record} already exists")]
+    DuplicateRecord { record: Uuid },
+
+    #[error("peer {peer} is not allowed to modify record {record}")]
+    PermissionDenied { record: Uuid, peer: Uuid },
+
+    #[cfg(test)]
+    #[error(
+        "region size {size:?} for world \"{world_name}\" must evenly divide table size ({table_size})"
+    )]
+    InvalidRegionSize {
+        world_name: String,
+        size: (u16, u16, u16),
+        table_size: u32,
+    },
+
+    #[error("query spans {tables} tables, above the limit of {limit}")]
+    TooManyTables { tables: usize, limit: usize },
 
     #[error(transparent)]
     PostgresError(#[from] tokio_postgres::Error),
 }
+
+impl DatabaseError {
+    #[inline]
+    pub(super) fn invalid_world_name(
+        world_name: &str,
+        record: Option<Uuid>,
+        source: SanitizeError,
+    ) -> Self {
+        Self::InvalidWorldName {
+            world_name: world_name.to_string(),
+            record,
+            source,
+        }
+    }
+}
+
+fn for_record(record: &Option<Uuid>) -> String {
+    match record {
+        None => String::new(),
+        Some(uuid) => format!(" for record {}", uuid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use deadpool_postgres::Manager;
+    use tokio_postgres::NoTls;
+
+    use super::*;
+    use crate::database::world_stats::WorldStats;
+
+    #[test]
+    fn parse_table_versions() {
+        assert_eq!(parse_table_version(None), 1);
+        assert_eq!(parse_table_version(Some("")), 1);
+        assert_eq!(parse_table_version(Some("player positions")), 1);
+        assert_eq!(parse_table_version(Some("worldql_table_version=x")), 1);
+        assert_eq!(parse_table_version(Some("worldql_table_version=2")), 2);
+        assert_eq!(parse_table_version(Some("worldql_table_version=7")), 7);
+    }
+
+    /// Connect to a disposable database, set `WQL_TEST_POSTGRES_CONNECTION_STRING` and run
+    /// with `cargo test -- --ignored`.
+    async fn test_client() -> DatabaseClient {
+        test_client_with_cache_size(1024).await
+    }
+
+    async fn test_client_with_cache_size(cache_size: usize) -> DatabaseClient {
+        let psql_conn = std::env::var("WQL_TEST_POSTGRES_CONNECTION_STRING")
+            .expect("WQL_TEST_POSTGRES_CONNECTION_STRING must be set");
+
+        let config = tokio_postgres::Config::from_str(&psql_conn).unwrap();
+        let pool = Pool::builder(Manager::new(config, NoTls)).build().unwrap();
+
+        let mut db = DatabaseClient::new(
+            pool,
+            16,
+            16,
+            16,
+            1024,
+            cache_size,
+            RetryPolicy::default(),
+            PartitionStrategy::FixedGrid,
+        );
+
+        db.ensure_schema().await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn upgrade_outdated_table() {
+        let mut db = test_client().await;
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute("DROP SCHEMA IF EXISTS w_upgrade_test CASCADE")
+            .await
+            .unwrap();
+
+        let record = |uuid: Uuid, data: &str| Record {
+            uuid,
+            position: Some(Vector3::new(1.0, 1.0, 1.0)),
+            world_name: "upgrade_test".into(),
+            data: Some(data.into()),
+            flex: None,
+        };
+
+        // Create the table, then roll it back to the first layout
+        let first = Uuid::new_v4();
+        assert!(db.insert_records(vec![record(first, "a")]).await.is_empty());
+
+        let suffix: i32 = db
+            .client()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT substr(table_name, 3)::int FROM information_schema.tables \
+                 WHERE table_schema = 'w_upgrade_test'",
+                &[],
+            )
+            .await
+            .unwrap()
+            .get(0);
+
+        let table = table_name("upgrade_test", suffix);
+        let downgrade = format!(
+            "
+            DROP INDEX w_upgrade_test.upgrade_test_{suffix}_uuid_uindex;
+            ALTER TABLE {table} DROP COLUMN flex, DROP COLUMN owner_uuid;
+            COMMENT ON TABLE {table} IS NULL;
+            INSERT INTO {table} (last_modified, region_id, x, y, z, uuid, data)
+            SELECT last_modified - interval '1 day', region_id, x, y, z, uuid, 'stale'
+            FROM {table};
+            ",
+            table = table,
+            suffix = suffix,
+        );
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute(&downgrade)
+            .await
+            .unwrap();
+
+        // Upserts conflict on the missing index, inserts on the missing columns
+        assert!(db.upsert_records(vec![record(first, "b")]).await.is_empty());
+        let second = Uuid::new_v4();
+        assert!(db
+            .insert_records(vec![record(second, "c")])
+            .await
+            .is_empty());
+
+        let rows = db
+            .client()
+            .await
+            .unwrap()
+            .query(
+                &format!("SELECT uuid, data FROM {} ORDER BY data", table),
+                &[],
+            )
+            .await
+            .unwrap();
+        let rows = rows
+            .iter()
+            .map(|row| (row.get::<_, Uuid>(0), row.get::<_, String>(1)))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(first, "b".into()), (second, "c".into())]);
+
+        let comment = db
+            .client()
+            .await
+            .unwrap()
+            .query_one(QUERY_TABLE_COMMENT, &[&table])
+            .await
+            .unwrap();
+        assert_eq!(parse_table_version(comment.get(0)), RECORD_TABLE_VERSION);
+
+        // Current tables aren't upgraded again, so their errors are surfaced
+        let errors = db.insert_records(vec![record(first, "d")]).await;
+        assert!(matches!(
+            errors.as_slice(),
+            [DatabaseError::DuplicateRecord { record }] if *record == first
+        ));
+
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute("DROP SCHEMA w_upgrade_test CASCADE")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn validate_records() {
+        let mut db = test_client().await;
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute(
+                "
+                DROP SCHEMA IF EXISTS w_validate_test CASCADE;
+                DELETE FROM navigation.tables WHERE world_name = 'validate_test';
+                DELETE FROM navigation.regions WHERE world_name = 'validate_test';
+                ",
+            )
+            .await
+            .unwrap();
+
+        let record = |uuid: Uuid, position: Option<Vector3>| Record {
+            uuid,
+            position,
+            world_name: "validate_test".into(),
+            data: None,
+            flex: None,
+        };
+
+        let (stored, global, new) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let position = Some(Vector3::new(1.0, 1.0, 1.0));
+        let far = Some(Vector3::new(5000.0, 1.0, 1.0));
+        let batch = vec![
+            record(stored, position),
+            record(global, None),
+            record(new, far),
+            record(new, far),
+        ];
+
+        // Only repeats within the batch are rejected, and nothing is allocated
+        let errors = db.validate_records(&batch).await;
+        assert!(matches!(
+            errors.as_slice(),
+            [DatabaseError::DuplicateRecord { record }] if *record == new
+        ));
+
+        let regions: i64 = db
+            .client()
+            .await
+            .unwrap()
+            .query_one(
+                "SELECT count(*) FROM navigation.regions WHERE world_name = 'validate_test'",
+                &[],
+            )
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(regions, 0);
+
+        // Stored records are duplicates of an insert
+        let owner = Uuid::new_v4();
+        let errors = db
+            .upsert_records_as(owner, vec![record(stored, position), record(global, None)])
+            .await;
+        assert!(errors.is_empty());
+
+        let mut duplicates = db
+            .validate_records(&batch[..3])
+            .await
+            .into_iter()
+            .map(|error| match error {
+                DatabaseError::DuplicateRecord { record } => record,
+                error => panic!("unexpected error: {}", error),
+            })
+            .collect::<Vec<_>>();
+        duplicates.sort();
+
+        let mut expected = vec![stored, global];
+        expected.sort();
+        assert_eq!(duplicates, expected);
+
+        // Upserts are only rejected for records owned by another peer
+        assert!(db.validate_records_as(owner, &batch).await.is_empty());
+
+        let other = Uuid::new_v4();
+        assert!(db.validate_records_as(other, &batch).await.is_empty());
+
+        db.set_record_ownership(vec![]);
+        assert!(db.validate_records_as(owner, &batch).await.is_empty());
+        assert_eq!(db.validate_records_as(other, &batch).await.len(), 2);
+
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute(
+                "
+                DROP SCHEMA w_validate_test CASCADE;
+                DELETE FROM navigation.tables WHERE world_name = 'validate_test';
+                DELETE FROM navigation.regions WHERE world_name = 'validate_test';
+                ",
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn region_size_override() {
+        let mut db = test_client().await;
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute(
+                "
+                DROP SCHEMA IF EXISTS w_size_test CASCADE;
+                DELETE FROM navigation.tables WHERE world_name = 'size_test';
+                DELETE FROM navigation.regions WHERE world_name = 'size_test';
+                ",
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            db.set_region_size("size test", 5, 8, 8),
+            Err(DatabaseError::InvalidRegionSize { .. })
+        ));
+
+        // Overrides apply to every spelling of the world
+        db.set_region_size("size test", 8, 8, 8).unwrap();
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(12.0, 1.0, 1.0)),
+            world_name: "size test".into(),
+            ..Default::default()
+        };
+
+        assert!(db.insert_records(vec![record]).await.is_empty());
+
+        let inside = db
+            .get_records_in_region("size test", Vector3::new(9.0, 1.0, 1.0), None)
+            .await
+            .unwrap();
+        let outside = db
+            .get_records_in_region("size_test", Vector3::new(3.0, 1.0, 1.0), None)
+            .await
+            .unwrap();
+
+        assert_eq!(inside.len(), 1);
+        assert!(outside.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn global_records() {
+        let mut db = test_client().await;
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute("DROP SCHEMA IF EXISTS w_global_test CASCADE")
+            .await
+            .unwrap();
+
+        let records = db.get_global_records("global_test", None).await.unwrap();
+        assert!(records.is_never_written());
+
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "global_test".into(),
+            data: Some("settings".into()),
+            ..Default::default()
+        };
+
+        assert!(db.insert_records(vec![record.clone()]).await.is_empty());
+
+        let records = db.get_global_records("global test", None).await.unwrap();
+        let records = records.into_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1.uuid, record.uuid);
+        assert!(records[0].1.position.is_none());
+
+        let after = records[0].0;
+        let records = db
+            .get_global_records("global_test", Some(after))
+            .await
+            .unwrap();
+        assert!(!records.is_never_written());
+        assert!(records.into_records().is_empty());
+    }
+
+    /// Drops every table and region of a test world.
+    async fn clear_world(db: &DatabaseClient, world_name: &str) {
+        let query = format!(
+            "
+            DROP SCHEMA IF EXISTS w_{world} CASCADE;
+            DELETE FROM navigation.tables WHERE world_name = '{world}';
+            DELETE FROM navigation.regions WHERE world_name = '{world}';
+            ",
+            world = world_name,
+        );
+
+        db.client()
+            .await
+            .unwrap()
+            .batch_execute(&query)
+            .await
+            .unwrap();
+    }
+
+    fn test_record(world_name: &str, x: f64) -> Record {
+        Record {
+            uuid: Uuid::new_v4(),
+            position: Some(Vector3::new(x, 1.0, 1.0)),
+            world_name: world_name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn sorted_uuids<'a>(records: impl IntoIterator<Item = &'a Record>) -> Vec<Uuid> {
+        let mut uuids = records
+            .into_iter()
+            .map(|record| record.uuid)
+            .collect::<Vec<_>>();
+        uuids.sort();
+        uuids
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn insert_record() {
+        let mut db = test_client().await;
+        clear_world(&db, "single_test").await;
+
+        let record = test_record("single test", 1.0);
+
+        #[allow(deprecated)]
+        db.insert_record(&record).await.unwrap();
+
+        let records = db
+            .get_records_in_region("single_test", Vector3::new(1.0, 1.0, 1.0), None)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1.uuid, record.uuid);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn insert_records_counted() {
+        let mut db = test_client().await;
+        clear_world(&db, "counted_test").await;
+
+        let first = test_record("counted_test", 1.0);
+        let global = Record {
+            position: None,
+            ..test_record("counted_test", 0.0)
+        };
+
+        let report = db.insert_records_counted(vec![first.clone(), global]).await;
+        assert!(report.errors.is_empty());
+        assert_eq!(report.inserted, 2);
+
+        // Duplicates are reported but not counted
+        let second = test_record("counted_test", 2.0);
+        let report = db.insert_records_counted(vec![first, second]).await;
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.inserted, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn insert_records_atomic() {
+        let mut db = test_client().await;
+        clear_world(&db, "atomic_test").await;
+
+        let stored = test_record("atomic_test", 1.0);
+        db.insert_records_atomic(vec![stored.clone()])
+            .await
+            .unwrap();
+
+        // A duplicate rolls back the whole batch, including newly created tables
+        let far = test_record("atomic_test", 5000.0);
+        let result = db
+            .insert_records_atomic(vec![far.clone(), stored.clone()])
+            .await;
+        assert!(matches!(
+            result,
+            Err(DatabaseError::DuplicateRecord { record }) if record == stored.uuid
+        ));
+
+        let records = db
+            .get_records_by_uuids("atomic_test", &[stored.uuid, far.uuid])
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            vec![stored.uuid]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn world_max_record_size() {
+        let mut db = test_client().await;
+        clear_world(&db, "limited_test").await;
+        clear_world(&db, "unlimited_test").await;
+
+        db.set_world_max_record_size("limited test", Some(4));
+        let record = |world_name: &str| Record {
+            data: Some("too long".into()),
+            ..test_record(world_name, 1.0)
+        };
+
+        let errors = db.insert_records(vec![record("limited_test")]).await;
+        assert!(matches!(
+            errors.as_slice(),
+            [DatabaseError::RecordTooLarge {
+                field: "data",
+                limit: 4,
+                ..
+            }]
+        ));
+
+        assert!(db
+            .insert_records(vec![record("unlimited_test")])
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn lookup_ids_batch() {
+        let mut db = test_client_with_cache_size(1).await;
+        clear_world(&db, "batch_test").await;
+
+        let evicted = Arc::new(std::sync::Mutex::new(vec![]));
+        let hook_evicted = evicted.clone();
+        db.set_eviction_hook(move |kind, world_name, region| {
+            if world_name == "batch_test" {
+                hook_evicted.lock().unwrap().push((kind, region));
+            }
+        });
+
+        let positions = [
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(20.0, 1.0, 1.0),
+            Vector3::new(2.0, 2.0, 2.0),
+        ];
+        let ids = db.lookup_ids_batch("batch test", &positions).await.unwrap();
+
+        // Both regions share a table, repeated regions share an ID
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[0], ids[2]);
+        assert_eq!(ids[0].0, ids[1].0);
+        assert_ne!(ids[0].1, ids[1].1);
+
+        // Only one region fits in each cache
+        assert_eq!(
+            db.lookup_ids_batch("batch_test", &positions[..1])
+                .await
+                .unwrap(),
+            vec![ids[0]]
+        );
+        assert!(evicted
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(kind, _)| *kind == CacheKind::Region));
+
+        let stats = db.cache_stats();
+        assert!(stats.region_misses > 0);
+        assert!((0.0..=1.0).contains(&stats.region_hit_ratio()));
+        assert!((0.0..=1.0).contains(&stats.table_hit_ratio()));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_since() {
+        let mut db = test_client().await;
+        clear_world(&db, "since_test").await;
+
+        let record = test_record("since_test", 1.0);
+        let point = Vector3::new(1.0, 1.0, 1.0);
+        assert!(db.insert_records(vec![record.clone()]).await.is_empty());
+
+        let minute = Duration::from_secs(60);
+        let before = SystemTime::now() - minute;
+        let records = db
+            .get_records_in_region_since("since test", point, before)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            vec![record.uuid]
+        );
+
+        let after = SystemTime::now() + minute;
+        let records = db
+            .get_records_in_region_since("since_test", point, after)
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_by_uuids() {
+        let mut db = test_client().await;
+        clear_world(&db, "uuids_test").await;
+
+        let positioned = test_record("uuids_test", 1.0);
+        let global = Record {
+            position: None,
+            ..test_record("uuids_test", 0.0)
+        };
+        let records = vec![positioned.clone(), global.clone()];
+        assert!(db.insert_records(records).await.is_empty());
+
+        // Missing records are skipped
+        let records = db
+            .get_records_by_uuids(
+                "uuids test",
+                &[positioned.uuid, global.uuid, Uuid::new_v4()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            sorted_uuids([&positioned, &global])
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_filtered() {
+        let mut db = test_client().await;
+        clear_world(&db, "filter_test").await;
+
+        let record = |flex: Option<&'static [u8]>| Record {
+            flex: flex.map(Bytes::from_static),
+            ..test_record("filter_test", 1.0)
+        };
+        let (matching, other, null) = (record(Some(&[1, 2])), record(Some(&[3])), record(None));
+        let records = vec![matching.clone(), other.clone(), null.clone()];
+        assert!(db.insert_records(records).await.is_empty());
+
+        let point = Vector3::new(1.0, 1.0, 1.0);
+        let filters = [
+            (FlexFilter::prefix(vec![1]), vec![matching.uuid]),
+            (
+                FlexFilter::prefix(vec![1]).include_null(true),
+                sorted_uuids([&matching, &null]),
+            ),
+            (
+                FlexFilter::default(),
+                sorted_uuids([&matching, &other, &null]),
+            ),
+        ];
+
+        for (filter, expected) in filters {
+            let records = db
+                .get_records_in_region_filtered("filter test", point, &filter)
+                .await
+                .unwrap();
+            assert_eq!(sorted_uuids(records.iter().map(|(_, r)| r)), expected);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_in_regions() {
+        let mut db = test_client().await;
+        clear_world(&db, "regions_test").await;
+
+        let (near, far) = (
+            test_record("regions_test", 1.0),
+            test_record("regions_test", 40.0),
+        );
+        let ignored = test_record("regions_test", 80.0);
+        let records = vec![near.clone(), far.clone(), ignored];
+        assert!(db.insert_records(records).await.is_empty());
+
+        // Repeated regions are only returned once
+        let points = vec![
+            Vector3::new(1.0, 1.0, 1.0),
+            Vector3::new(2.0, 1.0, 1.0),
+            Vector3::new(40.0, 1.0, 1.0),
+        ];
+        let records = db
+            .get_records_in_regions("regions test", points)
+            .await
+            .unwrap();
+        assert_eq!(sorted_uuids(&records), sorted_uuids([&near, &far]));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_paged() {
+        let mut db = test_client().await;
+        clear_world(&db, "paged_test").await;
+
+        let records = (0..3)
+            .map(|_| test_record("paged_test", 1.0))
+            .collect::<Vec<_>>();
+        assert!(db.insert_records(records.clone()).await.is_empty());
+
+        let point = Vector3::new(1.0, 1.0, 1.0);
+        let (first, more) = db
+            .get_records_in_region_paged("paged test", point, 2, 0)
+            .await
+            .unwrap();
+        assert!(more);
+
+        let (second, more) = db
+            .get_records_in_region_paged("paged_test", point, 2, 2)
+            .await
+            .unwrap();
+        assert!(!more);
+
+        // Pages are ordered by uuid
+        let paged = first
+            .iter()
+            .chain(&second)
+            .map(|(_, record)| record.uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(paged, sorted_uuids(&records));
+
+        let (empty, more) = db
+            .get_records_in_region_paged("paged_test", Vector3::new(5000.0, 1.0, 1.0), 2, 0)
+            .await
+            .unwrap();
+        assert!(empty.is_empty());
+        assert!(!more);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_stream() {
+        let mut db = test_client().await;
+        clear_world(&db, "stream_test").await;
+
+        let records = (0..3)
+            .map(|_| test_record("stream_test", 1.0))
+            .collect::<Vec<_>>();
+        assert!(db.insert_records(records.clone()).await.is_empty());
+
+        let streamed = db
+            .get_records_in_region_stream("stream test", Vector3::new(1.0, 1.0, 1.0))
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(sorted_uuids(&streamed), sorted_uuids(&records));
+
+        // Missing tables stream nothing
+        let streamed = db
+            .get_records_in_region_stream("stream_test", Vector3::new(5000.0, 1.0, 1.0))
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(streamed.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_in_radius() {
+        let mut db = test_client().await;
+        clear_world(&db, "radius_test").await;
+
+        // Both sides of a region border are inside the radius
+        let inside = vec![
+            test_record("radius_test", 14.0),
+            test_record("radius_test", 18.0),
+        ];
+        let outside = test_record("radius_test", 40.0);
+        let mut records = inside.clone();
+        records.push(outside);
+        assert!(db.insert_records(records).await.is_empty());
+
+        let records = db
+            .get_records_in_radius("radius test", Vector3::new(16.0, 1.0, 1.0), 5.0)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            sorted_uuids(&inside)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn nearest_records() {
+        let mut db = test_client().await;
+        clear_world(&db, "nearest_test").await;
+
+        let records = [100.0, 3.0, 20.0, 2000.0]
+            .iter()
+            .map(|x| test_record("nearest_test", *x))
+            .collect::<Vec<_>>();
+        assert!(db.insert_records(records.clone()).await.is_empty());
+
+        let center = Vector3::new(0.0, 1.0, 1.0);
+        let nearest = db
+            .get_nearest_records("nearest test", center, 2)
+            .await
+            .unwrap();
+        let nearest = nearest.iter().map(|r| r.uuid).collect::<Vec<_>>();
+        assert_eq!(nearest, vec![records[1].uuid, records[2].uuid]);
+
+        // Asking for more than exist returns every record
+        let nearest = db
+            .get_nearest_records("nearest_test", center, 10)
+            .await
+            .unwrap();
+        assert_eq!(nearest.len(), 4);
+        assert_eq!(nearest[3].uuid, records[3].uuid);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn delete_records() {
+        let mut db = test_client().await;
+        clear_world(&db, "delete_test").await;
+
+        let (deleted, kept) = (
+            test_record("delete_test", 1.0),
+            test_record("delete_test", 2.0),
+        );
+        let records = vec![deleted.clone(), kept.clone()];
+        assert!(db.insert_records(records).await.is_empty());
+
+        assert!(db.delete_records(vec![deleted]).await.is_empty());
+
+        let records = db
+            .get_records_in_region("delete_test", Vector3::new(1.0, 1.0, 1.0), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            vec![kept.uuid]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn delete_records_by_uuid() {
+        let mut db = test_client().await;
+        clear_world(&db, "delete_uuid_test").await;
+
+        // Tables that were never created are skipped
+        let errors = db
+            .delete_records_by_uuid("delete_uuid_test", vec![Uuid::new_v4()])
+            .await;
+        assert!(errors.is_empty());
+
+        let (near, far) = (
+            test_record("delete_uuid_test", 1.0),
+            test_record("delete_uuid_test", 5000.0),
+        );
+        let global = Record {
+            position: None,
+            ..test_record("delete_uuid_test", 0.0)
+        };
+        let kept = test_record("delete_uuid_test", 2.0);
+        let records = vec![near.clone(), far.clone(), global.clone(), kept.clone()];
+        assert!(db.insert_records(records).await.is_empty());
+
+        // Every table is found through the sanitized name, including the global table
+        let uuids = vec![near.uuid, far.uuid, global.uuid];
+        let errors = db.delete_records_by_uuid("delete uuid test", uuids).await;
+        assert!(errors.is_empty());
+
+        let uuids = [near.uuid, far.uuid, global.uuid, kept.uuid];
+        let records = db
+            .get_records_by_uuids("delete_uuid_test", &uuids)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            vec![kept.uuid]
+        );
+
+        // Invalid names are rejected even without records to delete
+        let errors = db.delete_records_by_uuid("", vec![]).await;
+        assert!(matches!(
+            errors.as_slice(),
+            [DatabaseError::InvalidWorldName { .. }]
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn update_record_position() {
+        let mut db = test_client().await;
+        clear_world(&db, "move_test").await;
+
+        let record = Record {
+            data: Some("kept".into()),
+            ..test_record("move_test", 1.0)
+        };
+        assert!(db.insert_records(vec![record.clone()]).await.is_empty());
+
+        // Moves within a table and into another table keep the data
+        for x in [2.0, 5000.0] {
+            let position = Vector3::new(x, 1.0, 1.0);
+            db.update_record_position("move test", record.uuid, position)
+                .await
+                .unwrap();
+
+            let records = db
+                .get_records_by_uuids("move_test", &[record.uuid])
+                .await
+                .unwrap();
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].1.position, Some(position));
+            assert_eq!(records[0].1.data.as_deref(), Some("kept"));
+        }
+
+        let missing = Uuid::new_v4();
+        let result = db
+            .update_record_position("move_test", missing, Vector3::new(1.0, 1.0, 1.0))
+            .await;
+        assert!(matches!(
+            result,
+            Err(DatabaseError::RecordNotFound { record }) if record == missing
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn world_stats_and_drop() {
+        let mut db = test_client().await;
+        clear_world(&db, "stats_test").await;
+
+        assert_eq!(
+            db.world_stats("stats_test").await.unwrap(),
+            WorldStats::default()
+        );
+
+        let global = Record {
+            position: None,
+            ..test_record("stats_test", 0.0)
+        };
+        let records = vec![
+            test_record("stats_test", 1.0),
+            test_record("stats_test", 5000.0),
+            global,
+        ];
+        assert!(db.insert_records(records).await.is_empty());
+
+        let stats = db.world_stats("stats test").await.unwrap();
+        assert_eq!(stats.table_count, 3);
+        assert_eq!(stats.record_count, 3);
+        assert!(stats.total_bytes > 0);
+
+        assert_eq!(db.drop_world("stats test").await.unwrap(), 3);
+        assert_eq!(
+            db.world_stats("stats_test").await.unwrap(),
+            WorldStats::default()
+        );
+
+        // Dropped worlds can be written to again
+        let record = test_record("stats_test", 1.0);
+        assert!(db.insert_records(vec![record]).await.is_empty());
+        assert_eq!(db.world_stats("stats_test").await.unwrap().record_count, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn write_buffer() {
+        let mut db = test_client().await;
+        clear_world(&db, "buffer_test").await;
+        db.set_write_buffer(3, Duration::from_secs(60));
+
+        let point = Vector3::new(1.0, 1.0, 1.0);
+        let records = vec![
+            test_record("buffer_test", 1.0),
+            test_record("buffer_test", 2.0),
+        ];
+        assert!(db.buffer_records(records, None).await.is_none());
+
+        // Buffered records aren't visible until flushed
+        let stored = db
+            .get_records_in_region("buffer_test", point, None)
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+
+        let report = db.flush().await;
+        assert!(report.errors.is_empty());
+        assert_eq!(report.inserted, 2);
+
+        // Full buffers are flushed straight away
+        let records = (0..3)
+            .map(|_| test_record("buffer_test", 1.0))
+            .collect::<Vec<_>>();
+        let report = db.buffer_records(records, None).await.unwrap();
+        assert_eq!(report.inserted, 3);
+
+        let stored = db
+            .get_records_in_region("buffer_test", point, None)
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 5);
+        assert_eq!(db.flush().await.inserted, 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn record_cache() {
+        let mut db = test_client().await;
+        let mut other = test_client().await;
+        clear_world(&db, "cache_test").await;
+        db.set_record_cache(Duration::from_secs(60), 16);
+
+        let point = Vector3::new(1.0, 1.0, 1.0);
+        let first = test_record("cache_test", 1.0);
+        assert!(db.insert_records(vec![first.clone()]).await.is_empty());
+
+        let cached = db
+            .get_records_in_region("cache_test", point, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(cached.iter().map(|(_, r)| r)),
+            vec![first.uuid]
+        );
+
+        // Writes from other clients aren't seen until the entry expires
+        let second = test_record("cache_test", 2.0);
+        assert!(other.insert_records(vec![second.clone()]).await.is_empty());
+
+        let cached = db
+            .get_records_in_region("cache test", point, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(cached.iter().map(|(_, r)| r)),
+            vec![first.uuid]
+        );
+
+        // Reads after a timestamp skip the cache
+        let after = NaiveDateTime::from_timestamp(0, 0);
+        let fresh = db
+            .get_records_in_region("cache_test", point, Some(after))
+            .await
+            .unwrap();
+        assert_eq!(fresh.len(), 2);
+
+        // Writes through this client invalidate the region
+        assert!(db.delete_records(vec![first]).await.is_empty());
+        let cached = db
+            .get_records_in_region("cache_test", point, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted_uuids(cached.iter().map(|(_, r)| r)),
+            vec![second.uuid]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn records_in_box() {
+        let mut db = test_client().await;
+        clear_world(&db, "box_test").await;
+
+        // Boxes include their faces, and can span tables
+        let inside = vec![
+            test_record("box_test", 10.0),
+            test_record("box_test", 20.0),
+            test_record("box_test", 1030.0),
+        ];
+        let outside = test_record("box_test", 2000.0);
+        let mut records = inside.clone();
+        records.push(outside);
+        assert!(db.insert_records(records).await.is_empty());
+
+        let min = Vector3::new(10.0, 0.0, 0.0);
+        let max = Vector3::new(1030.0, 1.0, 1.0);
+        let records = db.get_records_in_box("box test", min, max).await.unwrap();
+        assert_eq!(
+            sorted_uuids(records.iter().map(|(_, r)| r)),
+            sorted_uuids(&inside)
+        );
+
+        // Unwritten parts of the world are empty
+        let min = Vector3::new(-5000.0, 0.0, 0.0);
+        let max = Vector3::new(-4000.0, 1.0, 1.0);
+        let records = db.get_records_in_box("box_test", min, max).await.unwrap();
+        assert!(records.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::structures::Vector3;
+
+// region: Conversions
+/// Returns the minimum corner of the region containing `position`.
+///
+/// Regions include their minimum corner but not their maximum, on both sides of the origin.
+pub(super) fn position_to_region_coords(
+    position: &Vector3,
+    (x_size, y_size, z_size): (u16, u16, u16),
+) -> (i64, i64, i64) {
+    (
+        clamp_region_coord(*position.x(), x_size),
+        clamp_region_coord(*position.y(), y_size),
+        clamp_region_coord(*position.z(), z_size),
+    )
+}
+
+/// Returns the minimum corner of the [`super::PartitionStrategy::FixedGrid`] table
+/// containing the region with minimum corner `region`.
+///
+/// Table suffixes are allocated by the database when a table is first needed, so they
+/// are looked up by these bounds rather than computed from them.
+pub(super) fn region_to_table_coords(
+    (x, y, z): (i64, i64, i64),
+    table_size: i64,
+) -> (i64, i64, i64) {
+    (
+        clamp_table_size(x, table_size),
+        clamp_table_size(y, table_size),
+        clamp_table_size(z, table_size),
+    )
+}
+// endregion
+
+// region: Coordinate Clamp Functions
+/// Define region coords by their lowest possible value.
+///
+/// Uses floor division, so regions are the same size either side of the origin. Positions
+/// must be valid, see [`Vector3::is_valid_position`], which peers are held to when their
+/// messages are decoded.
+fn clamp_region_coord(c: f64, region_size: u16) -> i64 {
+    let index = (c / f64::from(region_size)).floor() as i64;
+
+    index * i64::from(region_size)
+}
+
+pub(super) fn clamp_table_size(c: i64, table_size: i64) -> i64 {
+    // On a table border, return
+    if c % table_size == 0 {
+        return c;
+    }
+
+    if c >= 0 {
+        let region_size = table_size;
+
+        c - (c % region_size)
+    } else {
+        let new_c = (-c) + table_size;
+        let result = clamp_table_size(new_c, table_size);
+
+        -result
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // region: clamp_region_coord
+    macro_rules! test_clamp_region_coord {
+        ($input: expr, $region_size: expr, $expected: expr) => {
+            let output = super::clamp_region_coord($input, $region_size);
+            assert_eq!(output, $expected);
+        };
+    }
+
+    #[test]
+    fn clamp_region_coord() {
+        // Unit case
+        test_clamp_region_coord!(0.0, 16, 0);
+
+        // Positive
+        test_clamp_region_coord!(0.1, 16, 0);
+        test_clamp_region_coord!(15.0, 16, 0);
+        test_clamp_region_coord!(16.0, 16, 16);
+        test_clamp_region_coord!(31.9, 16, 16);
+        test_clamp_region_coord!(32.0, 16, 32);
+        test_clamp_region_coord!(0.0, 256, 0);
+        test_clamp_region_coord!(0.1, 256, 0);
+        test_clamp_region_coord!(128.0, 256, 0);
+        test_clamp_region_coord!(255.9, 256, 0);
+        test_clamp_region_coord!(256.0, 256, 256);
+        test_clamp_region_coord!(511.9, 256, 256);
+        test_clamp_region_coord!(512.0, 256, 512);
+
+        // Negative
+        test_clamp_region_coord!(-0.1, 16, -16);
+        test_clamp_region_coord!(-1.0, 16, -16);
+        test_clamp_region_coord!(-15.0, 16, -16);
+        test_clamp_region_coord!(-16.0, 16, -16);
+        test_clamp_region_coord!(-16.1, 16, -32);
+        test_clamp_region_coord!(-31.9, 16, -32);
+        test_clamp_region_coord!(-32.0, 16, -32);
+        test_clamp_region_coord!(-32.1, 16, -48);
+        test_clamp_region_coord!(-1.0, 256, -256);
+        test_clamp_region_coord!(-128.0, 256, -256);
+        test_clamp_region_coord!(-255.9, 256, -256);
+        test_clamp_region_coord!(-256.0, 256, -256);
+        test_clamp_region_coord!(-256.1, 256, -512);
+
+        // Either side of the origin
+        test_clamp_region_coord!(-0.001, 16, -16);
+        test_clamp_region_coord!(0.001, 16, 0);
+        test_clamp_region_coord!(-0.0, 16, 0);
+    }
+    // endregion
+
+    // region: clamp_table_size
+    macro_rules! test_clamp_table_size {
+        ($input: expr, $table_size: expr, $expected: expr) => {
+            let output = super::clamp_table_size($input, $table_size);
+            assert_eq!(output, $expected);
+        };
+    }
+
+    #[test]
+    fn clamp_table_size() {
+        // Unit case
+        test_clamp_table_size!(0, 1024, 0);
+
+        // Positive
+        test_clamp_table_size!(1, 1024, 0);
+        test_clamp_table_size!(256, 1024, 0);
+        test_clamp_table_size!(1024, 1024, 1024);
+        test_clamp_table_size!(1800, 1024, 1024);
+        test_clamp_table_size!(2047, 1024, 1024);
+        test_clamp_table_size!(2048, 1024, 2048);
+
+        // Negative
+        test_clamp_table_size!(-1, 1024, -1024);
+        test_clamp_table_size!(-45, 1024, -1024);
+        test_clamp_table_size!(-687, 1024, -1024);
+        test_clamp_table_size!(-1023, 1024, -1024);
+        test_clamp_table_size!(-1024, 1024, -1024);
+        test_clamp_table_size!(-1025, 1024, -2048);
+    }
+    // endregion
+
+    // region: conversions
+    #[test]
+    fn position_to_region_coords() {
+        let mc_chunk = (16, 256, 16);
+        let convert = |x, y, z| super::position_to_region_coords(&Vector3::new(x, y, z), mc_chunk);
+
+        // Exactly on a boundary belongs to the region above
+        assert_eq!(convert(16.0, 256.0, 32.0), (16, 256, 32));
+        assert_eq!(convert(15.999, 255.999, 31.999), (0, 0, 16));
+
+        // Negative coordinates round down, negative boundaries belong to the region above
+        assert_eq!(convert(-0.001, -1.0, -15.999), (-16, -256, -16));
+        assert_eq!(convert(-16.0, -256.0, -32.0), (-16, -256, -32));
+        assert_eq!(convert(-16.001, -256.001, -32.001), (-32, -512, -48));
+
+        // Mixed
+        assert_eq!(convert(-45.0, 22.0, 1023.0), (-48, 0, 1008));
+    }
+
+    #[test]
+    fn region_to_table_coords() {
+        let table_size = 1024;
+
+        assert_eq!(
+            super::region_to_table_coords((0, 0, 0), table_size),
+            (0, 0, 0)
+        );
+
+        // Exactly on a boundary belongs to the table above, for both signs
+        assert_eq!(
+            super::region_to_table_coords((1024, -1024, 2048), table_size),
+            (1024, -1024, 2048)
+        );
+
+        // Just inside a boundary
+        assert_eq!(
+            super::region_to_table_coords((1008, -16, -1040), table_size),
+            (0, -1024, -2048)
+        );
+
+        // Matches the table of the region containing a position
+        let region =
+            super::position_to_region_coords(&Vector3::new(-0.5, 1023.9, 1024.0), (16, 16, 16));
+        assert_eq!(
+            super::region_to_table_coords(region, table_size),
+            (-1024, 0, 1024)
+        );
+    }
+    // endregion
+}
+// endregion
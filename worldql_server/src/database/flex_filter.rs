@@ -0,0 +1,89 @@
+use bytes::Bytes;
+
+// region: FlexFilter Struct
+/// Filters records by their `flex` data, see
+/// [`super::DatabaseClient::get_records_in_region_filtered`].
+///
+/// The default filter matches every record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlexFilter {
+    /// Only match records whose `flex` data starts with these bytes
+    prefix: Bytes,
+    /// Whether records without `flex` data match
+    include_null: bool,
+}
+
+impl Default for FlexFilter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            prefix: Bytes::new(),
+            include_null: true,
+        }
+    }
+}
+
+impl FlexFilter {
+    /// Match only records whose `flex` data starts with `prefix`.
+    ///
+    /// Records without `flex` data are excluded, use [`Self::include_null`] to change this.
+    pub fn prefix(prefix: impl Into<Bytes>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            include_null: false,
+        }
+    }
+
+    /// Set whether records without `flex` data match.
+    #[inline]
+    pub fn include_null(mut self, include_null: bool) -> Self {
+        self.include_null = include_null;
+        self
+    }
+
+    #[inline]
+    pub(super) fn prefix_bytes(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    #[inline]
+    pub(super) fn includes_null(&self) -> bool {
+        self.include_null
+    }
+
+    /// Returns `true` if a record with the given `flex` data matches this filter.
+    ///
+    /// Mirrors the filter applied in SQL.
+    pub fn matches(&self, flex: Option<&[u8]>) -> bool {
+        match flex {
+            None => self.include_null,
+            Some(flex) => flex.starts_with(&self.prefix),
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let filter = FlexFilter::default();
+        assert!(filter.matches(None));
+        assert!(filter.matches(Some(&[])));
+        assert!(filter.matches(Some(&[1, 2, 3])));
+
+        let filter = FlexFilter::prefix(vec![1, 2]);
+        assert!(!filter.matches(None));
+        assert!(!filter.matches(Some(&[1])));
+        assert!(!filter.matches(Some(&[2, 1, 3])));
+        assert!(filter.matches(Some(&[1, 2])));
+        assert!(filter.matches(Some(&[1, 2, 3])));
+
+        let filter = filter.include_null(true);
+        assert!(filter.matches(None));
+    }
+}
+// endregion
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+// region: IndexSpec Enum
+/// Which indexes are built on each world table, besides the unique `uuid` index.
+///
+/// Indexes are only created along with their table, so changing the spec leaves existing
+/// tables with the indexes they were created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexSpec {
+    /// B-tree on `region_id`, suited to reads of whole regions
+    Region,
+    /// B-tree on `(region_id, x, y, z)`, suited to coordinate ranges within a region
+    RegionPosition,
+    /// B-tree on `region_id` and a GiST index on the `(x, y, z)` position, for spatial
+    /// operators
+    ///
+    /// Requires the `cube` extension, which is created if missing.
+    Gist,
+}
+
+impl Default for IndexSpec {
+    fn default() -> Self {
+        Self::Region
+    }
+}
+
+impl FromStr for IndexSpec {
+    type Err = ParseIndexSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "region" => Ok(Self::Region),
+            "region-position" => Ok(Self::RegionPosition),
+            "gist" => Ok(Self::Gist),
+            _ => Err(ParseIndexSpecError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: region, region-position, gist")]
+pub struct ParseIndexSpecError;
+// endregion
@@ -1,27 +1,141 @@
 use color_eyre::Result;
+use tracing::{info, warn};
+use uuid::Uuid;
 
-use super::client::DatabaseClient;
+use super::client::{is_undefined_table, DatabaseClient, DatabaseError};
 use super::{
-    CREATE_REGION_NAVIGATION, CREATE_SCHEMA_NAVIGATION, CREATE_TABLE_NAVIGATION,
-    CREATE_TABLE_NAVIGATION_INDEX,
+    query_select_misplaced_records, CREATE_REGION_NAVIGATION, CREATE_SCHEMA_NAVIGATION,
+    CREATE_TABLE_NAVIGATION, CREATE_TABLE_NAVIGATION_INDEX, CREATE_TABLE_SCHEMA_VERSION,
+    FINISH_REHOME, MIGRATE_RECORD_OWNER, MIGRATE_RECORD_UUID_INDEX,
+    MIGRATE_REHOME_BOUNDARY_RECORDS, MIGRATE_WORLD_NAME_LENGTH, QUERY_INSERT_SCHEMA_VERSION,
+    QUERY_LOCK_MIGRATIONS, QUERY_LOOKUP_ALL_TABLE_SUFFIXES, QUERY_REHOME_PENDING,
+    QUERY_SCHEMA_VERSION,
 };
+use crate::structures::Vector3;
+
+/// Schema changes applied in order by [`DatabaseClient::ensure_schema`], the version of each
+/// is its index plus one.
+///
+/// Only ever append to this list, applied migrations are never run again.
+const MIGRATIONS: &[&[&str]] = &[
+    // 1: Navigation tables
+    &[
+        CREATE_SCHEMA_NAVIGATION,
+        CREATE_TABLE_NAVIGATION,
+        CREATE_REGION_NAVIGATION,
+        CREATE_TABLE_NAVIGATION_INDEX,
+    ],
+    // 2: Fit sanitized world names longer than 32 characters
+    &[MIGRATE_WORLD_NAME_LENGTH],
+    // 3: Track which peer created each record
+    &[MIGRATE_RECORD_OWNER],
+    // 4: Keep a single version of each record, so upserts can conflict on its uuid
+    &[MIGRATE_RECORD_UUID_INDEX],
+    // 5: Move records on a negative region boundary into the region above
+    &[MIGRATE_REHOME_BOUNDARY_RECORDS],
+];
 
 impl DatabaseClient {
-    pub async fn init_database(&self) -> Result<()> {
-        let queries = vec![
-            // Create schema
-            CREATE_SCHEMA_NAVIGATION,
-            // Create tables
-            CREATE_TABLE_NAVIGATION,
-            CREATE_REGION_NAVIGATION,
-            // Create index
-            CREATE_TABLE_NAVIGATION_INDEX,
-        ];
-
-        // Execute
-        let query = format!("{};", queries.join(";"));
-        self.client.batch_execute(&query).await?;
-
-        Ok(())
+    /// Create the base schema and apply any pending migrations, recording each applied
+    /// version in `navigation.schema_version`.
+    ///
+    /// Safe to run repeatedly and from several servers at once. Returns the schema version.
+    pub async fn ensure_schema(&mut self) -> Result<i32> {
+        // Dropping the transaction without committing rolls it back
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        transaction.execute(QUERY_LOCK_MIGRATIONS, &[]).await?;
+
+        let query = format!(
+            "{};{};",
+            CREATE_SCHEMA_NAVIGATION, CREATE_TABLE_SCHEMA_VERSION
+        );
+        transaction.batch_execute(&query).await?;
+
+        let current: i32 = transaction
+            .query_one(QUERY_SCHEMA_VERSION, &[])
+            .await?
+            .get(0);
+
+        let mut version = current;
+        for migration in MIGRATIONS.iter().skip(current as usize) {
+            version += 1;
+            info!("Applying database migration {}", version);
+
+            let query = format!("{};", migration.join(";"));
+            transaction.batch_execute(&query).await?;
+            transaction
+                .execute(QUERY_INSERT_SCHEMA_VERSION, &[&version])
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        // Runs until every record is moved, even if a previous server was interrupted
+        let pending: bool = self
+            .client()
+            .await?
+            .query_one(QUERY_REHOME_PENDING, &[])
+            .await?
+            .get(0);
+        if pending {
+            self.rehome_boundary_records().await?;
+        }
+
+        Ok(version)
+    }
+
+    /// Move records stored outside the bounds of their region into the region containing
+    /// them, returning how many were moved.
+    ///
+    /// Only records exactly on a negative region boundary are affected, which older versions
+    /// stored in the region below. Failed moves are logged and retried on the next start.
+    pub async fn rehome_boundary_records(&mut self) -> Result<u64, DatabaseError> {
+        let tables = self
+            .client()
+            .await?
+            .query(QUERY_LOOKUP_ALL_TABLE_SUFFIXES, &[])
+            .await?;
+
+        let mut moved = 0;
+        let mut failed = 0;
+        for table in tables {
+            let world_name: String = table.get("world_name");
+            let table_suffix: i32 = table.get("table_suffix");
+
+            let query = query_select_misplaced_records(&world_name, table_suffix);
+            let rows = match self.client().await?.query(&query, &[]).await {
+                Ok(rows) => rows,
+                Err(error) if is_undefined_table(&error) => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            for row in rows {
+                let uuid: Uuid = row.get("uuid");
+                let position = Vector3::new(row.get("x"), row.get("y"), row.get("z"));
+
+                // Moving a record to its own position re-homes it
+                match self
+                    .update_record_position(&world_name, uuid, position)
+                    .await
+                {
+                    Ok(()) => moved += 1,
+                    Err(error) => {
+                        warn!("failed to re-home record {}: {}", uuid, error);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+
+        if moved > 0 {
+            info!("Re-homed {} records on a negative region boundary", moved);
+        }
+
+        if failed == 0 {
+            self.client().await?.batch_execute(FINISH_REHOME).await?;
+        }
+
+        Ok(moved)
     }
 }
@@ -1,8 +1,33 @@
+mod breaker;
+mod cache_stats;
 mod client;
+mod coords;
+#[cfg(test)]
+mod flex_filter;
+mod index_spec;
 mod init;
 mod navigation;
+mod ownership;
+mod partition;
 mod query_constants;
+mod record_cache;
+mod region_records;
+mod retry;
+mod table_locks;
+mod world_drop;
 mod world_region;
+mod world_stats;
+mod write_buffer;
 
-pub use client::{DatabaseClient, DedupeData};
-pub(self) use query_constants::*;
+pub use breaker::{BreakerStats, CircuitBreaker};
+pub use cache_stats::{CacheStats, CacheStatsHandle};
+pub use client::DatabaseClient;
+#[cfg(feature = "admin")]
+pub use client::DatabaseError;
+pub use index_spec::IndexSpec;
+pub use partition::PartitionStrategy;
+use query_constants::*;
+pub use region_records::RegionRecords;
+pub use retry::RetryPolicy;
+#[cfg(feature = "admin")]
+pub use world_stats::WorldStats;
@@ -1,14 +1,85 @@
+use ahash::AHashMap;
+use lru::LruCache;
 use tokio_postgres::Error;
-use tracing::trace;
+use tracing::{debug, trace};
 
+use super::cache_stats::CacheKind;
+use super::client::DatabaseError;
 use super::world_region::WorldRegion;
 use super::{
-    DatabaseClient, QUERY_INSERT_REGION_ID, QUERY_INSERT_TABLE_SUFFIX, QUERY_LOOKUP_REGION_ID,
-    QUERY_LOOKUP_TABLE_SUFFIX,
+    DatabaseClient, QUERY_INSERT_REGION_ID, QUERY_INSERT_TABLE_SUFFIX, QUERY_LOOKUP_IDS_BATCH,
+    QUERY_LOOKUP_REGION_ID, QUERY_LOOKUP_TABLE_SUFFIX, QUERY_LOOKUP_WORLD_REGIONS,
+    QUERY_LOOKUP_WORLD_TABLE_SUFFIXES,
 };
+use crate::metrics::DB_DURATION;
 use crate::structures::Vector3;
 
 impl DatabaseClient {
+    /// Pre-populate the lookup caches with the most recently allocated regions of a world.
+    ///
+    /// Loads at most as many regions as the caches can hold, using a single query.
+    /// Returns the number of regions loaded.
+    pub async fn warm_cache(&mut self, world_name: &str) -> Result<usize, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("warm_cache");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+        let limit = i64::try_from(self.region_cache.cap()).unwrap_or(i64::MAX);
+
+        let rows = self
+            .client()
+            .await?
+            .query(QUERY_LOOKUP_WORLD_REGIONS, &[&world_name, &limit])
+            .await?;
+
+        // Rows are newest first, insert oldest first so the newest are most recently used
+        for row in rows.iter().rev() {
+            let region = WorldRegion::from_min(
+                &world_name,
+                row.try_get("min_x")?,
+                row.try_get("min_y")?,
+                row.try_get("min_z")?,
+            );
+
+            let region_id: i32 = row.try_get("region_id")?;
+            let table_suffix: Option<i32> = row.try_get("table_suffix")?;
+
+            if let Some(table_suffix) = table_suffix {
+                self.cache_put(CacheKind::Table, region.clone(), table_suffix);
+            }
+
+            self.cache_put(CacheKind::Region, region, region_id);
+        }
+
+        debug!(
+            "warmed lookup caches with {} regions for world \"{}\"",
+            rows.len(),
+            world_name
+        );
+
+        Ok(rows.len())
+    }
+
+    /// Insert into a lookup cache, calling the eviction hook if an entry is evicted.
+    pub(super) fn cache_put(&mut self, kind: CacheKind, region: WorldRegion, value: i32) {
+        let cache = match kind {
+            CacheKind::Table => &mut self.table_cache,
+            CacheKind::Region => &mut self.region_cache,
+        };
+
+        if let Some((evicted, _)) = put_evicting(cache, region, value) {
+            trace!("evicted {} from {:?} cache", evicted, kind);
+
+            if let Some(hook) = &self.eviction_hook {
+                hook(
+                    kind,
+                    evicted.world_name(),
+                    (*evicted.x(), *evicted.y(), *evicted.z()),
+                );
+            }
+        }
+    }
+
     /// Lookup both `table_suffix` and `region_id` in a single function.
     ///
     /// Returned tuple has the form `(table_suffix, region_id)`
@@ -18,32 +89,204 @@ impl DatabaseClient {
         point: &Vector3,
     ) -> Result<(i32, i32), Error> {
         let world_region = self.world_region(world_name, point);
-        let table_suffix = self.get_table_suffix(&world_region).await?;
-        let region_id = self.get_region_id(&world_region).await?;
+        self.lookup_region_ids(&world_region).await
+    }
+
+    /// Lookup both `table_suffix` and `region_id` for many positions in a single world.
+    ///
+    /// Regions missing from the lookup caches are resolved in a single query, only regions
+    /// that have never been allocated need further round-trips to create them.
+    /// Returned tuples have the form `(table_suffix, region_id)`, in the order of `positions`.
+    #[cfg(test)]
+    pub async fn lookup_ids_batch(
+        &mut self,
+        world_name: &str,
+        positions: &[Vector3],
+    ) -> Result<Vec<(i32, i32)>, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("lookup_ids_batch");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        Ok(self
+            .lookup_sanitized_ids_batch(&world_name, positions)
+            .await?)
+    }
+
+    /// Same as [`Self::lookup_ids_batch`], for an already sanitized world name.
+    pub(super) async fn lookup_sanitized_ids_batch(
+        &mut self,
+        world_name: &str,
+        positions: &[Vector3],
+    ) -> Result<Vec<(i32, i32)>, Error> {
+        let regions = positions
+            .iter()
+            .map(|position| self.world_region(world_name, position))
+            .collect::<Vec<_>>();
+
+        // Resolve each unique region from the caches first
+        let mut resolved: AHashMap<WorldRegion, (Option<i32>, Option<i32>)> = AHashMap::new();
+        let mut missing = vec![];
+        for region in &regions {
+            if resolved.contains_key(region) {
+                continue;
+            }
+
+            let table_suffix = self.table_cache.get(region).copied();
+            match table_suffix {
+                Some(_) => self.cache_counters.table_hit(),
+                None => self.cache_counters.table_miss(),
+            }
+
+            let region_id = self.region_cache.get(region).copied();
+            match region_id {
+                Some(_) => self.cache_counters.region_hit(),
+                None => self.cache_counters.region_miss(),
+            }
+
+            if table_suffix.is_none() || region_id.is_none() {
+                missing.push(region.clone());
+            }
+
+            resolved.insert(region.clone(), (table_suffix, region_id));
+        }
+
+        if !missing.is_empty() {
+            trace!(
+                "querying database for {} uncached regions in world \"{}\"",
+                missing.len(),
+                world_name
+            );
+
+            let xs = missing.iter().map(|r| *r.x()).collect::<Vec<_>>();
+            let ys = missing.iter().map(|r| *r.y()).collect::<Vec<_>>();
+            let zs = missing.iter().map(|r| *r.z()).collect::<Vec<_>>();
+
+            let rows = self
+                .client()
+                .await?
+                .query(QUERY_LOOKUP_IDS_BATCH, &[&world_name, &xs, &ys, &zs])
+                .await?;
+
+            for row in rows {
+                let idx: i64 = row.try_get("idx")?;
+                let region = &missing[idx as usize - 1];
+                let ids = resolved.get_mut(region).unwrap();
+
+                if ids.0.is_none() {
+                    ids.0 = row.try_get("table_suffix")?;
+                }
+
+                if ids.1.is_none() {
+                    ids.1 = row.try_get("region_id")?;
+                }
+            }
+
+            // Allocate anything that still wasn't found, then cache the results
+            // Several new regions may share a table, which must only be allocated once
+            let table_size = i64::from(self.table_size());
+            let mut allocated_tables = AHashMap::new();
+            for region in missing {
+                let (table_suffix, region_id) = resolved[&region];
+                let table_suffix = match table_suffix {
+                    Some(table_suffix) => table_suffix,
+                    None => {
+                        let bounds = self.partition_strategy().table_bounds(&region, table_size);
+                        match allocated_tables.get(&bounds) {
+                            Some(table_suffix) => *table_suffix,
+                            None => {
+                                let table_suffix = self.insert_table_suffix(&region).await?;
+                                allocated_tables.insert(bounds, table_suffix);
+                                table_suffix
+                            }
+                        }
+                    }
+                };
+
+                let region_id = match region_id {
+                    Some(region_id) => region_id,
+                    None => self.insert_region_id(&region).await?,
+                };
+
+                resolved.insert(region.clone(), (Some(table_suffix), Some(region_id)));
+                self.cache_put(CacheKind::Table, region.clone(), table_suffix);
+                self.cache_put(CacheKind::Region, region, region_id);
+            }
+        }
+
+        let ids = regions
+            .iter()
+            .map(|region| match resolved[region] {
+                (Some(table_suffix), Some(region_id)) => (table_suffix, region_id),
+                _ => unreachable!("every region is resolved above"),
+            })
+            .collect();
+
+        Ok(ids)
+    }
+
+    /// Lookup both `table_suffix` and `region_id` for an already resolved [`WorldRegion`].
+    ///
+    /// Returned tuple has the form `(table_suffix, region_id)`
+    pub(super) async fn lookup_region_ids(
+        &mut self,
+        world_region: &WorldRegion,
+    ) -> Result<(i32, i32), Error> {
+        let table_suffix = self.get_table_suffix(world_region).await?;
+        let region_id = self.get_region_id(world_region).await?;
 
         Ok((table_suffix, region_id))
     }
 
+    /// Lookup every `table_suffix` that has been allocated for a world.
+    pub(super) async fn lookup_world_table_suffixes(
+        &mut self,
+        world_name: &str,
+    ) -> Result<Vec<i32>, Error> {
+        trace!(
+            "looking up all table_suffix values for world \"{}\"",
+            world_name
+        );
+
+        let rows = self
+            .client()
+            .await?
+            .query(QUERY_LOOKUP_WORLD_TABLE_SUFFIXES, &[&world_name])
+            .await?;
+
+        let mut suffixes = Vec::with_capacity(rows.len());
+        for row in rows {
+            let table_suffix: i32 = row.try_get("table_suffix")?;
+            suffixes.push(table_suffix);
+        }
+
+        Ok(suffixes)
+    }
+
     async fn get_table_suffix(&mut self, region: &WorldRegion) -> Result<i32, Error> {
         trace!("looking up table_suffix for {}", region);
 
         // Early return for cached value
         if let Some(id) = self.table_cache.get(region) {
             trace!("region {} has cached table_suffix = {}", region, id);
+            self.cache_counters.table_hit();
             return Ok(*id);
         }
 
+        self.cache_counters.table_miss();
+
         // Query database for table_suffix
         trace!("querying database for {} table_suffix", region);
         let rows = self
-            .client
+            .client()
+            .await?
             .query(
                 QUERY_LOOKUP_TABLE_SUFFIX,
                 &[region.world_name(), region.x(), region.y(), region.z()],
             )
             .await?;
 
-        let table_suffix = match rows.get(0) {
+        let table_suffix = match rows.first() {
             // Suffix found, return
             Some(row) => {
                 let table_suffix: i32 = row.try_get("table_suffix")?;
@@ -57,44 +300,73 @@ impl DatabaseClient {
             }
 
             // No suffix found, create and return
-            None => {
-                trace!("table_suffix for {} not found in db, creating", region);
-
-                let table_size = i64::from(self.table_size());
-                let (min_x, max_x) = region.x_bounds(table_size);
-                let (min_y, max_y) = region.y_bounds(table_size);
-                let (min_z, max_z) = region.z_bounds(table_size);
-
-                // Insert new values into DB
-                let row = self
-                    .client
-                    .query_one(
-                        QUERY_INSERT_TABLE_SUFFIX,
-                        &[
-                            &min_x,
-                            &max_x,
-                            &min_y,
-                            &max_y,
-                            &min_z,
-                            &max_z,
-                            region.world_name(),
-                        ],
-                    )
-                    .await?;
-
-                let table_suffix: i32 = row.try_get("table_suffix")?;
-                trace!(
-                    "table_suffix for {} returned from db = {}",
-                    region,
-                    &table_suffix
-                );
-
-                table_suffix
-            }
+            None => self.insert_table_suffix(region).await?,
         };
 
         // Insert into cache and return
-        self.table_cache.put(region.clone(), table_suffix);
+        self.cache_put(CacheKind::Table, region.clone(), table_suffix);
+        Ok(table_suffix)
+    }
+
+    /// Returns the `table_suffix` of the table containing `region`, without allocating one
+    /// if it was never needed.
+    #[cfg(test)]
+    pub(super) async fn find_table_suffix(
+        &mut self,
+        region: &WorldRegion,
+    ) -> Result<Option<i32>, Error> {
+        if let Some(id) = self.table_cache.peek(region) {
+            return Ok(Some(*id));
+        }
+
+        let rows = self
+            .client()
+            .await?
+            .query(
+                QUERY_LOOKUP_TABLE_SUFFIX,
+                &[region.world_name(), region.x(), region.y(), region.z()],
+            )
+            .await?;
+
+        match rows.first() {
+            Some(row) => Ok(Some(row.try_get("table_suffix")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Allocate a new `table_suffix` for the table containing `region`.
+    async fn insert_table_suffix(&mut self, region: &WorldRegion) -> Result<i32, Error> {
+        trace!("table_suffix for {} not found in db, creating", region);
+
+        let table_size = i64::from(self.table_size());
+        let [(min_x, max_x), (min_y, max_y), (min_z, max_z)] =
+            self.partition_strategy().table_bounds(region, table_size);
+
+        // Insert new values into DB
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                QUERY_INSERT_TABLE_SUFFIX,
+                &[
+                    &min_x,
+                    &max_x,
+                    &min_y,
+                    &max_y,
+                    &min_z,
+                    &max_z,
+                    region.world_name(),
+                ],
+            )
+            .await?;
+
+        let table_suffix: i32 = row.try_get("table_suffix")?;
+        trace!(
+            "table_suffix for {} returned from db = {}",
+            region,
+            &table_suffix
+        );
+
         Ok(table_suffix)
     }
 
@@ -104,20 +376,24 @@ impl DatabaseClient {
         // Early return for cached value
         if let Some(id) = self.region_cache.get(region) {
             trace!("region {} has cached region_id = {}", region, id);
+            self.cache_counters.region_hit();
             return Ok(*id);
         }
 
+        self.cache_counters.region_miss();
+
         // Query database for region_id
         trace!("querying database for {} region_id", region);
         let rows = self
-            .client
+            .client()
+            .await?
             .query(
                 QUERY_LOOKUP_REGION_ID,
                 &[region.world_name(), region.x(), region.y(), region.z()],
             )
             .await?;
 
-        let region_id = match rows.get(0) {
+        let region_id = match rows.first() {
             // ID found, return
             Some(row) => {
                 let region_id: i32 = row.try_get("region_id")?;
@@ -127,43 +403,91 @@ impl DatabaseClient {
             }
 
             // No suffix found, create and return
-            None => {
-                trace!("region_id for {} not found in db, creating", region);
-
-                let min_x = *region.x();
-                let min_y = *region.y();
-                let min_z = *region.z();
-
-                let max_x = min_x + i64::from(self.region_x_size());
-                let max_y = min_y + i64::from(self.region_y_size());
-                let max_z = min_z + i64::from(self.region_z_size());
-
-                // Insert new values into DB
-                let row = self
-                    .client
-                    .query_one(
-                        QUERY_INSERT_REGION_ID,
-                        &[
-                            &min_x,
-                            &max_x,
-                            &min_y,
-                            &max_y,
-                            &min_z,
-                            &max_z,
-                            region.world_name(),
-                        ],
-                    )
-                    .await?;
-
-                let region_id: i32 = row.try_get("region_id")?;
-                trace!("region_id for {} returned from db = {}", region, &region_id);
-
-                region_id
-            }
+            None => self.insert_region_id(region).await?,
         };
 
         // Insert into cache and return
-        self.region_cache.put(region.clone(), region_id);
+        self.cache_put(CacheKind::Region, region.clone(), region_id);
         Ok(region_id)
     }
+
+    /// Allocate a new `region_id` for `region`.
+    async fn insert_region_id(&mut self, region: &WorldRegion) -> Result<i32, Error> {
+        trace!("region_id for {} not found in db, creating", region);
+
+        let min_x = *region.x();
+        let min_y = *region.y();
+        let min_z = *region.z();
+
+        let (region_x_size, region_y_size, region_z_size) = self.region_size(region.world_name());
+
+        let max_x = min_x + i64::from(region_x_size);
+        let max_y = min_y + i64::from(region_y_size);
+        let max_z = min_z + i64::from(region_z_size);
+
+        // Insert new values into DB
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                QUERY_INSERT_REGION_ID,
+                &[
+                    &min_x,
+                    &max_x,
+                    &min_y,
+                    &max_y,
+                    &min_z,
+                    &max_z,
+                    region.world_name(),
+                ],
+            )
+            .await?;
+
+        let region_id: i32 = row.try_get("region_id")?;
+        trace!("region_id for {} returned from db = {}", region, &region_id);
+
+        Ok(region_id)
+    }
+}
+
+/// Insert into an [`LruCache`], returning the least recently used entry if it was evicted
+/// to make room.
+fn put_evicting<K, V>(cache: &mut LruCache<K, V>, key: K, value: V) -> Option<(K, V)>
+where
+    K: std::hash::Hash + Eq,
+{
+    let evicted = match !cache.contains(&key) && cache.len() >= cache.cap() {
+        true => cache.pop_lru(),
+        false => None,
+    };
+
+    cache.put(key, value);
+    evicted
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_evicting_lru() {
+        let mut cache = LruCache::new(2);
+        assert_eq!(put_evicting(&mut cache, 1, "a"), None);
+        assert_eq!(put_evicting(&mut cache, 2, "b"), None);
+
+        // Updating an existing key never evicts
+        assert_eq!(put_evicting(&mut cache, 1, "c"), None);
+
+        // Least recently used entry is evicted
+        assert_eq!(put_evicting(&mut cache, 3, "d"), Some((2, "b")));
+        assert_eq!(cache.len(), 2);
+
+        // Unbounded caches never evict
+        let mut cache = LruCache::unbounded();
+        for i in 0..100 {
+            assert_eq!(put_evicting(&mut cache, i, i), None);
+        }
+    }
 }
+// endregion
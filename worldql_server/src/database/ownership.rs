@@ -0,0 +1,47 @@
+use ahash::AHashSet;
+use uuid::Uuid;
+
+use super::client::DatabaseError;
+use super::DatabaseClient;
+
+impl DatabaseClient {
+    /// Only let peers modify the records they created, besides `privileged_peers` which
+    /// may modify any record.
+    ///
+    /// Applies to [`Self::upsert_records_as`] and [`Self::delete_records_as`]. Records
+    /// created before ownership was tracked, or without an owner, can be modified by
+    /// anyone.
+    pub fn set_record_ownership(&mut self, privileged_peers: impl IntoIterator<Item = Uuid>) {
+        self.privileged_peers = Some(privileged_peers.into_iter().collect::<AHashSet<_>>());
+    }
+
+    /// Returns `true` if writes by `peer` are limited to the records it owns.
+    #[inline]
+    pub(super) fn enforces_ownership(&self, peer: &Uuid) -> bool {
+        match &self.privileged_peers {
+            Some(privileged_peers) => !privileged_peers.contains(peer),
+            None => false,
+        }
+    }
+
+    /// Returns a [`DatabaseError::PermissionDenied`] for each record out of `uuids` owned
+    /// by a peer other than `peer`, using a query built by `query_select_denied`.
+    pub(super) async fn denied_records(
+        &mut self,
+        query: &str,
+        uuids: Vec<Uuid>,
+        peer: Uuid,
+    ) -> Vec<DatabaseError> {
+        match self.query_cached(query, &[&uuids, &peer]).await {
+            Ok(rows) => rows
+                .iter()
+                .map(|row| DatabaseError::PermissionDenied {
+                    record: row.get("uuid"),
+                    peer,
+                })
+                .collect(),
+
+            Err(error) => vec![error.into()],
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::coords::region_to_table_coords;
+use super::world_region::WorldRegion;
+
+/// Bits per axis of the Hilbert curve, so indices fit in a `u64`.
+const HILBERT_BITS: u32 = 21;
+
+/// Hilbert cells further than this from the origin on any axis fall back to a fixed grid.
+const HILBERT_OFFSET: i64 = 1 << (HILBERT_BITS - 1);
+
+/// Consecutive Hilbert cells stored in each table, must be a power of 2.
+///
+/// Aligned runs of a power of 2 always form a box, so tables can still be looked up by
+/// their bounds. Runs of 4 are slabs of 2x2x1 cells, oriented along the curve.
+const HILBERT_CELLS_PER_TABLE: u64 = 4;
+
+type Bounds = [(i64, i64); 3];
+
+// region: PartitionStrategy Enum
+/// How regions are grouped into database tables.
+///
+/// Tables are looked up by their stored bounds, so the strategy must not change for an
+/// existing database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionStrategy {
+    /// Cubes with sides of `table_size`
+    FixedGrid,
+    /// Runs of cells with sides of `table_size` along a Hilbert curve, keeping nearby
+    /// cells in the same table more often than a grid
+    Hilbert,
+    /// Every region of a world in a single table
+    SingleTablePerWorld,
+}
+
+impl PartitionStrategy {
+    /// Returns the `[(min, max); 3]` bounds of the table containing `region`.
+    pub(super) fn table_bounds(&self, region: &WorldRegion, table_size: i64) -> Bounds {
+        match self {
+            Self::FixedGrid => grid_bounds(region, table_size),
+            Self::SingleTablePerWorld => [(i64::MIN, i64::MAX); 3],
+            Self::Hilbert => hilbert_bounds(region, table_size)
+                .unwrap_or_else(|| grid_bounds(region, table_size)),
+        }
+    }
+}
+
+impl Default for PartitionStrategy {
+    fn default() -> Self {
+        Self::FixedGrid
+    }
+}
+
+impl FromStr for PartitionStrategy {
+    type Err = ParseStrategyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed-grid" => Ok(Self::FixedGrid),
+            "hilbert" => Ok(Self::Hilbert),
+            "single-table" => Ok(Self::SingleTablePerWorld),
+            _ => Err(ParseStrategyError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: fixed-grid, hilbert, single-table")]
+pub struct ParseStrategyError;
+// endregion
+
+// region: Bounds
+fn grid_bounds(region: &WorldRegion, table_size: i64) -> Bounds {
+    let (x, y, z) = region_to_table_coords((*region.x(), *region.y(), *region.z()), table_size);
+
+    [
+        (x, x + table_size),
+        (y, y + table_size),
+        (z, z + table_size),
+    ]
+}
+
+/// Returns `None` if the region is outside the range covered by the curve.
+fn hilbert_bounds(region: &WorldRegion, table_size: i64) -> Option<Bounds> {
+    let mut cell = [0; 3];
+    for (axis, c) in [*region.x(), *region.y(), *region.z()].iter().enumerate() {
+        let shifted = c.div_euclid(table_size).checked_add(HILBERT_OFFSET)?;
+        cell[axis] = u32::try_from(shifted)
+            .ok()
+            .filter(|c| *c >> HILBERT_BITS == 0)?;
+    }
+
+    // Tables hold an aligned run of cells, which is always a box
+    let start = hilbert_index(cell) & !(HILBERT_CELLS_PER_TABLE - 1);
+    let mut bounds = [(i64::MAX, i64::MIN); 3];
+    for index in start..start + HILBERT_CELLS_PER_TABLE {
+        for (axis, c) in hilbert_cell(index).iter().enumerate() {
+            let min = (i64::from(*c) - HILBERT_OFFSET) * table_size;
+            bounds[axis].0 = bounds[axis].0.min(min);
+            bounds[axis].1 = bounds[axis].1.max(min + table_size);
+        }
+    }
+
+    Some(bounds)
+}
+// endregion
+
+// region: Hilbert Curve
+// Based on "Programming the Hilbert curve" by John Skilling, using the transposed form
+// where the index bits are interleaved across the axes from most significant down.
+
+fn hilbert_index(cell: [u32; 3]) -> u64 {
+    let mut x = cell;
+    let m = 1 << (HILBERT_BITS - 1);
+
+    // Inverse undo
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..3 {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+
+        q >>= 1;
+    }
+
+    // Gray encode
+    for i in 1..3 {
+        x[i] ^= x[i - 1];
+    }
+
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[2] & q != 0 {
+            t ^= q - 1;
+        }
+
+        q >>= 1;
+    }
+
+    for c in &mut x {
+        *c ^= t;
+    }
+
+    // Interleave
+    let mut index = 0;
+    for bit in (0..HILBERT_BITS).rev() {
+        for c in &x {
+            index = (index << 1) | u64::from((c >> bit) & 1);
+        }
+    }
+
+    index
+}
+
+fn hilbert_cell(index: u64) -> [u32; 3] {
+    // De-interleave
+    let mut x = [0u32; 3];
+    for bit in (0..HILBERT_BITS).rev() {
+        for (i, c) in x.iter_mut().enumerate() {
+            let shift = bit * 3 + (2 - i as u32);
+            *c |= (((index >> shift) & 1) as u32) << bit;
+        }
+    }
+
+    // Gray decode
+    let t = x[2] >> 1;
+    for i in (1..3).rev() {
+        x[i] ^= x[i - 1];
+    }
+    x[0] ^= t;
+
+    // Undo excess work
+    let n = 2 << (HILBERT_BITS - 1);
+    let mut q = 2;
+    while q != n {
+        let p = q - 1;
+        for i in (0..3).rev() {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+
+        q <<= 1;
+    }
+
+    x
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hilbert_round_trip() {
+        let start = hilbert_index([HILBERT_OFFSET as u32; 3]);
+        for index in start - 64..start + 64 {
+            let cell = hilbert_cell(index);
+            assert_eq!(hilbert_index(cell), index);
+
+            // Consecutive cells are always neighbours
+            let next = hilbert_cell(index + 1);
+            let distance = cell
+                .iter()
+                .zip(&next)
+                .map(|(a, b)| (i64::from(*a) - i64::from(*b)).abs())
+                .sum::<i64>();
+
+            assert_eq!(distance, 1);
+        }
+    }
+
+    #[test]
+    fn table_bounds() {
+        let strategy = PartitionStrategy::Hilbert;
+        let table_size = 1024;
+
+        for (x, y, z) in [(0, 0, 0), (-2048, 512, 4096), (123_904, -7168, 31_744)] {
+            let region = WorldRegion::from_min("world", x, y, z);
+            let bounds = strategy.table_bounds(&region, table_size);
+
+            // Contains the region and holds exactly the run of cells
+            for (c, (min, max)) in [x, y, z].iter().zip(&bounds) {
+                assert!(min <= c && c < max);
+            }
+
+            let volume = bounds
+                .iter()
+                .map(|(min, max)| (max - min) / table_size)
+                .product::<i64>();
+
+            assert_eq!(volume, HILBERT_CELLS_PER_TABLE as i64);
+        }
+
+        let region = WorldRegion::from_min("world", -16, 0, 16);
+        assert_eq!(
+            PartitionStrategy::FixedGrid.table_bounds(&region, table_size),
+            [(-1024, 0), (0, 1024), (0, 1024)]
+        );
+    }
+}
+// endregion
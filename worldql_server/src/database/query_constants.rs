@@ -1,3 +1,5 @@
+use super::IndexSpec;
+
 // region: Init
 pub(super) const CREATE_SCHEMA_NAVIGATION: &str = "
     CREATE SCHEMA IF NOT EXISTS navigation
@@ -38,6 +40,132 @@ pub(super) const CREATE_REGION_NAVIGATION: &str = "
 ";
 // endregion
 
+// region: Migrations
+/// Held until the migration transaction ends, so concurrent servers apply migrations once.
+pub(super) const QUERY_LOCK_MIGRATIONS: &str = "
+    SELECT pg_advisory_xact_lock(8675309)
+";
+
+pub(super) const CREATE_TABLE_SCHEMA_VERSION: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.schema_version
+    (
+        version    integer PRIMARY KEY,
+        applied_at timestamp NOT NULL DEFAULT NOW()
+    )
+";
+
+pub(super) const QUERY_SCHEMA_VERSION: &str = "
+    SELECT COALESCE(MAX(version), 0) FROM navigation.schema_version
+";
+
+pub(super) const QUERY_INSERT_SCHEMA_VERSION: &str = "
+    INSERT INTO navigation.schema_version (version) VALUES ($1)
+";
+
+pub(super) const MIGRATE_WORLD_NAME_LENGTH: &str = "
+    ALTER TABLE navigation.tables ALTER COLUMN world_name TYPE varchar;
+    ALTER TABLE navigation.regions ALTER COLUMN world_name TYPE varchar
+";
+
+/// Adds the `owner_uuid` column to every existing record table.
+pub(super) const MIGRATE_RECORD_OWNER: &str = "
+    DO $$
+    DECLARE
+        record_table record;
+    BEGIN
+        FOR record_table IN
+            SELECT table_schema, table_name FROM information_schema.columns
+            WHERE table_schema LIKE 'w\\_%' AND column_name = 'uuid'
+        LOOP
+            EXECUTE format(
+                'ALTER TABLE %I.%I ADD COLUMN IF NOT EXISTS owner_uuid uuid',
+                record_table.table_schema,
+                record_table.table_name
+            );
+        END LOOP;
+    END
+    $$
+";
+
+/// Keeps only the latest version of each record in every existing record table, then adds
+/// the unique `uuid` index that upserts conflict on.
+///
+/// Tables created before the index appended every write and deduplicated on read.
+pub(super) const MIGRATE_RECORD_UUID_INDEX: &str = "
+    DO $$
+    DECLARE
+        record_table record;
+        index_name   text;
+    BEGIN
+        FOR record_table IN
+            SELECT table_schema, table_name FROM information_schema.columns
+            WHERE table_schema LIKE 'w\\_%' AND column_name = 'uuid'
+        LOOP
+            EXECUTE format(
+                'DELETE FROM %1$I.%2$I a USING %1$I.%2$I b
+                WHERE a.uuid = b.uuid AND (a.last_modified, a.ctid) < (b.last_modified, b.ctid)',
+                record_table.table_schema,
+                record_table.table_name
+            );
+
+            -- Same names as query_create_world_index and query_create_world_global
+            IF record_table.table_name LIKE 't\\_%' THEN
+                index_name := substr(record_table.table_schema, 3) || '_' ||
+                    substr(record_table.table_name, 3) || '_uuid_uindex';
+            ELSE
+                index_name := record_table.table_name || '_uuid_uindex';
+            END IF;
+
+            EXECUTE format(
+                'CREATE UNIQUE INDEX IF NOT EXISTS %I ON %I.%I (uuid)',
+                index_name,
+                record_table.table_schema,
+                record_table.table_name
+            );
+        END LOOP;
+    END
+    $$
+";
+
+/// Marks records stored exactly on a negative region boundary as needing to be re-homed,
+/// see [`DatabaseClient::rehome_boundary_records`](super::DatabaseClient::rehome_boundary_records).
+///
+/// Re-homing moves records between tables, so can't be done in SQL alone. The marker is
+/// dropped once every record has been moved.
+pub(super) const MIGRATE_REHOME_BOUNDARY_RECORDS: &str = "
+    CREATE TABLE IF NOT EXISTS navigation.rehome_pending ()
+";
+
+pub(super) const QUERY_REHOME_PENDING: &str = "
+    SELECT to_regclass('navigation.rehome_pending') IS NOT NULL
+";
+
+pub(super) const FINISH_REHOME: &str = "
+    DROP TABLE IF EXISTS navigation.rehome_pending
+";
+
+pub(super) const QUERY_LOOKUP_ALL_TABLE_SUFFIXES: &str = "
+    SELECT world_name, table_suffix FROM navigation.tables
+";
+
+/// Records outside the bounds of the region they are stored in.
+///
+/// Regions used to round negative boundaries down, so a record exactly on one was stored
+/// in the region below it.
+pub(super) fn query_select_misplaced_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT r.uuid, r.x, r.y, r.z FROM {} r
+        JOIN navigation.regions n ON n.region_id = r.region_id
+        WHERE r.x >= n.max_x OR r.y >= n.max_y OR r.z >= n.max_z
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+// endregion
+
 // region: Lookups
 pub(super) const QUERY_LOOKUP_TABLE_SUFFIX: &str = "
     SELECT table_suffix FROM navigation.tables
@@ -53,6 +181,20 @@ pub(super) const QUERY_INSERT_TABLE_SUFFIX: &str = "
     RETURNING table_suffix
 ";
 
+pub(super) const QUERY_LOOKUP_WORLD_TABLE_SUFFIXES: &str = "
+    SELECT table_suffix FROM navigation.tables
+    WHERE world_name = $1
+";
+
+/// Tables of a world overlapping the box from `($2, $4, $6)` to `($3, $5, $7)` inclusive.
+pub(super) const QUERY_LOOKUP_TABLE_SUFFIXES_IN_BOX: &str = "
+    SELECT table_suffix FROM navigation.tables
+    WHERE world_name = $1 AND
+    max_x > $2::float8 AND min_x <= $3::float8 AND
+    max_y > $4::float8 AND min_y <= $5::float8 AND
+    max_z > $6::float8 AND min_z <= $7::float8
+";
+
 pub(super) const QUERY_LOOKUP_REGION_ID: &str = "
     SELECT region_id FROM navigation.regions
     WHERE world_name = $1 AND
@@ -66,6 +208,84 @@ pub(super) const QUERY_INSERT_REGION_ID: &str = "
     VALUES ($1, $2, $3, $4, $5, $6, $7)
     RETURNING region_id
 ";
+
+/// Lookup IDs for many region minimums at once, `idx` is the 1-based position in the input.
+pub(super) const QUERY_LOOKUP_IDS_BATCH: &str = "
+    SELECT q.idx, t.table_suffix, r.region_id
+    FROM unnest($2::bigint[], $3::bigint[], $4::bigint[]) WITH ORDINALITY AS q (x, y, z, idx)
+    LEFT JOIN navigation.tables t ON
+    t.world_name = $1 AND
+    q.x >= t.min_x AND q.x < t.max_x AND
+    q.y >= t.min_y AND q.y < t.max_y AND
+    q.z >= t.min_z AND q.z < t.max_z
+    LEFT JOIN navigation.regions r ON
+    r.world_name = $1 AND
+    q.x >= r.min_x AND q.x < r.max_x AND
+    q.y >= r.min_y AND q.y < r.max_y AND
+    q.z >= r.min_z AND q.z < r.max_z
+";
+
+pub(super) const QUERY_LOOKUP_WORLD_REGIONS: &str = "
+    SELECT r.min_x, r.min_y, r.min_z, r.region_id, t.table_suffix
+    FROM navigation.regions r
+    LEFT JOIN navigation.tables t ON
+    t.world_name = r.world_name AND
+    r.min_x >= t.min_x AND r.min_x < t.max_x AND
+    r.min_y >= t.min_y AND r.min_y < t.max_y AND
+    r.min_z >= t.min_z AND r.min_z < t.max_z
+    WHERE r.world_name = $1
+    ORDER BY r.region_id DESC
+    LIMIT $2
+";
+// endregion
+
+// region: World Stats
+/// Tables in a world's schema, the name is resolved like an unquoted identifier.
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub(super) const QUERY_WORLD_TABLE_SIZES: &str = "
+    SELECT c.relname::text AS table_name, pg_total_relation_size(c.oid) AS total_bytes
+    FROM pg_class c
+    WHERE c.relnamespace = to_regnamespace($1) AND c.relkind = 'r'
+";
+
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub(super) fn query_count_world_records(world_name: &str, tables: &[String]) -> String {
+    let counts = tables
+        .iter()
+        .map(|table| {
+            format!(
+                "SELECT count(*) AS n FROM w_{}.\"{}\"",
+                world_name,
+                table.replace('"', "\"\"")
+            )
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "SELECT COALESCE(SUM(n), 0)::bigint AS record_count FROM ({}) counts",
+        counts.join(" UNION ALL ")
+    )
+}
+// endregion
+
+// region: Drop World
+/// Navigation rows are matched like unquoted identifiers, as every such name shares the schema.
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub(super) const QUERY_DELETE_WORLD_TABLE_SUFFIXES: &str = "
+    DELETE FROM navigation.tables
+    WHERE lower(world_name) = lower($1)
+";
+
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub(super) const QUERY_DELETE_WORLD_REGION_IDS: &str = "
+    DELETE FROM navigation.regions
+    WHERE lower(world_name) = lower($1)
+";
+
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub(super) fn query_drop_world_schema(world_name: &str) -> String {
+    format!("DROP SCHEMA IF EXISTS w_{} CASCADE", world_name)
+}
 // endregion
 
 // region: Create World Table
@@ -81,15 +301,16 @@ pub(super) fn query_create_world_schema(world_name: &str) -> String {
 }
 
 #[inline]
-fn table_name(world_name: &str, suffix: i32) -> String {
+pub(super) fn table_name(world_name: &str, suffix: i32) -> String {
     format!("w_{0}.t_{1}", world_name, suffix)
 }
 
 pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
-        CREATE TABLE {}
+        CREATE TABLE IF NOT EXISTS {}
         (
+            created_at    timestamp NOT NULL DEFAULT NOW(),
             last_modified timestamp NOT NULL DEFAULT NOW(),
             region_id     integer NOT NULL,
             x             double precision,
@@ -97,7 +318,8 @@ pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
             z             double precision,
             uuid          uuid NOT NULL,
             data          varchar,
-            flex          bytea
+            flex          bytea,
+            owner_uuid    uuid
         )
         ",
         table_name(world_name, suffix)
@@ -106,24 +328,193 @@ pub(super) fn query_create_world(world_name: &str, suffix: i32) -> String {
     query
 }
 
-pub(super) fn query_create_world_index(world_name: &str, suffix: i32) -> String {
+pub(super) fn query_create_world_index(world_name: &str, suffix: i32, spec: IndexSpec) -> String {
+    let lookup_index = match spec {
+        IndexSpec::Region => format!(
+            "CREATE INDEX IF NOT EXISTS {0}_{1}_region_id_index ON {2} USING btree (region_id);",
+            world_name,
+            suffix,
+            table_name(world_name, suffix)
+        ),
+
+        IndexSpec::RegionPosition => format!(
+            "CREATE INDEX IF NOT EXISTS {0}_{1}_region_position_index ON {2} USING btree (region_id, x, y, z);",
+            world_name,
+            suffix,
+            table_name(world_name, suffix)
+        ),
+
+        IndexSpec::Gist => format!(
+            "
+            CREATE EXTENSION IF NOT EXISTS cube;
+
+            CREATE INDEX IF NOT EXISTS {0}_{1}_region_id_index ON {2} USING btree (region_id);
+
+            CREATE INDEX IF NOT EXISTS {0}_{1}_position_gist_index
+            ON {2} USING gist (cube(ARRAY[x, y, z]));
+            ",
+            world_name,
+            suffix,
+            table_name(world_name, suffix)
+        ),
+    };
+
     let query = format!(
         "
-        CREATE INDEX {0}_{1}_region_id_index
-        ON {2} USING btree (region_id);
+        {3}
+
+        CREATE UNIQUE INDEX IF NOT EXISTS {0}_{1}_uuid_uindex
+        ON {2} (uuid);
+
+        {4};
         ",
         world_name,
         suffix,
-        table_name(world_name, suffix)
+        table_name(world_name, suffix),
+        lookup_index,
+        query_set_table_version(&table_name(world_name, suffix))
     );
 
     query
 }
+
+#[inline]
+pub(super) fn global_table_name(world_name: &str) -> String {
+    format!("w_{0}.{0}_global", world_name)
+}
+
+pub(super) fn query_create_world_global(world_name: &str) -> String {
+    let query = format!(
+        "
+        CREATE TABLE IF NOT EXISTS {1}
+        (
+            created_at    timestamp NOT NULL DEFAULT NOW(),
+            last_modified timestamp NOT NULL DEFAULT NOW(),
+            uuid          uuid NOT NULL,
+            data          varchar,
+            flex          bytea,
+            owner_uuid    uuid
+        );
+
+        CREATE UNIQUE INDEX IF NOT EXISTS {0}_global_uuid_uindex
+        ON {1} (uuid);
+
+        {2};
+        ",
+        world_name,
+        global_table_name(world_name),
+        query_set_table_version(&global_table_name(world_name))
+    );
+
+    query
+}
+
+/// Layout version of the record tables created by [`query_create_world`] and
+/// [`query_create_world_global`], stored in the table's comment.
+///
+/// Tables without a version were created before it was recorded, and are version 1.
+pub(super) const RECORD_TABLE_VERSION: i32 = 2;
+
+/// Prefix of the version in a record table's comment, see [`RECORD_TABLE_VERSION`].
+pub(super) const TABLE_VERSION_PREFIX: &str = "worldql_table_version=";
+
+/// Returns the comment of the table named by `$1`.
+pub(super) const QUERY_TABLE_COMMENT: &str = "
+    SELECT obj_description($1::text::regclass, 'pg_class')
+";
+
+fn query_set_table_version(table: &str) -> String {
+    format!(
+        "COMMENT ON TABLE {} IS '{}{}'",
+        table, TABLE_VERSION_PREFIX, RECORD_TABLE_VERSION
+    )
+}
+
+/// Columns added since the first table layout, as `ADD COLUMN` clauses.
+///
+/// Only nullable or defaulted columns can be added to tables that already hold records.
+const UPGRADE_COLUMNS: &str = "
+    ADD COLUMN IF NOT EXISTS created_at    timestamp NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_modified timestamp NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS data          varchar,
+    ADD COLUMN IF NOT EXISTS flex          bytea,
+    ADD COLUMN IF NOT EXISTS owner_uuid    uuid
+";
+
+/// Bring a record table up to [`RECORD_TABLE_VERSION`].
+///
+/// Older tables can hold several versions of a record, all but the latest are deleted so
+/// the unique `uuid` index that upserts conflict on can be created.
+fn query_upgrade_table(table: &str, uuid_index: &str) -> String {
+    format!(
+        "
+        ALTER TABLE {0} {1};
+
+        DELETE FROM {0} a USING {0} b
+        WHERE a.uuid = b.uuid AND (a.last_modified, a.ctid) < (b.last_modified, b.ctid);
+
+        CREATE UNIQUE INDEX IF NOT EXISTS {2} ON {0} (uuid);
+
+        {3};
+        ",
+        table,
+        UPGRADE_COLUMNS,
+        uuid_index,
+        query_set_table_version(table)
+    )
+}
+
+/// Upgrade a region table created by an older version, see [`query_upgrade_table`].
+pub(super) fn query_upgrade_world(world_name: &str, suffix: i32) -> String {
+    query_upgrade_table(
+        &table_name(world_name, suffix),
+        &format!("{}_{}_uuid_uindex", world_name, suffix),
+    )
+}
+
+/// Upgrade a global table created by an older version, see [`query_upgrade_table`].
+pub(super) fn query_upgrade_world_global(world_name: &str) -> String {
+    query_upgrade_table(
+        &global_table_name(world_name),
+        &format!("{}_global_uuid_uindex", world_name),
+    )
+}
 // endregion
 
 // region: Record Manipulation
-pub(super) fn query_insert_record(world_name: &str, suffix: i32) -> String {
-    let query = format!(
+const UPSERT_CONFLICT_CLAUSE: &str = "
+    ON CONFLICT (uuid) DO UPDATE SET
+    last_modified = NOW(),
+    region_id = EXCLUDED.region_id,
+    x = EXCLUDED.x,
+    y = EXCLUDED.y,
+    z = EXCLUDED.z,
+    data = EXCLUDED.data,
+    flex = EXCLUDED.flex
+";
+
+const GLOBAL_UPSERT_CONFLICT_CLAUSE: &str = "
+    ON CONFLICT (uuid) DO UPDATE SET
+    last_modified = NOW(),
+    data = EXCLUDED.data,
+    flex = EXCLUDED.flex
+";
+
+/// Plain inserts skip records whose [`Uuid`](uuid::Uuid) is already stored, rather than
+/// failing every other record in the statement.
+const INSERT_CONFLICT_CLAUSE: &str = "
+    ON CONFLICT (uuid) DO NOTHING
+";
+
+/// Appended to an upsert conflict clause so only unowned records, or those owned by the
+/// writer, are overwritten. The existing row is aliased as `existing`.
+const OWNER_CONFLICT_GUARD: &str = "
+    WHERE existing.owner_uuid IS NULL OR existing.owner_uuid = EXCLUDED.owner_uuid
+";
+
+#[cfg(test)]
+pub(super) fn query_insert_record(world_name: &str, suffix: i32, upsert: bool) -> String {
+    let mut query = format!(
         "
         INSERT INTO {}
         (region_id, x, y, z, uuid, data, flex)
@@ -132,24 +523,40 @@ pub(super) fn query_insert_record(world_name: &str, suffix: i32) -> String {
         table_name(world_name, suffix)
     );
 
+    query += match upsert {
+        true => UPSERT_CONFLICT_CLAUSE,
+        false => INSERT_CONFLICT_CLAUSE,
+    };
+
     query
 }
 
-pub(super) fn query_insert_record_many(world_name: &str, suffix: i32, count: usize) -> String {
+/// Build a bulk insertion query, if `guarded` upserts only overwrite records the writer
+/// owns, see [`OWNER_CONFLICT_GUARD`].
+///
+/// Plain inserts return the `uuid` of every row written, so skipped records can be
+/// reported.
+pub(super) fn query_insert_record_many(
+    world_name: &str,
+    suffix: i32,
+    count: usize,
+    upsert: bool,
+    guarded: bool,
+) -> String {
     let mut query = format!(
         "
-        INSERT INTO {}
-        (region_id, x, y, z, uuid, data, flex)
+        INSERT INTO {} AS existing
+        (region_id, x, y, z, uuid, data, flex, owner_uuid)
         VALUES",
         table_name(world_name, suffix)
     );
 
     for i in 0..count {
-        let i = i * 7;
+        let i = i * 8;
         let prefix = if i == 0 { " " } else { ", " };
 
         query += &format!(
-            "{}(${}, ${}, ${}, ${}, ${}, ${}, ${})",
+            "{}(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
             prefix,
             i + 1,
             i + 2,
@@ -157,10 +564,120 @@ pub(super) fn query_insert_record_many(world_name: &str, suffix: i32, count: usi
             i + 4,
             i + 5,
             i + 6,
-            i + 7
+            i + 7,
+            i + 8
         );
     }
 
+    if upsert {
+        query += UPSERT_CONFLICT_CLAUSE;
+        if guarded {
+            query += OWNER_CONFLICT_GUARD;
+        }
+    } else {
+        query += INSERT_CONFLICT_CLAUSE;
+        query += "RETURNING uuid";
+    }
+
+    query
+}
+
+pub(super) fn query_insert_global_record(
+    world_name: &str,
+    count: usize,
+    upsert: bool,
+    guarded: bool,
+) -> String {
+    let mut query = format!(
+        "
+        INSERT INTO {} AS existing
+        (uuid, data, flex, owner_uuid)
+        VALUES",
+        global_table_name(world_name)
+    );
+
+    for i in 0..count {
+        let i = i * 4;
+        let prefix = if i == 0 { " " } else { ", " };
+
+        query += &format!("{}(${}, ${}, ${}, ${})", prefix, i + 1, i + 2, i + 3, i + 4);
+    }
+
+    if upsert {
+        query += GLOBAL_UPSERT_CONFLICT_CLAUSE;
+        if guarded {
+            query += OWNER_CONFLICT_GUARD;
+        }
+    } else {
+        query += INSERT_CONFLICT_CLAUSE;
+        query += "RETURNING uuid";
+    }
+
+    query
+}
+
+/// Returns the records out of `$1` that are owned by someone other than `$2`.
+pub(super) fn query_select_denied(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT uuid FROM {} WHERE
+        uuid = ANY($1) AND owner_uuid <> $2
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_select_global_denied(world_name: &str) -> String {
+    let query = format!(
+        "
+        SELECT uuid FROM {} WHERE
+        uuid = ANY($1) AND owner_uuid <> $2
+        ",
+        global_table_name(world_name)
+    );
+
+    query
+}
+
+/// Parameter is an array of record UUIDs, matched through the table's unique `uuid` index.
+#[cfg(test)]
+pub(super) fn query_select_records_by_uuid(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex
+        FROM {} WHERE uuid = ANY($1)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+#[cfg(test)]
+pub(super) fn query_select_global_records_by_uuid(world_name: &str) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, uuid, data, flex
+        FROM {} WHERE uuid = ANY($1)
+        ",
+        global_table_name(world_name)
+    );
+
+    query
+}
+
+/// Parameter is an optional timestamp, only records modified after it are selected.
+pub(super) fn query_select_global_records(world_name: &str) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, uuid, data, flex
+        FROM {} WHERE $1::timestamp IS NULL OR last_modified > $1
+        ",
+        global_table_name(world_name)
+    );
+
     query
 }
 
@@ -176,6 +693,62 @@ pub(super) fn query_select_records(world_name: &str, suffix: i32) -> String {
     query
 }
 
+#[cfg(test)]
+pub(super) fn query_select_records_paged(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "{} ORDER BY uuid LIMIT $2 OFFSET $3",
+        query_select_records(world_name, suffix).trim_end()
+    );
+
+    query
+}
+
+#[cfg(test)]
+pub(super) fn query_select_records_in_radius(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex
+        FROM {} WHERE region_id = $1 AND
+        (x - $2) ^ 2 + (y - $3) ^ 2 + (z - $4) ^ 2 <= $5
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Parameters are the inclusive `(min, max)` bounds of each axis in turn.
+pub(super) fn query_select_records_in_box(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex
+        FROM {} WHERE
+        x BETWEEN $1 AND $2 AND
+        y BETWEEN $3 AND $4 AND
+        z BETWEEN $5 AND $6
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// Parameters are `region_id`, the `(x, y, z)` center and the maximum number of records.
+#[cfg(test)]
+pub(super) fn query_select_nearest_records(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex
+        FROM {} WHERE region_id = $1
+        ORDER BY (x - $2) ^ 2 + (y - $3) ^ 2 + (z - $4) ^ 2
+        LIMIT $5
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
 pub(super) fn query_select_records_after(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
@@ -188,6 +761,23 @@ pub(super) fn query_select_records_after(world_name: &str, suffix: i32) -> Strin
     query
 }
 
+/// Parameters are `region_id`, whether to include NULL `flex` values, and the `flex` prefix.
+#[cfg(test)]
+pub(super) fn query_select_records_filtered(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        SELECT last_modified, x, y, z, uuid, data, flex
+        FROM {} WHERE region_id = $1 AND (
+            (flex IS NULL AND $2) OR
+            substring(flex from 1 for octet_length($3::bytea)) = $3::bytea
+        )
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
 pub(super) fn query_delete_record(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
@@ -200,15 +790,102 @@ pub(super) fn query_delete_record(world_name: &str, suffix: i32) -> String {
     query
 }
 
-pub(super) fn query_delete_duplictes(world_name: &str, suffix: i32) -> String {
+/// Like [`query_delete_record`], but leaves records owned by someone other than `$3`.
+pub(super) fn query_delete_owned_record(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        region_id = $1 AND uuid = $2 AND (owner_uuid IS NULL OR owner_uuid = $3)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+/// If `guarded`, records owned by someone other than `$6` are left unchanged.
+pub(super) fn query_update_record_position(world_name: &str, suffix: i32, guarded: bool) -> String {
+    let mut query = format!(
+        "
+        UPDATE {} SET
+        region_id = $1, x = $2, y = $3, z = $4, last_modified = NOW()
+        WHERE uuid = $5
+        ",
+        table_name(world_name, suffix)
+    );
+
+    if guarded {
+        query += "AND (owner_uuid IS NULL OR owner_uuid = $6)";
+    }
+
+    query
+}
+
+pub(super) fn query_take_record(world_name: &str, suffix: i32) -> String {
     let query = format!(
         "
         DELETE FROM {} WHERE
-        uuid = $1 AND last_modified < $2
+        uuid = $1
+        RETURNING created_at, data, flex, owner_uuid
         ",
         table_name(world_name, suffix)
     );
 
     query
 }
+
+/// Insert a record taken by [`query_take_record`] into another table, keeping its creation
+/// time and owner.
+pub(super) fn query_insert_moved_record(world_name: &str, suffix: i32) -> String {
+    let mut query = format!(
+        "
+        INSERT INTO {}
+        (created_at, region_id, x, y, z, uuid, data, flex, owner_uuid)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query += INSERT_CONFLICT_CLAUSE;
+    query
+}
+
+#[cfg(test)]
+pub(super) fn query_delete_records_by_uuid(world_name: &str, suffix: i32) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        uuid = ANY($1)
+        ",
+        table_name(world_name, suffix)
+    );
+
+    query
+}
+
+pub(super) fn query_delete_global_records_by_uuid(world_name: &str) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        uuid = ANY($1)
+        ",
+        global_table_name(world_name)
+    );
+
+    query
+}
+
+/// Like [`query_delete_global_records_by_uuid`], but leaves records owned by someone
+/// other than `$2`.
+pub(super) fn query_delete_owned_global_records(world_name: &str) -> String {
+    let query = format!(
+        "
+        DELETE FROM {} WHERE
+        uuid = ANY($1) AND (owner_uuid IS NULL OR owner_uuid = $2)
+        ",
+        global_table_name(world_name)
+    );
+
+    query
+}
 // endregion
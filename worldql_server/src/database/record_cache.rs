@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use super::{DatabaseClient, RegionRecords};
+
+type CachedRecords = RegionRecords;
+
+// region: RecordCache Struct
+/// Short-lived cache of region reads, see [`DatabaseClient::set_record_cache`].
+///
+/// Entries are keyed by world name as Postgres resolves the unquoted schema name, so
+/// world names that share tables also share entries.
+pub(super) struct RecordCache {
+    ttl: Duration,
+    entries: LruCache<(String, i32), CacheEntry>,
+}
+
+struct CacheEntry {
+    table_suffix: i32,
+    cached_at: Instant,
+    records: CachedRecords,
+}
+
+impl RecordCache {
+    pub(super) fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            entries: LruCache::new(capacity),
+        }
+    }
+
+    /// Returns the records cached for a region, if they haven't expired.
+    pub(super) fn get(&mut self, world_name: &str, region_id: i32) -> Option<CachedRecords> {
+        let key = (schema_key(world_name), region_id);
+        let expired = match self.entries.get(&key) {
+            None => return None,
+            Some(entry) => entry.cached_at.elapsed() >= self.ttl,
+        };
+
+        if expired {
+            self.entries.pop(&key);
+            return None;
+        }
+
+        self.entries.get(&key).map(|entry| entry.records.clone())
+    }
+
+    pub(super) fn put(
+        &mut self,
+        world_name: &str,
+        table_suffix: i32,
+        region_id: i32,
+        records: CachedRecords,
+    ) {
+        let entry = CacheEntry {
+            table_suffix,
+            cached_at: Instant::now(),
+            records,
+        };
+
+        self.entries.put((schema_key(world_name), region_id), entry);
+    }
+
+    /// Remove every region stored in a table, for writes that may have changed it.
+    pub(super) fn invalidate_table(&mut self, world_name: &str, table_suffix: i32) {
+        let world_name = schema_key(world_name);
+        self.invalidate_where(|(world, _), entry| {
+            *world == world_name && entry.table_suffix == table_suffix
+        });
+    }
+
+    /// Remove every region of a world, for writes that may have changed any table.
+    pub(super) fn invalidate_world(&mut self, world_name: &str) {
+        let world_name = schema_key(world_name);
+        self.invalidate_where(|(world, _), _| *world == world_name);
+    }
+
+    /// Remove every region.
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn invalidate_where(&mut self, predicate: impl Fn(&(String, i32), &CacheEntry) -> bool) {
+        let keys = self
+            .entries
+            .iter()
+            .filter(|(key, entry)| predicate(key, entry))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            self.entries.pop(&key);
+        }
+    }
+}
+
+/// Postgres folds unquoted identifiers to lowercase, but only ASCII letters.
+#[inline]
+fn schema_key(world_name: &str) -> String {
+    world_name.to_ascii_lowercase()
+}
+// endregion
+
+impl DatabaseClient {
+    /// Cache the records of up to `capacity` regions read with
+    /// [`Self::get_records_in_region`] for `ttl`.
+    ///
+    /// Writes made through this client invalidate the regions they may have changed.
+    /// Writes made by other clients, including other servers sharing the database, are
+    /// not seen until entries expire, so keep `ttl` short.
+    pub fn set_record_cache(&mut self, ttl: Duration, capacity: usize) {
+        self.record_cache = Some(RecordCache::new(ttl, capacity));
+    }
+
+    #[inline]
+    pub(super) fn invalidate_cached_table(&mut self, world_name: &str, table_suffix: i32) {
+        if let Some(cache) = &mut self.record_cache {
+            cache.invalidate_table(world_name, table_suffix);
+        }
+    }
+
+    #[inline]
+    pub(super) fn invalidate_cached_world(&mut self, world_name: &str) {
+        if let Some(cache) = &mut self.record_cache {
+            cache.invalidate_world(world_name);
+        }
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::structures::Record;
+
+    fn records(data: &str) -> CachedRecords {
+        let record = Record {
+            uuid: Uuid::new_v4(),
+            world_name: "world".into(),
+            data: Some(data.into()),
+            ..Default::default()
+        };
+
+        RegionRecords::Records(vec![(
+            NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+            record,
+        )])
+    }
+
+    #[test]
+    fn invalidation() {
+        let mut cache = RecordCache::new(Duration::from_secs(60), 16);
+        cache.put("World", 1, 10, records("a"));
+        cache.put("world", 1, 11, records("b"));
+        cache.put("world", 2, 20, records("c"));
+        cache.put("other", 1, 30, records("d"));
+
+        // Names resolving to the same schema share entries
+        assert!(cache.get("WORLD", 10).is_some());
+
+        cache.invalidate_table("world", 1);
+        assert!(cache.get("world", 10).is_none());
+        assert!(cache.get("world", 11).is_none());
+        assert!(cache.get("world", 20).is_some());
+        assert!(cache.get("other", 30).is_some());
+
+        cache.invalidate_world("World");
+        assert!(cache.get("world", 20).is_none());
+        assert!(cache.get("other", 30).is_some());
+    }
+
+    #[test]
+    fn expiry() {
+        let mut cache = RecordCache::new(Duration::from_secs(0), 16);
+        cache.put("world", 1, 10, records("a"));
+
+        assert!(cache.get("world", 10).is_none());
+    }
+}
+// endregion
@@ -0,0 +1,41 @@
+use chrono::NaiveDateTime;
+
+use crate::structures::Record;
+
+// region: RegionRecords Enum
+/// Records read from a region, see [`super::DatabaseClient::get_region_records`].
+#[derive(Debug, Clone)]
+pub enum RegionRecords {
+    /// The region's table doesn't exist, so nothing has ever been written to the region
+    NeverWritten,
+    /// The region's table exists, but has no matching records
+    Empty,
+    /// Matching records, with the time each was last modified
+    Records(Vec<(NaiveDateTime, Record)>),
+}
+
+impl RegionRecords {
+    /// Wraps rows read from an existing table.
+    pub fn from_rows(records: Vec<(NaiveDateTime, Record)>) -> Self {
+        if records.is_empty() {
+            Self::Empty
+        } else {
+            Self::Records(records)
+        }
+    }
+
+    #[inline]
+    pub fn is_never_written(&self) -> bool {
+        matches!(self, Self::NeverWritten)
+    }
+
+    /// Returns the records, which are empty unless this is [`RegionRecords::Records`].
+    #[cfg(test)]
+    pub fn into_records(self) -> Vec<(NaiveDateTime, Record)> {
+        match self {
+            Self::NeverWritten | Self::Empty => vec![],
+            Self::Records(records) => records,
+        }
+    }
+}
+// endregion
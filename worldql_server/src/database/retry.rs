@@ -0,0 +1,106 @@
+use std::error::Error as StdError;
+use std::time::Duration;
+
+use rand::Rng;
+use tokio_postgres::error::SqlState;
+
+// region: RetryPolicy Struct
+/// Controls how [`super::DatabaseClient`] retries queries that fail with transient errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a new policy.
+    ///
+    /// `max_attempts` includes the first attempt, a value of 0 is treated as 1.
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            base_delay,
+        }
+    }
+
+    #[inline]
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the delay before retrying after the given zero-based failed `attempt`.
+    ///
+    /// The delay doubles every attempt, with up to one `base_delay` of random jitter added.
+    pub(super) fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(attempt));
+
+        let jitter = if self.base_delay.is_zero() {
+            Duration::ZERO
+        } else {
+            rand::thread_rng().gen_range(Duration::ZERO..self.base_delay)
+        };
+
+        exponential.saturating_add(jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(50))
+    }
+}
+// endregion
+
+// region: Transient Errors
+const TRANSIENT_STATES: [SqlState; 6] = [
+    SqlState::T_R_SERIALIZATION_FAILURE,
+    SqlState::T_R_DEADLOCK_DETECTED,
+    SqlState::TOO_MANY_CONNECTIONS,
+    SqlState::CONNECTION_EXCEPTION,
+    SqlState::CONNECTION_FAILURE,
+    SqlState::CANNOT_CONNECT_NOW,
+];
+
+/// Returns `true` if the query that caused `error` is worth retrying.
+pub(super) fn is_transient(error: &tokio_postgres::Error) -> bool {
+    if let Some(db_error) = error.as_db_error() {
+        return TRANSIENT_STATES.contains(db_error.code());
+    }
+
+    // Retrying on a closed connection will never succeed
+    if error.is_closed() {
+        return false;
+    }
+
+    // Connection resets surface as IO errors
+    matches!(error.source(), Some(source) if source.is::<std::io::Error>())
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let base_delay = Duration::from_millis(10);
+        let policy = RetryPolicy::new(5, base_delay);
+
+        for attempt in 0..5 {
+            let min = base_delay * 2_u32.pow(attempt);
+            let delay = policy.backoff(attempt);
+
+            assert!(delay >= min);
+            assert!(delay < min + base_delay);
+        }
+
+        // Zero delay never sleeps
+        let policy = RetryPolicy::new(0, Duration::ZERO);
+        assert_eq!(policy.max_attempts(), 1);
+        assert_eq!(policy.backoff(3), Duration::ZERO);
+    }
+}
+// endregion
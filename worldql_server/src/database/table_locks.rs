@@ -0,0 +1,131 @@
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+use ahash::AHashMap;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+
+/// Entries are only pruned once the map holds at least this many.
+const MIN_PRUNE_LEN: usize = 64;
+
+type TableKey = (String, i32);
+
+// region: TableLocks Struct
+/// Per-table locks held while a table is being created, shared by every
+/// [`super::DatabaseClient`] that writes to the same database.
+///
+/// Region lookups allocate a table suffix before its table exists, so a read on another
+/// connection can find the suffix while the table is still being created. Readers that
+/// find no table wait for any creation in progress before trusting that result.
+///
+/// Only weak references are stored, so locks are freed once no writer or reader holds
+/// them, and dead entries are pruned whenever the map doubles in size.
+#[derive(Debug, Default)]
+pub struct TableLocks {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    locks: AHashMap<TableKey, Weak<RwLock<()>>>,
+    prune_at: usize,
+}
+
+impl TableLocks {
+    /// Lock the table `table_suffix` of `world_name` for creation.
+    ///
+    /// Tables created inside a transaction only become visible on commit, so the guard
+    /// should be held until then.
+    pub async fn lock_create(
+        &self,
+        world_name: &str,
+        table_suffix: i32,
+    ) -> OwnedRwLockWriteGuard<()> {
+        self.get_or_insert(world_name, table_suffix)
+            .write_owned()
+            .await
+    }
+
+    /// Wait for the creation of the table `table_suffix` of `world_name` to finish.
+    ///
+    /// Returns `true` if a creation may have been in progress, in which case the table
+    /// should be queried again.
+    pub async fn wait_created(&self, world_name: &str, table_suffix: i32) -> bool {
+        let lock = {
+            let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+            let key = (world_name.to_string(), table_suffix);
+            inner.locks.get(&key).and_then(Weak::upgrade)
+        };
+
+        match lock {
+            None => false,
+            Some(lock) => {
+                let _ = lock.read().await;
+                true
+            }
+        }
+    }
+
+    fn get_or_insert(&self, world_name: &str, table_suffix: i32) -> Arc<RwLock<()>> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (world_name.to_string(), table_suffix);
+        if let Some(lock) = inner.locks.get(&key).and_then(Weak::upgrade) {
+            return lock;
+        }
+
+        let lock = Arc::new(RwLock::new(()));
+        inner.locks.insert(key, Arc::downgrade(&lock));
+
+        if inner.locks.len() >= inner.prune_at.max(MIN_PRUNE_LEN) {
+            inner.locks.retain(|_, lock| lock.strong_count() > 0);
+            inner.prune_at = inner.locks.len() * 2;
+        }
+
+        lock
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.locks.len()
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn readers_wait_for_creation() {
+        let locks = Arc::new(TableLocks::default());
+        assert!(!locks.wait_created("world", 1).await);
+
+        let guard = locks.lock_create("world", 1).await;
+        let mut reader = tokio::spawn({
+            let locks = locks.clone();
+            async move { locks.wait_created("world", 1).await }
+        });
+
+        let timeout = tokio::time::timeout(Duration::from_millis(10), &mut reader).await;
+        assert!(timeout.is_err());
+
+        drop(guard);
+        assert!(reader.await.unwrap());
+
+        // Unused locks are freed
+        assert!(!locks.wait_created("world", 1).await);
+    }
+
+    #[tokio::test]
+    async fn prunes_unused_locks() {
+        let locks = TableLocks::default();
+        for table_suffix in 0..1000 {
+            drop(locks.lock_create("world", table_suffix).await);
+        }
+
+        assert!(locks.len() < MIN_PRUNE_LEN);
+    }
+}
+// endregion
@@ -0,0 +1,116 @@
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+use super::client::DatabaseError;
+use super::{
+    query_drop_world_schema, DatabaseClient, QUERY_DELETE_WORLD_REGION_IDS,
+    QUERY_DELETE_WORLD_TABLE_SUFFIXES, QUERY_WORLD_TABLE_SIZES,
+};
+use crate::metrics::DB_DURATION;
+
+/// Number of dropped worlds a client can fall behind by before it forgets every world.
+const WORLD_DROPS_CAPACITY: usize = 64;
+
+/// Worlds dropped by any client, shared so every client forgets its cached lookups for them.
+///
+/// Each clone receives the worlds dropped after it was made.
+pub struct WorldDrops {
+    tx: Sender<String>,
+    rx: Receiver<String>,
+}
+
+impl Default for WorldDrops {
+    fn default() -> Self {
+        let (tx, rx) = broadcast::channel(WORLD_DROPS_CAPACITY);
+        Self { tx, rx }
+    }
+}
+
+impl Clone for WorldDrops {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            rx: self.tx.subscribe(),
+        }
+    }
+}
+
+impl DatabaseClient {
+    /// Delete every record of a world, returning how many tables were dropped.
+    ///
+    /// The world's schema is dropped with all of its tables, including the global table,
+    /// and its tables and regions are removed from the navigation tables. Names that only
+    /// differ in ASCII case share a schema, so they are all dropped.
+    ///
+    /// Clients sharing this client's [`WorldDrops`] forget the world the next time they call
+    /// [`Self::forget_dropped_worlds`], see [`Self::with_world_drops`].
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub async fn drop_world(&mut self, world_name: &str) -> Result<u32, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("drop_world");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        let schema = format!("w_{}", world_name);
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await?;
+        let tables = transaction
+            .query(QUERY_WORLD_TABLE_SIZES, &[&schema])
+            .await?
+            .len();
+
+        transaction
+            .batch_execute(&query_drop_world_schema(&world_name))
+            .await?;
+        transaction
+            .execute(QUERY_DELETE_WORLD_TABLE_SUFFIXES, &[&world_name])
+            .await?;
+        transaction
+            .execute(QUERY_DELETE_WORLD_REGION_IDS, &[&world_name])
+            .await?;
+        transaction.commit().await?;
+
+        self.forget_world(&world_name);
+
+        // This client always holds a receiver, so sending never fails
+        let _ = self.world_drops.tx.send(world_name);
+        Ok(u32::try_from(tables).unwrap_or(u32::MAX))
+    }
+
+    /// Forget the cached lookups and records of worlds dropped by other clients.
+    pub fn forget_dropped_worlds(&mut self) {
+        loop {
+            match self.world_drops.rx.try_recv() {
+                Ok(world_name) => self.forget_world(&world_name),
+
+                // Some drops were missed, so any world may be stale
+                Err(TryRecvError::Lagged(_)) => {
+                    self.table_cache.clear();
+                    self.region_cache.clear();
+                    if let Some(cache) = &mut self.record_cache {
+                        cache.clear();
+                    }
+                }
+
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Remove every cached lookup and record for a world, and any world sharing its schema.
+    fn forget_world(&mut self, world_name: &str) {
+        for cache in [&mut self.table_cache, &mut self.region_cache] {
+            let stale = cache
+                .iter()
+                .filter(|(region, _)| region.world_name().eq_ignore_ascii_case(world_name))
+                .map(|(region, _)| region.clone())
+                .collect::<Vec<_>>();
+
+            for region in stale {
+                cache.pop(&region);
+            }
+        }
+
+        self.invalidate_cached_world(world_name);
+    }
+}
@@ -1,8 +1,15 @@
 use std::fmt::Display;
 
+#[cfg(test)]
+use ahash::AHashSet;
 use derive_getters::Getters;
 
+#[cfg(test)]
+use super::coords::clamp_table_size;
+use super::coords::position_to_region_coords;
 use super::DatabaseClient;
+#[cfg(test)]
+use super::PartitionStrategy;
 use crate::structures::Vector3;
 
 // region: WorldRegion Struct
@@ -22,10 +29,20 @@ impl WorldRegion {
         region_y_size: u16,
         region_z_size: u16,
     ) -> Self {
-        let x = clamp_region_coord(*vector.x(), region_x_size);
-        let y = clamp_region_coord(*vector.y(), region_y_size);
-        let z = clamp_region_coord(*vector.z(), region_z_size);
+        let (x, y, z) =
+            position_to_region_coords(vector, (region_x_size, region_y_size, region_z_size));
+
+        Self {
+            world_name: world_name.into(),
+            x,
+            y,
+            z,
+        }
+    }
 
+    /// Create a [`WorldRegion`] from already aligned minimum coordinates.
+    #[inline]
+    pub(super) fn from_min(world_name: &str, x: i64, y: i64, z: i64) -> Self {
         Self {
             world_name: world_name.into(),
             x,
@@ -34,6 +51,50 @@ impl WorldRegion {
         }
     }
 
+    /// Returns the minimum and maximum corners of this region.
+    #[cfg(test)]
+    pub(super) fn bounds(&self, (x_size, y_size, z_size): (u16, u16, u16)) -> (Vector3, Vector3) {
+        let min = Vector3::new(self.x as f64, self.y as f64, self.z as f64);
+        let max = Vector3::new(
+            (self.x + i64::from(x_size)) as f64,
+            (self.y + i64::from(y_size)) as f64,
+            (self.z + i64::from(z_size)) as f64,
+        );
+
+        (min, max)
+    }
+
+    /// Returns the squared distance from `point` to the closest point inside this region,
+    /// or `0.0` if the region contains it.
+    #[cfg(test)]
+    pub(super) fn distance_squared(&self, point: &Vector3, sizes: (u16, u16, u16)) -> f64 {
+        let (min, max) = self.bounds(sizes);
+        let closest = Vector3::new(
+            point.x().clamp(*min.x(), *max.x()),
+            point.y().clamp(*min.y(), *max.y()),
+            point.z().clamp(*min.z(), *max.z()),
+        );
+
+        point.distance_squared(&closest)
+    }
+
+    /// Returns the minimum and maximum corners of the table containing this region.
+    #[cfg(test)]
+    pub(super) fn table_bounds(
+        &self,
+        strategy: PartitionStrategy,
+        table_size: i64,
+    ) -> (Vector3, Vector3) {
+        let [(min_x, max_x), (min_y, max_y), (min_z, max_z)] =
+            strategy.table_bounds(self, table_size);
+
+        let min = Vector3::new(min_x as f64, min_y as f64, min_z as f64);
+        let max = Vector3::new(max_x as f64, max_y as f64, max_z as f64);
+
+        (min, max)
+    }
+
+    #[cfg(test)]
     #[inline]
     pub(super) fn x_bounds(&self, table_size: i64) -> (i64, i64) {
         let min_x = clamp_table_size(self.x, table_size);
@@ -42,6 +103,7 @@ impl WorldRegion {
         (min_x, max_x)
     }
 
+    #[cfg(test)]
     #[inline]
     pub(super) fn y_bounds(&self, table_size: i64) -> (i64, i64) {
         let min_y = clamp_table_size(self.y, table_size);
@@ -50,6 +112,7 @@ impl WorldRegion {
         (min_y, max_y)
     }
 
+    #[cfg(test)]
     #[inline]
     pub(super) fn z_bounds(&self, table_size: i64) -> (i64, i64) {
         let min_z = clamp_table_size(self.z, table_size);
@@ -75,57 +138,102 @@ impl DatabaseClient {
     /// Shorthand function to create a new [`WorldRegion`]
     #[inline]
     pub(super) fn world_region(&self, world_name: &str, vector: &Vector3) -> WorldRegion {
+        let (region_x_size, region_y_size, region_z_size) = self.region_size(world_name);
         WorldRegion::new(
             world_name,
             vector,
+            region_x_size,
+            region_y_size,
+            region_z_size,
+        )
+    }
+
+    /// Returns the minimum and maximum corners of the region containing `position`,
+    /// using the default region sizes.
+    ///
+    /// Regions include their minimum corner but not their maximum, on both sides of the
+    /// origin, matching how records are assigned to regions.
+    #[cfg(test)]
+    pub fn region_bounds(&self, position: &Vector3) -> (Vector3, Vector3) {
+        let sizes = (
             self.region_x_size(),
             self.region_y_size(),
             self.region_z_size(),
-        )
+        );
+        WorldRegion::new("", position, sizes.0, sizes.1, sizes.2).bounds(sizes)
     }
-}
-// endregion
 
-// region: Coordinate Clamp Functions
-/// Define region coords by their lowest possible value.
-///
-/// For negatives, they should still round down.
-fn clamp_region_coord(c: f64, region_size: u16) -> i64 {
-    // Unit case, 0 always return 0
-    if c == 0.0 || c == -0.0 {
-        return 0;
+    /// Returns the minimum and maximum corners of the region containing `position`,
+    /// taking any region size override for the world into account.
+    ///
+    /// See [`Self::region_bounds`].
+    #[cfg(test)]
+    pub fn world_region_bounds(&self, world_name: &str, position: &Vector3) -> (Vector3, Vector3) {
+        // Invalid world names can't have an override
+        let world_name = self.sanitize_world_name(world_name).unwrap_or_default();
+        let sizes = self.region_size(&world_name);
+
+        self.world_region(&world_name, position).bounds(sizes)
     }
 
-    if c >= 0.0 {
-        let region_size = i64::from(region_size);
-        let c = c as i64;
+    /// Returns the minimum and maximum corners of the database table containing `position`.
+    #[cfg(test)]
+    pub fn table_bounds(&self, position: &Vector3) -> (Vector3, Vector3) {
+        let sizes = (
+            self.region_x_size(),
+            self.region_y_size(),
+            self.region_z_size(),
+        );
+        let region = WorldRegion::new("", position, sizes.0, sizes.1, sizes.2);
+
+        region.table_bounds(self.partition_strategy(), i64::from(self.table_size()))
+    }
 
-        c - (c % region_size)
-    } else {
-        let new_c = (-c) + f64::from(region_size);
-        let result = clamp_region_coord(new_c, region_size);
+    /// Returns every [`WorldRegion`] that overlaps the axis-aligned box between `min` and `max`.
+    #[cfg(test)]
+    pub(super) fn world_regions_in_bounds(
+        &self,
+        world_name: &str,
+        min: &Vector3,
+        max: &Vector3,
+    ) -> Vec<WorldRegion> {
+        let (region_x_size, region_y_size, region_z_size) = self.region_size(world_name);
+        let xs = sample_axis(*min.x(), *max.x(), region_x_size);
+        let ys = sample_axis(*min.y(), *max.y(), region_y_size);
+        let zs = sample_axis(*min.z(), *max.z(), region_z_size);
+
+        let mut regions = AHashSet::new();
+        for x in &xs {
+            for y in &ys {
+                for z in &zs {
+                    let point = Vector3::new(*x, *y, *z);
+                    regions.insert(self.world_region(world_name, &point));
+                }
+            }
+        }
 
-        -result
+        regions.into_iter().collect()
     }
 }
+// endregion
 
-fn clamp_table_size(c: i64, table_size: i64) -> i64 {
-    // On a table border, return
-    if c % table_size == 0 {
-        return c;
+// region: Bounds Sampling
+/// Sample points along an axis no further apart than `region_size`.
+///
+/// Every region overlapping `min..=max` contains at least one sampled point.
+#[cfg(test)]
+fn sample_axis(min: f64, max: f64, region_size: u16) -> Vec<f64> {
+    let step = f64::from(region_size);
+
+    let mut points = vec![];
+    let mut c = min;
+    while c < max {
+        points.push(c);
+        c += step;
     }
 
-    if c >= 0 {
-        let region_size = table_size;
-        let c = c as i64;
-
-        c - (c % region_size)
-    } else {
-        let new_c = (-c) + table_size;
-        let result = clamp_table_size(new_c, table_size);
-
-        -result
-    }
+    points.push(max);
+    points
 }
 // endregion
 
@@ -134,79 +242,6 @@ fn clamp_table_size(c: i64, table_size: i64) -> i64 {
 mod tests {
     use super::*;
 
-    // region: clamp_region_coord
-    macro_rules! test_clamp_region_coord {
-        ($input: expr, $region_size: expr, $expected: expr) => {
-            let output = super::clamp_region_coord($input, $region_size);
-            assert_eq!(output, $expected);
-        };
-    }
-
-    #[test]
-    fn clamp_region_coord() {
-        // Unit case
-        test_clamp_region_coord!(0.0, 16, 0);
-
-        // Positive
-        test_clamp_region_coord!(0.1, 16, 0);
-        test_clamp_region_coord!(15.0, 16, 0);
-        test_clamp_region_coord!(16.0, 16, 16);
-        test_clamp_region_coord!(31.9, 16, 16);
-        test_clamp_region_coord!(32.0, 16, 32);
-        test_clamp_region_coord!(0.0, 256, 0);
-        test_clamp_region_coord!(0.1, 256, 0);
-        test_clamp_region_coord!(128.0, 256, 0);
-        test_clamp_region_coord!(255.9, 256, 0);
-        test_clamp_region_coord!(256.0, 256, 256);
-        test_clamp_region_coord!(511.9, 256, 256);
-        test_clamp_region_coord!(512.0, 256, 512);
-
-        // Negative
-        test_clamp_region_coord!(-0.1, 16, -16);
-        test_clamp_region_coord!(-1.0, 16, -16);
-        test_clamp_region_coord!(-15.0, 16, -16);
-        test_clamp_region_coord!(-16.0, 16, -32);
-        test_clamp_region_coord!(-31.9, 16, -32);
-        test_clamp_region_coord!(-32.0, 16, -48);
-        test_clamp_region_coord!(-32.1, 16, -48);
-        test_clamp_region_coord!(-1.0, 256, -256);
-        test_clamp_region_coord!(-128.0, 256, -256);
-        test_clamp_region_coord!(-255.9, 256, -256);
-        test_clamp_region_coord!(-256.0, 256, -512);
-    }
-    // endregion
-
-    // region: clamp_table_size
-    macro_rules! test_clamp_table_size {
-        ($input: expr, $table_size: expr, $expected: expr) => {
-            let output = super::clamp_table_size($input, $table_size);
-            assert_eq!(output, $expected);
-        };
-    }
-
-    #[test]
-    fn clamp_table_size() {
-        // Unit case
-        test_clamp_table_size!(0, 1024, 0);
-
-        // Positive
-        test_clamp_table_size!(1, 1024, 0);
-        test_clamp_table_size!(256, 1024, 0);
-        test_clamp_table_size!(1024, 1024, 1024);
-        test_clamp_table_size!(1800, 1024, 1024);
-        test_clamp_table_size!(2047, 1024, 1024);
-        test_clamp_table_size!(2048, 1024, 2048);
-
-        // Negative
-        test_clamp_table_size!(-1, 1024, -1024);
-        test_clamp_table_size!(-45, 1024, -1024);
-        test_clamp_table_size!(-687, 1024, -1024);
-        test_clamp_table_size!(-1023, 1024, -1024);
-        test_clamp_table_size!(-1024, 1024, -1024);
-        test_clamp_table_size!(-1025, 1024, -2048);
-    }
-    // endregion
-
     // region: conversion
     macro_rules! test_conversion {
         ($input: expr, $sizes: expr, $expected: expr) => {
@@ -240,6 +275,63 @@ mod tests {
     }
     // endregion
 
+    // region: bounds
+    #[test]
+    fn bounds() {
+        let mc_chunk = (16, 256, 16);
+
+        let region = WorldRegion::new("world", &Vector3::new(10.2, 486.5, -15.9), 16, 256, 16);
+        assert_eq!(
+            region.bounds(mc_chunk),
+            (
+                Vector3::new(0.0, 256.0, -16.0),
+                Vector3::new(16.0, 512.0, 0.0)
+            )
+        );
+
+        assert_eq!(
+            region.table_bounds(PartitionStrategy::FixedGrid, 1024),
+            (
+                Vector3::new(0.0, 0.0, -1024.0),
+                Vector3::new(1024.0, 1024.0, 0.0)
+            )
+        );
+    }
+    // endregion
+
+    // region: distance_squared
+    #[test]
+    fn distance_squared() {
+        let mc_chunk = (16, 256, 16);
+        let region = WorldRegion::from_min("world", 16, 0, -16);
+
+        // Inside
+        let inside = Vector3::new(20.0, 100.0, -1.0);
+        assert_eq!(region.distance_squared(&inside, mc_chunk), 0.0);
+
+        // Outside on one and two axes
+        let beside = Vector3::new(10.0, 100.0, -8.0);
+        assert_eq!(region.distance_squared(&beside, mc_chunk), 36.0);
+
+        let corner = Vector3::new(35.0, 100.0, 4.0);
+        assert_eq!(region.distance_squared(&corner, mc_chunk), 9.0 + 16.0);
+    }
+    // endregion
+
+    // region: sample_axis
+    #[test]
+    fn sample_axis() {
+        #![allow(clippy::float_cmp)]
+
+        assert_eq!(super::sample_axis(0.0, 0.0, 16), vec![0.0]);
+        assert_eq!(super::sample_axis(0.0, 10.0, 16), vec![0.0, 10.0]);
+        assert_eq!(
+            super::sample_axis(-20.0, 20.0, 16),
+            vec![-20.0, -4.0, 12.0, 20.0]
+        );
+    }
+    // endregion
+
     // region: table_bounds
     macro_rules! test_table_bounds {
         ($input: expr, $sizes: expr, $table_sizes: expr, $expected_x: expr, $expected_y: expr, $expected_z: expr) => {
@@ -360,5 +452,75 @@ mod tests {
             (0, 1024)
         );
     }
+    // endregion
+
+    // region: client bounds
+    fn unconnected_client() -> DatabaseClient {
+        use deadpool_postgres::{Manager, Pool};
+        use tokio_postgres::NoTls;
+
+        let manager = Manager::new(tokio_postgres::Config::new(), NoTls);
+        let pool = Pool::builder(manager).build().unwrap();
+
+        DatabaseClient::new(
+            pool,
+            16,
+            256,
+            16,
+            1024,
+            1024,
+            Default::default(),
+            PartitionStrategy::FixedGrid,
+        )
+    }
+
+    #[test]
+    fn client_bounds() {
+        let mut db = unconnected_client();
+        let position = Vector3::new(10.2, 486.5, -15.9);
+
+        let region = (
+            Vector3::new(0.0, 256.0, -16.0),
+            Vector3::new(16.0, 512.0, 0.0),
+        );
+        assert_eq!(db.region_bounds(&position), region);
+        assert_eq!(db.world_region_bounds("world", &position), region);
+
+        assert_eq!(
+            db.table_bounds(&position),
+            (
+                Vector3::new(0.0, 0.0, -1024.0),
+                Vector3::new(1024.0, 1024.0, 0.0)
+            )
+        );
+
+        // Overrides only apply to their own world
+        db.set_region_size("small world", 8, 8, 8).unwrap();
+        assert_eq!(db.region_bounds(&position), region);
+        assert_eq!(
+            db.world_region_bounds("small world", &position),
+            (
+                Vector3::new(8.0, 480.0, -16.0),
+                Vector3::new(16.0, 488.0, -8.0)
+            )
+        );
+    }
+
+    #[test]
+    fn world_regions_in_bounds() {
+        let db = unconnected_client();
+        let min = Vector3::new(-20.0, 0.0, 0.0);
+        let max = Vector3::new(20.0, 10.0, 10.0);
+
+        let mut regions = db
+            .world_regions_in_bounds("world", &min, &max)
+            .into_iter()
+            .map(|region| region.x)
+            .collect::<Vec<_>>();
+        regions.sort_unstable();
+
+        assert_eq!(regions, vec![-32, -16, 0, 16]);
+    }
+    // endregion
 }
 // endregion
@@ -0,0 +1,67 @@
+use serde::Serialize;
+
+use super::client::DatabaseError;
+use super::{query_count_world_records, DatabaseClient, QUERY_WORLD_TABLE_SIZES};
+use crate::metrics::DB_DURATION;
+
+// region: WorldStats Struct
+/// Amount of data stored for a single world.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub struct WorldStats {
+    /// Tables created for the world, including its global table
+    pub table_count: usize,
+    pub record_count: u64,
+    /// Size of every table on disk, including indexes and TOAST data
+    pub total_bytes: u64,
+}
+// endregion
+
+impl DatabaseClient {
+    /// Count the records stored for a world and the space they take up.
+    ///
+    /// Tables are created lazily, so only tables that currently exist in the world's
+    /// schema are included. Counting is exact, so this scans every table in the world.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub async fn world_stats(&mut self, world_name: &str) -> Result<WorldStats, DatabaseError> {
+        let _timer = DB_DURATION.start_timer("world_stats");
+        let world_name = self
+            .sanitize_world_name(world_name)
+            .map_err(|error| DatabaseError::invalid_world_name(world_name, None, error))?;
+
+        let schema = format!("w_{}", world_name);
+        let rows = self
+            .client()
+            .await?
+            .query(QUERY_WORLD_TABLE_SIZES, &[&schema])
+            .await?;
+
+        if rows.is_empty() {
+            return Ok(WorldStats::default());
+        }
+
+        let mut tables = Vec::with_capacity(rows.len());
+        let mut total_bytes = 0;
+        for row in rows {
+            let table_name: String = row.try_get("table_name")?;
+            let bytes: i64 = row.try_get("total_bytes")?;
+
+            tables.push(table_name);
+            total_bytes += u64::try_from(bytes).unwrap_or_default();
+        }
+
+        let query = query_count_world_records(&world_name, &tables);
+        let record_count: i64 = self
+            .client()
+            .await?
+            .query_one(&query, &[])
+            .await?
+            .try_get(0)?;
+
+        Ok(WorldStats {
+            table_count: tables.len(),
+            record_count: u64::try_from(record_count).unwrap_or_default(),
+            total_bytes,
+        })
+    }
+}
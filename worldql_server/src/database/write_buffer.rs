@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+use uuid::Uuid;
+
+use super::client::InsertReport;
+use super::DatabaseClient;
+use crate::structures::Record;
+
+// region: WriteBuffer Struct
+/// Records waiting to be inserted, see [`DatabaseClient::set_write_buffer`].
+#[derive(Debug)]
+pub(super) struct WriteBuffer {
+    max_records: usize,
+    max_delay: Duration,
+    /// Buffered records, along with the peer that owns them
+    records: Vec<(Option<Uuid>, Record)>,
+    /// Position in `records` of the latest record for each world and UUID
+    index: AHashMap<(String, Uuid), usize>,
+    /// When the oldest buffered record was added
+    oldest: Option<Instant>,
+}
+
+impl WriteBuffer {
+    pub(super) fn new(max_records: usize, max_delay: Duration) -> Self {
+        Self {
+            max_records,
+            max_delay,
+            records: vec![],
+            index: AHashMap::new(),
+            oldest: None,
+        }
+    }
+
+    /// Buffer a record owned by `owner`, replacing any buffered record with the same world
+    /// and UUID so only its latest state is inserted.
+    pub(super) fn push(&mut self, owner: Option<Uuid>, record: Record) {
+        self.oldest.get_or_insert_with(Instant::now);
+
+        let key = (record.world_name.clone(), record.uuid);
+        match self.index.get(&key) {
+            Some(i) => self.records[*i] = (owner, record),
+            None => {
+                self.index.insert(key, self.records.len());
+                self.records.push((owner, record));
+            }
+        }
+    }
+
+    /// Returns `true` if the buffer should be flushed.
+    pub(super) fn is_due(&self, now: Instant) -> bool {
+        self.records.len() >= self.max_records
+            || self.deadline().map_or(false, |deadline| now >= deadline)
+    }
+
+    /// Returns when the buffered records must be flushed, if there are any.
+    #[inline]
+    pub(super) fn deadline(&self) -> Option<Instant> {
+        self.oldest.map(|oldest| oldest + self.max_delay)
+    }
+
+    #[inline]
+    pub(super) fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Remove and return every buffered record and its owner, in the order they were first
+    /// added.
+    pub(super) fn take(&mut self) -> Vec<(Option<Uuid>, Record)> {
+        self.index.clear();
+        self.oldest = None;
+
+        std::mem::take(&mut self.records)
+    }
+}
+// endregion
+
+impl DatabaseClient {
+    /// Buffer inserts, flushing them in a single batch once `max_records` are buffered
+    /// or the oldest has waited `max_delay`, whichever comes first.
+    ///
+    /// Buffering trades durability for throughput: buffered records are lost if the
+    /// process exits without calling [`Self::flush`], and aren't visible to reads until
+    /// they are flushed.
+    pub fn set_write_buffer(&mut self, max_records: usize, max_delay: Duration) {
+        let mut buffer = WriteBuffer::new(max_records, max_delay);
+        if let Some(existing) = &mut self.write_buffer {
+            for (owner, record) in existing.take() {
+                buffer.push(owner, record);
+            }
+        }
+
+        self.write_buffer = Some(buffer);
+    }
+
+    /// Insert records owned by `owner` through the write buffer, see
+    /// [`Self::set_write_buffer`].
+    ///
+    /// Inserts immediately if buffering is disabled. Returns the report of the insert if
+    /// one happened, which may include previously buffered records.
+    pub async fn buffer_records(
+        &mut self,
+        records: Vec<Record>,
+        owner: Option<Uuid>,
+    ) -> Option<InsertReport> {
+        let buffer = match &mut self.write_buffer {
+            Some(buffer) => buffer,
+            None => return Some(self.write_records(records, false, owner).await),
+        };
+
+        for record in records {
+            buffer.push(owner, record);
+        }
+
+        if !buffer.is_due(Instant::now()) {
+            return None;
+        }
+
+        Some(self.flush().await)
+    }
+
+    /// Insert every buffered record now.
+    pub async fn flush(&mut self) -> InsertReport {
+        let records = match &mut self.write_buffer {
+            Some(buffer) if !buffer.is_empty() => buffer.take(),
+            _ => return InsertReport::default(),
+        };
+
+        // Owners are written per insert, each holding a distinct set of records
+        let mut owners: AHashMap<Option<Uuid>, Vec<Record>> = AHashMap::new();
+        for (owner, record) in records {
+            owners.entry(owner).or_default().push(record);
+        }
+
+        let mut report = InsertReport::default();
+        for (owner, records) in owners {
+            let owner_report = self.write_records(records, false, owner).await;
+            report.inserted += owner_report.inserted;
+            report.errors.extend(owner_report.errors);
+        }
+
+        report
+    }
+
+    /// Returns when buffered records must be flushed, or `None` if nothing is buffered.
+    #[inline]
+    pub fn flush_deadline(&self) -> Option<Instant> {
+        self.write_buffer.as_ref().and_then(WriteBuffer::deadline)
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(world_name: &str, uuid: Uuid, data: &str) -> Record {
+        Record {
+            uuid,
+            world_name: world_name.into(),
+            position: None,
+            data: Some(data.into()),
+            flex: None,
+        }
+    }
+
+    #[test]
+    fn write_buffer() {
+        let mut buffer = WriteBuffer::new(3, Duration::from_secs(60));
+        assert_eq!(buffer.deadline(), None);
+
+        let uuid = Uuid::new_v4();
+        buffer.push(None, record("world", uuid, "a"));
+        buffer.push(None, record("other", uuid, "b"));
+        buffer.push(Some(uuid), record("world", uuid, "c"));
+
+        // Latest state of each record replaces earlier ones in place
+        let now = Instant::now();
+        assert!(!buffer.is_due(now));
+        assert!(buffer.is_due(now + Duration::from_secs(60)));
+
+        buffer.push(None, record("world", Uuid::new_v4(), "d"));
+        assert!(buffer.is_due(now));
+
+        let data = buffer
+            .take()
+            .into_iter()
+            .map(|(_, record)| record.data.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(data, ["c", "b", "d"]);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.deadline(), None);
+    }
+}
+// endregion
@@ -1,4 +1,10 @@
-#[allow(dead_code, unused_imports, clippy::all)]
+#[allow(
+    dead_code,
+    unused_imports,
+    clippy::all,
+    clippy::pedantic,
+    mismatched_lifetime_syntaxes
+)]
 #[path = "./WorldQLFB_generated.rs"]
 mod generated;
 
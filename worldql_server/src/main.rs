@@ -7,30 +7,46 @@
 )]
 
 use std::collections::HashSet;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use color_eyre::Result;
+use deadpool_postgres::{Manager, Pool};
 use dotenv::dotenv;
 use tokio::sync::RwLock;
 use tokio_postgres::NoTls;
 use tracing::{debug, error, info, warn};
 
+#[cfg(feature = "admin")]
+use crate::admin::start_admin_server;
 use crate::args::Args;
-use crate::database::DatabaseClient;
-use crate::processing::start_processing_thread;
+use crate::database::{CircuitBreaker, DatabaseClient, RetryPolicy};
+use crate::processing::{db_worker_index, start_processing_thread, HandlerRegistry};
+use crate::server::{shutdown_signal, start_queue_monitor, Server};
+use crate::subscriptions::{ThreadWorldMap, WorldMap};
 #[cfg(feature = "http")]
 use crate::transport::start_http_server;
 #[cfg(feature = "websocket")]
 use crate::transport::start_websocket_server;
 #[cfg(feature = "zeromq")]
-use crate::transport::{start_zeromq_incoming, start_zeromq_outgoing};
+use crate::transport::{
+    start_dead_letter_writer, start_peer_eviction, start_zeromq_incoming, start_zeromq_outgoing,
+    AllowAllAuthenticator, CapacityHook, CurveConfig, HandshakeAuthenticator, HandshakeConfig,
+    HandshakeHook, InvalidMessagePolicy, MessageForwarder, SharedSecretAuthenticator,
+};
 use crate::transport::{PeerMap, ThreadPeerMap};
+use crate::utils::{sanitize_world_name, set_payload_logging, set_sanitize_config, SanitizeConfig};
 
+#[cfg(feature = "admin")]
+mod admin;
 mod args;
 mod database;
 mod flatbuffers;
+mod metrics;
 mod processing;
+mod server;
 mod structures;
 mod subscriptions;
 mod transport;
@@ -53,7 +69,7 @@ async fn main() -> Result<()> {
 
     let filter = match args.verbose {
         #[cfg(debug_assertions)]
-        0 | 1 | 2 => format!("{}=debug", env!("CARGO_PKG_NAME")),
+        0..=2 => format!("{}=debug", env!("CARGO_PKG_NAME")),
 
         #[cfg(not(debug_assertions))]
         0 => format!("{}=info", env!("CARGO_PKG_NAME")),
@@ -103,66 +119,176 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    let psql_result = tokio_postgres::connect(&args.psql_conn, NoTls).await;
-    if let Err(err) = psql_result {
-        error!("PostgreSQL Error: {}", err);
-        std::process::exit(1);
-    }
+    // Load CurveZMQ keys before anything starts listening
+    #[cfg(feature = "zeromq")]
+    let zmq_curve = match (
+        &args.zmq_curve_secret_key_file,
+        &args.zmq_curve_client_keys_file,
+    ) {
+        (None, None) => None,
+        (Some(secret_key_file), Some(client_keys_file)) => {
+            match CurveConfig::load(secret_key_file, client_keys_file) {
+                Ok(curve) => Some(curve),
+                Err(error) => {
+                    error!("Failed to load CurveZMQ keys: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
 
-    let (client, psql_conn) = psql_result.unwrap();
-    tokio::spawn(async move {
-        debug!("spawned postgres read thread");
-        if let Err(e) = psql_conn.await {
-            error!("PostgreSQL Connection Error: {}", e);
+        _ => {
+            error!(
+                "--zmq-curve-secret-key-file and --zmq-curve-client-keys-file must be set together"
+            );
+            std::process::exit(1);
         }
-    });
+    };
 
-    info!("Connected to PostgreSQL");
-    let client = DatabaseClient::new(
-        client,
-        args.db_region_x_size,
-        args.db_region_y_size,
-        args.db_region_z_size,
-        args.db_table_size,
-        args.db_cache_size,
+    if let Some(policy) = args.payload_logging {
+        set_payload_logging(policy);
+    }
+
+    // Subscriptions and the database must agree on world names
+    let sanitize_config = SanitizeConfig {
+        max_length: args.world_name_max_length,
+        charset: args.world_name_charset,
+        case_fold: args.world_name_case_fold,
+    };
+
+    let _ = set_sanitize_config(sanitize_config.clone());
+
+    // Each database worker has its own caches, sharing one connection pool, set of cache
+    // counters, table creation locks and one circuit breaker
+    let pools = connect_pools(&args).await;
+    let breaker = CircuitBreaker::new(
+        args.db_breaker_threshold,
+        Duration::from_secs(args.db_breaker_cooldown_secs),
     );
 
-    // Init database
-    if let Err(error) = client.init_database().await {
-        error!("Failed to create database tables!");
-        error!("{}", error);
+    let mut clients = Vec::with_capacity(args.db_workers);
+    let first_client = database_client(&args, &sanitize_config, &breaker, &pools);
+    let cache_stats = first_client.cache_stats_handle();
+    let table_locks = first_client.table_locks();
+    let world_drops = first_client.world_drops();
+    clients.push(first_client);
+
+    for _ in 1..args.db_workers {
+        let client = database_client(&args, &sanitize_config, &breaker, &pools);
+        clients.push(
+            client
+                .with_cache_stats(&cache_stats)
+                .with_table_locks(table_locks.clone())
+                .with_world_drops(world_drops.clone()),
+        );
+    }
 
-        std::process::exit(1);
+    // Anyone who can reach the admin API could drop worlds, so only allow it without a token
+    // on a loopback address
+    #[cfg(feature = "admin")]
+    if let Some(addr) = args.admin_addr {
+        if args.admin_auth_token.is_none() && !addr.ip().is_loopback() {
+            error!(
+                "--admin-addr {} requires --admin-auth-token, or a loopback address",
+                addr
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // The admin API has its own caches, so its lookups never wait on the workers
+    #[cfg(feature = "admin")]
+    let admin_client = match args.admin_addr {
+        None => None,
+        Some(_) => {
+            let client = database_client(&args, &sanitize_config, &breaker, &pools);
+            Some(
+                client
+                    .with_table_locks(table_locks.clone())
+                    .with_world_drops(world_drops.clone()),
+            )
+        }
     };
 
-    let (msg_tx, msg_rx) = flume::unbounded();
+    // Init database
+    match clients[0].ensure_schema().await {
+        Ok(version) => debug!("Database schema is at version {}", version),
+        Err(error) => {
+            error!("Failed to create database tables!");
+            error!("{}", error);
+
+            std::process::exit(1);
+        }
+    }
+
+    // Warm lookup caches for hot worlds, on the worker that will handle them
+    for world_name in &args.db_warm_worlds {
+        let client = &mut clients[db_worker_index(world_name, args.db_workers)];
+        match client.warm_cache(world_name).await {
+            Ok(count) => info!("Warmed cache with {} regions for \"{}\"", count, world_name),
+            Err(error) => warn!("Failed to warm cache for \"{}\": {}", world_name, error),
+        }
+    }
+
+    let (msg_tx, msg_rx) = flume::bounded(args.msg_queue_size);
     let (remove_tx, remove_rx) = flume::unbounded();
 
     let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
-    let mut handles = vec![];
+    let mut world_map = WorldMap::new(args.sub_region_size, args.sub_max_per_peer)?;
+    world_map.set_max_worlds(args.max_worlds);
+
+    // Allowlisted names must match the sanitized names sent by peers
+    let mut allowed_worlds = Vec::with_capacity(args.world_allowlist.len());
+    for world_name in &args.world_allowlist {
+        match sanitize_world_name(world_name) {
+            Ok(world_name) => allowed_worlds.push(world_name),
+            Err(error) => {
+                error!(
+                    "Invalid world name \"{}\" in --world-allowlist: {}",
+                    world_name, error
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    world_map.set_world_policy(args.world_policy, allowed_worlds);
+    let world_map: ThreadWorldMap = Arc::new(RwLock::new(world_map));
+
+    let mut server = Server::new(peer_map.clone(), world_map.clone(), cache_stats);
+    server.set_circuit_breaker(breaker);
+
+    #[cfg(feature = "admin")]
+    if let (Some(addr), Some(database_client)) = (args.admin_addr, admin_client) {
+        let (stats, world_map) = (server.stats_handle(), world_map.clone());
+        let auth_token = args.admin_auth_token.clone();
+        server.spawn_ingress(|token| {
+            start_admin_server(stats, world_map, database_client, addr, auth_token, token)
+        });
+    }
+
+    let mut queue_monitor = server.queue_monitor(args.queue_high_water);
+    queue_monitor.watch("messages", msg_rx.clone());
 
     #[cfg(feature = "http")]
     {
-        let http_handle = tokio::spawn(start_http_server(
-            msg_tx.clone(),
-            args.http_host,
-            args.http_port,
-            args.http_auth_token,
-        ));
-
-        handles.push(http_handle);
+        let msg_tx = msg_tx.clone();
+        server.spawn_ingress(|token| {
+            start_http_server(
+                msg_tx,
+                args.http_host,
+                args.http_port,
+                args.http_auth_token,
+                token,
+            )
+        });
     }
 
     #[cfg(feature = "websocket")]
     {
-        let ws_handle = tokio::spawn(start_websocket_server(
-            peer_map.clone(),
-            msg_tx.clone(),
-            args.ws_host,
-            args.ws_port,
-        ));
-
-        handles.push(ws_handle);
+        let (peer_map, msg_tx) = (peer_map.clone(), msg_tx.clone());
+        server.spawn_ingress(|token| {
+            start_websocket_server(peer_map, msg_tx, args.ws_host, args.ws_port, token)
+        });
     }
 
     #[cfg(feature = "zeromq")]
@@ -170,41 +296,219 @@ async fn main() -> Result<()> {
         let ctx = tmq::Context::new();
         let (zmq_msg_tx, zmq_msg_rx) = flume::unbounded();
         let (zmq_handshake_tx, zmq_handshake_rx) = flume::unbounded();
+        queue_monitor.watch("zeromq_outgoing", zmq_msg_rx.clone());
+        queue_monitor.watch("zeromq_handshakes", zmq_handshake_rx.clone());
+
+        let authenticator: Arc<dyn HandshakeAuthenticator> = match args.zmq_auth_secret {
+            None => Arc::new(AllowAllAuthenticator),
+            Some(secret) => Arc::new(SharedSecretAuthenticator::new(secret)),
+        };
+
+        let hooks: Vec<Arc<dyn HandshakeHook>> = vec![
+            Arc::new(args.zmq_uuid_policy),
+            Arc::new(CapacityHook::new(args.max_peers)),
+        ];
+        let hook: Arc<dyn HandshakeHook> = Arc::new(hooks);
+
+        // Dead letters are written until ZeroMQ incoming has shut down
+        let dead_letter_tx = match args.zmq_dead_letter_file {
+            None => None,
+            Some(path) => {
+                let (tx, rx) = flume::unbounded();
+                server.spawn_egress(|_| start_dead_letter_writer(rx, path));
+
+                Some(tx)
+            }
+        };
 
-        let zmq_incoming_handle = tokio::spawn(start_zeromq_incoming(
-            peer_map.clone(),
+        let forwarder = MessageForwarder::new(
             msg_tx,
-            zmq_handshake_tx,
-            args.zmq_server_host,
-            args.zmq_server_port,
-            ctx.clone(),
-        ));
-
-        let zmq_outgoing_handle = tokio::spawn(start_zeromq_outgoing(
-            peer_map.clone(),
-            zmq_msg_tx,
-            zmq_msg_rx,
-            zmq_handshake_rx,
-            ctx,
-            args.zmq_timeout_secs,
-        ));
-
-        handles.push(zmq_incoming_handle);
-        handles.push(zmq_outgoing_handle);
-    }
-
-    let proc_handle = tokio::spawn(start_processing_thread(
-        client,
-        peer_map,
-        msg_rx,
-        remove_rx,
-        args.sub_region_size,
-    ));
-
-    handles.push(proc_handle);
-
-    // Run all threads
-    let _ = futures_util::future::join_all(handles).await;
-
-    Ok(())
+            msg_rx.clone(),
+            args.zmq_overflow_policy,
+            server.dropped_messages(),
+        );
+
+        let invalid_policy = args
+            .zmq_invalid_threshold
+            .map(|threshold| InvalidMessagePolicy {
+                threshold,
+                window: Duration::from_secs(args.zmq_invalid_window_secs),
+                cooldown: Duration::from_secs(args.zmq_invalid_cooldown_secs),
+            });
+
+        let (incoming_peer_map, incoming_ctx) = (peer_map.clone(), ctx.clone());
+        server.spawn_ingress(|token| {
+            start_zeromq_incoming(
+                incoming_peer_map,
+                forwarder,
+                zmq_handshake_tx,
+                args.zmq_server_host,
+                args.zmq_server_port,
+                incoming_ctx,
+                args.zmq_rate_limit,
+                args.zmq_max_message_bytes,
+                authenticator,
+                hook,
+                args.zmq_auth_cooldown_secs.map(Duration::from_secs),
+                dead_letter_tx,
+                zmq_curve,
+                invalid_policy,
+                token,
+            )
+        });
+
+        let handshake_config = HandshakeConfig {
+            cube_size: args.sub_region_size,
+            region_size: (
+                args.db_region_x_size,
+                args.db_region_y_size,
+                args.db_region_z_size,
+            ),
+            uuid_policy: args.zmq_uuid_policy,
+        };
+
+        let outgoing_peer_map = peer_map.clone();
+        server.spawn_egress(|token| {
+            start_zeromq_outgoing(
+                outgoing_peer_map,
+                zmq_msg_tx,
+                zmq_msg_rx,
+                zmq_handshake_rx,
+                handshake_config,
+                Duration::from_secs(args.zmq_handshake_timeout_secs),
+                args.zmq_coalesce_ms
+                    .map(|ms| Duration::from_millis(u64::from(ms))),
+                ctx,
+                token,
+            )
+        });
+
+        // Only ZeroMQ peers can go silently idle
+        let eviction_peer_map = peer_map.clone();
+        server.spawn_ingress(|token| {
+            start_peer_eviction(
+                eviction_peer_map,
+                Duration::from_secs(u64::from(args.zmq_timeout_secs)),
+                token,
+            )
+        });
+    }
+
+    // Sampling stops with ingress, so the monitor never holds a queue open afterwards
+    server.spawn_ingress(|token| {
+        start_queue_monitor(
+            queue_monitor,
+            Duration::from_secs(u64::from(args.queue_sample_secs)),
+            token,
+        )
+    });
+
+    server.spawn_processing(|token| {
+        start_processing_thread(
+            HandlerRegistry::default(),
+            clients,
+            peer_map,
+            msg_rx,
+            remove_rx,
+            world_map,
+            args.global_history_size,
+            args.sub_disconnect_grace_secs.map(Duration::from_secs),
+            token,
+        )
+    });
+
+    // Run until asked to stop or a task fails, then drain in-flight messages
+    tokio::select! {
+        _ = shutdown_signal() => (),
+        _ = server.failed() => error!("a server task failed, shutting down"),
+    }
+
+    server.shutdown().await
+}
+
+/// Connect to PostgreSQL and its read replica, if set, exiting if the primary can't be
+/// reached.
+async fn connect_pools(args: &Args) -> (Pool, Option<Pool>) {
+    let pool = match connect_pool(&args.psql_conn, args.db_pool_size).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            error!("PostgreSQL Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    info!("Connected to PostgreSQL");
+
+    // Reads stay on the primary if the replica can't be reached
+    let replica = match &args.psql_replica_conn {
+        None => None,
+        Some(replica_conn) => match connect_pool(replica_conn, args.db_pool_size).await {
+            Ok(replica) => {
+                info!("Connected to PostgreSQL read replica");
+                Some(replica)
+            }
+
+            Err(error) => {
+                warn!("Failed to connect to PostgreSQL read replica: {}", error);
+                None
+            }
+        },
+    };
+
+    (pool, replica)
+}
+
+/// Build a connection pool for `psql_conn`, connecting once to check it is reachable.
+async fn connect_pool(psql_conn: &str, max_size: usize) -> Result<Pool> {
+    let config = tokio_postgres::Config::from_str(psql_conn)?;
+    let manager = Manager::new(config, NoTls);
+    let pool = Pool::builder(manager).max_size(max_size).build()?;
+    let _ = pool.get().await?;
+
+    Ok(pool)
+}
+
+/// Create a client using the connection `pools` returned by [`connect_pools`].
+fn database_client(
+    args: &Args,
+    sanitize_config: &SanitizeConfig,
+    breaker: &CircuitBreaker,
+    pools: &(Pool, Option<Pool>),
+) -> DatabaseClient {
+    let (pool, replica) = pools;
+    let mut client = DatabaseClient::new(
+        pool.clone(),
+        args.db_region_x_size,
+        args.db_region_y_size,
+        args.db_region_z_size,
+        args.db_table_size,
+        args.db_cache_size,
+        RetryPolicy::new(
+            args.db_retry_attempts,
+            Duration::from_millis(args.db_retry_delay_ms),
+        ),
+        args.db_partition_strategy,
+    );
+
+    client.set_index_spec(args.db_index);
+    client.set_max_record_size(args.db_max_record_size);
+    if let Some(ms) = args.db_write_buffer_ms {
+        client.set_write_buffer(args.db_write_buffer_records, Duration::from_millis(ms));
+    }
+
+    if let Some(ms) = args.db_record_cache_ms {
+        client.set_record_cache(Duration::from_millis(ms), args.db_record_cache_size);
+    }
+
+    client.set_sanitize_config(sanitize_config.clone());
+    client.set_circuit_breaker(breaker.clone());
+    if args.db_record_ownership {
+        client.set_record_ownership(args.db_privileged_peers.iter().copied());
+    }
+
+    if let Some(replica) = replica {
+        client = client.with_read_replica(replica.clone());
+    }
+
+    client
 }
@@ -0,0 +1,102 @@
+#[cfg(any(feature = "http", feature = "admin", test))]
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use ahash::AHashMap;
+
+// region: CounterVec Struct
+/// A family of monotonic counters, partitioned by a single label.
+#[derive(Debug)]
+// Labels are only read when rendering
+#[cfg_attr(not(any(feature = "http", feature = "admin")), allow(dead_code))]
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: RwLock<AHashMap<&'static str, AtomicU64>>,
+}
+
+impl CounterVec {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: RwLock::default(),
+        }
+    }
+
+    /// Increment the counter for `value` by one.
+    #[inline]
+    pub fn inc(&self, value: &'static str) {
+        self.inc_by(value, 1);
+    }
+
+    /// Increment the counter for `value` by `n`.
+    pub fn inc_by(&self, value: &'static str, n: u64) {
+        // Only take the write lock the first time a label value is seen
+        {
+            let values = self.values.read().unwrap();
+            if let Some(counter) = values.get(value) {
+                counter.fetch_add(n, Ordering::Relaxed);
+                return;
+            }
+        }
+
+        let mut values = self.values.write().unwrap();
+        values
+            .entry(value)
+            .or_default()
+            .fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Append this family in the Prometheus text format.
+    #[cfg(any(feature = "http", feature = "admin", test))]
+    pub fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} counter", self.name);
+
+        let values = self.values.read().unwrap();
+        let mut values = values.iter().collect::<Vec<_>>();
+        values.sort_unstable_by_key(|(value, _)| **value);
+
+        for (value, counter) in values {
+            let _ = writeln!(
+                out,
+                "{}{{{}=\"{}\"}} {}",
+                self.name,
+                self.label,
+                value,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let counter = CounterVec::new("messages_total", "Messages received", "instruction");
+        counter.inc("RecordRead");
+        counter.inc("Heartbeat");
+        counter.inc_by("RecordRead", 2);
+
+        let mut out = String::new();
+        counter.render(&mut out);
+
+        assert_eq!(
+            out,
+            "# HELP messages_total Messages received\n\
+             # TYPE messages_total counter\n\
+             messages_total{instruction=\"Heartbeat\"} 1\n\
+             messages_total{instruction=\"RecordRead\"} 3\n"
+        );
+    }
+}
+// endregion
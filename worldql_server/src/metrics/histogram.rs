@@ -0,0 +1,179 @@
+#[cfg(any(feature = "http", feature = "admin", test))]
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+
+/// Upper bounds of each bucket (seconds), matching the Prometheus client defaults.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// region: Histogram Struct
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+// endregion
+
+// region: HistogramVec Struct
+/// A family of duration histograms, partitioned by a single label.
+#[derive(Debug)]
+// Labels are only read when rendering
+#[cfg_attr(not(any(feature = "http", feature = "admin")), allow(dead_code))]
+pub struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: RwLock<AHashMap<&'static str, Arc<Histogram>>>,
+}
+
+impl HistogramVec {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            label,
+            values: RwLock::default(),
+        }
+    }
+
+    /// Record a single duration for `value`.
+    #[cfg(test)]
+    pub fn observe(&self, value: &'static str, duration: Duration) {
+        self.histogram(value).observe(duration);
+    }
+
+    /// Start timing, the elapsed time is recorded for `value` when the timer is dropped.
+    #[inline]
+    pub fn start_timer(&self, value: &'static str) -> Timer {
+        Timer {
+            histogram: self.histogram(value),
+            start: Instant::now(),
+        }
+    }
+
+    fn histogram(&self, value: &'static str) -> Arc<Histogram> {
+        // Only take the write lock the first time a label value is seen
+        if let Some(histogram) = self.values.read().unwrap().get(value) {
+            return histogram.clone();
+        }
+
+        let mut values = self.values.write().unwrap();
+        values.entry(value).or_default().clone()
+    }
+
+    /// Append this family in the Prometheus text format.
+    #[cfg(any(feature = "http", feature = "admin", test))]
+    pub fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", self.name);
+
+        let values = self.values.read().unwrap();
+        let mut values = values.iter().collect::<Vec<_>>();
+        values.sort_unstable_by_key(|(value, _)| **value);
+
+        let (name, label) = (self.name, self.label);
+        for (value, histogram) in values {
+            let count = histogram.count.load(Ordering::Relaxed);
+
+            let mut cumulative = 0;
+            for (bound, bucket) in BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}",
+                    name, label, value, bound, cumulative
+                );
+            }
+
+            let sum = histogram.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}",
+                name, label, value, count
+            );
+            let _ = writeln!(out, "{}_sum{{{}=\"{}\"}} {}", name, label, value, sum);
+            let _ = writeln!(out, "{}_count{{{}=\"{}\"}} {}", name, label, value, count);
+        }
+    }
+}
+// endregion
+
+// region: Timer Struct
+/// Records the time since it was created into a histogram when dropped.
+#[derive(Debug)]
+pub struct Timer {
+    histogram: Arc<Histogram>,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed());
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let histogram = HistogramVec::new("handler_seconds", "Handler time", "instruction");
+        histogram.observe("RecordRead", Duration::from_millis(20));
+        histogram.observe("RecordRead", Duration::from_millis(200));
+        histogram.observe("RecordRead", Duration::from_secs(20));
+
+        let mut out = String::new();
+        histogram.render(&mut out);
+
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "# TYPE handler_seconds histogram");
+        assert_eq!(
+            lines[2],
+            "handler_seconds_bucket{instruction=\"RecordRead\",le=\"0.005\"} 0"
+        );
+        assert_eq!(
+            lines[4],
+            "handler_seconds_bucket{instruction=\"RecordRead\",le=\"0.025\"} 1"
+        );
+        assert_eq!(
+            lines[7],
+            "handler_seconds_bucket{instruction=\"RecordRead\",le=\"0.25\"} 2"
+        );
+        assert_eq!(
+            lines[13],
+            "handler_seconds_bucket{instruction=\"RecordRead\",le=\"+Inf\"} 3"
+        );
+        assert_eq!(
+            lines[14],
+            "handler_seconds_sum{instruction=\"RecordRead\"} 20.22"
+        );
+        assert_eq!(
+            lines[15],
+            "handler_seconds_count{instruction=\"RecordRead\"} 3"
+        );
+    }
+}
+// endregion
@@ -0,0 +1,44 @@
+mod counter;
+mod histogram;
+
+use counter::CounterVec;
+use histogram::HistogramVec;
+use once_cell::sync::Lazy;
+
+/// Messages received from peers, by instruction.
+pub static MESSAGES_RECEIVED: Lazy<CounterVec> = Lazy::new(|| {
+    CounterVec::new(
+        "worldql_messages_received_total",
+        "Messages received from peers",
+        "instruction",
+    )
+});
+
+/// Time taken to handle a message on the processing thread, by instruction.
+pub static HANDLER_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        "worldql_handler_duration_seconds",
+        "Time taken to handle a message",
+        "instruction",
+    )
+});
+
+/// Time taken by database operations, by [`crate::database::DatabaseClient`] method.
+pub static DB_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        "worldql_db_operation_duration_seconds",
+        "Time taken by database operations",
+        "operation",
+    )
+});
+
+/// Render every metric in the Prometheus text exposition format.
+#[cfg(any(feature = "http", feature = "admin"))]
+pub fn render() -> String {
+    let mut out = String::new();
+    MESSAGES_RECEIVED.render(&mut out);
+    HANDLER_DURATION.render(&mut out);
+    DB_DURATION.render(&mut out);
+
+    out
+}
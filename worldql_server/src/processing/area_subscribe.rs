@@ -1,18 +1,26 @@
 use color_eyre::Result;
 use tracing::{debug, warn};
+use uuid::Uuid;
 
-use crate::structures::Message;
-use crate::subscriptions::WorldMap;
+use super::record_read::AREA_PARAMETER_PREFIX;
+use super::DbRouter;
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::{AddResult, CubeArea, ToCubeArea, WorldMap};
 use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
 use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
 
-pub(super) fn handle_area_subscribe(
+/// Subscribe `parameter` value for clients that only want live updates, skipping the
+/// records already stored in the area.
+pub(super) const LIVE_ONLY_PARAMETER: &str = "live_only";
+
+pub(super) async fn handle_area_subscribe(
     message: Message,
     peer_map: &ThreadPeerMap,
     world_map: &mut WorldMap,
+    db_tx: &DbRouter,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     // Ignore global world
     if message.world_name == GLOBAL_WORLD {
@@ -45,8 +53,76 @@ pub(super) fn handle_area_subscribe(
         }
     };
 
-    let area_map = world_map.get_mut(&world_name);
-    area_map.add_subscription(uuid, cube);
+    let (result, parameter) = match world_map.try_get_mut(&world_name) {
+        Ok(area_map) => {
+            let result = area_map.add_subscription(uuid, cube);
+            if result == AddResult::LimitReached {
+                warn!(
+                    "peer {} reached the subscription limit in world \"{}\", dropping AreaSubscribe",
+                    uuid, &world_name
+                );
+            }
+
+            (Some((result, area_map.cube_size())), result.to_string())
+        }
+
+        Err(error) => {
+            warn!("rejected AreaSubscribe from peer {}: {}", uuid, error);
+            (None, error.reason().to_string())
+        }
+    };
+
+    // Acknowledge with the outcome, echoing the world and position for correlation
+    let live_only = message.parameter.as_deref() == Some(LIVE_ONLY_PARAMETER);
+    let ack = Message {
+        instruction: Instruction::AreaSubscribe,
+        parameter: Some(parameter),
+        sender_uuid: Uuid::nil(),
+        world_name: message.world_name,
+        position: Some(cube),
+        ..Default::default()
+    };
+
+    let world_name = ack.world_name.clone();
+
+    // Lock peer map for only this section
+    {
+        let mut map = peer_map.write().await;
+        match map.get_mut(&uuid) {
+            Some(peer) => {
+                let _ = peer.send(ack).await;
+            }
+            None => {
+                warn!("Missing peer {} for AreaSubscribe ack!", &uuid);
+            }
+        }
+    }
+
+    let cube_size = match result {
+        Some((AddResult::Added, cube_size)) if !live_only => cube_size,
+        _ => return Ok(()),
+    };
+
+    // Send existing records for the newly subscribed area, the DB thread replies to the peer
+    let read = area_read(uuid, world_name, cube.to_cube_area(cube_size), cube_size);
+    if let Err(error) = db_tx.send(read) {
+        warn!("dropping area read for peer {}: {}", uuid, error);
+    }
 
     Ok(())
 }
+
+/// Returns an [`Instruction::RecordRead`] for every record stored within `cube`, sent on
+/// behalf of `uuid` so the database worker replies to the peer directly.
+pub(super) fn area_read(uuid: Uuid, world_name: String, cube: CubeArea, cube_size: u16) -> Message {
+    let (min, _) = cube.bounds(cube_size);
+
+    Message {
+        instruction: Instruction::RecordRead,
+        parameter: Some(format!("{}{}", AREA_PARAMETER_PREFIX, cube_size)),
+        sender_uuid: uuid,
+        world_name,
+        position: Some(min),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,183 @@
+use color_eyre::Result;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::area_subscribe::{area_read, LIVE_ONLY_PARAMETER};
+use super::DbRouter;
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::{AddResult, ToCubeArea, WorldMap};
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
+
+/// Largest radius accepted in a single [`Instruction::AreaSubscribeBulk`], in cubes.
+const MAX_BULK_RADIUS: u16 = 8;
+
+/// Subscribe to every area within a radius of `position` in one message.
+///
+/// `parameter` has the form `radius[;live_only]`, where `radius` is measured in cubes.
+/// A single ack is sent back with the parameter `added/total`, or the reason the world
+/// was rejected.
+pub(super) async fn handle_area_subscribe_bulk(
+    message: Message,
+    peer_map: &ThreadPeerMap,
+    world_map: &mut WorldMap,
+    db_tx: &DbRouter,
+) -> Result<()> {
+    trace_packet!(message);
+
+    // Ignore global world
+    if message.world_name == GLOBAL_WORLD {
+        return Ok(());
+    }
+
+    let uuid = message.sender_uuid;
+    let world_name = match sanitize_world_name(&message.world_name) {
+        Ok(world_name) => world_name,
+        Err(error) => {
+            warn!(
+                "peer {} sent invalid world name: {} ({})",
+                uuid, &message.world_name, error
+            );
+
+            return Ok(());
+        }
+    };
+
+    let parameter = message.parameter.as_deref().and_then(parse_parameter);
+    let (center, (radius, live_only)) = match (message.position, parameter) {
+        (Some(center), Some(parameter)) => (center, parameter),
+        _ => {
+            // TODO: Disconnect peer
+            debug!(
+                "invalid AreaSubscribeBulk from peer {}, missing position or radius",
+                &uuid
+            );
+
+            return Ok(());
+        }
+    };
+
+    if radius > MAX_BULK_RADIUS {
+        warn!(
+            "peer {} sent AreaSubscribeBulk with radius {} above the maximum of {}",
+            uuid, radius, MAX_BULK_RADIUS
+        );
+
+        return Ok(());
+    }
+
+    // Add every subscription under a single borrow
+    let area_map = match world_map.try_get_mut(&world_name) {
+        Ok(area_map) => area_map,
+        Err(error) => {
+            warn!("rejected AreaSubscribeBulk from peer {}: {}", uuid, error);
+
+            let ack = Message {
+                instruction: Instruction::AreaSubscribeBulk,
+                parameter: Some(error.reason().to_string()),
+                sender_uuid: Uuid::nil(),
+                world_name: message.world_name,
+                position: message.position,
+                ..Default::default()
+            };
+
+            let mut map = peer_map.write().await;
+            if let Some(peer) = map.get_mut(&uuid) {
+                let _ = peer.send(ack).await;
+            }
+
+            return Ok(());
+        }
+    };
+
+    let cube_size = area_map.cube_size();
+    let center = center.to_cube_area(cube_size);
+
+    let mut total = 0;
+    let mut added = vec![];
+    for cube in center.neighbors(radius, cube_size) {
+        total += 1;
+        match area_map.add_subscription(uuid, cube) {
+            AddResult::Added => added.push(cube),
+            AddResult::AlreadySubscribed => (),
+            AddResult::LimitReached => {
+                warn!(
+                    "peer {} reached the subscription limit in world \"{}\", dropping remaining AreaSubscribeBulk areas",
+                    uuid, &world_name
+                );
+
+                break;
+            }
+        }
+    }
+
+    // Acknowledge once with a summary, echoing the world and center for correlation
+    let ack = Message {
+        instruction: Instruction::AreaSubscribeBulk,
+        parameter: Some(format!("{}/{}", added.len(), total)),
+        sender_uuid: Uuid::nil(),
+        world_name: message.world_name,
+        position: message.position,
+        ..Default::default()
+    };
+
+    let world_name = ack.world_name.clone();
+
+    // Lock peer map for only this section
+    {
+        let mut map = peer_map.write().await;
+        match map.get_mut(&uuid) {
+            Some(peer) => {
+                let _ = peer.send(ack).await;
+            }
+            None => {
+                warn!("Missing peer {} for AreaSubscribeBulk ack!", &uuid);
+            }
+        }
+    }
+
+    if live_only {
+        return Ok(());
+    }
+
+    // Send existing records for each newly subscribed area, the DB thread replies to the peer
+    for cube in added {
+        let read = area_read(uuid, world_name.clone(), cube, cube_size);
+        if let Err(error) = db_tx.send(read) {
+            warn!("dropping area reads for peer {}: {}", uuid, error);
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a parameter of the form `radius[;live_only]`.
+fn parse_parameter(parameter: &str) -> Option<(u16, bool)> {
+    let mut parts = parameter.split(';');
+    let radius = parts.next()?.trim().parse().ok()?;
+
+    let live_only = match parts.next().map(str::trim) {
+        None => false,
+        Some(LIVE_ONLY_PARAMETER) => true,
+        Some(_) => return None,
+    };
+
+    Some((radius, live_only))
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_parameter() {
+        assert_eq!(super::parse_parameter("2"), Some((2, false)));
+        assert_eq!(super::parse_parameter("0;live_only"), Some((0, true)));
+
+        assert_eq!(super::parse_parameter(""), None);
+        assert_eq!(super::parse_parameter("-1"), None);
+        assert_eq!(super::parse_parameter("2;unknown"), None);
+    }
+}
+// endregion
@@ -1,18 +1,19 @@
 use color_eyre::Result;
 use tracing::{debug, warn};
+use uuid::Uuid;
 
-use crate::structures::Message;
+use crate::structures::{Instruction, Message};
 use crate::subscriptions::WorldMap;
 use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
 use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
 
-pub(super) fn handle_area_unsubscribe(
+pub(super) async fn handle_area_unsubscribe(
     message: Message,
     peer_map: &ThreadPeerMap,
     world_map: &mut WorldMap,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     // Ignore global world
     if message.world_name == GLOBAL_WORLD {
@@ -37,7 +38,7 @@ pub(super) fn handle_area_unsubscribe(
         None => {
             // TODO: Disconnect peer
             debug!(
-                "invalid AreaSubscribe from peer {}, missing position",
+                "invalid AreaUnsubscribe from peer {}, missing position",
                 &uuid
             );
 
@@ -45,8 +46,36 @@ pub(super) fn handle_area_unsubscribe(
         }
     };
 
-    let area_map = world_map.get_mut(&world_name);
-    area_map.remove_subscription(&uuid, cube);
+    // Never create a world just to unsubscribe from it
+    let removed = match world_map.get_existing_mut(&world_name) {
+        Some(area_map) => area_map.remove_subscription(&uuid, cube),
+        None => false,
+    };
+
+    // Acknowledge with the outcome, echoing the world and position for correlation
+    let parameter = match removed {
+        true => "unsubscribed",
+        false => "not_subscribed",
+    };
+
+    let ack = Message {
+        instruction: Instruction::AreaUnsubscribe,
+        parameter: Some(parameter.into()),
+        sender_uuid: Uuid::nil(),
+        world_name: message.world_name,
+        position: Some(cube),
+        ..Default::default()
+    };
+
+    let mut map = peer_map.write().await;
+    match map.get_mut(&uuid) {
+        Some(peer) => {
+            let _ = peer.send(ack).await;
+        }
+        None => {
+            warn!("Missing peer {} for AreaUnsubscribe ack!", &uuid);
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,153 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use color_eyre::Result;
+use flume::Sender;
+
+use crate::structures::{Instruction, Message, Record};
+use crate::utils::sanitize_world_name;
+
+// region: DbRouter Struct
+/// Forwards messages to the database workers, each of which owns its own
+/// [`crate::DatabaseClient`].
+///
+/// Every message for a world is sent to the same worker, so messages for a world are
+/// handled in order and its lookup caches and write buffer live on a single worker.
+#[derive(Debug, Clone)]
+pub struct DbRouter {
+    workers: Vec<Sender<Message>>,
+}
+
+impl DbRouter {
+    /// # Panics
+    /// Panics if `workers` is empty.
+    pub(super) fn new(workers: Vec<Sender<Message>>) -> Self {
+        assert!(
+            !workers.is_empty(),
+            "at least one database worker is required"
+        );
+        Self { workers }
+    }
+
+    /// Send `message` to the worker which handles its world.
+    ///
+    /// Record writes act on the world of each record rather than the message, so their
+    /// records are split across the workers which handle those worlds.
+    pub fn send(&self, mut message: Message) -> Result<()> {
+        let workers = self.workers.len();
+        let index = db_worker_index(&message.world_name, workers);
+
+        let writes_records = matches!(
+            message.instruction,
+            Instruction::RecordCreate | Instruction::RecordUpdate | Instruction::RecordDelete
+        );
+
+        if !writes_records || workers <= 1 {
+            self.workers[index].send(message)?;
+            return Ok(());
+        }
+
+        let mut split: Vec<Vec<Record>> = vec![Vec::new(); workers];
+        for record in std::mem::take(&mut message.records) {
+            let index = db_worker_index(&record.world_name, workers);
+            split[index].push(record);
+        }
+
+        for (index, records) in split.into_iter().enumerate() {
+            if records.is_empty() {
+                continue;
+            }
+
+            let message = Message {
+                records,
+                ..message.clone()
+            };
+
+            self.workers[index].send(message)?;
+        }
+
+        Ok(())
+    }
+}
+// endregion
+
+/// Returns the index of the database worker, out of `workers`, which handles `world_name`.
+///
+/// Names are sanitized first, so every spelling of a world maps to the same worker.
+pub fn db_worker_index(world_name: &str, workers: usize) -> usize {
+    if workers <= 1 {
+        return 0;
+    }
+
+    // The default hasher uses fixed keys, so a world always maps to the same worker
+    let mut hasher = DefaultHasher::new();
+    match sanitize_world_name(world_name) {
+        Ok(world_name) => world_name.hash(&mut hasher),
+        Err(_) => world_name.hash(&mut hasher),
+    }
+
+    (hasher.finish() % workers as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_worlds_consistently() {
+        let (tx_a, rx_a) = flume::unbounded();
+        let (tx_b, rx_b) = flume::unbounded();
+        let router = DbRouter::new(vec![tx_a, tx_b]);
+
+        for _ in 0..3 {
+            let message = Message {
+                world_name: "overworld".into(),
+                ..Default::default()
+            };
+
+            router.send(message).unwrap();
+        }
+
+        let counts = (rx_a.len(), rx_b.len());
+        assert!(counts == (3, 0) || counts == (0, 3));
+        assert_eq!(db_worker_index("overworld", 1), 0);
+    }
+
+    #[test]
+    fn splits_records_by_world() {
+        let (tx_a, rx_a) = flume::unbounded();
+        let (tx_b, rx_b) = flume::unbounded();
+        let router = DbRouter::new(vec![tx_a, tx_b]);
+
+        // Find two worlds handled by different workers
+        let world_a = "world_0".to_string();
+        let world_b = (1..)
+            .map(|i| format!("world_{}", i))
+            .find(|world| db_worker_index(world, 2) != db_worker_index(&world_a, 2))
+            .unwrap();
+
+        let record = |world_name: &str| Record {
+            world_name: world_name.into(),
+            ..Default::default()
+        };
+
+        let message = Message {
+            instruction: Instruction::RecordCreate,
+            world_name: world_a.clone(),
+            records: vec![record(&world_a), record(&world_b), record(&world_a)],
+            ..Default::default()
+        };
+
+        router.send(message).unwrap();
+
+        let messages = rx_a.drain().chain(rx_b.drain()).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        for message in messages {
+            let world_name = &message.records[0].world_name;
+            assert!(message.records.iter().all(|r| &r.world_name == world_name));
+
+            let expected = if world_name == &world_a { 2 } else { 1 };
+            assert_eq!(message.records.len(), expected);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+use uuid::Uuid;
+
+// region: DisconnectGrace Struct
+/// Disconnected peers whose subscriptions are kept until their grace window ends, so a
+/// quick reconnect with the same [`Uuid`] finds them in place.
+pub(super) struct DisconnectGrace {
+    grace: Duration,
+    /// Latest deadline for each peer, a peer that disconnects again restarts its window
+    deadlines: AHashMap<Uuid, Instant>,
+    /// Deadlines in the order they end, may hold stale entries for restarted windows
+    queue: VecDeque<(Instant, Uuid)>,
+}
+
+impl DisconnectGrace {
+    pub(super) fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            deadlines: AHashMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Start the grace window of a peer that disconnected at `now`.
+    pub(super) fn push(&mut self, uuid: Uuid, now: Instant) {
+        let deadline = now + self.grace;
+        self.deadlines.insert(uuid, deadline);
+        self.queue.push_back((deadline, uuid));
+    }
+
+    /// Returns when the next grace window ends.
+    #[inline]
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|(deadline, _)| *deadline)
+    }
+
+    /// Returns every peer whose grace window ended by `now`.
+    pub(super) fn expired(&mut self, now: Instant) -> Vec<Uuid> {
+        let mut expired = vec![];
+        while let Some((deadline, uuid)) = self.queue.front().copied() {
+            if deadline > now {
+                break;
+            }
+
+            self.queue.pop_front();
+            if self.deadlines.get(&uuid) == Some(&deadline) {
+                self.deadlines.remove(&uuid);
+                expired.push(uuid);
+            }
+        }
+
+        expired
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut grace = DisconnectGrace::new(second * 10);
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        grace.push(a, start);
+        grace.push(b, start + second);
+        assert_eq!(grace.next_deadline(), Some(start + second * 10));
+
+        // Disconnecting again restarts the window
+        grace.push(a, start + second * 5);
+
+        assert!(grace.expired(start + second * 9).is_empty());
+        assert_eq!(grace.expired(start + second * 11), vec![b]);
+        assert_eq!(grace.expired(start + second * 15), vec![a]);
+        assert_eq!(grace.next_deadline(), None);
+    }
+}
+// endregion
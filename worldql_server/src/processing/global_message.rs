@@ -1,21 +1,51 @@
 use color_eyre::Result;
 use tracing::warn;
+use uuid::Uuid;
 
 use crate::structures::{Message, Replication};
-use crate::subscriptions::WorldMap;
+use crate::subscriptions::{GlobalHistory, WorldMap};
 use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
 use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
 
+/// GlobalMessage `parameter` value which requests a replay of the world's recent history,
+/// instead of broadcasting.
+const HISTORY_PARAMETER: &str = "@history";
+
 pub(super) async fn handle_global_message(
     message: Message,
     peer_map: &ThreadPeerMap,
     world_map: &WorldMap,
+    history: &mut GlobalHistory,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     let uuid = message.sender_uuid;
-    if message.world_name == GLOBAL_WORLD {
+    let world_name = match message.world_name.as_str() {
+        GLOBAL_WORLD => GLOBAL_WORLD.to_string(),
+        _ => match sanitize_world_name(&message.world_name) {
+            Ok(world_name) => world_name,
+            Err(error) => {
+                warn!(
+                    "peer {} sent invalid world name: {} ({:?})",
+                    uuid, &message.world_name, error
+                );
+
+                return Ok(());
+            }
+        },
+    };
+
+    if message.parameter.as_deref() == Some(HISTORY_PARAMETER) {
+        return replay_history(uuid, &world_name, peer_map, history).await;
+    }
+
+    // Messages only sent to self aren't worth replaying
+    if history.is_enabled() && message.replication != Replication::OnlySelf {
+        history.push(&world_name, message.clone());
+    }
+
+    if world_name == GLOBAL_WORLD {
         // Broadcast to all
         let mut map = peer_map.write().await;
 
@@ -34,18 +64,6 @@ pub(super) async fn handle_global_message(
             }
         };
     } else {
-        let world_name = match sanitize_world_name(&message.world_name) {
-            Ok(world_name) => world_name,
-            Err(error) => {
-                warn!(
-                    "peer {} sent invalid world name: {} ({:?})",
-                    uuid, &message.world_name, error
-                );
-
-                return Ok(());
-            }
-        };
-
         // Broadcast to subscribed
         let area_map = world_map.get(&world_name);
         if area_map.is_none() {
@@ -86,3 +104,28 @@ pub(super) async fn handle_global_message(
 
     Ok(())
 }
+
+/// Send every stored message for the world to the requesting peer, oldest first.
+async fn replay_history(
+    uuid: Uuid,
+    world_name: &str,
+    peer_map: &ThreadPeerMap,
+    history: &GlobalHistory,
+) -> Result<()> {
+    let mut map = peer_map.write().await;
+    let peer = match map.get_mut(&uuid) {
+        Some(peer) => peer,
+        None => {
+            warn!("Missing peer {} for GlobalMessage history replay!", &uuid);
+            return Ok(());
+        }
+    };
+
+    for message in history.get(world_name) {
+        if peer.send(message.clone()).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
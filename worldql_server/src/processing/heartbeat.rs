@@ -7,7 +7,7 @@ use crate::trace_packet;
 use crate::transport::ThreadPeerMap;
 
 pub(super) async fn handle_heartbeat(message: Message, peer_map: &ThreadPeerMap) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     let uuid = message.sender_uuid;
     let mut map = peer_map.write().await;
@@ -30,8 +30,7 @@ pub(super) async fn handle_heartbeat(message: Message, peer_map: &ThreadPeerMap)
     };
 
     // Update last received time
-    #[cfg(feature = "zeromq")]
-    peer.update_last_heartbeat();
+    peer.touch();
 
     // Echo back heartbeat
     let message = Message {
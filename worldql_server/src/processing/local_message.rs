@@ -12,7 +12,7 @@ pub(super) async fn handle_local_message(
     peer_map: &ThreadPeerMap,
     world_map: &WorldMap,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     if message.world_name == GLOBAL_WORLD {
         debug!(
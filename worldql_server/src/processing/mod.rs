@@ -1,11 +1,23 @@
 mod area_subscribe;
+mod area_subscribe_bulk;
 mod area_unsubscribe;
+mod db_router;
+mod disconnect_grace;
 mod global_message;
 mod heartbeat;
 mod local_message;
+mod peer_disconnect;
+mod position_update;
+mod query_peers;
 mod record_create;
 mod record_delete;
 mod record_read;
+mod record_update;
+mod registry;
 mod thread;
+mod time_sync;
+mod unsubscribe_all;
 
+pub use db_router::{db_worker_index, DbRouter};
+pub use registry::HandlerRegistry;
 pub use thread::start_processing_thread;
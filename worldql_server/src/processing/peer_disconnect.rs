@@ -0,0 +1,40 @@
+use color_eyre::Result;
+use uuid::Uuid;
+
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::WorldMap;
+use crate::transport::ThreadPeerMap;
+
+/// Removes a disconnected peer's subscriptions, telling every peer that shared an area
+/// with it so they can drop it from their view of that world.
+pub(super) async fn handle_peer_disconnect(
+    uuid: Uuid,
+    peer_map: &ThreadPeerMap,
+    world_map: &mut WorldMap,
+) -> Result<()> {
+    // Neighbours must be found before the reverse index entries are removed
+    let neighbours = world_map
+        .get_peer_neighbours(&uuid)
+        .into_iter()
+        .map(|(world_name, peers)| (world_name.to_string(), peers))
+        .collect::<Vec<_>>();
+
+    world_map.remove_peer(&uuid);
+    if neighbours.is_empty() {
+        return Ok(());
+    }
+
+    let mut map = peer_map.write().await;
+    for (world_name, peers) in neighbours {
+        let message = Message {
+            instruction: Instruction::PeerDisconnect,
+            parameter: Some(uuid.to_string()),
+            world_name,
+            ..Default::default()
+        };
+
+        map.broadcast_to(message, peers.into_iter()).await?;
+    }
+
+    Ok(())
+}
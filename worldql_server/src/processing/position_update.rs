@@ -0,0 +1,45 @@
+use color_eyre::Result;
+use tracing::{debug, warn};
+
+use crate::structures::Message;
+use crate::utils::GLOBAL_WORLD;
+use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
+
+pub(super) async fn handle_position_update(
+    message: Message,
+    database_client: &mut DatabaseClient,
+    _peer_map: &ThreadPeerMap,
+) -> Result<()> {
+    trace_packet!(message);
+
+    // Ignore global world
+    if message.world_name == GLOBAL_WORLD {
+        return Ok(());
+    }
+
+    // Only the UUID and new position of each record are used
+    let uuid = message.sender_uuid;
+    for record in message.records {
+        let position = match record.position {
+            Some(position) => position,
+            None => {
+                debug!(
+                    "invalid PositionUpdate from peer {}, record {} missing position",
+                    &uuid, &record.uuid
+                );
+
+                continue;
+            }
+        };
+
+        let result = database_client
+            .update_record_position_as(uuid, &message.world_name, record.uuid, position)
+            .await;
+
+        if let Err(error) = result {
+            warn!("peer {} position update error: {}", uuid, error);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use color_eyre::Result;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::WorldMap;
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+use crate::utils::{sanitize_world_name, GLOBAL_WORLD};
+
+/// Maximum number of peers returned for a single area.
+const MAX_QUERY_PEERS: usize = 256;
+
+pub(super) async fn handle_query_peers(
+    message: Message,
+    peer_map: &ThreadPeerMap,
+    world_map: &WorldMap,
+) -> Result<()> {
+    trace_packet!(message);
+
+    // Ignore global world
+    if message.world_name == GLOBAL_WORLD {
+        return Ok(());
+    }
+
+    let uuid = message.sender_uuid;
+    let world_name = match sanitize_world_name(&message.world_name) {
+        Ok(world_name) => world_name,
+        Err(error) => {
+            warn!(
+                "peer {} sent invalid world name: {} ({})",
+                uuid, &message.world_name, error
+            );
+
+            return Ok(());
+        }
+    };
+
+    let cube = match message.position {
+        Some(pos) => pos,
+        None => {
+            debug!("invalid QueryPeers from peer {}, missing position", &uuid);
+            return Ok(());
+        }
+    };
+
+    let mut map = peer_map.write().await;
+
+    // Reply with a comma separated list of UUIDs, leaving out peers that opted out
+    let peers = match world_map.get(&world_name) {
+        None => vec![],
+        Some(area_map) => area_map
+            .get_subscribed_peers(cube)
+            .filter(|peer| map.get(peer).map_or(false, |peer| !peer.hidden()))
+            .take(MAX_QUERY_PEERS)
+            .map(|peer| peer.to_string())
+            .collect::<Vec<_>>(),
+    };
+
+    let reply = Message {
+        instruction: Instruction::QueryPeers,
+        parameter: Some(peers.join(",")),
+        sender_uuid: Uuid::nil(),
+        world_name: message.world_name,
+        position: Some(cube),
+        ..Default::default()
+    };
+
+    match map.get_mut(&uuid) {
+        Some(peer) => {
+            let _ = peer.send(reply).await;
+        }
+        None => {
+            warn!("Missing peer {} for QueryPeers reply!", &uuid);
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use color_eyre::Result;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use crate::structures::Message;
 use crate::utils::GLOBAL_WORLD;
@@ -8,9 +8,9 @@ use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
 pub(super) async fn handle_record_create(
     message: Message,
     database_client: &mut DatabaseClient,
-    peer_map: &ThreadPeerMap,
+    _peer_map: &ThreadPeerMap,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     // Ignore global world
     if message.world_name == GLOBAL_WORLD {
@@ -18,8 +18,24 @@ pub(super) async fn handle_record_create(
     }
 
     let uuid = message.sender_uuid;
-    let errors = database_client.insert_records(message.records).await;
-    for error in errors {
+    let count = message.records.len();
+    let report = match database_client
+        .buffer_records(message.records, Some(uuid))
+        .await
+    {
+        Some(report) => report,
+        None => {
+            debug!("peer {} buffered {} records", uuid, count);
+            return Ok(());
+        }
+    };
+
+    // A flush can include records buffered from other peers
+    debug!(
+        "peer {} inserted {}/{} records",
+        uuid, report.inserted, count
+    );
+    for error in report.errors {
         warn!("peer {} record create error: {}", uuid, error);
     }
 
@@ -8,9 +8,9 @@ use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
 pub(super) async fn handle_record_delete(
     message: Message,
     database_client: &mut DatabaseClient,
-    peer_map: &ThreadPeerMap,
+    _peer_map: &ThreadPeerMap,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     // Ignore global world
     if message.world_name == GLOBAL_WORLD {
@@ -18,7 +18,9 @@ pub(super) async fn handle_record_delete(
     }
 
     let uuid = message.sender_uuid;
-    let errors = database_client.delete_records(message.records).await;
+    let errors = database_client
+        .delete_records_as(uuid, message.records)
+        .await;
     for error in errors {
         warn!("peer {} record remove error: {}", uuid, error);
     }
@@ -1,19 +1,24 @@
-use std::collections::HashMap;
-
 use color_eyre::Result;
 use tracing::warn;
 
-use crate::database::DedupeData;
-use crate::structures::{Instruction, Message};
+use crate::database::RegionRecords;
+use crate::structures::{Instruction, Message, Vector3};
 use crate::utils::GLOBAL_WORLD;
 use crate::{trace_packet, DatabaseClient, ThreadPeerMap};
 
+/// Prefix of the [`Instruction::RecordRead`] parameter `area=<size>`, which reads every
+/// record in the box of `size` along each axis with its min corner at `position`.
+pub(super) const AREA_PARAMETER_PREFIX: &str = "area=";
+
+/// Maximum number of records sent in a single [`Instruction::RecordReply`].
+const RECORD_REPLY_CHUNK_SIZE: usize = 256;
+
 pub(super) async fn handle_record_read(
     message: Message,
     database_client: &mut DatabaseClient,
     peer_map: &ThreadPeerMap,
 ) -> Result<()> {
-    trace_packet!("{}", &message);
+    trace_packet!(message);
 
     // Ignore global world
     if message.world_name == GLOBAL_WORLD {
@@ -21,118 +26,142 @@ pub(super) async fn handle_record_read(
     }
 
     let uuid = message.sender_uuid;
-    match message.position {
-        // Handle messages with position
-        Some(position) => {
-            // Extract parameter
-            let after = match message.parameter {
-                None => None,
-                Some(parameter) => {
-                    let ts = match crate::utils::parse_epoch_millis(&parameter) {
-                        Ok(ts) => ts,
-                        Err(error) => {
-                            warn!("error parsing timestamp for {}: {}", uuid, error);
-                            return Ok(());
-                        }
-                    };
-
-                    Some(ts)
-                }
-            };
 
-            let result = database_client
-                .get_records_in_region(&message.world_name, position, after)
-                .await;
+    // Clients can correlate replies to requests by sending a request ID as `flex`
+    let request_id = message.flex;
 
-            let records = match result {
-                Ok(records) => records,
+    let parameter = message.parameter.as_deref();
+    let area = parameter.and_then(|parameter| parameter.strip_prefix(AREA_PARAMETER_PREFIX));
+    let result = match (message.position, area) {
+        // Read the box of the given size with its min corner at `position`
+        (Some(position), Some(size)) => {
+            let size = match size.parse::<u16>() {
+                Ok(size) => f64::from(size),
                 Err(error) => {
-                    warn!("error getting records for {}: {}", uuid, error);
+                    warn!("error parsing area size for {}: {}", uuid, error);
                     return Ok(());
                 }
             };
 
-            // Early return to avoid locking the peer map
-            if records.is_empty() {
-                return Ok(());
-            }
+            let max = position + Vector3::new(size, size, size);
+            database_client
+                .get_records_in_box(&message.world_name, position, max)
+                .await
+                .map(RegionRecords::from_rows)
+                .map_err(Into::into)
+        }
+
+        (None, Some(_)) => {
+            warn!(
+                "invalid RecordRead from {}, area reads need a position",
+                uuid
+            );
+            return Ok(());
+        }
 
-            // Deduplicate records
-            let deduped = {
-                let mut map = HashMap::new();
-                for (ts, record) in records {
-                    match map.get(&record.uuid) {
-                        // Not seen before, insert
-                        None => {
-                            map.insert(record.uuid, (ts, record));
-                        }
-
-                        // Seen before, check timestamp
-                        Some((existing_ts, _)) => {
-                            // Only insert if timestamp is later
-                            if &ts >= existing_ts {
-                                map.insert(record.uuid, (ts, record));
-                            }
-                        }
+        // Optionally only read records modified after a timestamp
+        (position, None) => {
+            let after = match parameter {
+                None => None,
+                Some(parameter) => match crate::utils::parse_epoch_millis(parameter) {
+                    Ok(ts) => Some(ts),
+                    Err(error) => {
+                        warn!("error parsing timestamp for {}: {}", uuid, error);
+                        return Ok(());
                     }
+                },
+            };
+
+            match position {
+                Some(position) => {
+                    database_client
+                        .get_region_records(&message.world_name, position, after)
+                        .await
                 }
 
-                map.into_values().collect::<Vec<_>>()
-            };
+                // Records without a position live in the world's global table
+                None => database_client
+                    .get_global_records(&message.world_name, after)
+                    .await
+                    .map_err(Into::into),
+            }
+        }
+    };
+
+    let records = match result {
+        Ok(RegionRecords::Records(records)) => records,
+        Ok(empty) => {
+            // Early return to avoid locking the peer map, unless the client awaits a reply
+            if request_id.is_none() {
+                return Ok(());
+            }
 
-            // Extract dedupe command information from list
-            let dedupe_ops = deduped
-                .iter()
-                .map(|(ts, record)| {
-                    let data: DedupeData = (
-                        record.uuid,
-                        *ts,
-                        record.world_name.clone(),
-                        // TODO: Handle records without position
-                        record.position.unwrap(),
-                    );
-
-                    data
-                })
-                .collect::<Vec<DedupeData>>();
-
-            // Extract only records from deduplicated list
-            let records = deduped
-                .into_iter()
-                .map(|(_, record)| record)
-                .collect::<Vec<_>>();
+            // Regions that were never written reply with no chunks at all, so clients
+            // know to initialize their default content
+            let parameter = if empty.is_never_written() {
+                "0/0"
+            } else {
+                "1/1"
+            };
 
             let reply = Message {
                 instruction: Instruction::RecordReply,
+                parameter: Some(parameter.into()),
                 world_name: message.world_name,
-                records,
+                flex: request_id,
                 ..Default::default()
             };
 
-            // Lock peer map for only this section
-            {
-                let mut map = peer_map.write().await;
-                let peer = map.get_mut(&uuid);
-                if peer.is_none() {
-                    warn!("Missing peer {} for GlobalMessage send!", &uuid);
-                    return Ok(());
-                }
-
-                let peer = peer.unwrap();
+            let mut map = peer_map.write().await;
+            if let Some(peer) = map.get_mut(&uuid) {
                 let _ = peer.send(reply).await;
             }
 
-            // Deduplicate records in background
-            let result = database_client.dedupe_records(dedupe_ops).await;
-            if let Err(error) = result {
-                warn!("error deduping records for {}: {}", uuid, error);
-                return Ok(());
-            }
+            return Ok(());
+        }
+        Err(error) => {
+            warn!("error getting records for {}: {}", uuid, error);
+            return Ok(());
+        }
+    };
+
+    let mut records = records
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect::<Vec<_>>();
+
+    // Split large replies into chunks, each tagged with `index/total`
+    let total = (records.len() + RECORD_REPLY_CHUNK_SIZE - 1) / RECORD_REPLY_CHUNK_SIZE;
+    let mut replies = Vec::with_capacity(total);
+    for index in 1..=total {
+        let rest = records.split_off(records.len().min(RECORD_REPLY_CHUNK_SIZE));
+        let chunk = std::mem::replace(&mut records, rest);
+
+        replies.push(Message {
+            instruction: Instruction::RecordReply,
+            parameter: Some(format!("{}/{}", index, total)),
+            world_name: message.world_name.clone(),
+            records: chunk,
+            flex: request_id.clone(),
+            ..Default::default()
+        });
+    }
+
+    // Lock peer map for only this section
+    {
+        let mut map = peer_map.write().await;
+        let peer = map.get_mut(&uuid);
+        if peer.is_none() {
+            warn!("Missing peer {} for RecordReply send!", &uuid);
+            return Ok(());
         }
 
-        // Handle messages without position
-        // TODO
-        None => todo!(),
+        let peer = peer.unwrap();
+        for reply in replies {
+            if peer.send(reply).await.is_err() {
+                break;
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,231 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use ahash::AHashMap;
+use color_eyre::Result;
+
+use super::area_subscribe::handle_area_subscribe as area_subscribe;
+use super::area_subscribe_bulk::handle_area_subscribe_bulk as area_subscribe_bulk;
+use super::area_unsubscribe::handle_area_unsubscribe as area_unsubscribe;
+use super::global_message::handle_global_message as global_message;
+use super::heartbeat::handle_heartbeat as heartbeat;
+use super::local_message::handle_local_message as local_message;
+use super::position_update::handle_position_update as position_update;
+use super::query_peers::handle_query_peers as query_peers;
+use super::record_create::handle_record_create as record_create;
+use super::record_delete::handle_record_delete as record_delete;
+use super::record_read::handle_record_read as record_read;
+use super::record_update::handle_record_update as record_update;
+use super::time_sync::handle_time_sync as time_sync;
+use super::unsubscribe_all::handle_unsubscribe_all as unsubscribe_all;
+use super::DbRouter;
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::{GlobalHistory, WorldMap};
+use crate::transport::ThreadPeerMap;
+use crate::DatabaseClient;
+
+/// Future returned by instruction handlers, borrowing from the handler's context.
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+type InlineHandler =
+    Box<dyn for<'a> Fn(Message, InlineContext<'a>) -> HandlerFuture<'a> + Send + Sync>;
+type SubHandler = Box<dyn for<'a> Fn(Message, SubContext<'a>) -> HandlerFuture<'a> + Send + Sync>;
+type DbHandler = Box<dyn for<'a> Fn(Message, DbContext<'a>) -> HandlerFuture<'a> + Send + Sync>;
+
+// region: Handler Contexts
+/// State available to handlers that run as soon as a message is received.
+pub struct InlineContext<'a> {
+    pub peer_map: &'a ThreadPeerMap,
+}
+
+/// State available to handlers on the subscription task, which owns the [`WorldMap`].
+pub struct SubContext<'a> {
+    pub peer_map: &'a ThreadPeerMap,
+    pub world_map: &'a mut WorldMap,
+    /// Forwards messages to the database workers
+    pub db_tx: &'a DbRouter,
+    pub history: &'a mut GlobalHistory,
+}
+
+/// State available to handlers on a database worker, which owns its own [`DatabaseClient`].
+pub struct DbContext<'a> {
+    pub peer_map: &'a ThreadPeerMap,
+    pub database_client: &'a mut DatabaseClient,
+}
+// endregion
+
+// region: HandlerRegistry Struct
+/// Where a registered [`Instruction`] is handled.
+pub(super) enum Route {
+    /// Handled immediately by the processing thread, for cheap handlers only
+    Inline(InlineHandler),
+    /// Handled in order on the subscription task
+    Sub(SubHandler),
+    /// Handled in order for each world, on the database worker for the message's world
+    Db(DbHandler),
+}
+
+/// Maps each [`Instruction`] to the async handler that processes it.
+///
+/// Registering an instruction again replaces its handler. Instructions without a handler
+/// are logged and dropped.
+pub struct HandlerRegistry {
+    routes: AHashMap<Instruction, Route>,
+}
+
+impl HandlerRegistry {
+    /// Creates a registry without any handlers.
+    pub fn empty() -> Self {
+        Self {
+            routes: AHashMap::new(),
+        }
+    }
+
+    /// Creates a registry with a handler for every built-in [`Instruction`].
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .on_inline(Instruction::Heartbeat, |message, ctx| {
+                Box::pin(heartbeat(message, ctx.peer_map))
+            })
+            .on_inline(Instruction::TimeSync, |message, ctx| {
+                Box::pin(time_sync(message, ctx.peer_map))
+            });
+
+        registry
+            .on_sub(Instruction::AreaSubscribe, |message, ctx| {
+                Box::pin(area_subscribe(
+                    message,
+                    ctx.peer_map,
+                    ctx.world_map,
+                    ctx.db_tx,
+                ))
+            })
+            .on_sub(Instruction::AreaSubscribeBulk, |message, ctx| {
+                Box::pin(area_subscribe_bulk(
+                    message,
+                    ctx.peer_map,
+                    ctx.world_map,
+                    ctx.db_tx,
+                ))
+            })
+            .on_sub(Instruction::AreaUnsubscribe, |message, ctx| {
+                Box::pin(area_unsubscribe(message, ctx.peer_map, ctx.world_map))
+            })
+            .on_sub(Instruction::UnsubscribeAll, |message, ctx| {
+                Box::pin(unsubscribe_all(message, ctx.peer_map, ctx.world_map))
+            })
+            .on_sub(Instruction::QueryPeers, |message, ctx| {
+                Box::pin(query_peers(message, ctx.peer_map, ctx.world_map))
+            })
+            .on_sub(Instruction::LocalMessage, |message, ctx| {
+                Box::pin(local_message(message, ctx.peer_map, ctx.world_map))
+            })
+            .on_sub(Instruction::GlobalMessage, |message, ctx| {
+                Box::pin(global_message(
+                    message,
+                    ctx.peer_map,
+                    ctx.world_map,
+                    ctx.history,
+                ))
+            });
+
+        registry
+            .on_db(Instruction::RecordCreate, |message, ctx| {
+                Box::pin(record_create(message, ctx.database_client, ctx.peer_map))
+            })
+            .on_db(Instruction::RecordRead, |message, ctx| {
+                Box::pin(record_read(message, ctx.database_client, ctx.peer_map))
+            })
+            .on_db(Instruction::RecordUpdate, |message, ctx| {
+                Box::pin(record_update(message, ctx.database_client, ctx.peer_map))
+            })
+            .on_db(Instruction::PositionUpdate, |message, ctx| {
+                Box::pin(position_update(message, ctx.database_client, ctx.peer_map))
+            })
+            .on_db(Instruction::RecordDelete, |message, ctx| {
+                Box::pin(record_delete(message, ctx.database_client, ctx.peer_map))
+            });
+
+        registry
+    }
+
+    /// Handle `instruction` immediately on the processing thread.
+    ///
+    /// Inline handlers delay every message behind them, so keep them cheap.
+    pub fn on_inline<F>(&mut self, instruction: Instruction, handler: F) -> &mut Self
+    where
+        F: for<'a> Fn(Message, InlineContext<'a>) -> HandlerFuture<'a> + Send + Sync + 'static,
+    {
+        self.routes
+            .insert(instruction, Route::Inline(Box::new(handler)));
+
+        self
+    }
+
+    /// Handle `instruction` in order with the other subscription messages.
+    pub fn on_sub<F>(&mut self, instruction: Instruction, handler: F) -> &mut Self
+    where
+        F: for<'a> Fn(Message, SubContext<'a>) -> HandlerFuture<'a> + Send + Sync + 'static,
+    {
+        self.routes
+            .insert(instruction, Route::Sub(Box::new(handler)));
+        self
+    }
+
+    /// Handle `instruction` in order with the other database messages.
+    pub fn on_db<F>(&mut self, instruction: Instruction, handler: F) -> &mut Self
+    where
+        F: for<'a> Fn(Message, DbContext<'a>) -> HandlerFuture<'a> + Send + Sync + 'static,
+    {
+        self.routes
+            .insert(instruction, Route::Db(Box::new(handler)));
+        self
+    }
+
+    /// Returns the route registered for `instruction`.
+    #[inline]
+    pub(super) fn get(&self, instruction: &Instruction) -> Option<&Route> {
+        self.routes.get(instruction)
+    }
+}
+
+impl Default for HandlerRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_routes() {
+        let mut registry = HandlerRegistry::with_builtins();
+        assert!(matches!(
+            registry.get(&Instruction::Heartbeat),
+            Some(Route::Inline(_))
+        ));
+        assert!(matches!(
+            registry.get(&Instruction::GlobalMessage),
+            Some(Route::Sub(_))
+        ));
+        assert!(matches!(
+            registry.get(&Instruction::PositionUpdate),
+            Some(Route::Db(_))
+        ));
+        assert!(registry.get(&Instruction::Unknown).is_none());
+        assert!(registry.get(&Instruction::RecordReply).is_none());
+
+        // Registering again replaces the route
+        registry.on_db(Instruction::Heartbeat, |_, _| Box::pin(async { Ok(()) }));
+        assert!(matches!(
+            registry.get(&Instruction::Heartbeat),
+            Some(Route::Db(_))
+        ));
+    }
+}
+// endregion
@@ -1,54 +1,109 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use color_eyre::Result;
 use flume::{Receiver, Sender};
-use tracing::{info, warn};
+use futures_util::future::try_join_all;
+use futures_util::FutureExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-use super::area_subscribe::handle_area_subscribe as area_subscribe;
-use super::area_unsubscribe::handle_area_unsubscribe as area_unsubscribe;
-use super::global_message::handle_global_message as global_message;
-use super::heartbeat::handle_heartbeat as heartbeat;
-use super::local_message::handle_local_message as local_message;
-use super::record_create::handle_record_create as record_create;
-use super::record_delete::handle_record_delete as record_delete;
-use super::record_read::handle_record_read as record_read;
+use super::disconnect_grace::DisconnectGrace;
+use super::peer_disconnect::handle_peer_disconnect as peer_disconnect;
+use super::registry::{DbContext, HandlerRegistry, InlineContext, Route, SubContext};
+use super::DbRouter;
+use crate::metrics::HANDLER_DURATION;
 use crate::structures::{Instruction, Message};
-use crate::subscriptions::WorldMap;
+use crate::subscriptions::{GlobalHistory, ThreadWorldMap, WorldMap};
 use crate::transport::ThreadPeerMap;
+use crate::utils::GLOBAL_WORLD;
 use crate::{trace_packet, DatabaseClient};
 
+/// Parameter of the reply to a database message skipped while the database is unreachable.
+const UNAVAILABLE_REASON: &str = "database_unavailable";
+
+/// Starts the processing stage, with a database worker for each of `database_clients`.
+///
+/// Subscriptions of disconnected peers are kept for `disconnect_grace` if set, so peers
+/// that reconnect with the same UUID in time keep them.
+///
+/// # Panics
+/// Panics if `database_clients` is empty.
+#[allow(clippy::too_many_arguments)]
 pub async fn start_processing_thread(
-    database_client: DatabaseClient,
+    registry: HandlerRegistry,
+    database_clients: Vec<DatabaseClient>,
     peer_map: ThreadPeerMap,
     msg_rx: Receiver<Message>,
     remove_rx: Receiver<Uuid>,
-    cube_size: u16,
+    world_map: ThreadWorldMap,
+    global_history_size: usize,
+    disconnect_grace: Option<Duration>,
+    token: CancellationToken,
 ) -> Result<()> {
     let (sub_tx, sub_rx) = flume::unbounded();
-    let (db_tx, db_rx) = flume::unbounded();
+    let registry = Arc::new(registry);
 
-    let mut db = tokio::spawn(handle_db_messages(db_rx, peer_map.clone(), database_client));
+    // Workers share nothing, so a slow query for one world never holds up the others
+    let mut db_txs = Vec::with_capacity(database_clients.len());
+    let mut db_workers = Vec::with_capacity(database_clients.len());
+    for database_client in database_clients {
+        let (db_tx, db_rx) = flume::unbounded();
+        db_txs.push(db_tx);
+        db_workers.push(tokio::spawn(handle_db_messages(
+            db_rx,
+            registry.clone(),
+            peer_map.clone(),
+            database_client,
+        )));
+    }
+
+    let db_tx = DbRouter::new(db_txs);
+    let mut db = tokio::spawn(async move {
+        try_join_all(
+            db_workers
+                .into_iter()
+                .map(|worker| async move { worker.await? }),
+        )
+        .await
+    });
     let mut sub = tokio::spawn(handle_sub_messages(
         sub_rx,
+        registry.clone(),
         remove_rx,
+        db_tx.clone(),
         peer_map.clone(),
-        cube_size,
+        world_map,
+        global_history_size,
+        disconnect_grace,
     ));
 
     loop {
         tokio::select! {
             // Handle incoming messages
             Ok(message) = msg_rx.recv_async() => {
-                handle_message(&sub_tx, &db_tx, &peer_map, message).await?;
+                handle_message(&registry, &sub_tx, &db_tx, &peer_map, message).await?;
+            },
+
+            // Exit early if sub processing stops
+            result = &mut sub => {
+                return result?;
             },
 
-            // Exit early if sub processing errors
-            Ok(Err(error)) = &mut sub => {
-                return Err(error);
+            // Exit early if any DB worker stops
+            result = &mut db => {
+                result??;
+                return Ok(());
             },
 
-            // Exit early if DB processing errors
-            Ok(Err(error)) = &mut db => {
-                return Err(error);
+            // Stop waiting for new messages on shutdown
+            _ = token.cancelled() => {
+                info!("processing thread shutdown triggered.");
+                break
             },
 
             // Both channels have closed, exit thread
@@ -59,120 +114,406 @@ pub async fn start_processing_thread(
         }
     }
 
+    // Flush messages that were queued before shutdown
+    while let Ok(message) = msg_rx.try_recv() {
+        handle_message(&registry, &sub_tx, &db_tx, &peer_map, message).await?;
+    }
+
+    // Closing the channels lets the sub task and DB workers finish their queues, then exit
+    // The sub task holds its own DB router, so it must finish first
+    drop(sub_tx);
+    drop(db_tx);
+
+    sub.await??;
+    db.await??;
+
     Ok(())
 }
 
 #[inline]
 async fn handle_message(
+    registry: &HandlerRegistry,
     sub_tx: &Sender<Message>,
-    db_tx: &Sender<Message>,
+    db_tx: &DbRouter,
     peer_map: &ThreadPeerMap,
     message: Message,
 ) -> Result<()> {
     match message.instruction {
-        // Panic on handshakes, they should never be sent to this thread.
-        Instruction::Handshake => panic!("recieved handshake instruction on processing thread"),
+        // Drop handshakes and client-bound instructions, a misbehaving peer shouldn't stop the server
+        Instruction::Handshake
+        | Instruction::HandshakeAck
+        | Instruction::HandshakeRejected
+        | Instruction::PeerConnect
+        | Instruction::PeerDisconnect
+        | Instruction::RecordReply => {
+            warn!(
+                "dropping {} instruction received from {}",
+                message.instruction, message.sender_uuid
+            );
 
-        // Panic on incoming client-bound instructions
-        Instruction::PeerConnect | Instruction::PeerDisconnect | Instruction::RecordReply => {
-            panic!("received incoming client-bound instruction")
+            return Ok(());
         }
 
-        // Instantly handle heartbeats
-        Instruction::Heartbeat => heartbeat(message, peer_map).await?,
+        _ => (),
+    }
 
-        // Handle subscription messages
-        Instruction::AreaSubscribe
-        | Instruction::AreaUnsubscribe
-        | Instruction::GlobalMessage
-        | Instruction::LocalMessage => {
-            sub_tx.send_async(message).await?;
+    match registry.get(&message.instruction) {
+        // Instantly handle cheap messages, eg: heartbeats
+        Some(Route::Inline(handler)) => {
+            let _timer = HANDLER_DURATION.start_timer(message.instruction.name());
+            let (instruction, sender) = (message.instruction.clone(), message.sender_uuid);
+            isolate(
+                instruction,
+                sender,
+                handler(message, InlineContext { peer_map }),
+            )
+            .await;
         }
 
+        // Handle subscription messages
+        Some(Route::Sub(_)) => sub_tx.send_async(message).await?,
+
         // Handle database messages
-        Instruction::RecordCreate
-        | Instruction::RecordRead
-        | Instruction::RecordUpdate
-        | Instruction::RecordDelete => {
-            db_tx.send_async(message).await?;
-        }
+        Some(Route::Db(_)) => db_tx.send(message)?,
 
-        // Warn on unknown instructions
-        Instruction::Unknown => {
+        // Warn on unknown or unhandled instructions
+        None => {
             let map = peer_map.read().await;
-            let peer = map.get(&message.sender_uuid).unwrap();
+            match map.get(&message.sender_uuid) {
+                Some(peer) => warn!(
+                    "Unhandled Instruction {} received from {}",
+                    message.instruction.name(),
+                    peer
+                ),
+                None => warn!(
+                    "Unhandled Instruction {} received from unknown peer {}",
+                    message.instruction.name(),
+                    message.sender_uuid
+                ),
+            }
 
-            warn!("Unknown Instruction received from {}", peer);
-            trace_packet!("{}", message);
+            trace_packet!(message);
         }
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_sub_messages(
     msg_rx: Receiver<Message>,
+    registry: Arc<HandlerRegistry>,
     remove_rx: Receiver<Uuid>,
+    db_tx: DbRouter,
     peer_map: ThreadPeerMap,
-    cube_size: u16,
+    world_map: ThreadWorldMap,
+    global_history_size: usize,
+    disconnect_grace: Option<Duration>,
 ) -> Result<()> {
-    let mut world_map = WorldMap::new(cube_size);
+    let mut history = GlobalHistory::new(global_history_size);
+    let mut grace = disconnect_grace.map(DisconnectGrace::new);
 
     loop {
+        let next_deadline = grace.as_ref().and_then(DisconnectGrace::next_deadline);
+
         tokio::select! {
             // Handle incoming peer IDs to be removed
-            Ok(peer) = remove_rx.recv_async() => {
-                world_map.remove_peer(&peer);
+            Ok(peer) = remove_rx.recv_async() => match &mut grace {
+                Some(grace) => grace.push(peer, Instant::now()),
+                None => {
+                    let mut world_map = world_map.write().await;
+                    disconnect(peer, &peer_map, &mut world_map).await;
+                }
             },
 
-            // Handle incoming messages
-            Ok(message) = msg_rx.recv_async() => {
-                match message.instruction {
-                    Instruction::AreaSubscribe => area_subscribe(message, &peer_map, &mut world_map)?,
-                    Instruction::AreaUnsubscribe => area_unsubscribe(message, &peer_map, &mut world_map)?,
-                    Instruction::LocalMessage => local_message(message, &peer_map, &world_map).await?,
-                    Instruction::GlobalMessage => global_message(message, &peer_map, &world_map).await?,
+            // Remove subscriptions of peers that didn't reconnect in time
+            _ = tokio::time::sleep_until(next_deadline.unwrap_or_else(Instant::now).into()), if next_deadline.is_some() => {
+                let expired = match &mut grace {
+                    Some(grace) => grace.expired(Instant::now()),
+                    None => vec![],
+                };
 
-                    _ => panic!("invalid message type"),
+                for peer in expired {
+                    if peer_map.read().await.contains_key(&peer) {
+                        debug!("peer {} reconnected, keeping its subscriptions", peer);
+                        continue;
+                    }
+
+                    let mut world_map = world_map.write().await;
+                    disconnect(peer, &peer_map, &mut world_map).await;
                 }
             },
 
-            // Both channels have closed, exit thread
-            else => {
-                info!("handle_sub_messages loop exiting");
-                break
+            // Handle incoming messages, exiting once the channel closes
+            message = msg_rx.recv_async() => {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(_) => break,
+                };
+
+                // Only this task writes, so the lock is never contended except by stats readers
+                let _timer = HANDLER_DURATION.start_timer(message.instruction.name());
+                let mut world_map = world_map.write().await;
+                let handler = match registry.get(&message.instruction) {
+                    Some(Route::Sub(handler)) => handler,
+                    _ => panic!("invalid message type"),
+                };
+
+                let ctx = SubContext {
+                    peer_map: &peer_map,
+                    world_map: &mut world_map,
+                    db_tx: &db_tx,
+                    history: &mut history,
+                };
+
+                let (instruction, sender) = (message.instruction.clone(), message.sender_uuid);
+                if !isolate(instruction, sender, handler(message, ctx)).await {
+                    world_map.rebuild_indexes();
+                }
             },
         }
     }
 
+    info!("handle_sub_messages loop exiting");
     Ok(())
 }
 
 async fn handle_db_messages(
     msg_rx: Receiver<Message>,
+    registry: Arc<HandlerRegistry>,
     peer_map: ThreadPeerMap,
     mut database_client: DatabaseClient,
 ) -> Result<()> {
+    // Exit once the channel closes and all queued messages are handled
     loop {
-        let message = msg_rx.recv_async().await?;
-        match message.instruction {
-            Instruction::RecordCreate => {
-                record_create(message, &mut database_client, &peer_map).await?
+        // Wake up to flush buffered records if no messages arrive in time
+        let message = match database_client.flush_deadline() {
+            None => msg_rx.recv_async().await,
+            Some(deadline) => {
+                match tokio::time::timeout_at(deadline.into(), msg_rx.recv_async()).await {
+                    Ok(message) => message,
+                    Err(_) => {
+                        flush_write_buffer(&mut database_client).await;
+                        continue;
+                    }
+                }
             }
+        };
 
-            Instruction::RecordRead => {
-                record_read(message, &mut database_client, &peer_map).await?
-            }
+        let message = match message {
+            Ok(message) => message,
+            Err(_) => break,
+        };
 
-            Instruction::RecordUpdate => {
-                todo!()
-            }
+        // Worlds dropped through the admin API may still be cached
+        database_client.forget_dropped_worlds();
 
-            Instruction::RecordDelete => {
-                record_delete(message, &mut database_client, &peer_map).await?;
-            }
+        // Messaging carries on without the database, so its messages are dropped while down
+        if !database_client.is_available().await {
+            debug!(
+                "skipping {} message, database is unreachable",
+                message.instruction
+            );
 
+            reply_unavailable(&peer_map, message).await;
+            continue;
+        }
+
+        // Other instructions must observe buffered records
+        if message.instruction != Instruction::RecordCreate {
+            flush_write_buffer(&mut database_client).await;
+        }
+
+        let _timer = HANDLER_DURATION.start_timer(message.instruction.name());
+        let handler = match registry.get(&message.instruction) {
+            Some(Route::Db(handler)) => handler,
             _ => panic!("invalid message type"),
+        };
+
+        let ctx = DbContext {
+            peer_map: &peer_map,
+            database_client: &mut database_client,
+        };
+
+        let (instruction, sender) = (message.instruction.clone(), message.sender_uuid);
+        isolate(instruction, sender, handler(message, ctx)).await;
+    }
+
+    // Buffered records would otherwise be lost on shutdown
+    flush_write_buffer(&mut database_client).await;
+
+    info!("handle_db_messages loop exiting");
+    Ok(())
+}
+
+/// Tells the sender of a database message skipped while the database is unreachable,
+/// if it waits for a reply.
+///
+/// Reads are always answered with a [`Instruction::RecordReply`]. Writes are only answered
+/// if they carry a request ID as `flex`, with their own instruction.
+async fn reply_unavailable(peer_map: &ThreadPeerMap, message: Message) {
+    // Global world messages are never handled, so never replied to
+    if message.world_name == GLOBAL_WORLD {
+        return;
+    }
+
+    let instruction = match message.instruction {
+        Instruction::RecordRead => Instruction::RecordReply,
+        _ if message.flex.is_some() => message.instruction,
+        _ => return,
+    };
+
+    let reply = Message {
+        instruction,
+        parameter: Some(UNAVAILABLE_REASON.into()),
+        sender_uuid: Uuid::nil(),
+        world_name: message.world_name,
+        flex: message.flex,
+        ..Default::default()
+    };
+
+    let mut map = peer_map.write().await;
+    if let Some(peer) = map.get_mut(&message.sender_uuid) {
+        let _ = peer.send(reply).await;
+    }
+}
+
+/// Removes a disconnected peer's subscriptions, rebuilding the world indexes if the
+/// removal panics part way through.
+async fn disconnect(peer: Uuid, peer_map: &ThreadPeerMap, world_map: &mut WorldMap) {
+    let removal = peer_disconnect(peer, peer_map, world_map);
+    if !isolate(Instruction::PeerDisconnect, peer, removal).await {
+        world_map.rebuild_indexes();
+    }
+}
+
+async fn flush_write_buffer(database_client: &mut DatabaseClient) {
+    if database_client.flush_deadline().is_none() {
+        return;
+    }
+
+    let report = database_client.flush().await;
+    debug!("flushed {} buffered records", report.inserted);
+    for error in report.errors {
+        warn!("buffered record create error: {}", error);
+    }
+}
+
+/// Runs a handler, logging its error or panic instead of stopping the processing stage.
+///
+/// Handlers borrow their context, so panics are caught on the handler's future rather
+/// than a spawned task. Returns `false` if the handler panicked, in which case any state
+/// it was mutating may be half updated and must be repaired by the caller.
+async fn isolate<F>(instruction: Instruction, sender: Uuid, handler: F) -> bool
+where
+    F: Future<Output = Result<()>>,
+{
+    match AssertUnwindSafe(handler).catch_unwind().await {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
+            error!("{} handler failed for {}: {:?}", instruction, sender, err);
+            true
+        }
+        Err(panic) => {
+            error!(
+                "{} handler panicked for {}: {}",
+                instruction,
+                sender,
+                panic_message(&*panic)
+            );
+            false
         }
     }
 }
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => match panic.downcast_ref::<String>() {
+            Some(message) => message,
+            None => "unknown panic",
+        },
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::structures::WireFormat;
+    use crate::transport::{Compression, Peer};
+
+    #[tokio::test]
+    async fn isolate_catches_errors_and_panics() {
+        let sender = Uuid::nil();
+        assert!(isolate(Instruction::RecordRead, sender, async { Ok(()) }).await);
+        assert!(
+            isolate(Instruction::RecordRead, sender, async {
+                Err(color_eyre::eyre::eyre!("query failed"))
+            })
+            .await
+        );
+
+        // Only panics can leave state half updated
+        assert!(
+            !isolate(Instruction::RecordRead, sender, async {
+                panic!("bad message");
+            })
+            .await
+        );
+
+        let panic = std::panic::catch_unwind(|| panic!("{}", "formatted")).unwrap_err();
+        assert_eq!(panic_message(&*panic), "formatted");
+    }
+
+    #[tokio::test]
+    async fn reply_unavailable() {
+        let (remove_tx, _remove_rx) = flume::unbounded();
+        let peer_map = Arc::new(tokio::sync::RwLock::new(crate::transport::PeerMap::new(
+            remove_tx,
+        )));
+
+        let sender = Uuid::new_v4();
+        let (tx, rx) = flume::unbounded();
+        let peer = Peer::new_zmq(
+            "127.0.0.1:5000".parse().unwrap(),
+            sender,
+            tx,
+            WireFormat::Json,
+            Compression::None,
+        );
+        peer_map.write().await.insert(sender, peer).await;
+
+        let message = |instruction: Instruction, flex: Option<&'static [u8]>| Message {
+            instruction,
+            sender_uuid: sender,
+            world_name: "world".into(),
+            flex: flex.map(Bytes::from_static),
+            ..Default::default()
+        };
+
+        // Writes without a request ID don't wait for a reply
+        super::reply_unavailable(&peer_map, message(Instruction::RecordCreate, None)).await;
+        super::reply_unavailable(&peer_map, message(Instruction::RecordRead, None)).await;
+        super::reply_unavailable(
+            &peer_map,
+            message(Instruction::RecordDelete, Some(b"request-1")),
+        )
+        .await;
+
+        let replies = rx
+            .drain()
+            .map(|(bytes, _)| Message::deserialize_as(&bytes, WireFormat::Json).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0].instruction, Instruction::RecordReply);
+        assert_eq!(replies[0].parameter.as_deref(), Some(UNAVAILABLE_REASON));
+        assert_eq!(replies[0].world_name, "world");
+        assert_eq!(replies[1].instruction, Instruction::RecordDelete);
+        assert_eq!(replies[1].flex.as_deref(), Some(&b"request-1"[..]));
+    }
+}
+// endregion
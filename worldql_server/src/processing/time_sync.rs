@@ -0,0 +1,92 @@
+use color_eyre::Result;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::structures::{Instruction, Message};
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+use crate::utils::{epoch_millis, monotonic_micros};
+
+/// Replies with the server's clocks, so clients can estimate their offset from the time
+/// the request was sent and the reply received.
+///
+/// The reply `parameter` has the form `wall_ms=<epoch millis>;monotonic_us=<micros>`,
+/// and `flex` is echoed back so clients can correlate replies to requests.
+pub(super) async fn handle_time_sync(message: Message, peer_map: &ThreadPeerMap) -> Result<()> {
+    trace_packet!(message);
+
+    let uuid = message.sender_uuid;
+    let reply = time_sync_reply(message, epoch_millis(), monotonic_micros());
+
+    let mut map = peer_map.write().await;
+    match map.get_mut(&uuid) {
+        Some(peer) => {
+            let _ = peer.send(reply).await;
+        }
+        None => {
+            warn!("Missing peer {} for TimeSync reply!", &uuid);
+        }
+    }
+
+    Ok(())
+}
+
+fn time_sync_reply(message: Message, wall_ms: u64, monotonic_us: u64) -> Message {
+    Message {
+        instruction: Instruction::TimeSync,
+        parameter: Some(format!("wall_ms={};monotonic_us={}", wall_ms, monotonic_us)),
+        sender_uuid: Uuid::nil(),
+        flex: message.flex,
+        ..Default::default()
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::structures::WireFormat;
+
+    #[test]
+    fn time_sync_reply() {
+        let request = Message {
+            instruction: Instruction::TimeSync,
+            sender_uuid: Uuid::new_v4(),
+            flex: Some(Bytes::from_static(b"request-1")),
+            ..Default::default()
+        };
+
+        let reply = super::time_sync_reply(request, 1_650_000_000_123, 42);
+        let reply = Message::deserialize_as(
+            &reply.serialize_as(WireFormat::FlatBuffers),
+            WireFormat::FlatBuffers,
+        )
+        .unwrap();
+
+        assert_eq!(reply.instruction, Instruction::TimeSync);
+        assert!(reply.sender_uuid.is_nil());
+        assert_eq!(reply.flex.as_deref(), Some(&b"request-1"[..]));
+
+        let fields = reply
+            .parameter
+            .as_deref()
+            .unwrap()
+            .split(';')
+            .filter_map(|field| field.split_once('='))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            fields,
+            vec![("wall_ms", "1650000000123"), ("monotonic_us", "42")]
+        );
+    }
+
+    #[test]
+    fn monotonic_clock() {
+        let first = monotonic_micros();
+        assert!(monotonic_micros() >= first);
+    }
+}
+// endregion
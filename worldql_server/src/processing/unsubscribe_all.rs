@@ -0,0 +1,44 @@
+use color_eyre::Result;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::structures::{Instruction, Message};
+use crate::subscriptions::WorldMap;
+use crate::trace_packet;
+use crate::transport::ThreadPeerMap;
+
+pub(super) async fn handle_unsubscribe_all(
+    message: Message,
+    peer_map: &ThreadPeerMap,
+    world_map: &mut WorldMap,
+) -> Result<()> {
+    trace_packet!(message);
+
+    let uuid = message.sender_uuid;
+    let cleared = world_map.clear_peer_everywhere(&uuid);
+
+    // Acknowledge with the outcome, matching AreaUnsubscribe
+    let parameter = match cleared {
+        0 => "not_subscribed",
+        _ => "unsubscribed",
+    };
+
+    let ack = Message {
+        instruction: Instruction::UnsubscribeAll,
+        parameter: Some(parameter.into()),
+        sender_uuid: Uuid::nil(),
+        ..Default::default()
+    };
+
+    let mut map = peer_map.write().await;
+    match map.get_mut(&uuid) {
+        Some(peer) => {
+            let _ = peer.send(ack).await;
+        }
+        None => {
+            warn!("Missing peer {} for UnsubscribeAll ack!", &uuid);
+        }
+    }
+
+    Ok(())
+}
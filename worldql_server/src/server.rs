@@ -0,0 +1,495 @@
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use futures_util::FutureExt;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::database::{BreakerStats, CacheStats, CacheStatsHandle, CircuitBreaker};
+use crate::subscriptions::ThreadWorldMap;
+use crate::transport::ThreadPeerMap;
+
+type TaskHandle = JoinHandle<Result<()>>;
+type SharedQueueStats = Arc<Mutex<Vec<QueueStats>>>;
+
+// region: Server Struct
+/// Owns every long-running task and shuts them down in dependency order.
+///
+/// Tasks are spawned into one of three stages:
+/// - **ingress**: accepts messages from peers and forwards them to processing
+/// - **processing**: handles messages, including database writes
+/// - **egress**: delivers outgoing messages to peers
+///
+/// Each stage is given its own [`CancellationToken`] so it can stop cleanly once the stage
+/// before it has finished. A task failing in any stage is reported by [`Self::failed`],
+/// so the server can shut down instead of running without it.
+#[derive(Debug)]
+pub struct Server {
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    peer_map: ThreadPeerMap,
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    world_map: ThreadWorldMap,
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    cache_stats: CacheStatsHandle,
+    breaker: Option<CircuitBreaker>,
+    /// Incoming messages discarded because the processing queue was full
+    dropped_messages: Arc<AtomicU64>,
+    /// Last sampled depth of each monitored queue
+    queue_stats: SharedQueueStats,
+
+    ingress_token: CancellationToken,
+    processing_token: CancellationToken,
+    egress_token: CancellationToken,
+    /// Cancelled once any task exits with an error or panics
+    failed_token: CancellationToken,
+
+    ingress: Vec<TaskHandle>,
+    processing: Vec<TaskHandle>,
+    egress: Vec<TaskHandle>,
+}
+
+impl Server {
+    pub fn new(
+        peer_map: ThreadPeerMap,
+        world_map: ThreadWorldMap,
+        cache_stats: CacheStatsHandle,
+    ) -> Self {
+        Self {
+            peer_map,
+            world_map,
+            cache_stats,
+            breaker: None,
+            dropped_messages: Arc::new(AtomicU64::new(0)),
+            queue_stats: Arc::default(),
+
+            ingress_token: CancellationToken::new(),
+            processing_token: CancellationToken::new(),
+            egress_token: CancellationToken::new(),
+            failed_token: CancellationToken::new(),
+
+            ingress: vec![],
+            processing: vec![],
+            egress: vec![],
+        }
+    }
+
+    /// Returns a handle for taking [`StatsHandle::stats`] snapshots from other tasks.
+    ///
+    /// Call after [`Self::set_circuit_breaker`], so database health is included.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            peer_map: self.peer_map.clone(),
+            world_map: self.world_map.clone(),
+            cache_stats: self.cache_stats.clone(),
+            breaker: self.breaker.clone(),
+            dropped_messages: self.dropped_messages.clone(),
+            queue_stats: self.queue_stats.clone(),
+        }
+    }
+
+    /// Include the state of the database workers' circuit breaker in [`StatsHandle::stats`].
+    #[inline]
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.breaker = Some(breaker);
+    }
+
+    /// Returns the counter ingress tasks increment for each incoming message they drop.
+    #[inline]
+    pub fn dropped_messages(&self) -> Arc<AtomicU64> {
+        self.dropped_messages.clone()
+    }
+
+    /// Returns an empty [`QueueMonitor`] whose samples are included in [`Self::stats`].
+    #[inline]
+    pub fn queue_monitor(&self, high_water: usize) -> QueueMonitor {
+        QueueMonitor::new(high_water, self.queue_stats.clone())
+    }
+
+    /// Spawn a task which stops accepting new messages once `token` is cancelled.
+    pub fn spawn_ingress<F, Fut>(&mut self, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handle = self.spawn(task(self.ingress_token.clone()));
+        self.ingress.push(handle);
+    }
+
+    /// Spawn a task which flushes its queued messages and exits once `token` is cancelled.
+    pub fn spawn_processing<F, Fut>(&mut self, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handle = self.spawn(task(self.processing_token.clone()));
+        self.processing.push(handle);
+    }
+
+    /// Spawn a task which delivers any remaining outgoing messages and exits once `token`
+    /// is cancelled.
+    pub fn spawn_egress<F, Fut>(&mut self, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handle = self.spawn(task(self.egress_token.clone()));
+        self.egress.push(handle);
+    }
+
+    /// Spawn `task`, cancelling the failed token if it returns an error or panics.
+    fn spawn<Fut>(&self, task: Fut) -> TaskHandle
+    where
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let failed_token = self.failed_token.clone();
+        tokio::spawn(async move {
+            // The panic itself is printed by the panic hook
+            let result = match AssertUnwindSafe(task).catch_unwind().await {
+                Ok(result) => result,
+                Err(_) => Err(eyre!("task panicked")),
+            };
+
+            if result.is_err() {
+                failed_token.cancel();
+            }
+
+            result
+        })
+    }
+
+    /// Resolves once any task exits with an error or panics.
+    pub async fn failed(&self) {
+        self.failed_token.cancelled().await;
+    }
+
+    /// Gracefully shut down the server.
+    ///
+    /// Ingress tasks are stopped first, so no new messages are accepted. Processing tasks
+    /// then drain any queued messages and wait for outstanding database queries. Finally,
+    /// egress tasks flush all outgoing messages.
+    ///
+    /// Every stage is shut down even if tasks fail, returns an error if any task did.
+    pub async fn shutdown(self) -> Result<()> {
+        info!("shutting down, no longer accepting messages");
+        self.ingress_token.cancel();
+        let mut failed = join_stage("ingress", self.ingress).await;
+
+        info!("draining queued messages");
+        self.processing_token.cancel();
+        failed += join_stage("processing", self.processing).await;
+
+        info!("flushing outgoing messages");
+        self.egress_token.cancel();
+        failed += join_stage("egress", self.egress).await;
+
+        if failed > 0 {
+            return Err(eyre!("{} server tasks exited with errors", failed));
+        }
+
+        info!("shutdown complete");
+        Ok(())
+    }
+}
+
+/// Wait for every task in a stage, logging failures. Returns the number of failed tasks.
+async fn join_stage(stage: &str, handles: Vec<TaskHandle>) -> usize {
+    let mut failed = 0;
+    for result in futures_util::future::join_all(handles).await {
+        match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(error)) => error!("{} task exited with error: {}", stage, error),
+            Err(error) => error!("{} task failed: {}", stage, error),
+        }
+
+        failed += 1;
+    }
+
+    failed
+}
+// endregion
+
+// region: StatsHandle Struct
+/// Cheaply cloneable source of [`ServerStats`] snapshots, see [`Server::stats_handle`].
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub struct StatsHandle {
+    peer_map: ThreadPeerMap,
+    world_map: ThreadWorldMap,
+    cache_stats: CacheStatsHandle,
+    breaker: Option<CircuitBreaker>,
+    dropped_messages: Arc<AtomicU64>,
+    queue_stats: SharedQueueStats,
+}
+
+impl StatsHandle {
+    /// Take a snapshot of connected peers, subscriptions, database cache usage and health,
+    /// and queue depths.
+    ///
+    /// Only holds one read lock at a time, so is cheap enough to call periodically.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub async fn stats(&self) -> ServerStats {
+        let peers = self.peer_map.read().await.size();
+
+        let mut world_subscriptions = {
+            let world_map = self.world_map.read().await;
+            world_map
+                .subscription_counts()
+                .map(|(world_name, count)| (world_name.to_string(), count))
+                .collect::<Vec<_>>()
+        };
+
+        world_subscriptions.sort_unstable();
+
+        ServerStats {
+            peers,
+            world_subscriptions,
+            cache: self.cache_stats.snapshot(),
+            database: self.breaker.as_ref().map(CircuitBreaker::snapshot),
+            dropped_messages: self.dropped_messages.load(Ordering::Relaxed),
+            queues: self
+                .queue_stats
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+}
+// endregion
+
+// region: ServerStats Struct
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+pub struct ServerStats {
+    /// Total number of connected peers
+    pub peers: usize,
+    /// Total subscriptions in each world, sorted by world name
+    pub world_subscriptions: Vec<(String, usize)>,
+    pub cache: CacheStats,
+    /// Database circuit breaker, `None` if there isn't one
+    pub database: Option<BreakerStats>,
+    /// Incoming messages dropped by the overflow policy since startup
+    pub dropped_messages: u64,
+    /// Depth of each monitored queue, as of the last sample
+    pub queues: Vec<QueueStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueStats {
+    pub name: &'static str,
+    /// Messages waiting in the queue
+    pub len: usize,
+    /// Maximum number of messages, `None` if the queue is unbounded
+    pub capacity: Option<usize>,
+    /// Highest sampled length since startup
+    pub peak: usize,
+}
+// endregion
+
+// region: Queue Monitor
+/// A message queue whose depth can be sampled.
+pub trait QueueDepth: Send {
+    /// Returns the number of messages waiting in the queue.
+    fn depth(&self) -> usize;
+
+    /// Returns the maximum number of messages, `None` if the queue is unbounded.
+    fn capacity(&self) -> Option<usize>;
+}
+
+impl<T: Send> QueueDepth for flume::Receiver<T> {
+    #[inline]
+    fn depth(&self) -> usize {
+        self.len()
+    }
+
+    #[inline]
+    fn capacity(&self) -> Option<usize> {
+        flume::Receiver::capacity(self)
+    }
+}
+
+struct MonitoredQueue {
+    name: &'static str,
+    queue: Box<dyn QueueDepth>,
+    peak: usize,
+    /// Whether the last sample was over the high-water mark
+    over: bool,
+}
+
+/// Periodically samples the depth of message queues, so processing falling behind
+/// ingestion shows up in [`ServerStats`].
+///
+/// Monitored queues should be receivers, so holding them never keeps a sender waiting on
+/// a consumer that has exited. See [`Server::queue_monitor`].
+pub struct QueueMonitor {
+    queues: Vec<MonitoredQueue>,
+    /// Length above which a queue is logged as backed up
+    high_water: usize,
+    stats: SharedQueueStats,
+}
+
+impl QueueMonitor {
+    fn new(high_water: usize, stats: SharedQueueStats) -> Self {
+        Self {
+            queues: vec![],
+            high_water,
+            stats,
+        }
+    }
+
+    /// Include `queue` in every sample, reported as `name`.
+    pub fn watch(&mut self, name: &'static str, queue: impl QueueDepth + 'static) {
+        self.queues.push(MonitoredQueue {
+            name,
+            queue: Box::new(queue),
+            peak: 0,
+            over: false,
+        });
+    }
+
+    /// Sample every queue, warning once each time a queue passes the high-water mark.
+    pub fn sample(&mut self) {
+        let mut stats = Vec::with_capacity(self.queues.len());
+        for monitored in &mut self.queues {
+            let len = monitored.queue.depth();
+            monitored.peak = monitored.peak.max(len);
+
+            let over = len > self.high_water;
+            if over && !monitored.over {
+                warn!(
+                    "{} queue has {} messages waiting, processing is falling behind",
+                    monitored.name, len
+                );
+            } else if !over && monitored.over {
+                info!("{} queue has drained to {} messages", monitored.name, len);
+            }
+
+            monitored.over = over;
+            stats.push(QueueStats {
+                name: monitored.name,
+                len,
+                capacity: monitored.queue.capacity(),
+                peak: monitored.peak,
+            });
+        }
+
+        *self.stats.lock().unwrap_or_else(PoisonError::into_inner) = stats;
+    }
+}
+
+/// Sample `monitor` every `period` until `token` is cancelled.
+pub async fn start_queue_monitor(
+    mut monitor: QueueMonitor,
+    period: Duration,
+    token: CancellationToken,
+) -> Result<()> {
+    let mut interval = interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => monitor.sample(),
+            _ = token.cancelled() => break,
+        }
+    }
+
+    Ok(())
+}
+// endregion
+
+// region: Signals
+/// Resolves once the process is asked to terminate, either by Ctrl+C or SIGTERM.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = match signal(SignalKind::terminate()) {
+            Ok(sigterm) => sigterm,
+            Err(error) => {
+                error!("failed to listen for SIGTERM: {}", error);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = sigterm.recv() => (),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_depth_and_peak() {
+        let (tx, rx) = flume::bounded(8);
+        let stats = SharedQueueStats::default();
+        let mut monitor = QueueMonitor::new(2, stats.clone());
+        monitor.watch("messages", rx.clone());
+
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+
+        monitor.sample();
+        assert!(monitor.queues[0].over);
+
+        rx.drain();
+        monitor.sample();
+
+        let stats = stats.lock().unwrap();
+        assert_eq!(
+            *stats,
+            vec![QueueStats {
+                name: "messages",
+                len: 0,
+                capacity: Some(8),
+                peak: 3,
+            }]
+        );
+        assert!(!monitor.queues[0].over);
+    }
+
+    #[tokio::test]
+    async fn failed_tasks_fail_shutdown() {
+        let (remove_tx, _remove_rx) = flume::unbounded();
+        let peer_map = Arc::new(tokio::sync::RwLock::new(crate::transport::PeerMap::new(
+            remove_tx,
+        )));
+        let world_map = Arc::new(tokio::sync::RwLock::new(
+            crate::subscriptions::WorldMap::new(16, None).unwrap(),
+        ));
+
+        let mut server = Server::new(peer_map, world_map, CacheStatsHandle::default());
+        server.spawn_ingress(|token| async move {
+            token.cancelled().await;
+            Ok(())
+        });
+        server.spawn_processing(|_| async { Err(eyre!("database worker crashed")) });
+
+        // Reported before shutdown is requested
+        tokio::time::timeout(Duration::from_secs(5), server.failed())
+            .await
+            .unwrap();
+
+        assert!(server.shutdown().await.is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use super::Vector3;
+
 pub(super) trait Encode<T> {
     fn encode(self) -> T;
 }
@@ -17,4 +19,7 @@ pub enum DecodeError {
 
     #[error(transparent)]
     InvalidUuid(#[from] uuid::Error),
+
+    #[error("invalid position: {0}")]
+    InvalidPosition(Vector3),
 }
@@ -1,10 +1,11 @@
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::{Decode, DecodeError, Encode, Vector3};
 use crate::flatbuffers::EntityT;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Entity {
     pub uuid: Uuid,
     pub position: Vector3,
@@ -1,21 +1,51 @@
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
 use super::{Decode, DecodeError, Encode};
 use crate::flatbuffers::Instruction as InstructionFB;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Wire value of [`Instruction::AreaSubscribeBulk`], the next free value in the schema.
+const AREA_SUBSCRIBE_BULK: InstructionFB = InstructionFB(13);
+
+/// Wire value of [`Instruction::UnsubscribeAll`].
+const UNSUBSCRIBE_ALL: InstructionFB = InstructionFB(14);
+
+/// Wire value of [`Instruction::HandshakeAck`].
+const HANDSHAKE_ACK: InstructionFB = InstructionFB(15);
+
+/// Wire value of [`Instruction::PositionUpdate`].
+const POSITION_UPDATE: InstructionFB = InstructionFB(16);
+
+/// Wire value of [`Instruction::QueryPeers`].
+const QUERY_PEERS: InstructionFB = InstructionFB(17);
+
+/// Wire value of [`Instruction::HandshakeRejected`].
+const HANDSHAKE_REJECTED: InstructionFB = InstructionFB(18);
+
+/// Wire value of [`Instruction::TimeSync`].
+const TIME_SYNC: InstructionFB = InstructionFB(19);
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum Instruction {
     Heartbeat,
     Handshake,
+    HandshakeAck,
+    HandshakeRejected,
     PeerConnect,
     PeerDisconnect,
     AreaSubscribe,
+    AreaSubscribeBulk,
     AreaUnsubscribe,
+    UnsubscribeAll,
+    QueryPeers,
+    TimeSync,
     GlobalMessage,
     LocalMessage,
     RecordCreate,
     RecordRead,
     RecordUpdate,
+    PositionUpdate,
     RecordDelete,
     RecordReply,
 
@@ -35,15 +65,22 @@ impl Encode<InstructionFB> for Instruction {
         match self {
             Instruction::Heartbeat => InstructionFB::Heartbeat,
             Instruction::Handshake => InstructionFB::Handshake,
+            Instruction::HandshakeAck => HANDSHAKE_ACK,
+            Instruction::HandshakeRejected => HANDSHAKE_REJECTED,
             Instruction::PeerConnect => InstructionFB::PeerConnect,
             Instruction::PeerDisconnect => InstructionFB::PeerDisconnect,
             Instruction::AreaSubscribe => InstructionFB::AreaSubscribe,
+            Instruction::AreaSubscribeBulk => AREA_SUBSCRIBE_BULK,
             Instruction::AreaUnsubscribe => InstructionFB::AreaUnsubscribe,
+            Instruction::UnsubscribeAll => UNSUBSCRIBE_ALL,
+            Instruction::QueryPeers => QUERY_PEERS,
+            Instruction::TimeSync => TIME_SYNC,
             Instruction::GlobalMessage => InstructionFB::GlobalMessage,
             Instruction::LocalMessage => InstructionFB::LocalMessage,
             Instruction::RecordCreate => InstructionFB::RecordCreate,
             Instruction::RecordRead => InstructionFB::RecordRead,
             Instruction::RecordUpdate => InstructionFB::RecordUpdate,
+            Instruction::PositionUpdate => POSITION_UPDATE,
             Instruction::RecordDelete => InstructionFB::RecordDelete,
             Instruction::RecordReply => InstructionFB::RecordReply,
 
@@ -58,15 +95,22 @@ impl Decode<InstructionFB> for Instruction {
         let instruction = match encoded {
             InstructionFB::Heartbeat => Instruction::Heartbeat,
             InstructionFB::Handshake => Instruction::Handshake,
+            HANDSHAKE_ACK => Instruction::HandshakeAck,
+            HANDSHAKE_REJECTED => Instruction::HandshakeRejected,
             InstructionFB::PeerConnect => Instruction::PeerConnect,
             InstructionFB::PeerDisconnect => Instruction::PeerDisconnect,
             InstructionFB::AreaSubscribe => Instruction::AreaSubscribe,
+            AREA_SUBSCRIBE_BULK => Instruction::AreaSubscribeBulk,
             InstructionFB::AreaUnsubscribe => Instruction::AreaUnsubscribe,
+            UNSUBSCRIBE_ALL => Instruction::UnsubscribeAll,
+            QUERY_PEERS => Instruction::QueryPeers,
+            TIME_SYNC => Instruction::TimeSync,
             InstructionFB::GlobalMessage => Instruction::GlobalMessage,
             InstructionFB::LocalMessage => Instruction::LocalMessage,
             InstructionFB::RecordCreate => Instruction::RecordCreate,
             InstructionFB::RecordRead => Instruction::RecordRead,
             InstructionFB::RecordUpdate => Instruction::RecordUpdate,
+            POSITION_UPDATE => Instruction::PositionUpdate,
             InstructionFB::RecordDelete => Instruction::RecordDelete,
             InstructionFB::RecordReply => Instruction::RecordReply,
 
@@ -79,27 +123,39 @@ impl Decode<InstructionFB> for Instruction {
 // endregion
 
 // region: Display Trait
-impl Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match self {
+impl Instruction {
+    /// Returns the name of this instruction, as used in logs and metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
             Self::Heartbeat => "Heartbeat",
             Self::Handshake => "Handshake",
+            Self::HandshakeAck => "HandshakeAck",
+            Self::HandshakeRejected => "HandshakeRejected",
             Self::PeerConnect => "PeerConnect",
             Self::PeerDisconnect => "PeerDisconnect",
             Self::AreaSubscribe => "AreaSubscribe",
+            Self::AreaSubscribeBulk => "AreaSubscribeBulk",
             Self::AreaUnsubscribe => "AreaUnsubscribe",
+            Self::UnsubscribeAll => "UnsubscribeAll",
+            Self::QueryPeers => "QueryPeers",
+            Self::TimeSync => "TimeSync",
             Self::GlobalMessage => "GlobalMessage",
             Self::LocalMessage => "LocalMessage",
             Self::RecordCreate => "RecordCreate",
             Self::RecordRead => "RecordRead",
             Self::RecordUpdate => "RecordUpdate",
+            Self::PositionUpdate => "PositionUpdate",
             Self::RecordDelete => "RecordDelete",
             Self::RecordReply => "RecordReply",
 
             Self::Unknown => "Unknown",
-        };
+        }
+    }
+}
 
-        write!(f, "{}", name)
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
     }
 }
 // endregion
@@ -4,20 +4,26 @@ use std::sync::Mutex;
 use bytes::Bytes;
 use flatbuffers::{FlatBufferBuilder, InvalidFlatbuffer};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{Decode, DecodeError, Encode, Entity, Instruction, Record, Replication, Vector3};
+use super::{
+    Decode, DecodeError, Encode, Entity, Instruction, Record, Replication, Vector3, WireFormat,
+};
 use crate::flatbuffers::{root_as_message, MessageT};
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub instruction: Instruction,
     pub parameter: Option<String>,
     pub sender_uuid: Uuid,
     pub world_name: String,
+    #[serde(default)]
     pub replication: Replication,
+    #[serde(default)]
     pub records: Vec<Record>,
+    #[serde(default)]
     pub entities: Vec<Entity>,
     pub position: Option<Vector3>,
     pub flex: Option<Bytes>,
@@ -29,13 +35,13 @@ impl Encode<MessageT> for Message {
         let records = self
             .records
             .into_iter()
-            .map(|r| r.encode())
+            .map(Encode::encode)
             .collect::<Vec<_>>();
 
         let entities = self
             .entities
             .into_iter()
-            .map(|e| e.encode())
+            .map(Encode::encode)
             .collect::<Vec<_>>();
 
         MessageT {
@@ -46,7 +52,7 @@ impl Encode<MessageT> for Message {
             replication: self.replication.encode(),
             records: Some(records),
             entities: Some(entities),
-            position: self.position.map(|p| p.encode()),
+            position: self.position.map(Encode::encode),
             flex: self.flex.map(|flex| flex.to_vec()),
         }
     }
@@ -140,6 +146,23 @@ impl Message {
         let message = Message::decode(message_t)?;
         Ok(message)
     }
+
+    /// Serialize using the given [`WireFormat`].
+    pub fn serialize_as(self, format: WireFormat) -> Bytes {
+        match format {
+            WireFormat::FlatBuffers => self.serialize(),
+            // Serializing plain data to JSON never fails
+            WireFormat::Json => Bytes::from(serde_json::to_vec(&self).unwrap()),
+        }
+    }
+
+    /// Deserialize using the given [`WireFormat`].
+    pub fn deserialize_as(buf: &[u8], format: WireFormat) -> Result<Self, DeserializeError> {
+        match format {
+            WireFormat::FlatBuffers => Self::deserialize(buf),
+            WireFormat::Json => Ok(serde_json::from_slice(buf)?),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -149,6 +172,9 @@ pub enum DeserializeError {
 
     #[error(transparent)]
     DecodeError(#[from] DecodeError),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
 }
 // endregion
 
@@ -168,7 +194,12 @@ macro_rules! write_optional {
 impl Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.instruction {
-            Instruction::Heartbeat | Instruction::Handshake => {
+            Instruction::Heartbeat
+            | Instruction::Handshake
+            | Instruction::HandshakeAck
+            | Instruction::HandshakeRejected
+            | Instruction::UnsubscribeAll
+            | Instruction::TimeSync => {
                 write!(
                     f,
                     "{} = {{ sender = \"{}\"",
@@ -186,14 +217,31 @@ impl Display for Message {
                 self.parameter.as_ref().unwrap()
             ),
 
-            Instruction::AreaSubscribe | Instruction::AreaUnsubscribe => write!(
-                f,
-                "{} = {{ sender = \"{}\", world = \"{}\", area = {} }}",
-                self.instruction,
-                self.sender_uuid,
-                self.world_name,
-                self.position.as_ref().unwrap()
-            ),
+            Instruction::AreaSubscribe | Instruction::AreaUnsubscribe | Instruction::QueryPeers => {
+                write!(
+                    f,
+                    "{} = {{ sender = \"{}\", world = \"{}\", area = {} }}",
+                    self.instruction,
+                    self.sender_uuid,
+                    self.world_name,
+                    self.position.as_ref().unwrap()
+                )
+            }
+
+            Instruction::AreaSubscribeBulk => {
+                write!(
+                    f,
+                    "{} = {{ sender = \"{}\", world = \"{}\"",
+                    self.instruction, self.sender_uuid, self.world_name
+                )?;
+
+                if let Some(position) = &self.position {
+                    write!(f, ", center = {}", position)?;
+                }
+
+                write_optional!(f, self);
+                write!(f, " }}")
+            }
 
             Instruction::GlobalMessage => {
                 write!(
@@ -222,6 +270,7 @@ impl Display for Message {
 
             Instruction::RecordCreate
             | Instruction::RecordUpdate
+            | Instruction::PositionUpdate
             | Instruction::RecordDelete
             | Instruction::RecordReply => write!(
                 f,
@@ -232,14 +281,20 @@ impl Display for Message {
                 self.records.len()
             ),
 
-            Instruction::RecordRead => write!(
-                f,
-                "{} = {{ sender = \"{}\", world = \"{}\", position = {} }}",
-                self.instruction,
-                self.sender_uuid,
-                self.world_name,
-                self.position.as_ref().unwrap()
-            ),
+            Instruction::RecordRead => {
+                write!(
+                    f,
+                    "{} = {{ sender = \"{}\", world = \"{}\"",
+                    self.instruction, self.sender_uuid, self.world_name
+                )?;
+
+                // Reads without a position target the world's global records
+                if let Some(position) = &self.position {
+                    write!(f, ", position = {}", position)?;
+                }
+
+                write!(f, " }}")
+            }
 
             Instruction::Unknown => write!(
                 f,
@@ -250,3 +305,51 @@ impl Display for Message {
     }
 }
 // endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let uuid = Uuid::new_v4();
+        let message = Message {
+            instruction: Instruction::LocalMessage,
+            parameter: Some("parameter".into()),
+            sender_uuid: uuid,
+            world_name: "world".into(),
+            position: Some(Vector3::new(1.0, 2.0, 3.0)),
+            flex: Some(Bytes::from_static(&[1, 2, 3])),
+            ..Default::default()
+        };
+
+        for format in [WireFormat::FlatBuffers, WireFormat::Json] {
+            let bytes = message.clone().serialize_as(format);
+            assert_eq!(WireFormat::detect(&bytes), format);
+
+            let decoded = Message::deserialize_as(&bytes, format).unwrap();
+            assert_eq!(decoded.instruction, Instruction::LocalMessage);
+            assert_eq!(decoded.parameter.as_deref(), Some("parameter"));
+            assert_eq!(decoded.sender_uuid, uuid);
+            assert_eq!(decoded.world_name, "world");
+            assert_eq!(decoded.replication, Replication::ExceptSelf);
+            assert_eq!(decoded.position, message.position);
+            assert_eq!(decoded.flex, message.flex);
+        }
+    }
+
+    #[test]
+    fn round_trip_area_subscribe_bulk() {
+        // Not part of the generated schema, so encoded by value
+        let message = Message {
+            instruction: Instruction::AreaSubscribeBulk,
+            ..Default::default()
+        };
+
+        let bytes = message.serialize_as(WireFormat::FlatBuffers);
+        let decoded = Message::deserialize_as(&bytes, WireFormat::FlatBuffers).unwrap();
+        assert_eq!(decoded.instruction, Instruction::AreaSubscribeBulk);
+    }
+}
+// endregion
@@ -5,12 +5,14 @@ mod message;
 mod record;
 mod replication;
 mod vector3;
+mod wire_format;
 
 pub use codec::DecodeError;
-pub(self) use codec::{Decode, Encode};
+use codec::{Decode, Encode};
 pub use entity::Entity;
 pub use instruction::Instruction;
 pub use message::Message;
 pub use record::Record;
 pub use replication::Replication;
 pub use vector3::Vector3;
+pub use wire_format::WireFormat;
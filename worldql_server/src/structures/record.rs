@@ -1,11 +1,14 @@
 use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use thiserror::Error;
 use tokio_postgres::Row;
 use uuid::Uuid;
 
 use super::{Decode, DecodeError, Encode, Vector3};
 use crate::flatbuffers::RecordT;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Record {
     pub uuid: Uuid,
     pub position: Option<Vector3>,
@@ -18,7 +21,7 @@ impl Encode<RecordT> for Record {
     fn encode(self) -> RecordT {
         RecordT {
             uuid: Some(self.uuid.to_string()),
-            position: self.position.map(|p| p.encode()),
+            position: self.position.map(Encode::encode),
             world_name: Some(self.world_name),
             data: self.data,
             flex: self.flex.map(|flex| flex.to_vec()),
@@ -54,6 +57,13 @@ impl Decode<RecordT> for Record {
 }
 
 impl Record {
+    /// Returns a [`RecordBuilder`] for constructing a validated [`Record`].
+    #[cfg(test)]
+    #[inline]
+    pub fn builder(world_name: impl Into<String>) -> RecordBuilder {
+        RecordBuilder::new(world_name)
+    }
+
     pub fn from_postgres_row(row: Row, world_name: &str) -> Self {
         let x: f64 = row.get("x");
         let y: f64 = row.get("y");
@@ -68,4 +78,211 @@ impl Record {
             flex: flex.map(Bytes::from),
         }
     }
+
+    /// Like [`Self::from_postgres_row`], for rows of a world's global table which have
+    /// no position.
+    pub fn from_postgres_global_row(row: Row, world_name: &str) -> Self {
+        let flex: Option<Vec<u8>> = row.get("flex");
+
+        Self {
+            uuid: row.get("uuid"),
+            position: None,
+            world_name: world_name.to_string(),
+            data: row.get("data"),
+            flex: flex.map(Bytes::from),
+        }
+    }
+}
+
+// region: MessagePack Flex
+/// Typed access to `flex` for clients that encode it as MessagePack.
+///
+/// The raw bytes are always kept as is, the wire format never depends on these helpers.
+#[cfg(feature = "msgpack")]
+impl Record {
+    /// Decodes `flex` as MessagePack, returns `None` if the record has no `flex`.
+    pub fn flex_as<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> Option<Result<T, rmp_serde::decode::Error>> {
+        self.flex.as_deref().map(rmp_serde::from_slice)
+    }
+
+    /// Returns the record with `flex` set to `value` encoded as MessagePack, keeping
+    /// struct field names so other clients can decode it.
+    pub fn with_flex<T: Serialize>(mut self, value: &T) -> Result<Self, rmp_serde::encode::Error> {
+        self.flex = Some(Bytes::from(rmp_serde::to_vec_named(value)?));
+        Ok(self)
+    }
+}
+// endregion
+
+// region: RecordBuilder Struct
+#[cfg(test)]
+#[derive(Debug, Default, Clone)]
+pub struct RecordBuilder {
+    uuid: Option<Uuid>,
+    position: Option<Vector3>,
+    global: bool,
+    world_name: String,
+    data: Option<String>,
+    flex: Option<Bytes>,
+}
+
+#[cfg(test)]
+impl RecordBuilder {
+    #[inline]
+    pub fn new(world_name: impl Into<String>) -> Self {
+        Self {
+            world_name: world_name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Set the record UUID, a random one is generated if not set.
+    #[inline]
+    pub fn uuid(mut self, uuid: Uuid) -> Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    #[inline]
+    pub fn position(mut self, position: Vector3) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    /// Mark the record as global, meaning it has no position.
+    #[inline]
+    pub fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
+    #[inline]
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    #[inline]
+    pub fn flex(mut self, flex: impl Into<Bytes>) -> Self {
+        self.flex = Some(flex.into());
+        self
+    }
+
+    /// Validate and build the [`Record`].
+    ///
+    /// Records must have a world name, and must have a position unless marked as global.
+    pub fn build(self) -> Result<Record, RecordBuildError> {
+        if self.world_name.is_empty() {
+            return Err(RecordBuildError::EmptyWorldName);
+        }
+
+        match (self.global, self.position) {
+            (false, None) => return Err(RecordBuildError::MissingPosition),
+            (true, Some(_)) => return Err(RecordBuildError::GlobalWithPosition),
+            _ => (),
+        }
+
+        let record = Record {
+            uuid: self.uuid.unwrap_or_else(Uuid::new_v4),
+            position: self.position,
+            world_name: self.world_name,
+            data: self.data,
+            flex: self.flex,
+        };
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RecordBuildError {
+    #[error("world name must not be empty")]
+    EmptyWorldName,
+
+    #[error("position is required unless the record is global")]
+    MissingPosition,
+
+    #[error("global records must not have a position")]
+    GlobalWithPosition,
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let uuid = Uuid::new_v4();
+        let position = Vector3::new(1.0, 2.0, 3.0);
+
+        let record = Record::builder("world")
+            .uuid(uuid)
+            .position(position)
+            .data("data")
+            .build()
+            .unwrap();
+
+        assert_eq!(record.uuid, uuid);
+        assert_eq!(record.position, Some(position));
+        assert_eq!(record.data.as_deref(), Some("data"));
+        assert!(record.flex.is_none());
+
+        let record = Record::builder("world")
+            .global()
+            .flex(vec![1, 2])
+            .build()
+            .unwrap();
+        assert!(record.position.is_none());
+        assert!(!record.uuid.is_nil());
+        assert_eq!(record.flex.as_deref(), Some(&[1, 2][..]));
+
+        let error = Record::builder("").position(position).build().unwrap_err();
+        assert_eq!(error, RecordBuildError::EmptyWorldName);
+
+        let error = Record::builder("world").build().unwrap_err();
+        assert_eq!(error, RecordBuildError::MissingPosition);
+
+        let error = Record::builder("world")
+            .global()
+            .position(position)
+            .build()
+            .unwrap_err();
+
+        assert_eq!(error, RecordBuildError::GlobalWithPosition);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_flex() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Flex {
+            health: u32,
+            name: String,
+        }
+
+        let record = Record::builder("world").global().build().unwrap();
+        assert!(record.flex_as::<Flex>().is_none());
+
+        let flex = Flex {
+            health: 20,
+            name: "Steve".into(),
+        };
+
+        let record = record.with_flex(&flex).unwrap();
+        assert_eq!(record.flex_as::<Flex>().unwrap().unwrap(), flex);
+
+        // Other formats are left to the caller
+        let record = Record {
+            flex: Some(Bytes::from_static(b"\xc1")),
+            ..record
+        };
+
+        assert!(record.flex_as::<Flex>().unwrap().is_err());
+    }
 }
+// endregion
@@ -1,9 +1,11 @@
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
 use super::{Decode, DecodeError, Encode};
 use crate::flatbuffers::Replication as ReplicationFB;
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[allow(clippy::enum_variant_names)]
 pub enum Replication {
     ExceptSelf,
@@ -2,18 +2,26 @@ use std::fmt::Display;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
 
 use super::{Decode, DecodeError, Encode};
 use crate::flatbuffers::Vec3dT;
 use crate::subscriptions::CubeArea;
 
-#[derive(Debug, Default, Getters, Clone, Copy, PartialEq)]
+#[derive(Debug, Default, Getters, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawVector3")]
 pub struct Vector3 {
     x: f64,
     y: f64,
     z: f64,
 }
 
+/// Largest absolute coordinate accepted from peers.
+///
+/// Keeps region and table coordinates well within `i64`, and positions exactly
+/// representable as `f64`.
+pub const MAX_COORDINATE: f64 = 1e15;
+
 impl Vector3 {
     #[inline]
     pub fn new(x: f64, y: f64, z: f64) -> Self {
@@ -24,6 +32,53 @@ impl Vector3 {
     pub fn zero() -> Self {
         Self::new(0.0, 0.0, 0.0)
     }
+
+    /// Returns `true` if every coordinate is finite and within [`MAX_COORDINATE`].
+    #[inline]
+    pub fn is_valid_position(&self) -> bool {
+        [self.x, self.y, self.z]
+            .iter()
+            .all(|c| c.is_finite() && c.abs() <= MAX_COORDINATE)
+    }
+
+    // region: Geometry
+    /// Returns the squared length of this vector, avoiding a square root.
+    #[inline]
+    pub fn length_squared(&self) -> f64 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    /// Returns the length of this vector.
+    #[inline]
+    pub fn length(&self) -> f64 {
+        self.length_squared().sqrt()
+    }
+
+    /// Returns the squared distance between two points, avoiding a square root.
+    #[inline]
+    pub fn distance_squared(&self, other: &Vector3) -> f64 {
+        (*self - *other).length_squared()
+    }
+
+    /// Returns the distance between two points.
+    #[inline]
+    pub fn distance(&self, other: &Vector3) -> f64 {
+        self.distance_squared(other).sqrt()
+    }
+
+    /// Returns a vector with the same direction and a length of 1.
+    ///
+    /// A zero length vector is returned unchanged.
+    #[inline]
+    pub fn normalized(&self) -> Self {
+        let length = self.length();
+        if length == 0.0 {
+            return *self;
+        }
+
+        *self / length
+    }
+    // endregion
 }
 
 // region: Display Trait
@@ -67,7 +122,32 @@ impl Decode<Vec3dT> for Vector3 {
         let y = encoded.y;
         let z = encoded.z;
 
-        Ok(Self { x, y, z })
+        let position = Self { x, y, z };
+        match position.is_valid_position() {
+            true => Ok(position),
+            false => Err(DecodeError::InvalidPosition(position)),
+        }
+    }
+}
+
+/// Unchecked [`Vector3`] as deserialized by serde, before the same checks as [`Decode`].
+#[derive(Deserialize)]
+struct RawVector3 {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl TryFrom<RawVector3> for Vector3 {
+    type Error = DecodeError;
+
+    #[inline]
+    fn try_from(raw: RawVector3) -> Result<Self, Self::Error> {
+        Self::decode(Vec3dT {
+            x: raw.x,
+            y: raw.y,
+            z: raw.z,
+        })
     }
 }
 // endregion
@@ -223,3 +303,45 @@ impl Neg for Vector3 {
     }
 }
 // endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry() {
+        let a = Vector3::new(1.0, 2.0, 2.0);
+        let b = Vector3::new(4.0, 6.0, 2.0);
+
+        assert_eq!(a.length_squared(), 9.0);
+        assert_eq!(a.length(), 3.0);
+        assert_eq!(a.distance_squared(&b), 25.0);
+        assert_eq!(a.distance(&b), 5.0);
+        assert_eq!(b.distance(&a), 5.0);
+
+        assert_eq!(a + b, Vector3::new(5.0, 8.0, 4.0));
+        assert_eq!(b - a, Vector3::new(3.0, 4.0, 0.0));
+
+        assert_eq!(
+            Vector3::new(0.0, 3.0, 4.0).normalized(),
+            Vector3::new(0.0, 0.6, 0.8)
+        );
+        assert!((a.normalized().length() - 1.0).abs() < f64::EPSILON);
+        assert_eq!(Vector3::zero().normalized(), Vector3::zero());
+    }
+
+    #[test]
+    fn decode_rejects_invalid_positions() {
+        let decode = |x, y, z| Vector3::decode(Vec3dT { x, y, z });
+
+        assert!(decode(-16.0, 0.0, 1e15).is_ok());
+        assert!(decode(f64::NAN, 0.0, 0.0).is_err());
+        assert!(decode(0.0, f64::INFINITY, 0.0).is_err());
+        assert!(decode(0.0, 0.0, -1e300).is_err());
+
+        assert!(serde_json::from_str::<Vector3>(r#"{"x":1,"y":2,"z":3}"#).is_ok());
+        assert!(serde_json::from_str::<Vector3>(r#"{"x":1e300,"y":2,"z":3}"#).is_err());
+    }
+}
+// endregion
@@ -0,0 +1,86 @@
+use std::fmt::Display;
+
+use crate::utils::handshake_capabilities;
+
+// region: WireFormat Enum
+/// Serialization format used for [`super::Message`] on the wire.
+///
+/// Negotiated per peer during the handshake, defaults to FlatBuffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WireFormat {
+    FlatBuffers,
+    Json,
+}
+
+impl Default for WireFormat {
+    #[inline]
+    fn default() -> Self {
+        Self::FlatBuffers
+    }
+}
+
+impl WireFormat {
+    /// Parse the format advertised in a handshake `parameter` of the form
+    /// `addr[;capability...]`.
+    pub fn from_handshake(parameter: &str) -> Self {
+        let json = handshake_capabilities(parameter).any(|capability| capability == "json");
+
+        match json {
+            true => Self::Json,
+            false => Self::FlatBuffers,
+        }
+    }
+
+    /// Guess the format of a serialized message.
+    ///
+    /// JSON messages are always objects, while FlatBuffers messages start with a small
+    /// little-endian root table offset, so are never mistaken for an opening brace.
+    pub fn detect(data: &[u8]) -> Self {
+        let first = data.iter().find(|byte| !byte.is_ascii_whitespace());
+
+        match first {
+            Some(b'{') => Self::Json,
+            _ => Self::FlatBuffers,
+        }
+    }
+}
+
+impl Display for WireFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FlatBuffers => write!(f, "flatbuffers"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_handshake() {
+        assert_eq!(
+            WireFormat::from_handshake("127.0.0.1:5556"),
+            WireFormat::FlatBuffers
+        );
+
+        assert_eq!(
+            WireFormat::from_handshake("127.0.0.1:5556;lz4;json"),
+            WireFormat::Json
+        );
+
+        // Address is never treated as a capability
+        assert_eq!(WireFormat::from_handshake("json"), WireFormat::FlatBuffers);
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(WireFormat::detect(b" {\"a\": 1}"), WireFormat::Json);
+        assert_eq!(WireFormat::detect(&[16, 0, 0, 0]), WireFormat::FlatBuffers);
+        assert_eq!(WireFormat::detect(&[]), WireFormat::FlatBuffers);
+    }
+}
+// endregion
@@ -1,35 +1,107 @@
 use std::fmt::Display;
 
 use ahash::{AHashMap, AHashSet};
+use tokio::sync::broadcast;
 use tracing::trace;
 use uuid::Uuid;
 
-use super::{CubeArea, ToCubeArea};
+#[cfg(test)]
+use super::world_map::CubeSizeError;
+use super::{CubeArea, SubscriptionEvent, ToCubeArea};
 
 #[derive(Debug)]
 pub struct AreaMap {
     cube_size: u16,
     world_name: String,
 
+    max_subscriptions_per_peer: Option<usize>,
+
     map: AHashMap<CubeArea, AHashSet<Uuid>>,
+    peer_areas: AHashMap<Uuid, AHashSet<CubeArea>>,
     subscribed_peers: AHashSet<Uuid>,
     empty_set: AHashSet<Uuid>,
+
+    events: Option<broadcast::Sender<SubscriptionEvent>>,
 }
 
 impl AreaMap {
-    pub fn new(cube_size: u16, world_name: String) -> Self {
+    /// Creates an empty map of `cube_size` areas, which must be greater than 0.
+    ///
+    /// Areas are sized independently of the [`crate::database::DatabaseClient`] regions
+    /// records are stored in. Keeping each region size a multiple of `cube_size` means
+    /// every area lies inside a single region, so the records read for a position always
+    /// cover the area a peer is subscribed to there.
+    #[cfg(test)]
+    pub fn new(
+        cube_size: u16,
+        world_name: String,
+        max_subscriptions_per_peer: Option<usize>,
+    ) -> Result<Self, CubeSizeError> {
+        if cube_size == 0 {
+            return Err(CubeSizeError::Zero);
+        }
+
+        Ok(Self::new_unchecked(
+            cube_size,
+            world_name,
+            max_subscriptions_per_peer,
+        ))
+    }
+
+    /// Same as [`Self::new`], for a `cube_size` already checked to be greater than 0.
+    pub(super) fn new_unchecked(
+        cube_size: u16,
+        world_name: String,
+        max_subscriptions_per_peer: Option<usize>,
+    ) -> Self {
         Self {
             cube_size,
             world_name,
+            max_subscriptions_per_peer,
 
             map: AHashMap::new(),
+            peer_areas: AHashMap::new(),
             subscribed_peers: AHashSet::new(),
             empty_set: AHashSet::new(),
+
+            events: None,
         }
     }
 
+    /// Sets the channel subscription changes are emitted to.
+    #[inline]
+    pub fn set_event_sender(&mut self, events: Option<broadcast::Sender<SubscriptionEvent>>) {
+        self.events = events;
+    }
+
+    /// Emit an event, only building it if anyone is listening.
+    #[inline]
+    fn emit(&self, event: impl FnOnce(String) -> SubscriptionEvent) {
+        if let Some(events) = &self.events {
+            if events.receiver_count() > 0 {
+                let _ = events.send(event(self.world_name.clone()));
+            }
+        }
+    }
+
+    /// Returns the size of each [`CubeArea`] in this world.
+    #[inline]
+    pub fn cube_size(&self) -> u16 {
+        self.cube_size
+    }
+
+    /// Sets the maximum number of areas a single peer can subscribe to.
+    ///
+    /// Existing subscriptions above the new limit are kept.
+    #[cfg(test)]
+    #[inline]
+    pub fn set_max_subscriptions_per_peer(&mut self, limit: Option<usize>) {
+        self.max_subscriptions_per_peer = limit;
+    }
+
     /// Returns `true` if the [`crate::transport::Peer`] corresponding to the given UUID
     /// is subscribed to the given area.
+    #[cfg(test)]
     pub fn is_peer_subscribed(&self, uuid: &Uuid, cube: impl ToCubeArea) -> bool {
         let cube = cube.to_cube_area(self.cube_size);
         let entry = self.map.get(&cube);
@@ -42,6 +114,7 @@ impl AreaMap {
 
     /// Returns `true` if the [`crate::transport::Peer`] corresponding to the given UUID
     /// is subscribed to this world.
+    #[cfg(test)]
     #[inline]
     pub fn is_peer_subscribed_any(&self, uuid: &Uuid) -> bool {
         self.subscribed_peers.contains(uuid)
@@ -59,6 +132,28 @@ impl AreaMap {
         }
     }
 
+    /// Returns the number of [`crate::transport::Peer`] structs which are subscribed to the
+    /// given area.
+    #[cfg(test)]
+    #[inline]
+    pub fn subscriber_count(&self, cube: impl ToCubeArea) -> usize {
+        let cube = cube.to_cube_area(self.cube_size);
+        self.map.get(&cube).map_or(0, |set| set.len())
+    }
+
+    /// Returns the total number of subscriptions across every area in this world.
+    #[inline]
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn total_subscriptions(&self) -> usize {
+        self.map.values().map(|set| set.len()).sum()
+    }
+
+    /// Returns `true` if no peer is subscribed to any area.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
     /// Returns a vector of [`crate::transport::Peer`] structs which are subscribed to
     /// this world.
     #[inline]
@@ -66,12 +161,48 @@ impl AreaMap {
         self.subscribed_peers.iter().copied()
     }
 
-    /// If the subscription was added, `true` is returned.
-    ///
-    /// If the subscription was already present, `false` is returned
-    pub fn add_subscription(&mut self, uuid: Uuid, cube: impl ToCubeArea) -> bool {
+    /// Returns every area the [`crate::transport::Peer`] corresponding to the given UUID
+    /// is subscribed to.
+    #[cfg(test)]
+    pub fn get_peer_areas(&self, uuid: &Uuid) -> Vec<CubeArea> {
+        match self.peer_areas.get(uuid) {
+            None => vec![],
+            Some(set) => set.iter().copied().collect(),
+        }
+    }
+
+    /// Returns every other peer subscribed to at least one area the
+    /// [`crate::transport::Peer`] corresponding to the given UUID is subscribed to.
+    pub fn get_peer_neighbours(&self, uuid: &Uuid) -> AHashSet<Uuid> {
+        let mut neighbours = AHashSet::new();
+        if let Some(areas) = self.peer_areas.get(uuid) {
+            for cube in areas {
+                if let Some(peers) = self.map.get(cube) {
+                    neighbours.extend(peers.iter().filter(|peer| *peer != uuid));
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    /// Returns whether the subscription was added, was already present, or would exceed
+    /// the per-peer subscription limit.
+    pub fn add_subscription(&mut self, uuid: Uuid, cube: impl ToCubeArea) -> AddResult {
         let cube = cube.to_cube_area(self.cube_size);
-        let entry = self.map.entry(cube).or_insert_with(Default::default);
+        let areas = self.peer_areas.get(&uuid);
+
+        // Early return if already subscribed
+        if areas.map_or(false, |set| set.contains(&cube)) {
+            return AddResult::AlreadySubscribed;
+        }
+
+        // Refuse subscriptions above the limit
+        if let Some(limit) = self.max_subscriptions_per_peer {
+            if areas.map_or(0, |set| set.len()) >= limit {
+                return AddResult::LimitReached;
+            }
+        }
 
         trace!(
             "peer {} subscribed to region {} in world \"{}\"",
@@ -81,7 +212,41 @@ impl AreaMap {
         );
 
         self.subscribed_peers.insert(uuid);
-        entry.insert(uuid)
+        self.peer_areas.entry(uuid).or_default().insert(cube);
+        self.map.entry(cube).or_default().insert(uuid);
+
+        self.emit(|world_name| SubscriptionEvent::Added {
+            uuid,
+            world_name,
+            cube,
+        });
+
+        AddResult::Added
+    }
+
+    /// Subscribe to every area within `cube_radius` cubes of `center` on each axis.
+    ///
+    /// Returns the number of subscriptions that were newly added. Stops early if the
+    /// per-peer subscription limit is reached.
+    #[cfg(test)]
+    pub fn add_subscription_radius(
+        &mut self,
+        uuid: Uuid,
+        center: impl ToCubeArea,
+        cube_radius: u16,
+    ) -> usize {
+        let center = center.to_cube_area(self.cube_size);
+
+        let mut added = 0;
+        for cube in center.neighbors(cube_radius, self.cube_size) {
+            match self.add_subscription(uuid, cube) {
+                AddResult::Added => added += 1,
+                AddResult::AlreadySubscribed => (),
+                AddResult::LimitReached => break,
+            }
+        }
+
+        added
     }
 
     /// Returns whether the subscription was removed.
@@ -101,7 +266,7 @@ impl AreaMap {
         );
 
         // Remove from HashSet
-        let entry = self.map.entry(cube).or_insert_with(Default::default);
+        let entry = self.map.entry(cube).or_default();
         let removed = entry.remove(uuid);
 
         // Remove HashSet from HashMap if empty
@@ -109,32 +274,145 @@ impl AreaMap {
             self.map.remove(&cube);
         }
 
-        // Remove from subscriptions set if no subscriptions are left
-        let has_other_subs = self.map.values().any(|set| set.contains(uuid));
-        if !has_other_subs {
-            self.subscribed_peers.remove(uuid);
+        // Remove from reverse index and subscriptions set if no subscriptions are left
+        if let Some(areas) = self.peer_areas.get_mut(uuid) {
+            areas.remove(&cube);
+            if areas.is_empty() {
+                self.peer_areas.remove(uuid);
+                self.subscribed_peers.remove(uuid);
+            }
+        }
+
+        if removed {
+            self.emit(|world_name| SubscriptionEvent::Removed {
+                uuid: *uuid,
+                world_name,
+                cube,
+            });
         }
 
         removed
     }
 
+    /// Move a subscription from one area to another in a single operation.
+    ///
+    /// The returned [`MoveResult`] describes which areas gained and lost the peer, so the
+    /// caller can compute which records to send or drop. Moving to the same area is a no-op.
+    #[cfg(test)]
+    pub fn move_subscription(
+        &mut self,
+        uuid: Uuid,
+        from: impl ToCubeArea,
+        to: impl ToCubeArea,
+    ) -> MoveResult {
+        let from = from.to_cube_area(self.cube_size);
+        let to = to.to_cube_area(self.cube_size);
+
+        // Early return if nothing would change
+        if from == to {
+            return MoveResult::default();
+        }
+
+        // Remove first so moving never counts towards the subscription limit
+        let lost = self.remove_subscription(&uuid, from);
+        let gained = self.add_subscription(uuid, to) == AddResult::Added;
+
+        MoveResult {
+            lost: lost.then(|| from),
+            gained: gained.then(|| to),
+        }
+    }
+
     /// Completely removes a [`crate::transport::Peer`] from the map.
     ///
     /// Used in the event of a disconnect.
     pub fn remove_peer(&mut self, uuid: &Uuid) -> bool {
         self.subscribed_peers.remove(uuid);
 
-        let mut removed = false;
-        for peers in self.map.values_mut() {
-            if peers.remove(uuid) {
-                removed = true;
+        // Only visit areas the peer actually joined
+        let areas = match self.peer_areas.remove(uuid) {
+            None => return false,
+            Some(areas) => areas,
+        };
+
+        for cube in &areas {
+            if let Some(peers) = self.map.get_mut(cube) {
+                peers.remove(uuid);
+
+                // Remove HashSet from HashMap if empty
+                if peers.is_empty() {
+                    self.map.remove(cube);
+                }
             }
         }
 
-        removed
+        self.emit(|world_name| SubscriptionEvent::PeerDropped {
+            uuid: *uuid,
+            world_name,
+            cubes: areas.into_iter().collect(),
+        });
+
+        true
+    }
+
+    /// Rebuilds the per-peer indexes from the peers subscribed to each area, dropping
+    /// empty areas.
+    ///
+    /// Updates touch the areas and indexes one after another, so an update that stops
+    /// part way through can leave them disagreeing.
+    pub fn rebuild_indexes(&mut self) {
+        self.map.retain(|_, peers| !peers.is_empty());
+
+        self.peer_areas.clear();
+        for (cube, peers) in &self.map {
+            for uuid in peers {
+                self.peer_areas.entry(*uuid).or_default().insert(*cube);
+            }
+        }
+
+        self.subscribed_peers = self.peer_areas.keys().copied().collect();
+    }
+
+    /// Removes every subscription the [`crate::transport::Peer`] corresponding to the
+    /// given UUID has in this world.
+    ///
+    /// Unlike [`Self::remove_peer`] the peer is still connected, such as when switching
+    /// worlds. Returns `false` if the peer had no subscriptions.
+    #[inline]
+    pub fn clear_peer(&mut self, uuid: &Uuid) -> bool {
+        self.remove_peer(uuid)
+    }
+}
+
+/// Outcome of [`AreaMap::add_subscription`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddResult {
+    Added,
+    AlreadySubscribed,
+    LimitReached,
+}
+
+impl Display for AddResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added => write!(f, "subscribed"),
+            Self::AlreadySubscribed => write!(f, "already_subscribed"),
+            Self::LimitReached => write!(f, "limit_reached"),
+        }
     }
 }
 
+/// Areas affected by [`AreaMap::move_subscription`].
+#[cfg(test)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MoveResult {
+    /// Area the peer was unsubscribed from, if it was subscribed.
+    pub lost: Option<CubeArea>,
+
+    /// Area the peer was newly subscribed to, if it wasn't already.
+    pub gained: Option<CubeArea>,
+}
+
 impl Display for AreaMap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -154,7 +432,7 @@ mod tests {
     #[test]
     fn area_subscriptions() {
         let uuid = Uuid::new_v4();
-        let mut map = AreaMap::new(16, "world".into());
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
 
         let cube_1 = CubeArea::new(0, 0, 0);
         let cube_2 = CubeArea::new(16, 16, 16);
@@ -211,7 +489,7 @@ mod tests {
 
         let cube_1 = CubeArea::new(0, 0, 0);
         let cube_2 = CubeArea::new(16, 16, 16);
-        let mut map = AreaMap::new(16, "world".into());
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
 
         // Neither are subscribed
         assert!(!map.is_peer_subscribed_any(&uuid_1));
@@ -252,4 +530,240 @@ mod tests {
         assert!(!map.is_peer_subscribed_any(&uuid_1));
         assert!(!map.is_peer_subscribed_any(&uuid_2));
     }
+
+    #[test]
+    fn rebuild_indexes() {
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+        let (uuid_1, uuid_2) = (Uuid::new_v4(), Uuid::new_v4());
+        let cube_1 = CubeArea::new(16, 16, 16);
+        let cube_2 = CubeArea::new(32, 16, 16);
+
+        map.add_subscription(uuid_1, cube_1);
+        map.add_subscription(uuid_2, cube_2);
+
+        // Stop part way through removing and adding subscriptions
+        map.map.get_mut(&cube_1).unwrap().remove(&uuid_1);
+        map.map.entry(cube_2).or_default().insert(uuid_1);
+
+        map.rebuild_indexes();
+        assert_eq!(map.get_peer_areas(&uuid_1), vec![cube_2]);
+        assert_eq!(map.subscriber_count(cube_1), 0);
+        assert_eq!(map.subscriber_count(cube_2), 2);
+        assert_eq!(map.total_subscriptions(), 2);
+
+        map.map.get_mut(&cube_2).unwrap().clear();
+        map.rebuild_indexes();
+        assert!(!map.is_peer_subscribed_any(&uuid_1));
+        assert!(!map.is_peer_subscribed_any(&uuid_2));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn peer_areas() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+
+        let cube_1 = CubeArea::new(16, 16, 16);
+        let cube_2 = CubeArea::new(32, 32, 32);
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+
+        // No subscriptions yet
+        assert!(map.get_peer_areas(&uuid_1).is_empty());
+
+        map.add_subscription(uuid_1, cube_1);
+        map.add_subscription(uuid_1, cube_2);
+        map.add_subscription(uuid_2, cube_2);
+
+        let mut areas = map.get_peer_areas(&uuid_1);
+        areas.sort_by_key(|cube| *cube.x());
+        assert_eq!(areas, vec![cube_1, cube_2]);
+        assert_eq!(map.get_peer_areas(&uuid_2), vec![cube_2]);
+
+        // Unsubscribe keeps the index in sync
+        map.remove_subscription(&uuid_1, cube_1);
+        assert_eq!(map.get_peer_areas(&uuid_1), vec![cube_2]);
+
+        // Removing a peer clears only its own areas
+        map.remove_peer(&uuid_1);
+        assert!(map.get_peer_areas(&uuid_1).is_empty());
+        assert_eq!(map.get_peer_areas(&uuid_2), vec![cube_2]);
+
+        // Neighbours share at least one area
+        let uuid_3 = Uuid::new_v4();
+        map.add_subscription(uuid_1, cube_2);
+        map.add_subscription(uuid_3, cube_1);
+        assert_eq!(
+            map.get_peer_neighbours(&uuid_2),
+            [uuid_1].iter().copied().collect()
+        );
+        assert_eq!(
+            map.get_peer_neighbours(&uuid_1),
+            [uuid_2].iter().copied().collect()
+        );
+        assert!(map.get_peer_neighbours(&uuid_3).is_empty());
+        map.remove_peer(&uuid_1);
+        map.remove_peer(&uuid_3);
+
+        // Emptied areas are cleaned up
+        assert!(map.remove_peer(&uuid_2));
+        assert!(!map.remove_peer(&uuid_2));
+        assert!(map.map.is_empty());
+    }
+
+    #[test]
+    fn move_subscriptions() {
+        let uuid = Uuid::new_v4();
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+
+        let cube_1 = CubeArea::new(16, 16, 16);
+        let cube_2 = CubeArea::new(32, 32, 32);
+
+        // Same area is a no-op
+        map.add_subscription(uuid, cube_1);
+        assert_eq!(
+            map.move_subscription(uuid, cube_1, cube_1),
+            MoveResult::default()
+        );
+        assert!(map.is_peer_subscribed(&uuid, cube_1));
+
+        // Move to a new area
+        let result = map.move_subscription(uuid, cube_1, cube_2);
+        assert_eq!(result.lost, Some(cube_1));
+        assert_eq!(result.gained, Some(cube_2));
+        assert!(!map.is_peer_subscribed(&uuid, cube_1));
+        assert!(map.is_peer_subscribed(&uuid, cube_2));
+        assert!(map.is_peer_subscribed_any(&uuid));
+
+        // Repeating the move changes nothing
+        let result = map.move_subscription(uuid, cube_1, cube_2);
+        assert_eq!(result.lost, None);
+        assert_eq!(result.gained, None);
+    }
+
+    #[test]
+    fn radius_subscriptions() {
+        let uuid = Uuid::new_v4();
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+
+        let center = CubeArea::new(16, 16, 16);
+
+        // Radius of 0 is a single cube
+        assert_eq!(map.add_subscription_radius(uuid, center, 0), 1);
+        assert!(map.is_peer_subscribed(&uuid, center));
+
+        // Radius of 1 is a 3x3x3 box, the center is already subscribed
+        assert_eq!(map.add_subscription_radius(uuid, center, 1), 26);
+        assert!(map.is_peer_subscribed(&uuid, CubeArea::new(32, 32, 32)));
+        assert!(map.is_peer_subscribed(&uuid, CubeArea::new(-16, -16, -16)));
+        assert!(!map.is_peer_subscribed(&uuid, CubeArea::new(48, 16, 16)));
+
+        // Nothing new to add
+        assert_eq!(map.add_subscription_radius(uuid, center, 1), 0);
+    }
+
+    #[test]
+    fn subscription_counts() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+
+        let cube_1 = CubeArea::new(16, 16, 16);
+        let cube_2 = CubeArea::new(32, 32, 32);
+
+        // No subscriptions yet
+        assert_eq!(map.subscriber_count(cube_1), 0);
+        assert_eq!(map.total_subscriptions(), 0);
+
+        map.add_subscription(uuid_1, cube_1);
+        map.add_subscription(uuid_2, cube_1);
+        map.add_subscription(uuid_2, cube_2);
+
+        assert_eq!(map.subscriber_count(cube_1), 2);
+        assert_eq!(map.subscriber_count(cube_2), 1);
+        assert_eq!(map.total_subscriptions(), 3);
+
+        map.remove_peer(&uuid_2);
+        assert_eq!(map.subscriber_count(cube_1), 1);
+        assert_eq!(map.subscriber_count(cube_2), 0);
+        assert_eq!(map.total_subscriptions(), 1);
+    }
+
+    #[test]
+    fn subscription_limit() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+        let mut map = AreaMap::new(16, "world".into(), Some(2)).unwrap();
+
+        let cube_1 = CubeArea::new(16, 16, 16);
+        let cube_2 = CubeArea::new(32, 32, 32);
+        let cube_3 = CubeArea::new(48, 48, 48);
+
+        assert_eq!(map.add_subscription(uuid_1, cube_1), AddResult::Added);
+        assert_eq!(
+            map.add_subscription(uuid_1, cube_1),
+            AddResult::AlreadySubscribed
+        );
+        assert_eq!(map.add_subscription(uuid_1, cube_2), AddResult::Added);
+        assert_eq!(
+            map.add_subscription(uuid_1, cube_3),
+            AddResult::LimitReached
+        );
+        assert!(!map.is_peer_subscribed(&uuid_1, cube_3));
+
+        // Limit is per-peer
+        assert_eq!(map.add_subscription(uuid_2, cube_3), AddResult::Added);
+
+        // Moving doesn't count towards the limit
+        let result = map.move_subscription(uuid_1, cube_2, cube_3);
+        assert_eq!(result.gained, Some(cube_3));
+
+        // Radius subscriptions stop at the limit
+        assert_eq!(map.add_subscription_radius(uuid_2, cube_1, 1), 1);
+        assert_eq!(map.get_peer_areas(&uuid_2).len(), 2);
+    }
+
+    #[test]
+    fn subscription_events() {
+        let uuid = Uuid::new_v4();
+        let cube = CubeArea::new(16, 16, 16);
+        let mut map = AreaMap::new(16, "world".into(), None).unwrap();
+
+        let (tx, mut rx) = broadcast::channel(16);
+        map.set_event_sender(Some(tx));
+
+        map.add_subscription(uuid, cube);
+        map.add_subscription(uuid, cube);
+        map.remove_subscription(&uuid, cube);
+        map.remove_subscription(&uuid, cube);
+        map.add_subscription(uuid, cube);
+        map.remove_peer(&uuid);
+
+        let world_name = String::from("world");
+        let added = SubscriptionEvent::Added {
+            uuid,
+            world_name: world_name.clone(),
+            cube,
+        };
+
+        // Only changes are emitted
+        assert_eq!(rx.try_recv().unwrap(), added);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            SubscriptionEvent::Removed {
+                uuid,
+                world_name: world_name.clone(),
+                cube,
+            }
+        );
+        assert_eq!(rx.try_recv().unwrap(), added);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            SubscriptionEvent::PeerDropped {
+                uuid,
+                world_name,
+                cubes: vec![cube],
+            }
+        );
+        assert!(rx.try_recv().is_err());
+    }
 }
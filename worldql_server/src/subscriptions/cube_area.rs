@@ -1,11 +1,13 @@
 use std::fmt::Display;
 
 use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
 
 use crate::structures::Vector3;
 
 // region: CubeArea
-#[derive(Debug, Default, Getters, Clone, Copy, PartialEq, Eq, Hash)]
+/// Serializes as `{ "x": .., "y": .., "z": .. }`, the [`Display`] format is kept for logs.
+#[derive(Debug, Default, Getters, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CubeArea {
     x: i64,
     y: i64,
@@ -17,6 +19,20 @@ impl CubeArea {
         Self { x, y, z }
     }
 
+    /// Create a [`CubeArea`] from `(x, y, z)` coordinates, eg: captured with [`Self::to_coords`].
+    ///
+    /// Coordinates are used as-is, they must already be aligned to the cube size.
+    #[inline]
+    pub fn from_coords((x, y, z): (i64, i64, i64)) -> Self {
+        Self::new(x, y, z)
+    }
+
+    /// Returns the `(x, y, z)` coordinates of this area.
+    #[inline]
+    pub fn to_coords(self) -> (i64, i64, i64) {
+        (self.x, self.y, self.z)
+    }
+
     /// Clamp to largest absolute coordinate value.
     ///
     /// This allows us to disambiguate positive and negative areas.
@@ -54,6 +70,80 @@ impl CubeArea {
 
         Self::new(x, y, z)
     }
+
+    /// Returns the [`CubeArea`] offset from this one by the given number of cubes on
+    /// each axis.
+    ///
+    /// Cube coordinates skip zero, so the neighbour of `size` in the negative direction
+    /// is `-size`.
+    pub(super) fn offset(&self, dx: i64, dy: i64, dz: i64, size: u16) -> Self {
+        let size = i64::from(size);
+
+        let x = Self::coord_offset(self.x, dx, size);
+        let y = Self::coord_offset(self.y, dy, size);
+        let z = Self::coord_offset(self.z, dz, size);
+
+        Self::new(x, y, z)
+    }
+
+    /// Returns the `(min, max)` corners of the space covered by this area.
+    ///
+    /// Positive coordinates are the far corner of their cube and negative coordinates the
+    /// near corner, see [`Self::coord_clamp`].
+    pub fn bounds(&self, size: u16) -> (Vector3, Vector3) {
+        let size = i64::from(size);
+        let min = |coord: i64| match coord > 0 {
+            true => (coord - size) as f64,
+            false => coord as f64,
+        };
+
+        let min = Vector3::new(min(self.x), min(self.y), min(self.z));
+        let size = size as f64;
+
+        (min, min + Vector3::new(size, size, size))
+    }
+
+    /// Returns every [`CubeArea`] within a Chebyshev distance of `radius` cubes,
+    /// including this one.
+    pub fn neighbors(&self, radius: u16, size: u16) -> impl Iterator<Item = CubeArea> {
+        let center = *self;
+        Self::offsets(radius).map(move |(dx, dy, dz)| center.offset(dx, dy, dz, size))
+    }
+
+    /// Returns every [`CubeArea`] at a Chebyshev distance of exactly `radius` cubes.
+    ///
+    /// A radius of 0 yields only this cube.
+    pub fn ring(&self, radius: u16, size: u16) -> impl Iterator<Item = CubeArea> {
+        let center = *self;
+        let radius_i = i64::from(radius);
+
+        Self::offsets(radius)
+            .filter(move |(dx, dy, dz)| dx.abs().max(dy.abs()).max(dz.abs()) == radius_i)
+            .map(move |(dx, dy, dz)| center.offset(dx, dy, dz, size))
+    }
+
+    /// Every `(dx, dy, dz)` offset within `radius` cubes on each axis.
+    fn offsets(radius: u16) -> impl Iterator<Item = (i64, i64, i64)> {
+        let radius = i64::from(radius);
+
+        (-radius..=radius).flat_map(move |dx| {
+            (-radius..=radius).flat_map(move |dy| (-radius..=radius).map(move |dz| (dx, dy, dz)))
+        })
+    }
+
+    fn coord_offset(coord: i64, steps: i64, size: i64) -> i64 {
+        // Map to a contiguous cube index, where 0 is the first positive cube
+        let index = match coord > 0 {
+            true => coord / size - 1,
+            false => coord / size,
+        };
+
+        let index = index + steps;
+        match index >= 0 {
+            true => (index + 1) * size,
+            false => index * size,
+        }
+    }
 }
 // endregion
 
@@ -174,5 +264,84 @@ mod tests {
         test_from_vector3!((25.0, -13.2, -0.1), (30, -20, -10), 10);
     }
     // endregion
+
+    // region: offset()
+    #[test]
+    fn offset() {
+        let cube = CubeArea::new(10, -10, 20);
+
+        assert_eq!(cube.offset(0, 0, 0, 10), cube);
+        assert_eq!(cube.offset(1, 1, 1, 10), CubeArea::new(20, 10, 30));
+        assert_eq!(cube.offset(-1, -1, -1, 10), CubeArea::new(-10, -20, 10));
+        assert_eq!(cube.offset(-2, 2, -3, 10), CubeArea::new(-20, 20, -20));
+    }
+    // endregion
+
+    // region: bounds()
+    #[test]
+    fn bounds() {
+        let (min, max) = CubeArea::new(10, -10, 20).bounds(10);
+        assert_eq!(min, Vector3::new(0.0, -10.0, 10.0));
+        assert_eq!(max, Vector3::new(10.0, 0.0, 20.0));
+
+        // Every point of a cube lies within its bounds
+        for point in [(0.0, -0.1, 10.1), (9.9, -10.0, 20.0), (5.0, -5.0, 15.0)] {
+            let point = Vector3::new(point.0, point.1, point.2);
+            let (min, max) = point.to_cube_area(10).bounds(10);
+
+            assert!(min.x() <= point.x() && point.x() <= max.x());
+            assert!(min.y() <= point.y() && point.y() <= max.y());
+            assert!(min.z() <= point.z() && point.z() <= max.z());
+        }
+    }
+    // endregion
+
+    // region: Serialization
+    #[test]
+    fn serialization() {
+        let cube = CubeArea::new(10, -10, 20);
+        assert_eq!(CubeArea::from_coords(cube.to_coords()), cube);
+
+        let json = serde_json::to_string(&cube).unwrap();
+        assert_eq!(json, r#"{"x":10,"y":-10,"z":20}"#);
+        assert_eq!(serde_json::from_str::<CubeArea>(&json).unwrap(), cube);
+
+        // Log format is unchanged
+        assert_eq!(cube.to_string(), "{ x = 10, y = -10, z = 20 }");
+    }
+    // endregion
+
+    // region: neighbors() / ring()
+    #[test]
+    fn neighbors() {
+        let cube = CubeArea::new(10, 10, 10);
+
+        assert_eq!(cube.neighbors(0, 10).collect::<Vec<_>>(), vec![cube]);
+        assert_eq!(cube.neighbors(1, 10).count(), 27);
+        assert_eq!(cube.neighbors(2, 10).count(), 125);
+
+        // Crosses the origin without producing a zero coordinate
+        assert!(cube
+            .neighbors(1, 10)
+            .any(|neighbor| neighbor == CubeArea::new(-10, -10, -10)));
+        assert!(cube
+            .neighbors(1, 10)
+            .all(|neighbor| neighbor.x != 0 && neighbor.y != 0 && neighbor.z != 0));
+    }
+
+    #[test]
+    fn ring() {
+        let cube = CubeArea::new(10, 10, 10);
+
+        assert_eq!(cube.ring(0, 10).collect::<Vec<_>>(), vec![cube]);
+        assert_eq!(cube.ring(1, 10).count(), 27 - 1);
+        assert_eq!(cube.ring(2, 10).count(), 125 - 27);
+
+        assert!(!cube.ring(1, 10).any(|neighbor| neighbor == cube));
+        assert!(cube
+            .ring(2, 10)
+            .any(|neighbor| neighbor == CubeArea::new(30, -10, 10)));
+    }
+    // endregion
 }
 // endregion
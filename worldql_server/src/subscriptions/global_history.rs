@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+
+use ahash::AHashMap;
+
+use crate::structures::Message;
+
+/// Ring buffer of the most recent global messages sent to each world.
+///
+/// Allows peers that join late to replay recent history, eg: for chat-like channels.
+#[derive(Debug)]
+pub struct GlobalHistory {
+    capacity: usize,
+    map: AHashMap<String, VecDeque<Message>>,
+}
+
+impl GlobalHistory {
+    /// Create a new history which keeps `capacity` messages per world.
+    ///
+    /// A capacity of 0 disables history.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            map: AHashMap::new(),
+        }
+    }
+
+    /// Returns `true` if messages are being stored.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Store a message for the given world, evicting the oldest message if full.
+    pub fn push(&mut self, world_name: &str, message: Message) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let buffer = self
+            .map
+            .entry(world_name.to_string())
+            .or_insert_with(|| VecDeque::with_capacity(self.capacity));
+
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+
+        buffer.push_back(message);
+    }
+
+    /// Returns the stored messages for the given world, oldest first.
+    pub fn get(&self, world_name: &str) -> impl Iterator<Item = &Message> + '_ {
+        self.map.get(world_name).into_iter().flatten()
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(parameter: &str) -> Message {
+        Message {
+            parameter: Some(parameter.into()),
+            ..Default::default()
+        }
+    }
+
+    fn parameters<'a>(history: &'a GlobalHistory, world_name: &str) -> Vec<&'a str> {
+        history
+            .get(world_name)
+            .filter_map(|message| message.parameter.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let mut history = GlobalHistory::new(2);
+        history.push("world", message("a"));
+        history.push("world", message("b"));
+        history.push("world", message("c"));
+        history.push("other", message("d"));
+
+        // Oldest messages are evicted per world
+        assert_eq!(parameters(&history, "world"), vec!["b", "c"]);
+        assert_eq!(parameters(&history, "other"), vec!["d"]);
+        assert!(parameters(&history, "missing").is_empty());
+
+        // Zero capacity stores nothing
+        let mut history = GlobalHistory::new(0);
+        history.push("world", message("a"));
+        assert!(parameters(&history, "world").is_empty());
+    }
+}
+// endregion
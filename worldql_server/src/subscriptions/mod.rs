@@ -1,7 +1,11 @@
 mod area_map;
 mod cube_area;
+mod global_history;
+mod subscription_event;
 mod world_map;
 
-pub use area_map::AreaMap;
+pub use area_map::{AddResult, AreaMap};
 pub use cube_area::{CubeArea, ToCubeArea};
-pub use world_map::WorldMap;
+pub use global_history::GlobalHistory;
+pub use subscription_event::SubscriptionEvent;
+pub use world_map::{ThreadWorldMap, WorldMap, WorldPolicy};
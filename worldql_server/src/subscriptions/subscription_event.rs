@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::CubeArea;
+
+// region: SubscriptionEvent Enum
+/// A change to the subscriptions of a [`super::WorldMap`].
+///
+/// Emitted to every receiver of [`super::WorldMap::subscribe_events`]. Events can be
+/// serialized to capture a session's subscriptions and replay them later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscriptionEvent {
+    /// A peer subscribed to an area
+    Added {
+        uuid: Uuid,
+        world_name: String,
+        cube: CubeArea,
+    },
+
+    /// A peer unsubscribed from an area
+    Removed {
+        uuid: Uuid,
+        world_name: String,
+        cube: CubeArea,
+    },
+
+    /// A peer was removed from every area in a world, eg: after disconnecting
+    PeerDropped {
+        uuid: Uuid,
+        world_name: String,
+        cubes: Vec<CubeArea>,
+    },
+}
+// endregion
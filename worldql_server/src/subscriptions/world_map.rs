@@ -1,40 +1,225 @@
 use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+use thiserror::Error;
+use tokio::sync::{broadcast, RwLock};
 use tracing::debug;
 use uuid::Uuid;
 
-use super::AreaMap;
+use super::{AreaMap, SubscriptionEvent};
+
+pub type ThreadWorldMap = Arc<RwLock<WorldMap>>;
+
+/// Number of events buffered per receiver before slow receivers start missing events.
+#[cfg(test)]
+const SUBSCRIPTION_EVENT_CAPACITY: usize = 1024;
 
 #[derive(Debug)]
 pub struct WorldMap {
     cube_size: u16,
+    world_cube_sizes: AHashMap<String, u16>,
+    max_subscriptions_per_peer: Option<usize>,
+    world_max_subscriptions: AHashMap<String, Option<usize>>,
+    policy: WorldPolicy,
+    allowed_worlds: AHashSet<String>,
+    max_worlds: Option<usize>,
     map: AHashMap<String, AreaMap>,
+    events: Option<broadcast::Sender<SubscriptionEvent>>,
 }
 
 impl WorldMap {
-    pub fn new(cube_size: u16) -> Self {
-        Self {
+    /// Creates an empty map whose worlds use `cube_size` areas unless overridden, see
+    /// [`AreaMap::new`].
+    pub fn new(
+        cube_size: u16,
+        max_subscriptions_per_peer: Option<usize>,
+    ) -> Result<Self, CubeSizeError> {
+        if cube_size == 0 {
+            return Err(CubeSizeError::Zero);
+        }
+
+        Ok(Self {
             cube_size,
+            world_cube_sizes: AHashMap::new(),
+            max_subscriptions_per_peer,
+            world_max_subscriptions: AHashMap::new(),
+            policy: WorldPolicy::default(),
+            allowed_worlds: AHashSet::new(),
+            max_worlds: None,
             map: AHashMap::new(),
+            events: None,
+        })
+    }
+
+    /// Returns a live feed of subscription changes across every world.
+    ///
+    /// Receivers that fall more than [`SUBSCRIPTION_EVENT_CAPACITY`] events behind
+    /// skip the oldest events, see [`broadcast::Receiver::recv`].
+    #[cfg(test)]
+    pub fn subscribe_events(&mut self) -> broadcast::Receiver<SubscriptionEvent> {
+        if let Some(events) = &self.events {
+            return events.subscribe();
+        }
+
+        let (tx, rx) = broadcast::channel(SUBSCRIPTION_EVENT_CAPACITY);
+        for area_map in self.map.values_mut() {
+            area_map.set_event_sender(Some(tx.clone()));
         }
+
+        self.events = Some(tx);
+        rx
+    }
+
+    /// Set which worlds peers can create, `allowed_worlds` is only used by
+    /// [`WorldPolicy::Allowlist`].
+    ///
+    /// Worlds that already exist are kept.
+    pub fn set_world_policy(
+        &mut self,
+        policy: WorldPolicy,
+        allowed_worlds: impl IntoIterator<Item = String>,
+    ) {
+        self.policy = policy;
+        self.allowed_worlds = allowed_worlds.into_iter().collect();
     }
 
-    /// Gets an [`AreaMap`] for the given world name.
+    /// Set the maximum number of worlds that can exist at once, unlimited if `None`.
+    pub fn set_max_worlds(&mut self, max_worlds: Option<usize>) {
+        self.max_worlds = max_worlds;
+    }
+
+    /// Override the maximum number of areas a single peer can subscribe to in one world.
+    #[cfg(test)]
+    pub fn set_max_subscriptions_per_peer(&mut self, world_name: &str, limit: Option<usize>) {
+        self.world_max_subscriptions
+            .insert(world_name.to_string(), limit);
+
+        if let Some(area_map) = self.map.get_mut(world_name) {
+            area_map.set_max_subscriptions_per_peer(limit);
+        }
+    }
+
+    /// Override the cube size used for one world, falling back to the default when unset.
+    ///
+    /// Existing subscriptions are keyed by cube, so the size of a world that already has
+    /// subscriptions can't be changed without rebuilding them and an error is returned instead.
+    #[cfg(test)]
+    pub fn set_cube_size(&mut self, world_name: &str, cube_size: u16) -> Result<(), CubeSizeError> {
+        if cube_size == 0 {
+            return Err(CubeSizeError::Zero);
+        }
+
+        if let Some(area_map) = self.map.get(world_name) {
+            let current = area_map.cube_size();
+            if current != cube_size {
+                if area_map.total_subscriptions() > 0 {
+                    return Err(CubeSizeError::Populated {
+                        world_name: world_name.to_string(),
+                        current,
+                    });
+                }
+
+                // Recreated with the new size on next access
+                self.map.remove(world_name);
+            }
+        }
+
+        self.world_cube_sizes
+            .insert(world_name.to_string(), cube_size);
+
+        Ok(())
+    }
+
+    /// Gets an [`AreaMap`] for the given world name, `None` if the world doesn't exist.
+    ///
+    /// Never creates worlds, so query handlers can tell a nonexistent world from an empty
+    /// one without allocating a world for every name they are sent.
     #[inline]
     pub fn get(&self, world_name: &str) -> Option<&AreaMap> {
         self.map.get(world_name)
     }
 
-    /// Gets a mutable [`AreaMap`] for the given world name.
+    /// Returns the total number of subscriptions in each world.
+    #[cfg_attr(not(feature = "admin"), allow(dead_code))]
+    pub fn subscription_counts(&self) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.map
+            .iter()
+            .map(|(world_name, area_map)| (world_name.as_str(), area_map.total_subscriptions()))
+    }
+
+    /// Gets a mutable [`AreaMap`] for the given world name, if the world exists.
+    #[inline]
+    pub fn get_existing_mut(&mut self, world_name: &str) -> Option<&mut AreaMap> {
+        self.map.get_mut(world_name)
+    }
+
+    /// Gets a mutable [`AreaMap`] for the given world name, only creating the world if
+    /// allowed by the [`WorldPolicy`] and world limit.
+    ///
+    /// Empty worlds are removed to make room once the limit is reached.
+    ///
+    /// Use this for world names sent by peers.
+    pub fn try_get_mut(&mut self, world_name: &str) -> Result<&mut AreaMap, WorldError> {
+        if !self.map.contains_key(world_name) {
+            if self.policy == WorldPolicy::Allowlist && !self.allowed_worlds.contains(world_name) {
+                return Err(WorldError::NotAllowed {
+                    world_name: world_name.to_string(),
+                });
+            }
+
+            if let Some(max_worlds) = self.max_worlds {
+                // Worlds everyone has unsubscribed from don't count towards the limit
+                if self.map.len() >= max_worlds {
+                    self.map.retain(|_, area_map| !area_map.is_empty());
+                }
+
+                if self.map.len() >= max_worlds {
+                    return Err(WorldError::LimitReached { max_worlds });
+                }
+            }
+        }
+
+        Ok(self.get_mut(world_name))
+    }
+
+    /// Gets a mutable [`AreaMap`] for the given world name, creating the world if needed.
+    ///
+    /// Ignores the [`WorldPolicy`] and world limit. Only subscribing creates worlds, so
+    /// handlers go through [`Self::try_get_mut`] or [`Self::get_existing_mut`] instead.
     #[inline]
-    pub fn get_mut(&mut self, world_name: &str) -> &mut AreaMap {
+    fn get_mut(&mut self, world_name: &str) -> &mut AreaMap {
         self.map.entry(world_name.to_string()).or_insert_with(|| {
             debug!("creating new world: {}", world_name);
-            AreaMap::new(self.cube_size, world_name.to_string())
+
+            let limit = match self.world_max_subscriptions.get(world_name) {
+                Some(limit) => *limit,
+                None => self.max_subscriptions_per_peer,
+            };
+
+            let cube_size = match self.world_cube_sizes.get(world_name) {
+                Some(cube_size) => *cube_size,
+                None => self.cube_size,
+            };
+
+            let mut area_map = AreaMap::new_unchecked(cube_size, world_name.to_string(), limit);
+            area_map.set_event_sender(self.events.clone());
+
+            area_map
         })
     }
 
+    /// Returns the peers sharing an area with the [`crate::transport::Peer`] corresponding
+    /// to the given UUID, for each world it has neighbours in.
+    pub fn get_peer_neighbours(&self, uuid: &Uuid) -> Vec<(&str, AHashSet<Uuid>)> {
+        self.map
+            .iter()
+            .map(|(world_name, area_map)| (world_name.as_str(), area_map.get_peer_neighbours(uuid)))
+            .filter(|(_, neighbours)| !neighbours.is_empty())
+            .collect()
+    }
+
     /// Completely removes a [`crate::transport::Peer`] from the map.
     ///
     /// Used in the event of a disconnect.
@@ -59,6 +244,33 @@ impl WorldMap {
 
         removed
     }
+
+    /// Removes every subscription the [`crate::transport::Peer`] corresponding to the
+    /// given UUID has, across all worlds.
+    ///
+    /// Returns the number of worlds the peer was subscribed to.
+    pub fn clear_peer_everywhere(&mut self, uuid: &Uuid) -> usize {
+        let mut cleared = 0;
+        for (world_name, area_map) in self.map.iter_mut() {
+            if area_map.clear_peer(uuid) {
+                debug!(
+                    "cleared peer {} from \"{}\" subscriptions",
+                    uuid, world_name
+                );
+
+                cleared += 1;
+            }
+        }
+
+        cleared
+    }
+
+    /// Rebuilds the indexes of every world, see [`AreaMap::rebuild_indexes`].
+    pub fn rebuild_indexes(&mut self) {
+        for area_map in self.map.values_mut() {
+            area_map.rebuild_indexes();
+        }
+    }
 }
 
 impl Display for WorldMap {
@@ -76,3 +288,295 @@ impl Display for WorldMap {
         write!(f, "]")
     }
 }
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CubeSizeError {
+    #[error("cube size must be greater than 0")]
+    Zero,
+
+    #[cfg(test)]
+    #[error("world \"{world_name}\" already has subscriptions with cube size {current}")]
+    Populated { world_name: String, current: u16 },
+}
+
+// region: WorldPolicy Enum
+/// Which worlds peers can create by referencing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldPolicy {
+    /// Any world is created on first use
+    Open,
+    /// Only worlds on the allowlist can be used
+    Allowlist,
+}
+
+impl Default for WorldPolicy {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+impl FromStr for WorldPolicy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "allowlist" => Ok(Self::Allowlist),
+            _ => Err(ParsePolicyError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: open, allowlist")]
+pub struct ParsePolicyError;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WorldError {
+    #[error("world \"{world_name}\" is not on the allowlist")]
+    NotAllowed { world_name: String },
+
+    #[error("the maximum of {max_worlds} worlds has been reached")]
+    LimitReached { max_worlds: usize },
+}
+
+impl WorldError {
+    /// Short reason sent to peers in acknowledgements.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NotAllowed { .. } => "world_not_allowed",
+            Self::LimitReached { .. } => "world_limit_reached",
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::structures::Vector3;
+    use crate::subscriptions::{AddResult, ToCubeArea};
+
+    #[test]
+    fn set_cube_size() {
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        world_map.set_cube_size("small", 4).unwrap();
+
+        assert_eq!(world_map.get_mut("small").cube_size(), 4);
+        assert_eq!(world_map.get_mut("default").cube_size(), 16);
+
+        // Unpopulated worlds can still be resized
+        world_map.set_cube_size("default", 8).unwrap();
+        assert_eq!(world_map.get_mut("default").cube_size(), 8);
+
+        let uuid = Uuid::new_v4();
+        world_map
+            .get_mut("small")
+            .add_subscription(uuid, Vector3::new(0.0, 0.0, 0.0));
+
+        assert_eq!(world_map.set_cube_size("small", 4), Ok(()));
+        assert_eq!(
+            world_map.set_cube_size("small", 32),
+            Err(CubeSizeError::Populated {
+                world_name: "small".into(),
+                current: 4
+            })
+        );
+        assert_eq!(
+            world_map.set_cube_size("other", 0),
+            Err(CubeSizeError::Zero)
+        );
+
+        assert_eq!(WorldMap::new(0, None).err(), Some(CubeSizeError::Zero));
+        assert_eq!(
+            AreaMap::new(0, "world".into(), None).err(),
+            Some(CubeSizeError::Zero)
+        );
+    }
+
+    #[test]
+    fn world_policy() {
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        world_map.set_world_policy(WorldPolicy::Allowlist, vec!["lobby".to_string()]);
+        world_map.set_max_worlds(Some(2));
+
+        let uuid = Uuid::new_v4();
+        let pos = Vector3::new(0.0, 0.0, 0.0);
+
+        // Trusted callers can always create worlds
+        world_map.get_mut("internal").add_subscription(uuid, pos);
+
+        assert!(world_map.try_get_mut("lobby").is_ok());
+        world_map.get_mut("lobby").add_subscription(uuid, pos);
+        assert_eq!(
+            world_map.try_get_mut("other").unwrap_err(),
+            WorldError::NotAllowed {
+                world_name: "other".into()
+            }
+        );
+
+        // Existing worlds are always returned
+        world_map.set_world_policy(WorldPolicy::Open, vec![]);
+        assert!(world_map.try_get_mut("internal").is_ok());
+        assert_eq!(
+            world_map.try_get_mut("other").unwrap_err(),
+            WorldError::LimitReached { max_worlds: 2 }
+        );
+
+        assert!(world_map.get_existing_mut("other").is_none());
+    }
+
+    #[test]
+    fn empty_worlds_make_room() {
+        let uuid = Uuid::new_v4();
+        let pos = Vector3::new(0.0, 0.0, 0.0);
+
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        world_map.set_max_worlds(Some(1));
+        world_map
+            .try_get_mut("first")
+            .unwrap()
+            .add_subscription(uuid, pos);
+
+        assert_eq!(
+            world_map.try_get_mut("second").unwrap_err(),
+            WorldError::LimitReached { max_worlds: 1 }
+        );
+
+        world_map
+            .get_existing_mut("first")
+            .unwrap()
+            .remove_subscription(&uuid, pos);
+
+        world_map
+            .try_get_mut("second")
+            .unwrap()
+            .add_subscription(uuid, pos);
+
+        assert!(world_map.get("first").is_none());
+        assert!(world_map
+            .get("second")
+            .unwrap()
+            .is_peer_subscribed(&uuid, pos));
+    }
+
+    #[test]
+    fn get_does_not_create_worlds() {
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        assert!(world_map.get("queried").is_none());
+        assert!(world_map.get_existing_mut("queried").is_none());
+        assert_eq!(world_map.subscription_counts().count(), 0);
+
+        // Subscribing creates the world, even once it is empty again
+        let uuid = Uuid::new_v4();
+        let pos = Vector3::new(0.0, 0.0, 0.0);
+        world_map
+            .try_get_mut("queried")
+            .unwrap()
+            .add_subscription(uuid, pos);
+        world_map.clear_peer_everywhere(&uuid);
+
+        let area_map = world_map.get("queried").unwrap();
+        assert_eq!(area_map.total_subscriptions(), 0);
+    }
+
+    #[test]
+    fn clear_peer_everywhere() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+        let pos = Vector3::new(0.0, 0.0, 0.0);
+
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        world_map.get_mut("a").add_subscription(uuid_1, pos);
+        world_map
+            .get_mut("a")
+            .add_subscription(uuid_1, Vector3::new(64.0, 0.0, 0.0));
+        world_map.get_mut("b").add_subscription(uuid_1, pos);
+        world_map.get_mut("b").add_subscription(uuid_2, pos);
+
+        assert_eq!(world_map.clear_peer_everywhere(&uuid_1), 2);
+        assert_eq!(world_map.clear_peer_everywhere(&uuid_1), 0);
+
+        assert!(!world_map.get_mut("a").is_peer_subscribed_any(&uuid_1));
+        assert!(!world_map.get_mut("b").is_peer_subscribed_any(&uuid_1));
+        assert!(world_map.get_mut("b").is_peer_subscribed(&uuid_2, pos));
+    }
+
+    #[test]
+    fn max_subscriptions_per_peer() {
+        let uuid = Uuid::new_v4();
+        let far = Vector3::new(64.0, 0.0, 0.0);
+
+        let mut world_map = WorldMap::new(16, Some(1)).unwrap();
+        world_map
+            .get_mut("existing")
+            .add_subscription(uuid, Vector3::new(0.0, 0.0, 0.0));
+
+        // Overrides apply to existing and future worlds
+        world_map.set_max_subscriptions_per_peer("existing", Some(2));
+        world_map.set_max_subscriptions_per_peer("future", None);
+
+        let existing = world_map.get_mut("existing");
+        assert_eq!(existing.add_subscription(uuid, far), AddResult::Added);
+        assert_eq!(
+            existing.add_subscription(uuid, Vector3::new(128.0, 0.0, 0.0)),
+            AddResult::LimitReached
+        );
+
+        for i in 0..4 {
+            let pos = Vector3::new(f64::from(i) * 64.0, 0.0, 0.0);
+            let result = world_map.get_mut("future").add_subscription(uuid, pos);
+            assert_eq!(result, AddResult::Added);
+        }
+
+        world_map
+            .get_mut("default")
+            .add_subscription(uuid, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(
+            world_map.get_mut("default").add_subscription(uuid, far),
+            AddResult::LimitReached
+        );
+    }
+
+    #[test]
+    fn subscribe_events() {
+        let uuid = Uuid::new_v4();
+        let pos = Vector3::new(0.0, 0.0, 0.0);
+
+        let mut world_map = WorldMap::new(16, None).unwrap();
+        world_map.get_mut("existing").add_subscription(uuid, pos);
+
+        // Events are sent for worlds created before and after subscribing
+        let mut events = world_map.subscribe_events();
+        let mut second = world_map.subscribe_events();
+
+        world_map
+            .get_mut("existing")
+            .add_subscription(uuid, Vector3::new(64.0, 0.0, 0.0));
+        world_map.get_mut("new").add_subscription(uuid, pos);
+
+        let expected = [
+            SubscriptionEvent::Added {
+                uuid,
+                world_name: "existing".into(),
+                cube: Vector3::new(64.0, 0.0, 0.0).to_cube_area(16),
+            },
+            SubscriptionEvent::Added {
+                uuid,
+                world_name: "new".into(),
+                cube: pos.to_cube_area(16),
+            },
+        ];
+
+        for event in expected {
+            assert_eq!(events.try_recv().unwrap(), event);
+            assert_eq!(second.try_recv().unwrap(), event);
+        }
+
+        assert!(events.try_recv().is_err());
+    }
+}
+// endregion
@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::utils::handshake_capabilities;
+
+/// Prefix for compressed frames, uncompressed flatbuffers never start with these bytes.
+const MAGIC: &[u8; 4] = b"WQLZ";
+
+// region: Compression Enum
+/// Wire compression negotiated with a [`super::Peer`] during the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+}
+
+impl Default for Compression {
+    #[inline]
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl Compression {
+    /// Parse a ZeroMQ handshake `parameter` of the form `addr[;capability...]`.
+    ///
+    /// Returns the address and the compression advertised by the peer.
+    pub fn parse_handshake(parameter: &str) -> (&str, Self) {
+        let addr = parameter.split(';').next().unwrap_or_default();
+
+        let compression = match handshake_capabilities(parameter).any(|c| c == "lz4") {
+            true => Self::Lz4,
+            false => Self::None,
+        };
+
+        (addr, compression)
+    }
+
+    /// Compress an outgoing frame, if compression is enabled.
+    pub fn compress(&self, bytes: Bytes) -> Bytes {
+        match self {
+            Self::None => bytes,
+            Self::Lz4 => {
+                let compressed = lz4_flex::compress_prepend_size(&bytes);
+
+                let mut framed = Vec::with_capacity(MAGIC.len() + compressed.len());
+                framed.extend_from_slice(MAGIC);
+                framed.extend_from_slice(&compressed);
+
+                framed.into()
+            }
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+// endregion
+
+// region: Decompression
+/// Decompress an incoming frame.
+///
+/// Frames without the compression magic bytes are returned unchanged, so legacy peers
+/// which never negotiated compression still work. Frames which would decompress to more
+/// than `max_size` bytes are rejected, guarding against decompression bombs.
+pub fn decompress(data: &[u8], max_size: usize) -> Result<Cow<'_, [u8]>, DecompressError> {
+    let compressed = match data.strip_prefix(MAGIC) {
+        None => return Ok(Cow::Borrowed(data)),
+        Some(compressed) => compressed,
+    };
+
+    let (size, block) = lz4_flex::block::uncompressed_size(compressed)?;
+    if size > max_size {
+        return Err(DecompressError::TooLarge(size));
+    }
+
+    let decompressed = lz4_flex::decompress(block, size)?;
+    Ok(Cow::Owned(decompressed))
+}
+
+#[derive(Debug, Error)]
+pub enum DecompressError {
+    #[error("decompressed size {0} exceeds limit")]
+    TooLarge(usize),
+
+    #[error(transparent)]
+    Lz4Error(#[from] lz4_flex::block::DecompressError),
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_handshake() {
+        assert_eq!(
+            Compression::parse_handshake("127.0.0.1:5556"),
+            ("127.0.0.1:5556", Compression::None)
+        );
+
+        assert_eq!(
+            Compression::parse_handshake("127.0.0.1:5556;lz4"),
+            ("127.0.0.1:5556", Compression::Lz4)
+        );
+
+        assert_eq!(
+            Compression::parse_handshake("127.0.0.1:5556;zstd"),
+            ("127.0.0.1:5556", Compression::None)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = Bytes::from_static(&[1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4]);
+
+        // Uncompressed frames pass through
+        let uncompressed = Compression::None.compress(data.clone());
+        assert_eq!(uncompressed, data);
+        assert!(matches!(
+            decompress(&uncompressed, 1024),
+            Ok(Cow::Borrowed(_))
+        ));
+
+        // Compressed frames are restored
+        let compressed = Compression::Lz4.compress(data.clone());
+        assert!(compressed.starts_with(MAGIC));
+        assert_eq!(
+            decompress(&compressed, 1024).unwrap().as_ref(),
+            data.as_ref()
+        );
+
+        // Frames decompressing past the limit are rejected
+        assert!(matches!(
+            decompress(&compressed, data.len() - 1),
+            Err(DecompressError::TooLarge(size)) if size == data.len()
+        ));
+
+        // Corrupt frames are rejected
+        let corrupt = [&MAGIC[..], &[255, 255, 255, 255]].concat();
+        assert!(decompress(&corrupt, 1024).is_err());
+    }
+}
+// endregion
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use ahash::AHashSet;
+use color_eyre::Result;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::transport::ThreadPeerMap;
+
+/// Periodically remove peers that haven't sent a message for longer than `timeout`.
+///
+/// Removed peers are also removed from every world's subscriptions by the processing
+/// thread, see [`crate::transport::PeerMap::remove`].
+pub async fn start_peer_eviction(
+    peer_map: ThreadPeerMap,
+    timeout: Duration,
+    token: CancellationToken,
+) -> Result<()> {
+    info!("Started idle peer eviction, timeout = {:?}", timeout);
+
+    let mut interval = time::interval(timeout);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => evict_stale_peers(&peer_map, timeout).await,
+            _ = token.cancelled() => break,
+        }
+    }
+
+    Ok(())
+}
+
+async fn evict_stale_peers(peer_map: &ThreadPeerMap, max_duration: Duration) {
+    let uuids = {
+        let map = peer_map.read().await;
+        map.stale_peers_iter(max_duration).collect::<AHashSet<_>>()
+    };
+
+    // Do nothing if no Peers are stale
+    if uuids.is_empty() {
+        return;
+    }
+
+    // Remove stale peers
+    let mut map = peer_map.write().await;
+    for uuid in uuids {
+        debug!("evicting idle peer {}", uuid);
+        map.remove(&uuid).await;
+    }
+}
@@ -3,17 +3,20 @@ use std::net::{IpAddr, SocketAddr};
 use axum::extract::{Extension, TypedHeader};
 use axum::headers::authorization::Bearer;
 use axum::headers::Authorization;
-use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{AddExtensionLayer, Json, Router};
 use color_eyre::Result;
 use flume::Sender;
 use serde::Deserialize;
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 use tracing::info;
 use uuid::Uuid;
 
+use crate::metrics::MESSAGES_RECEIVED;
 use crate::structures::{Instruction, Message, Replication};
 
 pub async fn start_http_server(
@@ -21,17 +24,20 @@ pub async fn start_http_server(
     host: IpAddr,
     port: u16,
     auth_token: Option<String>,
+    token: CancellationToken,
 ) -> Result<()> {
     let addr = SocketAddr::new(host, port);
     info!("HTTP Server listening on {}", addr);
 
     let app = Router::new()
         .route("/global_message", post(post_global_message))
+        .route("/metrics", get(get_metrics))
         .layer(AddExtensionLayer::new(auth_token))
         .layer(AddExtensionLayer::new(msg_tx));
 
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
+        .with_graceful_shutdown(async move { token.cancelled().await })
         .await?;
 
     Ok(())
@@ -99,7 +105,17 @@ async fn post_global_message(
 
     // Send message to other clients
     let message: Message = partial_message.into();
+    MESSAGES_RECEIVED.inc(message.instruction.name());
     msg_tx.send_async(message).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Serve metrics in the Prometheus text exposition format.
+async fn get_metrics() -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    let content_type = HeaderValue::from_static("text/plain; version=0.0.4");
+    headers.insert(CONTENT_TYPE, content_type);
+
+    (headers, crate::metrics::render())
+}
@@ -4,23 +4,39 @@ use color_eyre::Result;
 use flume::Sender;
 use futures_util::StreamExt;
 use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, trace};
 use uuid::Uuid;
 
+use crate::metrics::MESSAGES_RECEIVED;
 use crate::structures::{Instruction, Message};
-use crate::transport::{Peer, ThreadPeerMap};
+use crate::transport::{is_hidden_handshake, Peer, PeerMetadata, ThreadPeerMap};
 
 pub async fn start_websocket_server(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<Message>,
     ws_host: IpAddr,
     ws_port: u16,
+    token: CancellationToken,
 ) -> Result<()> {
     let addr = SocketAddr::new(ws_host, ws_port);
     let listener = TcpListener::bind(&addr).await?;
     info!("WebSocket Server listening on {}", addr);
 
-    while let Ok((stream, _)) = listener.accept().await {
+    loop {
+        let stream = tokio::select! {
+            result = listener.accept() => match result {
+                Ok((stream, _)) => stream,
+                Err(_) => break,
+            },
+
+            // Stop accepting connections on shutdown
+            _ = token.cancelled() => {
+                info!("WebSocket Server no longer accepting connections");
+                break
+            },
+        };
+
         let addr = stream.peer_addr()?;
         debug!("websocket peer address: {}", addr);
 
@@ -78,6 +94,13 @@ async fn handle_connection(
                 return Ok(());
             }
 
+            // Capabilities follow a leading field as in ZeroMQ handshakes, which WebSocket
+            // peers have no use for, eg: `<uuid>;hidden`
+            if let Some(parameter) = &message.parameter {
+                peer.set_metadata(PeerMetadata::from_handshake(parameter));
+                peer.set_hidden(is_hidden_handshake(parameter));
+            }
+
             // Only lock for as long as we need
             {
                 let mut map = peer_map.write().await;
@@ -169,5 +192,6 @@ fn parse_message(
         return ParseResult::Close;
     }
 
+    MESSAGES_RECEIVED.inc(message.instruction.name());
     ParseResult::Message(message)
 }
@@ -1,17 +1,31 @@
+#[cfg(feature = "zeromq")]
+mod compression;
+#[cfg(feature = "zeromq")]
+mod eviction;
 #[cfg(any(feature = "http", feature = "websocket"))]
 mod http;
 mod peer;
 mod peer_map;
+mod peer_metadata;
 #[cfg(feature = "zeromq")]
 mod zeromq;
 
+#[cfg(feature = "zeromq")]
+pub use compression::{decompress, Compression};
+#[cfg(feature = "zeromq")]
+pub use eviction::start_peer_eviction;
 #[cfg(feature = "http")]
 pub use http::start_http_server;
 #[cfg(feature = "websocket")]
 pub use http::start_websocket_server;
 #[cfg(feature = "zeromq")]
 pub use peer::ZmqOutgoingPair;
-pub use peer::{Peer, PeerConnection, SendError};
+pub use peer::{is_hidden_handshake, Peer, SendError};
 pub use peer_map::{PeerMap, ThreadPeerMap};
+pub use peer_metadata::PeerMetadata;
 #[cfg(feature = "zeromq")]
-pub use zeromq::{start_zeromq_incoming, start_zeromq_outgoing};
+pub use zeromq::{
+    start_dead_letter_writer, start_zeromq_incoming, start_zeromq_outgoing, AllowAllAuthenticator,
+    CapacityHook, CurveConfig, HandshakeAuthenticator, HandshakeConfig, HandshakeHook,
+    InvalidMessagePolicy, MessageForwarder, OverflowPolicy, SharedSecretAuthenticator, UuidPolicy,
+};
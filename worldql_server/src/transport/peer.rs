@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
@@ -19,7 +20,11 @@ use tokio_tungstenite::tungstenite::Message as WsMessage;
 use tokio_tungstenite::WebSocketStream;
 use uuid::Uuid;
 
-use crate::structures::Message;
+use super::PeerMetadata;
+use crate::structures::{Message, WireFormat};
+#[cfg(feature = "zeromq")]
+use crate::transport::Compression;
+use crate::utils::handshake_capabilities;
 
 #[cfg(feature = "websocket")]
 type WebSocketConnection = SplitSink<WebSocketStream<TcpStream>, WsMessage>;
@@ -29,11 +34,35 @@ pub type ZmqOutgoingPair = (Bytes, Uuid);
 #[cfg(feature = "zeromq")]
 type ZmqConnection = Sender<ZmqOutgoingPair>;
 
+/// Handshake capability for peers that don't want to be listed in presence queries.
+const HIDDEN_CAPABILITY: &str = "hidden";
+
+/// Returns `true` if a handshake `parameter` opts out of presence queries.
+#[inline]
+pub fn is_hidden_handshake(parameter: &str) -> bool {
+    handshake_capabilities(parameter).any(|capability| capability == HIDDEN_CAPABILITY)
+}
+
 #[derive(Debug, Getters)]
 pub struct Peer {
     addr: SocketAddr,
     uuid: Uuid,
     connection: PeerConnection,
+    format: WireFormat,
+    #[cfg(feature = "zeromq")]
+    compression: Compression,
+    /// Set from the handshake, dropped along with the peer on disconnect
+    metadata: PeerMetadata,
+    /// Left out of presence queries, set from the handshake
+    hidden: bool,
+
+    #[getter(skip)]
+    connected_at: Instant,
+    /// Milliseconds after `connected_at` that a message was last received
+    #[getter(skip)]
+    last_seen: AtomicU64,
+    /// Consecutive sends that have failed, reset by any successful send
+    send_failures: u32,
 }
 
 impl Peer {
@@ -43,48 +72,127 @@ impl Peer {
             addr,
             uuid,
             connection: PeerConnection::WebSocket(ws_conn),
+            format: WireFormat::FlatBuffers,
+            #[cfg(feature = "zeromq")]
+            compression: Compression::None,
+            metadata: PeerMetadata::default(),
+            hidden: false,
+
+            connected_at: Instant::now(),
+            last_seen: AtomicU64::new(0),
+            send_failures: 0,
         }
     }
 
     #[cfg(feature = "zeromq")]
-    pub fn new_zmq(addr: SocketAddr, uuid: Uuid, zmq_tx: ZmqConnection) -> Self {
+    pub fn new_zmq(
+        addr: SocketAddr,
+        uuid: Uuid,
+        zmq_tx: ZmqConnection,
+        format: WireFormat,
+        compression: Compression,
+    ) -> Self {
         Self {
             addr,
             uuid,
-            connection: PeerConnection::ZeroMQ((zmq_tx, Instant::now())),
+            connection: PeerConnection::ZeroMQ(zmq_tx),
+            format,
+            compression,
+            metadata: PeerMetadata::default(),
+            hidden: false,
+
+            connected_at: Instant::now(),
+            last_seen: AtomicU64::new(0),
+            send_failures: 0,
         }
     }
 
-    /// Returns `true` if the duration between the last recieved heartbeat is greater than `max_duration`
+    /// Returns `true` if this peer is connected over ZeroMQ.
+    #[cfg(feature = "zeromq")]
+    #[inline]
+    pub fn is_zmq(&self) -> bool {
+        matches!(self.connection, PeerConnection::ZeroMQ(_))
+    }
+
+    /// Update the address and negotiated capabilities of a ZeroMQ peer which has sent
+    /// another handshake, eg: after reconnecting from a new endpoint.
+    #[cfg(feature = "zeromq")]
+    pub fn update_zmq(&mut self, addr: SocketAddr, format: WireFormat, compression: Compression) {
+        self.addr = addr;
+        self.format = format;
+        self.compression = compression;
+
+        self.touch();
+    }
+
+    /// Sets whether this peer is left out of presence queries.
+    #[inline]
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// Replace the metadata sent by this peer during its handshake.
+    #[inline]
+    pub fn set_metadata(&mut self, metadata: PeerMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Returns the [`Instant`] a message was last received from this peer.
+    #[inline]
+    pub fn last_seen(&self) -> Instant {
+        let millis = self.last_seen.load(Ordering::Relaxed);
+        self.connected_at + Duration::from_millis(millis)
+    }
+
+    /// Update the last seen [`Instant`] to the current time.
+    ///
+    /// Only requires a shared reference, so it can be called while holding a read lock.
+    #[inline]
+    pub fn touch(&self) {
+        let millis = self.connected_at.elapsed().as_millis();
+        let millis = u64::try_from(millis).unwrap_or(u64::MAX);
+
+        self.last_seen.fetch_max(millis, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the duration since the last received message is greater than `max_duration`
+    ///
+    /// WebSocket peers are never stale, their connection closing removes them instead.
     pub fn is_stale(&self, now: &Instant, max_duration: &Duration) -> bool {
         match self.connection {
             #[cfg(feature = "websocket")]
             PeerConnection::WebSocket(_) => false,
             #[cfg(feature = "zeromq")]
-            PeerConnection::ZeroMQ((_, last_heartbeat)) => {
-                let duration = *now - last_heartbeat;
+            PeerConnection::ZeroMQ(_) => {
+                let duration = now.saturating_duration_since(self.last_seen());
                 duration > *max_duration
             }
         }
     }
 
-    /// Update the Last Received [`Instant`] to the current time.
-    #[cfg(feature = "zeromq")]
-    #[inline]
-    pub fn update_last_heartbeat(&mut self) {
-        self.connection.update_last_heartbeat()
-    }
-
-    /// Send a [`Message`] to this peer.
+    /// Send a [`Message`] to this peer, serialized using its negotiated [`WireFormat`].
     #[inline]
     pub async fn send(&mut self, message: Message) -> Result<(), SendError> {
-        self.connection.send(self.uuid, message).await
+        self.send_raw(message.serialize_as(self.format)).await
     }
 
     /// Send a raw byte array to this peer.
+    ///
+    /// Bytes must already be serialized using this peer's [`WireFormat`].
+    ///
+    /// Bytes are compressed if compression was negotiated during the handshake.
     #[inline]
     pub async fn send_raw(&mut self, bytes: Bytes) -> Result<(), SendError> {
-        self.connection.send_raw(self.uuid, bytes).await
+        #[cfg(feature = "zeromq")]
+        let bytes = self.compression.compress(bytes);
+
+        let result = self.connection.send_raw(self.uuid, bytes).await;
+        match result {
+            Ok(_) => self.send_failures = 0,
+            Err(_) => self.send_failures = self.send_failures.saturating_add(1),
+        }
+
+        result
     }
 }
 
@@ -110,30 +218,10 @@ pub enum PeerConnection {
     #[cfg(feature = "websocket")]
     WebSocket(WebSocketConnection),
     #[cfg(feature = "zeromq")]
-    ZeroMQ((ZmqConnection, Instant)),
+    ZeroMQ(ZmqConnection),
 }
 
 impl PeerConnection {
-    /// Update the Last Received [`Instant`] to the current time.
-    #[cfg(feature = "zeromq")]
-    #[inline]
-    fn update_last_heartbeat(&mut self) {
-        #[cfg(feature = "zeromq")]
-        if let PeerConnection::ZeroMQ((_, last_recv)) = self {
-            // Set the last received instant to now
-            *last_recv = Instant::now()
-        }
-    }
-
-    /// Send a [`Message`] to this connection.
-    #[inline]
-    async fn send(&mut self, uuid: Uuid, message: Message) -> Result<(), SendError> {
-        let bytes = message.serialize();
-        self.send_raw(uuid, bytes).await?;
-
-        Ok(())
-    }
-
     /// Send a raw byte array to this connection.
     #[inline]
     async fn send_raw(&mut self, uuid: Uuid, bytes: Bytes) -> Result<(), SendError> {
@@ -146,7 +234,7 @@ impl PeerConnection {
                 Ok(())
             }
             #[cfg(feature = "zeromq")]
-            PeerConnection::ZeroMQ((tx, _)) => {
+            PeerConnection::ZeroMQ(tx) => {
                 tx.send_async((bytes, uuid)).await?;
 
                 Ok(())
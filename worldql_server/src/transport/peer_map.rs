@@ -2,17 +2,23 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use ahash::{AHashMap, AHashSet};
+use bytes::Bytes;
 use flume::Sender;
 use tokio::sync::RwLock;
 use tracing::{debug, info, trace};
 use uuid::Uuid;
 
 use super::peer::Peer;
+#[cfg(test)]
+use super::peer_metadata::PeerMetadata;
 use super::SendError;
-use crate::structures::{Instruction, Message};
+use crate::structures::{Instruction, Message, WireFormat};
 
 pub type ThreadPeerMap = Arc<RwLock<PeerMap>>;
 
+/// Consecutive failed sends after which a peer's connection is assumed to be dead.
+const MAX_SEND_FAILURES: u32 = 3;
+
 #[derive(Debug)]
 pub struct PeerMap {
     map: AHashMap<Uuid, Peer>,
@@ -21,17 +27,21 @@ pub struct PeerMap {
 
 macro_rules! broadcast_to {
     ($message: expr, $peers: expr) => {{
-        let bytes = $message.serialize();
+        let message: Message = $message;
+
+        let mut serialized = vec![];
 
         let mut jobs = vec![];
         for peer in $peers {
-            jobs.push(peer.send_raw(bytes.clone()));
+            let bytes = serialize_cached(&mut serialized, &message, *peer.format());
+            let uuid = *peer.uuid();
+            jobs.push(async move { (uuid, peer.send_raw(bytes).await) });
         }
 
-        for result in futures_util::future::join_all(jobs).await {
+        // Failures are counted by each peer, see `PeerMap::remove_failed_peers`
+        for (uuid, result) in futures_util::future::join_all(jobs).await {
             if let Err(error) = result {
-                // TODO: Remove peers that error
-                debug!("broadcast error: {:?}", error);
+                debug!("broadcast error for peer {}: {:?}", uuid, error);
             }
         }
 
@@ -66,6 +76,13 @@ impl PeerMap {
         self.map.get_mut(uuid)
     }
 
+    /// Returns the metadata sent during the handshake of the [`Peer`] corresponding to the [`Uuid`].
+    #[cfg(test)]
+    #[inline]
+    pub fn get_metadata(&self, uuid: &Uuid) -> Option<&PeerMetadata> {
+        self.map.get(uuid).map(Peer::metadata)
+    }
+
     /// Returns the number of connected Peers.
     #[inline]
     pub fn size(&self) -> usize {
@@ -73,6 +90,7 @@ impl PeerMap {
     }
 
     /// Returns an iterator of [`Uuid`] items for each contained [`Peer`].
+    #[cfg(test)]
     #[inline]
     pub fn peers_iter(&self) -> impl Iterator<Item = Uuid> + '_ {
         self.map.keys().copied()
@@ -144,7 +162,10 @@ impl PeerMap {
     // region: Broadcast Functions
     /// Broadcast a [`Message`] to all peers in the map.
     pub async fn broadcast_all(&mut self, message: Message) -> Result<(), SendError> {
-        broadcast_to!(message, self.map.values_mut())
+        let result = broadcast_to!(message, self.map.values_mut());
+        self.remove_failed_peers().await;
+
+        result
     }
 
     /// Broadcast a [`Message`] to all peers that correspond to the [`Uuid`] iterator.
@@ -159,7 +180,10 @@ impl PeerMap {
             .values_mut()
             .filter(|peer| peers.contains(peer.uuid()));
 
-        broadcast_to!(message, peers)
+        let result = broadcast_to!(message, peers);
+        self.remove_failed_peers().await;
+
+        result
     }
 
     /// Broadcast a [`Message`] to every peer except one, usually the one who triggered the
@@ -170,7 +194,178 @@ impl PeerMap {
         except: Uuid,
     ) -> Result<(), SendError> {
         let peers = self.map.values_mut().filter(|peer| *peer.uuid() != except);
-        broadcast_to!(message, peers)
+        let result = broadcast_to!(message, peers);
+        self.remove_failed_peers().await;
+
+        result
+    }
+
+    /// Send a [`Message`] to each peer in `uuids`, skipping any that aren't in the map.
+    ///
+    /// Unlike the broadcast functions, send failures are returned to the caller.
+    #[cfg(test)]
+    pub async fn send_to_many(&mut self, uuids: &[Uuid], message: Message) -> Vec<SendError> {
+        let uuids = uuids.iter().collect::<AHashSet<_>>();
+        let mut serialized = vec![];
+
+        let mut jobs = vec![];
+        for peer in self.map.values_mut() {
+            if !uuids.contains(peer.uuid()) {
+                continue;
+            }
+
+            let bytes = serialize_cached(&mut serialized, &message, *peer.format());
+            jobs.push(peer.send_raw(bytes));
+        }
+
+        let errors = futures_util::future::join_all(jobs)
+            .await
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+
+        self.remove_failed_peers().await;
+        errors
+    }
+
+    /// Remove every peer whose last [`MAX_SEND_FAILURES`] sends have failed, as if it had
+    /// disconnected.
+    async fn remove_failed_peers(&mut self) {
+        // Announcing a removal can fail more sends, so repeat until no failed peers are left
+        loop {
+            let failed = self
+                .map
+                .values()
+                .filter(|peer| *peer.send_failures() >= MAX_SEND_FAILURES)
+                .map(|peer| *peer.uuid())
+                .collect::<Vec<_>>();
+
+            if failed.is_empty() {
+                break;
+            }
+
+            for uuid in failed {
+                if let Some(peer) = self.map.remove(&uuid) {
+                    info!(
+                        "[{}] {} Peer Disconnected after {} failed sends",
+                        peer.addr(),
+                        peer.connection(),
+                        peer.send_failures()
+                    );
+                }
+
+                let message = Message {
+                    instruction: Instruction::PeerDisconnect,
+                    parameter: Some(uuid.to_string()),
+                    ..Default::default()
+                };
+
+                let _: Result<(), SendError> = broadcast_to!(message, self.map.values_mut());
+                let _ = self.on_remove.send(uuid);
+            }
+        }
     }
     // endregion
 }
+
+/// Serialize a [`Message`] at most once per [`WireFormat`].
+fn serialize_cached(
+    cache: &mut Vec<(WireFormat, Bytes)>,
+    message: &Message,
+    format: WireFormat,
+) -> Bytes {
+    if let Some((_, bytes)) = cache.iter().find(|(cached, _)| *cached == format) {
+        return bytes.clone();
+    }
+
+    let bytes = message.clone().serialize_as(format);
+    cache.push((format, bytes.clone()));
+
+    bytes
+}
+
+// region: Tests
+#[cfg(all(test, feature = "zeromq"))]
+mod tests {
+    use super::*;
+    use crate::transport::Compression;
+
+    #[tokio::test]
+    async fn remove_failed_peers() {
+        let (remove_tx, remove_rx) = flume::unbounded();
+        let mut map = PeerMap::new(remove_tx);
+
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let (live_tx, live_rx) = flume::unbounded();
+        let (dead_tx, _) = flume::unbounded();
+
+        let (live, dead) = (Uuid::new_v4(), Uuid::new_v4());
+        for (uuid, tx) in [(live, live_tx), (dead, dead_tx)] {
+            let peer = Peer::new_zmq(addr, uuid, tx, WireFormat::Json, Compression::None);
+            map.insert(uuid, peer).await;
+        }
+
+        // Peers are only removed once enough sends in a row have failed
+        for i in 1..=MAX_SEND_FAILURES {
+            map.broadcast_all(Message::default()).await.unwrap();
+            assert_eq!(map.contains_key(&dead), i < MAX_SEND_FAILURES);
+        }
+
+        assert!(map.contains_key(&live));
+        assert_eq!(remove_rx.try_recv(), Ok(dead));
+
+        // Live peer was told about the removal
+        let (bytes, _) = live_rx.drain().last().unwrap();
+        let message = Message::deserialize_as(&bytes, WireFormat::Json).unwrap();
+        assert_eq!(message.instruction, Instruction::PeerDisconnect);
+    }
+
+    #[tokio::test]
+    async fn send_to_many() {
+        let (remove_tx, _remove_rx) = flume::unbounded();
+        let mut map = PeerMap::new(remove_tx);
+
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let mut peers = vec![];
+        for _ in 0..3 {
+            let uuid = Uuid::new_v4();
+            let (tx, rx) = flume::unbounded();
+            let mut peer = Peer::new_zmq(addr, uuid, tx, WireFormat::Json, Compression::None);
+            peer.set_metadata(PeerMetadata::from_handshake(
+                "127.0.0.1:5000;meta.username=Steve",
+            ));
+
+            map.insert(uuid, peer).await;
+            peers.push((uuid, rx));
+        }
+
+        let mut uuids = map.peers_iter().collect::<Vec<_>>();
+        uuids.sort();
+        let mut expected = peers.iter().map(|(uuid, _)| *uuid).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(uuids, expected);
+
+        let metadata = map.get_metadata(&peers[0].0).unwrap();
+        assert_eq!(metadata.get("username"), Some("Steve"));
+        assert!(map.get_metadata(&Uuid::new_v4()).is_none());
+
+        // Unknown peers are skipped
+        for (_, rx) in &peers {
+            rx.drain();
+        }
+
+        let targets = [peers[0].0, peers[1].0, Uuid::new_v4()];
+        let errors = map.send_to_many(&targets, Message::default()).await;
+        assert!(errors.is_empty());
+
+        assert_eq!(peers[0].1.len(), 1);
+        assert_eq!(peers[1].1.len(), 1);
+        assert!(peers[2].1.is_empty());
+
+        // Failed sends are returned
+        let (dead, _) = peers.pop().unwrap();
+        let errors = map.send_to_many(&[dead], Message::default()).await;
+        assert_eq!(errors.len(), 1);
+    }
+}
+// endregion
@@ -0,0 +1,84 @@
+use ahash::AHashMap;
+
+use crate::utils::handshake_capabilities;
+
+/// Handshake capabilities with this prefix are stored as metadata, eg: `meta.username=Steve`
+const METADATA_PREFIX: &str = "meta.";
+
+const MAX_METADATA_ENTRIES: usize = 16;
+const MAX_METADATA_LENGTH: usize = 256;
+
+// region: PeerMetadata Struct
+/// Arbitrary key value pairs describing a [`super::Peer`], such as its username,
+/// client version or platform.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerMetadata(AHashMap<String, String>);
+
+impl PeerMetadata {
+    /// Parse metadata from the `;` separated capabilities of a handshake `parameter`.
+    ///
+    /// Only capabilities of the form `meta.<key>=<value>` are kept, so other capabilities
+    /// such as auth tokens are never stored. Oversized entries are ignored.
+    pub fn from_handshake(parameter: &str) -> Self {
+        let entries = handshake_capabilities(parameter)
+            .filter_map(|capability| capability.strip_prefix(METADATA_PREFIX))
+            .filter_map(|entry| entry.split_once('='))
+            .filter(|(key, value)| {
+                !key.is_empty()
+                    && key.len() <= MAX_METADATA_LENGTH
+                    && value.len() <= MAX_METADATA_LENGTH
+            })
+            .take(MAX_METADATA_ENTRIES)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        Self(entries)
+    }
+
+    /// Returns the value for `key`, if the peer sent one.
+    #[cfg(test)]
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Returns an iterator over every key value pair.
+    #[cfg(test)]
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    #[cfg(test)]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_handshake() {
+        let metadata = PeerMetadata::from_handshake(
+            "127.0.0.1:5556;lz4;token=secret;meta.username=Steve;meta.version=1.2=beta;meta.=x",
+        );
+
+        assert_eq!(metadata.get("username"), Some("Steve"));
+        assert_eq!(metadata.get("version"), Some("1.2=beta"));
+        assert_eq!(metadata.get("token"), None);
+        assert_eq!(metadata.iter().count(), 2);
+
+        assert!(PeerMetadata::from_handshake("127.0.0.1:5556;lz4").is_empty());
+
+        // The leading address is never metadata
+        assert!(PeerMetadata::from_handshake("meta.username=Steve").is_empty());
+    }
+}
+// endregion
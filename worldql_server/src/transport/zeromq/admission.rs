@@ -0,0 +1,264 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::structures::Message;
+use crate::transport::ThreadPeerMap;
+
+/// A handshake on its way to the outgoing thread, with the address it was received from
+/// and the reason it was rejected if any.
+pub type HandshakeRequest = (Message, Option<IpAddr>, Option<RejectReason>);
+
+// region: RejectReason Enum
+/// Why a handshake was rejected, sent to the peer as the `parameter` of an
+/// [`crate::structures::Instruction::HandshakeRejected`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The server has reached its peer limit
+    Full,
+    /// The peer's UUID isn't of the version required by the [`UuidPolicy`]
+    InvalidUuid,
+    /// The peer's UUID belongs to a connected peer, see [`UuidPolicy::Validate`]
+    DuplicateUuid,
+}
+
+impl RejectReason {
+    /// Returns the reason code sent to the peer.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::InvalidUuid => "invalid-uuid",
+            Self::DuplicateUuid => "duplicate-uuid",
+        }
+    }
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+// endregion
+
+// region: HandshakeHook Trait
+/// Decides whether an authenticated ZeroMQ handshake is accepted, eg: to cap concurrent peers.
+#[async_trait]
+pub trait HandshakeHook: Send + Sync {
+    /// Returns the reason to reject the handshake [`Message`], if it should be rejected.
+    async fn admit(&self, message: &Message, peer_map: &ThreadPeerMap) -> Result<(), RejectReason>;
+}
+
+/// Runs every hook in order, rejecting with the first reason given.
+#[async_trait]
+impl HandshakeHook for Vec<Arc<dyn HandshakeHook>> {
+    async fn admit(&self, message: &Message, peer_map: &ThreadPeerMap) -> Result<(), RejectReason> {
+        for hook in self {
+            hook.admit(message, peer_map).await?;
+        }
+
+        Ok(())
+    }
+}
+// endregion
+
+// region: UuidPolicy Enum
+/// How the UUID a peer presents in its handshake is treated.
+///
+/// Known peers may handshake again to update their route, so with [`UuidPolicy::Trust`]
+/// anyone sharing a peer's address can claim its UUID to receive its messages. Only
+/// [`UuidPolicy::Validate`] and [`UuidPolicy::Assign`] prevent this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UuidPolicy {
+    /// Any UUID is accepted
+    Trust,
+    /// Only UUIDs of this version are accepted, others are rejected with
+    /// [`RejectReason::InvalidUuid`]
+    ///
+    /// Connected peers can't handshake again, so their UUID can't be taken over. These
+    /// handshakes are rejected with [`RejectReason::DuplicateUuid`].
+    Validate(usize),
+    /// Every handshake is given a fresh UUID, sent back in the
+    /// [`crate::structures::Instruction::HandshakeAck`]
+    ///
+    /// Peers that handshake again are given another UUID, so they join as a new peer.
+    Assign,
+}
+
+impl Default for UuidPolicy {
+    fn default() -> Self {
+        Self::Trust
+    }
+}
+
+impl FromStr for UuidPolicy {
+    type Err = ParseUuidPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trust" => Ok(Self::Trust),
+            "assign" => Ok(Self::Assign),
+            "v1" => Ok(Self::Validate(1)),
+            "v2" => Ok(Self::Validate(2)),
+            "v3" => Ok(Self::Validate(3)),
+            "v4" => Ok(Self::Validate(4)),
+            "v5" => Ok(Self::Validate(5)),
+            _ => Err(ParseUuidPolicyError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: trust, assign, v1, v2, v3, v4, v5")]
+pub struct ParseUuidPolicyError;
+
+/// Rejects handshakes whose UUID isn't of the version required by
+/// [`UuidPolicy::Validate`], or belongs to a connected peer. Assigning UUIDs is left to the
+/// outgoing thread.
+#[async_trait]
+impl HandshakeHook for UuidPolicy {
+    async fn admit(&self, message: &Message, peer_map: &ThreadPeerMap) -> Result<(), RejectReason> {
+        let version = match self {
+            Self::Validate(version) => *version,
+            _ => return Ok(()),
+        };
+
+        if message.sender_uuid.get_version_num() != version {
+            return Err(RejectReason::InvalidUuid);
+        }
+
+        match peer_map.read().await.contains_key(&message.sender_uuid) {
+            true => Err(RejectReason::DuplicateUuid),
+            false => Ok(()),
+        }
+    }
+}
+// endregion
+
+// region: Hooks
+/// Rejects handshakes from new peers once `max_peers` peers are connected, across all
+/// transports. Connected peers can always handshake again.
+#[derive(Debug, Default)]
+pub struct CapacityHook {
+    max_peers: Option<usize>,
+}
+
+impl CapacityHook {
+    /// Creates a hook that accepts any number of peers if `max_peers` is `None`.
+    pub fn new(max_peers: Option<usize>) -> Self {
+        Self { max_peers }
+    }
+}
+
+#[async_trait]
+impl HandshakeHook for CapacityHook {
+    async fn admit(&self, message: &Message, peer_map: &ThreadPeerMap) -> Result<(), RejectReason> {
+        let max_peers = match self.max_peers {
+            None => return Ok(()),
+            Some(max_peers) => max_peers,
+        };
+
+        let map = peer_map.read().await;
+        if map.size() < max_peers || map.contains_key(&message.sender_uuid) {
+            Ok(())
+        } else {
+            Err(RejectReason::Full)
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::structures::WireFormat;
+    use crate::transport::{Compression, Peer, PeerMap};
+
+    #[tokio::test]
+    async fn capacity_hook() {
+        let (remove_tx, _) = flume::unbounded();
+        let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+        let connected = Uuid::new_v4();
+        let (tx, _) = flume::unbounded();
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let peer = Peer::new_zmq(addr, connected, tx, WireFormat::Json, Compression::None);
+        peer_map.write().await.insert(connected, peer).await;
+
+        let handshake = |sender_uuid| Message {
+            sender_uuid,
+            ..Default::default()
+        };
+
+        let hook = CapacityHook::new(Some(1));
+        let new_peer = handshake(Uuid::new_v4());
+        assert_eq!(
+            hook.admit(&new_peer, &peer_map).await,
+            Err(RejectReason::Full)
+        );
+        assert_eq!(hook.admit(&handshake(connected), &peer_map).await, Ok(()));
+
+        let hook = CapacityHook::new(None);
+        assert_eq!(hook.admit(&new_peer, &peer_map).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn uuid_policy() {
+        let (remove_tx, _) = flume::unbounded();
+        let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+        let v4 = Message {
+            sender_uuid: Uuid::new_v4(),
+            ..Default::default()
+        };
+        let nil = Message::default();
+
+        let policy: UuidPolicy = "v4".parse().unwrap();
+        assert_eq!(policy.admit(&v4, &peer_map).await, Ok(()));
+        assert_eq!(
+            policy.admit(&nil, &peer_map).await,
+            Err(RejectReason::InvalidUuid)
+        );
+
+        // Connected peers can't be taken over
+        let (tx, _) = flume::unbounded();
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let peer = Peer::new_zmq(
+            addr,
+            v4.sender_uuid,
+            tx,
+            WireFormat::Json,
+            Compression::None,
+        );
+        peer_map.write().await.insert(v4.sender_uuid, peer).await;
+        assert_eq!(
+            policy.admit(&v4, &peer_map).await,
+            Err(RejectReason::DuplicateUuid)
+        );
+        assert_eq!(UuidPolicy::Trust.admit(&v4, &peer_map).await, Ok(()));
+        peer_map.write().await.remove(&v4.sender_uuid).await;
+
+        for policy in [UuidPolicy::Trust, UuidPolicy::Assign] {
+            assert_eq!(policy.admit(&nil, &peer_map).await, Ok(()));
+        }
+
+        // Chained hooks reject with the first reason
+        let hooks: Vec<Arc<dyn HandshakeHook>> =
+            vec![Arc::new(policy), Arc::new(CapacityHook::new(Some(0)))];
+        assert_eq!(
+            hooks.admit(&nil, &peer_map).await,
+            Err(RejectReason::InvalidUuid)
+        );
+        assert_eq!(hooks.admit(&v4, &peer_map).await, Err(RejectReason::Full));
+    }
+}
+// endregion
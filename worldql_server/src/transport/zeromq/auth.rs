@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+
+use crate::structures::Message;
+use crate::utils::{constant_time_eq, handshake_capabilities};
+
+// region: HandshakeAuthenticator Trait
+/// Verifies ZeroMQ handshakes before a [`crate::transport::Peer`] is created.
+#[async_trait]
+pub trait HandshakeAuthenticator: Send + Sync {
+    /// Returns `true` if the handshake [`Message`] should be accepted.
+    async fn verify(&self, message: &Message) -> bool;
+}
+// endregion
+
+// region: Authenticators
+/// Accepts every handshake.
+#[derive(Debug, Default)]
+pub struct AllowAllAuthenticator;
+
+#[async_trait]
+impl HandshakeAuthenticator for AllowAllAuthenticator {
+    #[inline]
+    async fn verify(&self, _: &Message) -> bool {
+        true
+    }
+}
+
+/// Accepts handshakes with a `token=` capability matching a shared secret.
+#[derive(Debug)]
+pub struct SharedSecretAuthenticator {
+    secret: String,
+}
+
+impl SharedSecretAuthenticator {
+    pub fn new(secret: String) -> Self {
+        Self { secret }
+    }
+}
+
+#[async_trait]
+impl HandshakeAuthenticator for SharedSecretAuthenticator {
+    async fn verify(&self, message: &Message) -> bool {
+        let token = message.parameter.as_deref().and_then(handshake_token);
+
+        match token {
+            None => false,
+            Some(token) => constant_time_eq(token.as_bytes(), self.secret.as_bytes()),
+        }
+    }
+}
+// endregion
+
+// region: Utils
+/// Extract the token from a handshake `parameter` of the form `addr[;capability...]`.
+fn handshake_token(parameter: &str) -> Option<&str> {
+    handshake_capabilities(parameter).find_map(|capability| capability.strip_prefix("token="))
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn handshake_token() {
+        assert_eq!(super::handshake_token("127.0.0.1:5556"), None);
+        assert_eq!(super::handshake_token("127.0.0.1:5556;lz4"), None);
+        assert_eq!(
+            super::handshake_token("127.0.0.1:5556;lz4;token=secret"),
+            Some("secret")
+        );
+    }
+}
+// endregion
@@ -0,0 +1,71 @@
+use ahash::AHashMap;
+use bytes::Bytes;
+use uuid::Uuid;
+
+/// Messages buffered for a single peer before the batch is sent early.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// Buffers outgoing messages per peer so they can be sent as a single multipart frame.
+#[derive(Debug, Default)]
+pub(super) struct Coalescer {
+    pending: AHashMap<Uuid, Vec<Bytes>>,
+}
+
+impl Coalescer {
+    /// Queue a message for `uuid`.
+    ///
+    /// Returns the peer's batch if it is full and should be sent now.
+    pub(super) fn push(&mut self, uuid: Uuid, bytes: Bytes) -> Option<Vec<Bytes>> {
+        let batch = self.pending.entry(uuid).or_default();
+        batch.push(bytes);
+
+        match batch.len() >= MAX_BATCH_SIZE {
+            true => self.pending.remove(&uuid),
+            false => None,
+        }
+    }
+
+    /// Take every pending batch, leaving the buffer empty.
+    pub(super) fn drain(&mut self) -> impl Iterator<Item = (Uuid, Vec<Bytes>)> + '_ {
+        self.pending.drain()
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesce() {
+        let uuid_1 = Uuid::new_v4();
+        let uuid_2 = Uuid::new_v4();
+        let bytes = Bytes::from_static(&[1, 2, 3]);
+
+        let mut coalescer = Coalescer::default();
+        assert_eq!(coalescer.push(uuid_1, bytes.clone()), None);
+        assert_eq!(coalescer.push(uuid_2, bytes.clone()), None);
+        assert_eq!(coalescer.push(uuid_1, bytes.clone()), None);
+
+        let mut batches = coalescer.drain().collect::<Vec<_>>();
+        batches.sort_by_key(|(_, batch)| batch.len());
+        assert_eq!(
+            batches,
+            vec![
+                (uuid_2, vec![bytes.clone()]),
+                (uuid_1, vec![bytes.clone(); 2])
+            ]
+        );
+        assert_eq!(coalescer.drain().count(), 0);
+
+        // Full batches are returned immediately
+        for _ in 1..MAX_BATCH_SIZE {
+            assert_eq!(coalescer.push(uuid_1, bytes.clone()), None);
+        }
+
+        let batch = coalescer.push(uuid_1, bytes).unwrap();
+        assert_eq!(batch.len(), MAX_BATCH_SIZE);
+        assert_eq!(coalescer.drain().count(), 0);
+    }
+}
+// endregion
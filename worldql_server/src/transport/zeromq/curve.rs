@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ahash::AHashSet;
+use color_eyre::Result;
+use thiserror::Error;
+use tmq::pull::Pull;
+use tmq::FromZmqSocket;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+/// Raw CurveZMQ public or secret key.
+pub type CurveKey = [u8; 32];
+
+/// Endpoint libzmq sends authentication requests to, see ZMQ RFC 27.
+const ZAP_ENDPOINT: &str = "inproc://zeromq.zap.01";
+const ZAP_DOMAIN: &str = "worldql";
+const ZAP_VERSION: &[u8] = b"1.0";
+
+/// How often the ZAP handler checks for shutdown while idle
+const ZAP_POLL_INTERVAL_MS: i64 = 250;
+
+// region: CurveConfig Struct
+/// Keys used to encrypt and authenticate incoming ZeroMQ connections with CurveZMQ.
+pub struct CurveConfig {
+    secret_key: CurveKey,
+    client_keys: Arc<AHashSet<CurveKey>>,
+}
+
+impl CurveConfig {
+    /// Load the server secret key and the public keys of every client allowed to connect.
+    ///
+    /// Keys are Z85 encoded, either one per line or as ZeroMQ certificate files.
+    pub fn load(secret_key_file: &Path, client_keys_file: &Path) -> Result<Self, CurveKeyError> {
+        if zmq::has("curve") != Some(true) {
+            return Err(CurveKeyError::Unsupported);
+        }
+
+        let contents = read_key_file(secret_key_file)?;
+        let secret_key = parse_keys(&contents, "secret-key")
+            .next()
+            .ok_or_else(|| CurveKeyError::MissingKey(secret_key_file.to_path_buf()))?
+            .map_err(|line| CurveKeyError::InvalidKey(secret_key_file.to_path_buf(), line))?;
+
+        let contents = read_key_file(client_keys_file)?;
+        let client_keys = parse_keys(&contents, "public-key")
+            .collect::<Result<AHashSet<_>, _>>()
+            .map_err(|line| CurveKeyError::InvalidKey(client_keys_file.to_path_buf(), line))?;
+
+        if client_keys.is_empty() {
+            return Err(CurveKeyError::MissingKey(client_keys_file.to_path_buf()));
+        }
+
+        Ok(Self {
+            secret_key,
+            client_keys: Arc::new(client_keys),
+        })
+    }
+
+    #[inline]
+    pub(super) fn client_keys(&self) -> Arc<AHashSet<CurveKey>> {
+        self.client_keys.clone()
+    }
+}
+
+impl std::fmt::Debug for CurveConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CurveConfig")
+            .field("client_keys", &self.client_keys.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CurveKeyError {
+    #[error("libzmq was built without CURVE support")]
+    Unsupported,
+
+    #[error("failed to read {}: {1}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+
+    #[error("{} contains no CURVE keys", .0.display())]
+    MissingKey(PathBuf),
+
+    #[error("{}:{1}: invalid Z85 CURVE key", .0.display())]
+    InvalidKey(PathBuf, usize),
+}
+
+fn read_key_file(path: &Path) -> Result<String, CurveKeyError> {
+    std::fs::read_to_string(path).map_err(|error| CurveKeyError::Io(path.to_path_buf(), error))
+}
+
+/// Parse keys from a file, yielding the line number of any invalid keys.
+///
+/// Accepts bare Z85 keys, and `name = "key"` entries from ZeroMQ certificates. Comments,
+/// certificate section headers and other certificate entries are skipped.
+fn parse_keys<'a>(
+    contents: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = std::result::Result<CurveKey, usize>> + 'a {
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(move |(index, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line == "metadata" || line == "curve" {
+                return None;
+            }
+
+            let encoded = match line.split_once('=') {
+                None => line,
+                Some((key, value)) if key.trim() == name => value.trim().trim_matches('"'),
+                Some(_) => return None,
+            };
+
+            Some(decode_key(encoded).ok_or(index + 1))
+        })
+}
+
+fn decode_key(encoded: &str) -> Option<CurveKey> {
+    // Z85 decoding silently truncates input that isn't a multiple of 5 characters
+    if encoded.len() != 40 {
+        return None;
+    }
+
+    let bytes = zmq::z85_decode(encoded).ok()?;
+    bytes.try_into().ok()
+}
+// endregion
+
+// region: Sockets
+/// Bind a PULL socket, encrypted with CurveZMQ if configured.
+///
+/// Frames larger than `max_message_bytes` make libzmq drop the connection before they
+/// are buffered, so oversized messages never reach memory.
+pub(super) fn bind_pull(
+    ctx: &tmq::Context,
+    endpoint: &str,
+    curve: Option<&CurveConfig>,
+    max_message_bytes: usize,
+) -> Result<Pull> {
+    let max_message_bytes = i64::try_from(max_message_bytes).unwrap_or(i64::MAX);
+    let curve = match curve {
+        None => {
+            let pull = tmq::pull(ctx)
+                .set_maxmsgsize(max_message_bytes)
+                .bind(endpoint)?;
+
+            return Ok(pull);
+        }
+        Some(curve) => curve,
+    };
+
+    let socket = ctx.socket(zmq::PULL)?;
+    socket.set_maxmsgsize(max_message_bytes)?;
+    socket.set_curve_server(true)?;
+    socket.set_curve_secretkey(&curve.secret_key)?;
+    socket.set_zap_domain(ZAP_DOMAIN)?;
+    socket.bind(endpoint)?;
+
+    Ok(Pull::from_zmq_socket(socket)?)
+}
+
+/// Bind the ZAP handler which checks client public keys.
+///
+/// Must be bound before any CURVE sockets, or libzmq accepts every client.
+pub(super) fn bind_zap(ctx: &tmq::Context) -> Result<zmq::Socket> {
+    let socket = ctx.socket(zmq::REP)?;
+    socket.bind(ZAP_ENDPOINT)?;
+
+    Ok(socket)
+}
+
+/// Answer ZAP requests until `token` is cancelled, only accepting known client keys.
+///
+/// Blocks the current thread, as async REP sockets can't be moved between threads.
+pub(super) fn start_zap_handler(
+    socket: zmq::Socket,
+    client_keys: Arc<AHashSet<CurveKey>>,
+    token: CancellationToken,
+) -> Result<()> {
+    info!(
+        "CurveZMQ enabled, accepting {} client keys",
+        client_keys.len()
+    );
+
+    while !token.is_cancelled() {
+        if socket.poll(zmq::POLLIN, ZAP_POLL_INTERVAL_MS)? == 0 {
+            continue;
+        }
+
+        let request = socket.recv_multipart(0)?;
+        let frames = request.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        socket.send_multipart(zap_reply(&frames, &client_keys), 0)?;
+    }
+
+    Ok(())
+}
+
+/// Build the reply to a ZAP request, see ZMQ RFC 27.
+fn zap_reply<'a>(request: &[&'a [u8]], client_keys: &AHashSet<CurveKey>) -> Vec<&'a [u8]> {
+    let request_id = request.get(1).copied().unwrap_or_default();
+
+    let accepted = match request {
+        [ZAP_VERSION, _, _, _, _, b"CURVE", key] => {
+            let accepted =
+                <CurveKey>::try_from(*key).map_or(false, |key| client_keys.contains(&key));
+            if !accepted {
+                let address = String::from_utf8_lossy(request[3]);
+                debug!("rejected CurveZMQ client with unknown key from {}", address);
+            }
+
+            accepted
+        }
+
+        _ => false,
+    };
+
+    let (status, text): (&[u8], &[u8]) = match accepted {
+        true => (b"200", b"OK"),
+        false => (b"400", b"Unknown client key"),
+    };
+
+    vec![ZAP_VERSION, request_id, status, text, b"", b""]
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        let key = [7; 32];
+        let encoded = zmq::z85_encode(&key).unwrap();
+
+        // Bare keys, with comments
+        let contents = format!("# clients\n\n{}\n  {}  \n", encoded, encoded);
+        let keys = super::parse_keys(&contents, "public-key").collect::<Vec<_>>();
+        assert_eq!(keys, vec![Ok(key), Ok(key)]);
+
+        // ZeroMQ certificates
+        let contents = format!(
+            "metadata\n    name = \"client\"\ncurve\n    public-key = \"{}\"\n    secret-key = \"{}\"\n",
+            encoded, encoded
+        );
+
+        let keys = super::parse_keys(&contents, "secret-key").collect::<Vec<_>>();
+        assert_eq!(keys, vec![Ok(key)]);
+
+        // Invalid keys report their line
+        let contents = format!("{}\nnot-a-key\n", encoded);
+        let keys = super::parse_keys(&contents, "public-key").collect::<Vec<_>>();
+        assert_eq!(keys, vec![Ok(key), Err(2)]);
+    }
+
+    #[test]
+    fn zap_reply() {
+        let key = [7; 32];
+        let client_keys = [key].into_iter().collect::<AHashSet<_>>();
+
+        let status = |request: &[&[u8]]| {
+            let reply = super::zap_reply(request, &client_keys);
+            assert_eq!(reply[1], b"id");
+
+            reply[2].to_vec()
+        };
+
+        let accepted: &[&[u8]] = &[b"1.0", b"id", b"worldql", b"127.0.0.1", b"", b"CURVE", &key];
+        assert_eq!(status(accepted), b"200");
+
+        let unknown: &[&[u8]] = &[
+            b"1.0",
+            b"id",
+            b"worldql",
+            b"127.0.0.1",
+            b"",
+            b"CURVE",
+            &[1; 32],
+        ];
+        assert_eq!(status(unknown), b"400");
+
+        let plain: &[&[u8]] = &[b"1.0", b"id", b"worldql", b"127.0.0.1", b"", b"NULL"];
+        assert_eq!(status(plain), b"400");
+    }
+
+    #[tokio::test]
+    async fn pull_limits_message_size() {
+        use tmq::SocketExt;
+
+        let ctx = tmq::Context::new();
+        let pull = bind_pull(&ctx, "inproc://pull_limits_message_size", None, 1024).unwrap();
+        assert_eq!(pull.get_maxmsgsize().unwrap(), 1024);
+    }
+}
+// endregion
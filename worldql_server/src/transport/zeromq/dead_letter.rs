@@ -0,0 +1,86 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use color_eyre::Result;
+use flume::Receiver;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+// region: RawMessage Struct
+/// An incoming message that could not be processed.
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    /// Raw bytes as received, before decompression
+    pub data: Bytes,
+    /// Address of the sender, if the transport exposes it
+    pub source: Option<SocketAddr>,
+    pub error: String,
+}
+
+impl RawMessage {
+    /// Format as a single tab separated line of `source`, `error`, `length` and hex `data`.
+    fn to_line(&self) -> String {
+        let source = match self.source {
+            Some(source) => source.to_string(),
+            None => "unknown".into(),
+        };
+
+        let mut line = format!("{}\t{}\t{}\t", source, self.error, self.data.len());
+        for byte in self.data.iter() {
+            let _ = write!(line, "{:02x}", byte);
+        }
+
+        line.push('\n');
+        line
+    }
+}
+// endregion
+
+// region: Writer
+/// Append every dead-lettered message to a file, one per line.
+///
+/// Exits once all senders have been dropped and every queued message is written.
+pub async fn start_dead_letter_writer(rx: Receiver<RawMessage>, path: PathBuf) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+
+    info!(
+        "Writing dead-lettered ZeroMQ messages to {}",
+        path.display()
+    );
+
+    while let Ok(message) = rx.recv_async().await {
+        file.write_all(message.to_line().as_bytes()).await?;
+    }
+
+    file.flush().await?;
+    Ok(())
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_line() {
+        let message = RawMessage {
+            data: Bytes::from_static(&[0, 15, 255]),
+            source: None,
+            error: "invalid flatbuffer".into(),
+        };
+
+        assert_eq!(
+            message.to_line(),
+            "unknown\tinvalid flatbuffer\t3\t000fff\n"
+        );
+    }
+}
+// endregion
@@ -0,0 +1,156 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use flume::{Receiver, SendError, Sender, TrySendError};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::structures::Message;
+
+// region: OverflowPolicy Enum
+/// What to do with an incoming message when the processing queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for space, stalling ingestion for every peer
+    Block,
+    /// Discard the oldest queued message to make room
+    DropOldest,
+    /// Discard the incoming message
+    DropNewest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "drop-newest" => Ok(Self::DropNewest),
+            _ => Err(ParsePolicyError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: block, drop-oldest, drop-newest")]
+pub struct ParsePolicyError;
+// endregion
+
+// region: MessageForwarder Struct
+/// Hands incoming messages to the processing queue, applying an [`OverflowPolicy`] when
+/// the queue is full instead of always waiting for space.
+#[derive(Debug)]
+pub struct MessageForwarder {
+    tx: Sender<Message>,
+    /// Used to discard queued messages under [`OverflowPolicy::DropOldest`]
+    rx: Receiver<Message>,
+    policy: OverflowPolicy,
+    dropped: Arc<AtomicU64>,
+}
+
+impl MessageForwarder {
+    /// `dropped` is incremented for every message discarded by the policy.
+    pub fn new(
+        tx: Sender<Message>,
+        rx: Receiver<Message>,
+        policy: OverflowPolicy,
+        dropped: Arc<AtomicU64>,
+    ) -> Self {
+        Self {
+            tx,
+            rx,
+            policy,
+            dropped,
+        }
+    }
+
+    /// Queue a [`Message`] for processing.
+    ///
+    /// Only returns an error if processing has stopped.
+    pub async fn forward(&self, message: Message) -> Result<(), SendError<Message>> {
+        let message = match self.tx.try_send(message) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Disconnected(message)) => return Err(SendError(message)),
+            Err(TrySendError::Full(message)) => message,
+        };
+
+        match self.policy {
+            OverflowPolicy::Block => self.tx.send_async(message).await,
+            OverflowPolicy::DropNewest => {
+                self.record_drop();
+                Ok(())
+            }
+            OverflowPolicy::DropOldest => {
+                if self.rx.try_recv().is_ok() {
+                    self.record_drop();
+                }
+
+                // Other transports share the queue and may have refilled it
+                match self.tx.try_send(message) {
+                    Ok(()) => Ok(()),
+                    Err(TrySendError::Disconnected(message)) => Err(SendError(message)),
+                    Err(TrySendError::Full(_)) => {
+                        self.record_drop();
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    fn record_drop(&self) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        if dropped == 1 || dropped % 1000 == 0 {
+            warn!(
+                "processing queue is full, {} incoming messages dropped",
+                dropped
+            );
+        }
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn message(n: u8) -> Message {
+        Message {
+            flex: Some(vec![n].into()),
+            ..Default::default()
+        }
+    }
+
+    /// Forward three messages into a queue with room for two, returning the queued `flex` values.
+    async fn forward_three(policy: OverflowPolicy) -> (Vec<Option<Bytes>>, u64) {
+        let (tx, rx) = flume::bounded(2);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let forwarder = MessageForwarder::new(tx, rx.clone(), policy, dropped.clone());
+
+        for n in 0..3 {
+            forwarder.forward(message(n)).await.unwrap();
+        }
+
+        (
+            rx.drain().map(|message| message.flex).collect(),
+            dropped.load(Ordering::Relaxed),
+        )
+    }
+
+    #[tokio::test]
+    async fn overflow_policies() {
+        let (queued, dropped) = forward_three(OverflowPolicy::DropNewest).await;
+        assert_eq!(queued, vec![message(0).flex, message(1).flex]);
+        assert_eq!(dropped, 1);
+
+        let (queued, dropped) = forward_three(OverflowPolicy::DropOldest).await;
+        assert_eq!(queued, vec![message(1).flex, message(2).flex]);
+        assert_eq!(dropped, 1);
+    }
+}
+// endregion
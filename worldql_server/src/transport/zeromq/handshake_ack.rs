@@ -0,0 +1,93 @@
+use uuid::Uuid;
+
+use super::admission::UuidPolicy;
+use crate::structures::{Instruction, Message, WireFormat};
+
+/// Server settings sent to ZeroMQ peers in a [`Instruction::HandshakeAck`] once their
+/// handshake succeeds, so they can match the server's spatial math.
+#[derive(Debug, Clone, Copy)]
+pub struct HandshakeConfig {
+    /// Side length of subscription region cubes
+    pub cube_size: u16,
+    /// Default `(x, y, z)` size of database regions
+    pub region_size: (u16, u16, u16),
+    pub uuid_policy: UuidPolicy,
+}
+
+impl HandshakeConfig {
+    /// Build the acknowledgement for a peer using `format`.
+    ///
+    /// The parameter is a `;` separated list of `key=value` pairs, in the same style as the
+    /// handshake capabilities. With [`UuidPolicy::Assign`], it ends with the peer's new
+    /// `uuid`, which the peer must send all further messages as.
+    pub(super) fn ack_message(&self, format: WireFormat, uuid: Uuid) -> Message {
+        let (x, y, z) = self.region_size;
+        let mut parameter = format!(
+            "cube_size={};region_size={},{},{};format={};features={}",
+            self.cube_size,
+            x,
+            y,
+            z,
+            format,
+            features().join(",")
+        );
+
+        if self.uuid_policy == UuidPolicy::Assign {
+            parameter.push_str(&format!(";uuid={}", uuid));
+        }
+
+        Message {
+            instruction: Instruction::HandshakeAck,
+            parameter: Some(parameter),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the transports this server was compiled with.
+fn features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "http") {
+        features.push("http");
+    }
+
+    if cfg!(feature = "websocket") {
+        features.push("websocket");
+    }
+
+    if cfg!(feature = "zeromq") {
+        features.push("zeromq");
+    }
+
+    features
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_message() {
+        let mut config = HandshakeConfig {
+            cube_size: 16,
+            region_size: (16, 256, 16),
+            uuid_policy: UuidPolicy::Trust,
+        };
+
+        let uuid = Uuid::new_v4();
+        let message = config.ack_message(WireFormat::Json, uuid);
+        let parameter = message.parameter.unwrap();
+
+        assert_eq!(message.instruction, Instruction::HandshakeAck);
+        assert!(parameter.starts_with("cube_size=16;region_size=16,256,16;format=json;features="));
+        assert!(parameter.contains("zeromq"));
+        assert!(!parameter.contains("uuid="));
+
+        config.uuid_policy = UuidPolicy::Assign;
+        let message = config.ack_message(WireFormat::Json, uuid);
+        let parameter = message.parameter.unwrap();
+        assert!(parameter.ends_with(&format!(";uuid={}", uuid)));
+    }
+}
+// endregion
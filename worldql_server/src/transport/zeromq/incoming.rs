@@ -1,42 +1,146 @@
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use ahash::AHashMap;
+use bytes::Bytes;
 use color_eyre::Result;
 use flume::Sender;
 use futures_util::StreamExt;
-use tracing::{debug, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-use crate::structures::{Instruction, Message};
-use crate::transport::ThreadPeerMap;
+use super::admission::{HandshakeHook, HandshakeRequest};
+use super::auth::HandshakeAuthenticator;
+use super::curve::{bind_pull, bind_zap, start_zap_handler, CurveConfig};
+use super::dead_letter::RawMessage;
+use super::forward::MessageForwarder;
+use super::rate_limit::RateLimiter;
+use super::strikes::{InvalidMessagePolicy, StrikeCounter};
+use crate::metrics::MESSAGES_RECEIVED;
+use crate::structures::{Instruction, Message, WireFormat};
+use crate::transport::{decompress, ThreadPeerMap};
 
+const HANDSHAKE_RATE_PER_SEC: u32 = 1;
+const HANDSHAKE_RATE_BURST: u32 = 3;
+
+/// Number of messages between pruning idle rate limiters
+const RATE_LIMIT_PRUNE_INTERVAL: u32 = 4096;
+
+#[allow(clippy::too_many_arguments)]
 pub async fn start_zeromq_incoming(
     peer_map: ThreadPeerMap,
-    msg_tx: Sender<Message>,
-    handshake_tx: Sender<Message>,
+    forwarder: MessageForwarder,
+    handshake_tx: Sender<HandshakeRequest>,
     server_host: IpAddr,
     server_port: u16,
     ctx: tmq::Context,
+    rate_limit: Option<u32>,
+    max_message_bytes: usize,
+    authenticator: Arc<dyn HandshakeAuthenticator>,
+    hook: Arc<dyn HandshakeHook>,
+    auth_cooldown: Option<Duration>,
+    dead_letter_tx: Option<Sender<RawMessage>>,
+    curve: Option<CurveConfig>,
+    invalid_policy: Option<InvalidMessagePolicy>,
+    token: CancellationToken,
 ) -> Result<()> {
+    let mut blacklist: AHashMap<IpAddr, Instant> = AHashMap::new();
+    let mut message_limiter = rate_limit.map(|rate| RateLimiter::new(rate, rate));
+    let mut handshake_limiter = RateLimiter::new(HANDSHAKE_RATE_PER_SEC, HANDSHAKE_RATE_BURST);
+    let mut strikes = invalid_policy.map(StrikeCounter::new);
+    let mut prune_counter = 0;
+
     let pull_addr = format!("tcp://{}:{}", &server_host, &server_port);
-    let mut pull_socket = tmq::pull(&ctx.clone()).bind(&pull_addr)?;
+    // The ZAP handler must be listening before clients can connect
+    if let Some(curve) = curve.as_ref() {
+        let zap_socket = bind_zap(&ctx)?;
+        let (client_keys, token) = (curve.client_keys(), token.clone());
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = start_zap_handler(zap_socket, client_keys, token) {
+                error!("ZAP handler exited with error: {}", error);
+            }
+        });
+    }
+
+    let mut pull_socket = bind_pull(&ctx, &pull_addr, curve.as_ref(), max_message_bytes)?;
     info!(
         "ZeroMQ PULL Server listening on {}:{}",
         server_host, server_port
     );
 
     loop {
-        let msg = pull_socket.next().await;
+        let msg = tokio::select! {
+            msg = pull_socket.next() => msg,
+
+            // Stop accepting messages on shutdown
+            _ = token.cancelled() => {
+                info!("zeromq_incoming thread loop exiting");
+                break
+            },
+        };
+
         match msg {
             None => continue,
             Some(msg) => {
                 let msg = msg?;
+                let now = Instant::now();
+
+                // Concatenate frames, aborting if the message grows too large
+                let mut raw = vec![];
+                let mut oversized = false;
+                let mut source_ip = None;
+                for (i, mut frame) in msg.into_iter().enumerate() {
+                    if i == 0 {
+                        source_ip = peer_ip(&mut frame);
+                    }
+
+                    if raw.len() + frame.len() > max_message_bytes {
+                        oversized = true;
+                        break;
+                    }
+
+                    raw.extend_from_slice(&frame);
+                }
+
+                // Ignore blacklisted addresses before spending any time decoding
+                if let Some(ip) = source_ip {
+                    if blacklist.get(&ip).map_or(false, |until| *until > now) {
+                        continue;
+                    }
+                }
+
+                if oversized {
+                    warn!(
+                        "dropping oversized zmq message from {}: exceeds {} bytes",
+                        display_ip(source_ip),
+                        max_message_bytes
+                    );
+
+                    strike(&mut strikes, &mut blacklist, source_ip, now);
+                    continue;
+                }
+
+                // Uncompressed frames are passed through unchanged, decompressed frames are
+                // held to the same size limit
+                let data = match decompress(&raw, max_message_bytes) {
+                    Ok(data) => data,
+                    Err(error) => {
+                        debug!("dropping invalid zmq message: decompress error");
+
+                        #[cfg(debug_assertions)]
+                        tracing::error!("{:?}", error);
 
-                let data = msg
-                    .into_iter()
-                    .map(|m| m.to_vec())
-                    .flatten()
-                    .collect::<Vec<_>>();
+                        dead_letter(&dead_letter_tx, &raw, &error);
+                        strike(&mut strikes, &mut blacklist, source_ip, now);
+                        continue;
+                    }
+                };
 
-                let message_result = Message::deserialize(&data);
+                // Handshakes may use any format, so detect it before the peer is known
+                let format = WireFormat::detect(&data);
+                let message_result = Message::deserialize_as(&data, format);
                 let message = match message_result {
                     Ok(m) => m,
                     Err(error) => {
@@ -45,32 +149,264 @@ pub async fn start_zeromq_incoming(
                         #[cfg(debug_assertions)]
                         tracing::error!("{:?}", error);
 
+                        dead_letter(&dead_letter_tx, &raw, &error);
+                        strike(&mut strikes, &mut blacklist, source_ip, now);
                         continue;
                     }
                 };
 
-                // Run in new scope to avoid blocking PeerMap Lock
-                {
-                    let map = peer_map.read().await;
-                    if map.contains_key(&message.sender_uuid) {
-                        // Only forward non-handshake messages
-                        if message.instruction != Instruction::Handshake {
-                            msg_tx.send_async(message).await?;
-                        }
+                let uuid = message.sender_uuid;
+                MESSAGES_RECEIVED.inc(message.instruction.name());
 
-                        continue;
+                // Periodically forget idle rate limits
+                prune_counter += 1;
+                if prune_counter >= RATE_LIMIT_PRUNE_INTERVAL {
+                    prune_counter = 0;
+                    handshake_limiter.prune(now);
+                    if let Some(limiter) = &mut message_limiter {
+                        limiter.prune(now);
                     }
+
+                    if let Some(strikes) = &mut strikes {
+                        strikes.prune(now);
+                    }
+
+                    blacklist.retain(|_, until| *until > now);
                 }
 
+                // Messages from known peers are forwarded, only handshakes are left to handle
+                let message = match forward_known(
+                    message,
+                    format,
+                    &peer_map,
+                    &forwarder,
+                    &mut message_limiter,
+                    now,
+                )
+                .await?
+                {
+                    Some(message) => message,
+                    None => continue,
+                };
+
                 if message.instruction != Instruction::Handshake || message.parameter.is_none() {
-                    // Ignore message
-                    // TODO: Drop connection
+                    // Unknown peers must handshake first, repeat offenders are blacklisted
+                    strike(&mut strikes, &mut blacklist, source_ip, now);
+                    continue;
+                }
+
+                // Handshakes have their own stricter limit
+                if let Err(dropped) = handshake_limiter.check(uuid, now) {
+                    if dropped == 1 || dropped % 100 == 0 {
+                        warn!(
+                            "peer {} is handshake rate limited, {} handshakes dropped",
+                            uuid, dropped
+                        );
+                    }
+
+                    continue;
+                }
+
+                if !authenticator.verify(&message).await {
+                    warn!("peer {} failed handshake authentication", uuid);
+
+                    // Blacklist the address the handshake came from, not the one it claims
+                    if let (Some(ip), Some(cooldown)) = (source_ip, auth_cooldown) {
+                        blacklist.insert(ip, now + cooldown);
+                    }
+
                     continue;
                 }
 
+                // Rejected peers are still sent a reply, so they know why they can't connect
+                let rejection = hook.admit(&message, &peer_map).await.err();
+                if let Some(reason) = rejection {
+                    info!("rejecting zmq handshake from peer {}: {}", uuid, reason);
+                }
+
                 // Send handshake message to ZeroMQ Outgoing Thread
-                handshake_tx.send_async(message).await?;
+                handshake_tx
+                    .send_async((message, source_ip, rejection))
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Forward a message from a peer that has already handshaked.
+///
+/// Returns the message back if its sender is unknown, or it's a handshake, which update
+/// the route of known peers. The peer map lock is released before forwarding, as draining
+/// a full queue may need processing to write to the peer map, eg: to answer heartbeats.
+async fn forward_known(
+    message: Message,
+    format: WireFormat,
+    peer_map: &ThreadPeerMap,
+    forwarder: &MessageForwarder,
+    message_limiter: &mut Option<RateLimiter>,
+    now: Instant,
+) -> Result<Option<Message>> {
+    let uuid = message.sender_uuid;
+    if message.instruction == Instruction::Handshake {
+        return Ok(Some(message));
+    }
+
+    // Run in new scope to avoid holding the PeerMap lock while forwarding
+    {
+        let map = peer_map.read().await;
+        let peer = match map.get(&uuid) {
+            Some(peer) => peer,
+            None => return Ok(Some(message)),
+        };
+
+        peer.touch();
+
+        // Only accept the format negotiated during the handshake
+        if format != *peer.format() {
+            debug!(
+                "dropping zmq message from peer {}: expected {}, got {}",
+                uuid,
+                peer.format(),
+                format
+            );
+
+            return Ok(None);
+        }
+    }
+
+    // Drop messages over the rate limit
+    if let Some(limiter) = message_limiter {
+        if let Err(dropped) = limiter.check(uuid, now) {
+            if dropped == 1 || dropped % 100 == 0 {
+                warn!(
+                    "peer {} is rate limited, {} messages dropped",
+                    uuid, dropped
+                );
             }
+
+            return Ok(None);
+        }
+    }
+
+    // Only waits for space if the overflow policy is to block
+    forwarder.forward(message).await?;
+    Ok(None)
+}
+
+/// Count an invalid message against its source address, blacklisting the address once
+/// it sends too many.
+fn strike(
+    strikes: &mut Option<StrikeCounter>,
+    blacklist: &mut AHashMap<IpAddr, Instant>,
+    ip: Option<IpAddr>,
+    now: Instant,
+) {
+    if let (Some(strikes), Some(ip)) = (strikes, ip) {
+        if let Some(until) = strikes.strike(ip, now) {
+            warn!("blacklisting {} after repeated invalid zmq messages", ip);
+            blacklist.insert(ip, until);
         }
     }
 }
+
+/// Returns the address a message was received from, only available over TCP.
+#[inline]
+fn peer_ip(frame: &mut tmq::Message) -> Option<IpAddr> {
+    frame.gets("Peer-Address")?.parse().ok()
+}
+
+#[inline]
+pub(super) fn display_ip(ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(ip) => ip.to_string(),
+        None => "unknown sender".into(),
+    }
+}
+
+/// Forward a message that failed processing to the dead-letter channel, if configured.
+#[inline]
+fn dead_letter(tx: &Option<Sender<RawMessage>>, raw: &[u8], error: &impl std::fmt::Display) {
+    if let Some(tx) = tx {
+        let message = RawMessage {
+            data: Bytes::copy_from_slice(raw),
+            // PULL sockets only expose the sender IP, not its full address
+            source: None,
+            error: error.to_string(),
+        };
+
+        if tx.try_send(message).is_err() {
+            debug!("dead-letter channel closed, dropping message");
+        }
+    }
+}
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::super::forward::OverflowPolicy;
+    use super::*;
+    use crate::transport::{Compression, Peer, PeerMap};
+
+    #[tokio::test]
+    async fn forwarding_releases_peer_map() {
+        let (remove_tx, _) = flume::unbounded();
+        let peer_map: ThreadPeerMap = Arc::new(RwLock::new(PeerMap::new(remove_tx)));
+
+        let uuid = Uuid::new_v4();
+        let (tx, _rx) = flume::unbounded();
+        let addr = "127.0.0.1:5000".parse().unwrap();
+        let peer = Peer::new_zmq(addr, uuid, tx, WireFormat::Json, Compression::None);
+        peer_map.write().await.insert(uuid, peer).await;
+
+        // Fill the queue, so the next message waits for space
+        let (msg_tx, msg_rx) = flume::bounded(1);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let forwarder =
+            MessageForwarder::new(msg_tx, msg_rx.clone(), OverflowPolicy::Block, dropped);
+
+        let heartbeat = Message {
+            instruction: Instruction::Heartbeat,
+            sender_uuid: uuid,
+            ..Default::default()
+        };
+
+        forwarder.forward(heartbeat.clone()).await.unwrap();
+
+        let forwarding = {
+            let peer_map = peer_map.clone();
+            tokio::spawn(async move {
+                let now = Instant::now();
+                forward_known(
+                    heartbeat,
+                    WireFormat::Json,
+                    &peer_map,
+                    &forwarder,
+                    &mut None,
+                    now,
+                )
+                .await
+            })
+        };
+
+        // Give ingress time to start waiting for space
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Answering the queued heartbeat needs the write lock while ingress waits for space
+        let map = tokio::time::timeout(Duration::from_secs(5), peer_map.write())
+            .await
+            .expect("peer map is locked while forwarding");
+        drop(map);
+
+        msg_rx.recv_async().await.unwrap();
+        assert!(forwarding.await.unwrap().unwrap().is_none());
+        assert_eq!(msg_rx.len(), 1);
+    }
+}
+// endregion
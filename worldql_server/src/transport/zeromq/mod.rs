@@ -1,5 +1,21 @@
+mod admission;
+mod auth;
+mod coalesce;
+mod curve;
+mod dead_letter;
+mod forward;
+mod handshake_ack;
 mod incoming;
 mod outgoing;
+mod rate_limit;
+mod strikes;
 
+pub use admission::{CapacityHook, HandshakeHook, UuidPolicy};
+pub use auth::{AllowAllAuthenticator, HandshakeAuthenticator, SharedSecretAuthenticator};
+pub use curve::CurveConfig;
+pub use dead_letter::start_dead_letter_writer;
+pub use forward::{MessageForwarder, OverflowPolicy};
+pub use handshake_ack::HandshakeConfig;
 pub use incoming::start_zeromq_incoming;
 pub use outgoing::start_zeromq_outgoing;
+pub use strikes::InvalidMessagePolicy;
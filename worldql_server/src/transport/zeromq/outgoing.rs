@@ -1,50 +1,94 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use ahash::{AHashMap, AHashSet};
+use ahash::AHashMap;
+use bytes::Bytes;
 use color_eyre::Result;
 use flume::{Receiver, Sender};
-use futures_util::SinkExt;
+use futures_util::{SinkExt, StreamExt};
 use tmq::push::Push;
-use tokio::time;
-use tracing::{debug, info};
+use tmq::{FromZmqSocket, Multipart};
+use tokio::time::{interval, timeout, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use crate::structures::{Instruction, Message};
-use crate::transport::{Peer, ThreadPeerMap, ZmqOutgoingPair};
+use super::admission::{HandshakeRequest, RejectReason, UuidPolicy};
+use super::coalesce::Coalescer;
+use super::incoming::display_ip;
+use super::HandshakeConfig;
+use crate::structures::{Instruction, Message, WireFormat};
+use crate::transport::{
+    is_hidden_handshake, Compression, Peer, PeerMetadata, ThreadPeerMap, ZmqOutgoingPair,
+};
+use crate::utils::handshake_capabilities;
 
-type SocketMap = AHashMap<Uuid, Push>;
+type SocketMap = AHashMap<Uuid, PeerSocket>;
 
+struct PeerSocket {
+    push: Push,
+    /// Peer accepts several messages in one multipart frame
+    multipart: bool,
+    /// Address the peer's handshake was received from, only available over TCP
+    source_ip: Option<IpAddr>,
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn start_zeromq_outgoing(
     peer_map: ThreadPeerMap,
     msg_tx: Sender<ZmqOutgoingPair>,
     msg_rx: Receiver<ZmqOutgoingPair>,
-    handshake_rx: Receiver<Message>,
+    handshake_rx: Receiver<HandshakeRequest>,
+    handshake_config: HandshakeConfig,
+    handshake_timeout: Duration,
+    coalesce_window: Option<Duration>,
     ctx: tmq::Context,
-    timeout_secs: u8,
+    token: CancellationToken,
 ) -> Result<()> {
     let mut sockets: SocketMap = AHashMap::new();
     info!("Started ZeroMQ PUSH Manager");
 
-    let duration = Duration::from_secs(u64::from(timeout_secs));
-    let mut interval = time::interval(duration);
-    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+    // Only used when coalescing is enabled, the interval period is never zero
+    let mut coalescer = coalesce_window.map(|_| Coalescer::default());
+    let mut flush = interval(coalesce_window.unwrap_or(Duration::from_secs(1)));
+    flush.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     loop {
         tokio::select! {
             // Handle outgoing Message Bytes
             Ok(pair) = msg_rx.recv_async() => {
-                handle_message(&peer_map, &mut sockets, pair).await?
+                handle_message(&peer_map, &mut sockets, coalescer.as_mut(), pair).await?
             },
 
             // Handle incoming Handshake Messages
-            Ok(message) = handshake_rx.recv_async() => {
-                handle_handshake(&peer_map, msg_tx.clone(), &ctx, &mut sockets, message).await?
+            Ok((message, source_ip, rejection)) = handshake_rx.recv_async() => match rejection {
+                Some(reason) => reject_handshake(&ctx, handshake_timeout, message, reason).await?,
+                None => {
+                    let multipart = coalescer.is_some();
+                    handle_handshake(&peer_map, msg_tx.clone(), &ctx, &mut sockets, &handshake_config, handshake_timeout, multipart, message, source_ip).await?
+                }
             },
 
-            // Repeating interval, check peers which haven't sent
-            // a heartbeat recently and remove
-            _ = interval.tick() => {
-                check_stale_peers(&peer_map, duration).await?;
+            // Send coalesced messages at the end of each window
+            _ = flush.tick(), if coalescer.is_some() => {
+                if let Some(coalescer) = &mut coalescer {
+                    flush_batches(&mut sockets, coalescer).await?
+                }
+            },
+
+            // Flush queued messages on shutdown
+            _ = token.cancelled() => {
+                while let Ok(pair) = msg_rx.try_recv() {
+                    handle_message(&peer_map, &mut sockets, coalescer.as_mut(), pair).await?
+                }
+
+                if let Some(coalescer) = &mut coalescer {
+                    flush_batches(&mut sockets, coalescer).await?
+                }
+
+                info!("zeromq_outgoing thread shutdown complete");
+                break
             },
 
             // Both channels have closed, exit thread
@@ -61,13 +105,23 @@ pub async fn start_zeromq_outgoing(
 async fn handle_message(
     peer_map: &ThreadPeerMap,
     sockets: &mut SocketMap,
+    coalescer: Option<&mut Coalescer>,
     (bytes, uuid): ZmqOutgoingPair,
 ) -> Result<()> {
     match sockets.get_mut(&uuid) {
-        Some(socket) => {
-            let zmq_msg = tmq::Message::from(bytes.as_ref());
-            socket.send(zmq_msg).await?;
-        }
+        Some(socket) => match coalescer {
+            // Buffer until the window ends, unless the batch is already full
+            Some(coalescer) if socket.multipart => {
+                if let Some(batch) = coalescer.push(uuid, bytes) {
+                    send_batch(socket, batch).await?;
+                }
+            }
+
+            _ => {
+                let zmq_msg = tmq::Message::from(bytes.as_ref());
+                socket.push.send(zmq_msg).await?;
+            }
+        },
         None => {
             // Remove sockets from PeerMap if they are not in SocketMap
             let mut map = peer_map.write().await;
@@ -78,23 +132,77 @@ async fn handle_message(
     Ok(())
 }
 
+/// Send every buffered batch, dropping those for peers that have since disconnected.
+async fn flush_batches(sockets: &mut SocketMap, coalescer: &mut Coalescer) -> Result<()> {
+    for (uuid, batch) in coalescer.drain() {
+        if let Some(socket) = sockets.get_mut(&uuid) {
+            send_batch(socket, batch).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Send a batch of messages as a single multipart frame, with one part per message.
+async fn send_batch(socket: &mut PeerSocket, batch: Vec<Bytes>) -> Result<()> {
+    let parts = batch
+        .iter()
+        .map(|bytes| tmq::Message::from(bytes.as_ref()))
+        .collect::<Multipart>();
+
+    socket.push.send(parts).await?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_handshake(
     peer_map: &ThreadPeerMap,
     msg_tx: Sender<ZmqOutgoingPair>,
     ctx: &tmq::Context,
     sockets: &mut SocketMap,
+    handshake_config: &HandshakeConfig,
+    handshake_timeout: Duration,
+    coalesce: bool,
     message: Message,
+    source_ip: Option<IpAddr>,
 ) -> Result<()> {
-    // Check for clashing UUIDs
+    // Assigned UUIDs are fresh, so never clash with a known peer
+    let uuid = match handshake_config.uuid_policy {
+        UuidPolicy::Assign => Uuid::new_v4(),
+        _ => message.sender_uuid,
+    };
+
+    // Check for clashing UUIDs, known ZeroMQ peers may handshake again to update their route
     {
         let map = peer_map.read().await;
-        if map.contains_key(&message.sender_uuid) {
-            // UUID already exists, drop handshake
+        if let Some(peer) = map.get(&uuid) {
+            if !peer.is_zmq() {
+                // UUID belongs to another transport, drop handshake
+                return Ok(());
+            }
+        }
+    }
+
+    // Only the address a peer first handshaked from may move its route
+    if let Some(socket) = sockets.get(&uuid) {
+        if socket.source_ip != source_ip {
+            warn!(
+                "dropping zeromq handshake for peer {} from {}, peer connected from {}",
+                uuid,
+                display_ip(source_ip),
+                display_ip(socket.source_ip)
+            );
+
             return Ok(());
         }
     }
 
     let parameter = message.parameter.unwrap();
+    let format = WireFormat::from_handshake(&parameter);
+    let metadata = PeerMetadata::from_handshake(&parameter);
+    let hidden = is_hidden_handshake(&parameter);
+    let multipart = coalesce && handshake_capabilities(&parameter).any(|c| c == "multipart");
+    let (parameter, compression) = Compression::parse_handshake(&parameter);
     let addr = match parameter.parse() {
         Ok(addr) => addr,
         Err(_) => {
@@ -106,45 +214,182 @@ async fn handle_handshake(
     let endpoint = format!("tcp://{}", &parameter);
     debug!("zeromq peer address: {}", endpoint);
 
-    let mut socket = tmq::push(ctx).connect(&endpoint)?;
+    // Acknowledge negotiated capabilities, the handshake reply itself is never compressed
+    let mut capabilities = vec![];
+    if compression != Compression::None {
+        capabilities.push(compression.to_string());
+    }
+
+    if format != WireFormat::FlatBuffers {
+        capabilities.push(format.to_string());
+    }
+
+    if multipart {
+        capabilities.push("multipart".to_string());
+    }
+
     let handshake_msg = Message {
         instruction: Instruction::Handshake,
+        parameter: match capabilities.is_empty() {
+            true => None,
+            false => Some(capabilities.join(";")),
+        },
         ..Default::default()
     };
 
-    // Directly send handshake message back to socket
-    let handshake_data = handshake_msg.serialize();
-    let handshake_msg = tmq::Message::from(handshake_data.as_ref());
-    socket.send(handshake_msg).await?;
+    // Directly send handshake message back to socket, followed by the server's configuration
+    let handshake_data = handshake_msg.serialize_as(format);
+    let ack_data = handshake_config
+        .ack_message(format, uuid)
+        .serialize_as(format);
+    let replies = async {
+        let mut push = connect_push(ctx, &endpoint).await?;
+        push.send(tmq::Message::from(handshake_data.as_ref()))
+            .await?;
+        push.send(tmq::Message::from(ack_data.as_ref())).await?;
+
+        Ok::<_, color_eyre::Report>(push)
+    };
+
+    // A peer whose reply socket can't be reached would otherwise stall this thread
+    let push = match timeout(handshake_timeout, replies).await {
+        Ok(result) => result?,
+        Err(_) => {
+            warn!(
+                "abandoning zeromq handshake from {} ({}), no reply channel after {:?}",
+                addr, uuid, handshake_timeout
+            );
+
+            return Ok(());
+        }
+    };
+
+    let socket = PeerSocket {
+        push,
+        multipart,
+        source_ip,
+    };
 
     // Add peer to PeerMap and SocketMap
     {
         let mut map = peer_map.write().await;
-        let peer = Peer::new_zmq(addr, message.sender_uuid, msg_tx);
 
-        sockets.insert(message.sender_uuid, socket);
-        map.insert(message.sender_uuid, peer).await;
+        // Replace the stale route of a reconnecting peer, without announcing it again
+        if let Some(peer) = map.get_mut(&uuid) {
+            if !peer.is_zmq() {
+                return Ok(());
+            }
+
+            debug!("zeromq peer {} handshaked again, now at {}", uuid, endpoint);
+            peer.update_zmq(addr, format, compression);
+            peer.set_metadata(metadata);
+            peer.set_hidden(hidden);
+            sockets.insert(uuid, socket);
+
+            return Ok(());
+        }
+
+        let mut peer = Peer::new_zmq(addr, uuid, msg_tx, format, compression);
+        peer.set_metadata(metadata);
+        peer.set_hidden(hidden);
+
+        sockets.insert(uuid, socket);
+        map.insert(uuid, peer).await;
     }
 
     Ok(())
 }
 
-async fn check_stale_peers(peer_map: &ThreadPeerMap, max_duration: Duration) -> Result<()> {
-    let uuids = {
-        let map = peer_map.read().await;
-        map.stale_peers_iter(max_duration).collect::<AHashSet<_>>()
-    };
+/// Connect a PUSH socket to `endpoint`, waiting until the connection is established.
+///
+/// PUSH sockets queue messages for endpoints that never accept the connection, so sends
+/// alone can't tell whether a peer's reply socket is reachable.
+async fn connect_push(ctx: &tmq::Context, endpoint: &str) -> Result<Push> {
+    // Every socket needs its own monitor endpoint
+    static MONITORS: AtomicU64 = AtomicU64::new(0);
+    let monitor_endpoint = format!(
+        "inproc://zmq-push-monitor-{}",
+        MONITORS.fetch_add(1, Ordering::Relaxed)
+    );
+
+    // Abandoned sockets must not keep unsent replies around
+    let socket = ctx.socket(zmq::PUSH)?;
+    socket.set_linger(0)?;
+    socket.monitor(
+        &monitor_endpoint,
+        zmq::SocketEvent::CONNECTED.to_raw().into(),
+    )?;
+
+    // Listen before connecting, so the event can't be missed
+    let mut monitor = tmq::pair(ctx).connect(&monitor_endpoint)?;
+    socket.connect(endpoint)?;
+
+    if let Some(event) = monitor.next().await {
+        event?;
+    }
 
-    // Do nothing if no Peers are stale
-    if uuids.is_empty() {
+    Ok(Push::from_zmq_socket(socket)?)
+}
+
+/// Reply to a rejected handshake with the reason, without adding the peer.
+async fn reject_handshake(
+    ctx: &tmq::Context,
+    handshake_timeout: Duration,
+    message: Message,
+    reason: RejectReason,
+) -> Result<()> {
+    let parameter = message.parameter.unwrap();
+    let format = WireFormat::from_handshake(&parameter);
+    let (parameter, _) = Compression::parse_handshake(&parameter);
+    if parameter.parse::<SocketAddr>().is_err() {
+        // Invalid socket address, drop handshake message
         return Ok(());
     }
 
-    // Remove stale peers
-    let mut map = peer_map.write().await;
-    for uuid in uuids {
-        map.remove(&uuid).await;
+    // The socket is dropped right after sending, so linger long enough to deliver the reply
+    let linger = i32::try_from(handshake_timeout.as_millis()).unwrap_or(i32::MAX);
+    let endpoint = format!("tcp://{}", &parameter);
+    let mut push = tmq::push(ctx).set_linger(linger).connect(&endpoint)?;
+
+    let reply = Message {
+        instruction: Instruction::HandshakeRejected,
+        parameter: Some(reason.code().into()),
+        ..Default::default()
+    };
+
+    let data = reply.serialize_as(format);
+    let send = push.send(tmq::Message::from(data.as_ref()));
+    match timeout(handshake_timeout, send).await {
+        Ok(result) => result?,
+        Err(_) => warn!(
+            "abandoning zeromq handshake rejection for {}, no reply channel after {:?}",
+            message.sender_uuid, handshake_timeout
+        ),
     }
 
     Ok(())
 }
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_push_waits_for_connection() {
+        let ctx = tmq::Context::new();
+        let connect = connect_push(&ctx, "tcp://127.0.0.1:1");
+        assert!(timeout(Duration::from_millis(200), connect).await.is_err());
+
+        let pull = ctx.socket(zmq::PULL).unwrap();
+        pull.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = pull.get_last_endpoint().unwrap().unwrap();
+
+        let connect = connect_push(&ctx, &endpoint);
+        assert!(timeout(Duration::from_secs(5), connect)
+            .await
+            .unwrap()
+            .is_ok());
+    }
+}
+// endregion
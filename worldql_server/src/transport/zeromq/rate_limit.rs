@@ -0,0 +1,128 @@
+use std::time::Instant;
+
+use ahash::AHashMap;
+use uuid::Uuid;
+
+// region: TokenBucket Struct
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    dropped: u64,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: now,
+            dropped: 0,
+        }
+    }
+
+    /// Refill based on elapsed time, then try to take a single token.
+    fn try_acquire(&mut self, capacity: f64, per_sec: f64, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return true;
+        }
+
+        self.dropped += 1;
+        false
+    }
+}
+// endregion
+
+// region: RateLimiter Struct
+/// Per-peer token bucket rate limiter.
+#[derive(Debug)]
+pub(super) struct RateLimiter {
+    per_sec: f64,
+    burst: f64,
+    buckets: AHashMap<Uuid, TokenBucket>,
+}
+
+impl RateLimiter {
+    /// Allow `per_sec` messages per second on average, with bursts of up to `burst`.
+    pub(super) fn new(per_sec: u32, burst: u32) -> Self {
+        Self {
+            per_sec: f64::from(per_sec),
+            burst: f64::from(burst.max(1)),
+            buckets: AHashMap::new(),
+        }
+    }
+
+    /// Returns `Ok(())` if the message is allowed, otherwise the total number of messages
+    /// dropped for this peer.
+    pub(super) fn check(&mut self, uuid: Uuid, now: Instant) -> Result<(), u64> {
+        let burst = self.burst;
+        let bucket = self
+            .buckets
+            .entry(uuid)
+            .or_insert_with(|| TokenBucket::new(burst, now));
+
+        match bucket.try_acquire(burst, self.per_sec, now) {
+            true => Ok(()),
+            false => Err(bucket.dropped),
+        }
+    }
+
+    /// Forget buckets that would have refilled completely by `now`.
+    ///
+    /// A full bucket behaves the same as a new one, so this only bounds memory usage.
+    pub(super) fn prune(&mut self, now: Instant) {
+        let (burst, per_sec) = (self.burst, self.per_sec);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now
+                .saturating_duration_since(bucket.last_refill)
+                .as_secs_f64();
+
+            bucket.tokens + elapsed * per_sec < burst
+        });
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let uuid = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(2, 2);
+
+        // Burst is allowed, then limited
+        assert_eq!(limiter.check(uuid, now), Ok(()));
+        assert_eq!(limiter.check(uuid, now), Ok(()));
+        assert_eq!(limiter.check(uuid, now), Err(1));
+        assert_eq!(limiter.check(uuid, now), Err(2));
+
+        // Other peers are unaffected
+        assert_eq!(limiter.check(other, now), Ok(()));
+
+        // Tokens refill over time
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.check(uuid, later), Ok(()));
+        assert_eq!(limiter.check(uuid, later), Err(3));
+
+        // Only idle buckets are pruned, other has refilled by now
+        let idle = later + Duration::from_secs(1);
+        limiter.prune(later);
+        assert_eq!(limiter.buckets.len(), 1);
+        limiter.prune(idle);
+        assert!(limiter.buckets.is_empty());
+    }
+}
+// endregion
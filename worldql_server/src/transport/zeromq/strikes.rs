@@ -0,0 +1,108 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+
+// region: InvalidMessagePolicy Struct
+/// How many invalid messages an address may send before it is blacklisted.
+#[derive(Debug, Clone, Copy)]
+pub struct InvalidMessagePolicy {
+    /// Invalid messages allowed within `window`, the next one blacklists the address
+    pub threshold: u32,
+    pub window: Duration,
+    /// How long a blacklisted address is ignored for
+    pub cooldown: Duration,
+}
+// endregion
+
+// region: StrikeCounter Struct
+#[derive(Debug)]
+struct Strikes {
+    window_start: Instant,
+    count: u32,
+}
+
+/// Counts invalid messages from each address within a fixed window.
+#[derive(Debug)]
+pub(super) struct StrikeCounter {
+    policy: InvalidMessagePolicy,
+    strikes: AHashMap<IpAddr, Strikes>,
+}
+
+impl StrikeCounter {
+    pub(super) fn new(policy: InvalidMessagePolicy) -> Self {
+        Self {
+            policy,
+            strikes: AHashMap::new(),
+        }
+    }
+
+    /// Record an invalid message from `ip`.
+    ///
+    /// Returns the time to blacklist the address until once it exceeds the threshold,
+    /// then starts counting again.
+    pub(super) fn strike(&mut self, ip: IpAddr, now: Instant) -> Option<Instant> {
+        let strikes = self.strikes.entry(ip).or_insert(Strikes {
+            window_start: now,
+            count: 0,
+        });
+
+        if now.saturating_duration_since(strikes.window_start) > self.policy.window {
+            strikes.window_start = now;
+            strikes.count = 0;
+        }
+
+        strikes.count += 1;
+        if strikes.count > self.policy.threshold {
+            self.strikes.remove(&ip);
+            return Some(now + self.policy.cooldown);
+        }
+
+        None
+    }
+
+    /// Forget addresses whose window has expired.
+    pub(super) fn prune(&mut self, now: Instant) {
+        let window = self.policy.window;
+        self.strikes
+            .retain(|_, strikes| now.saturating_duration_since(strikes.window_start) <= window);
+    }
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn strike() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let other = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let now = Instant::now();
+        let mut counter = StrikeCounter::new(InvalidMessagePolicy {
+            threshold: 2,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(60),
+        });
+
+        assert_eq!(counter.strike(ip, now), None);
+        assert_eq!(counter.strike(ip, now), None);
+        assert_eq!(counter.strike(other, now), None);
+        assert_eq!(counter.strike(ip, now), Some(now + Duration::from_secs(60)));
+
+        // Counting restarts after the threshold is crossed
+        assert_eq!(counter.strike(ip, now), None);
+
+        // Strikes outside the window are forgotten
+        let later = now + Duration::from_secs(11);
+        assert_eq!(counter.strike(other, later), None);
+        assert_eq!(counter.strike(other, later), None);
+
+        counter.prune(later);
+        assert_eq!(counter.strikes.len(), 1);
+    }
+}
+// endregion
@@ -0,0 +1,20 @@
+/// Returns the capabilities of a handshake `parameter` of the form `addr[;capability...]`,
+/// trimmed and without the leading address.
+pub fn handshake_capabilities(parameter: &str) -> impl Iterator<Item = &str> {
+    parameter.split(';').skip(1).map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities() {
+        let capabilities = handshake_capabilities("127.0.0.1:5556; lz4 ;json;").collect::<Vec<_>>();
+        assert_eq!(capabilities, vec!["lz4", "json", ""]);
+
+        // The address is never a capability
+        assert_eq!(handshake_capabilities("hidden").count(), 0);
+        assert_eq!(handshake_capabilities("").count(), 0);
+    }
+}
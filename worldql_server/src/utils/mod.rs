@@ -1,8 +1,18 @@
+#[cfg(any(feature = "zeromq", feature = "admin"))]
+mod constant_time;
+mod handshake;
 mod round;
 mod time;
 mod trace_packet;
 mod world_names;
 
+#[cfg(any(feature = "zeromq", feature = "admin"))]
+pub use constant_time::constant_time_eq;
+pub use handshake::handshake_capabilities;
 pub use round::round_by_multiple;
-pub use time::{parse_epoch_millis, ParseEpochError};
-pub use world_names::{sanitize_world_name, SanitizeError, GLOBAL_WORLD};
+pub use time::{epoch_millis, monotonic_micros, parse_epoch_millis};
+pub use trace_packet::{payload_logging, set_payload_logging, PacketTrace, PayloadLogging};
+pub use world_names::{
+    sanitize_world_name, sanitize_world_name_with, set_sanitize_config, Charset, SanitizeConfig,
+    SanitizeError, GLOBAL_WORLD,
+};
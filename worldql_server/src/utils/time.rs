@@ -1,8 +1,13 @@
 use std::num::ParseIntError;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use chrono::prelude::*;
+use once_cell::sync::Lazy;
 use thiserror::Error;
 
+/// Reference point for [`monotonic_micros`], set on first use.
+static MONOTONIC_START: Lazy<Instant> = Lazy::new(Instant::now);
+
 pub fn parse_epoch_millis(timestamp: &str) -> Result<NaiveDateTime, ParseEpochError> {
     let ts = timestamp.parse::<u64>()?;
 
@@ -15,6 +20,21 @@ pub fn parse_epoch_millis(timestamp: &str) -> Result<NaiveDateTime, ParseEpochEr
     }
 }
 
+/// Returns the wall-clock time in milliseconds since the Unix epoch.
+pub fn epoch_millis() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    u64::try_from(since_epoch.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Returns microseconds elapsed on a clock that never goes backwards, unlike wall-clock time.
+pub fn monotonic_micros() -> u64 {
+    let elapsed = MONOTONIC_START.elapsed();
+    u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX)
+}
+
 #[derive(Debug, Error)]
 pub enum ParseEpochError {
     #[error(transparent)]
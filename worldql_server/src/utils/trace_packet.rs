@@ -1,7 +1,223 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use thiserror::Error;
+
+use crate::structures::Message;
+
+/// Logs a [`Message`] at trace level, as allowed by the current [`PayloadLogging`] policy.
 #[macro_export]
 macro_rules! trace_packet {
-    ($target: expr, $($field:tt)*) => {
-        #[cfg(all(debug_assertions, feature = "trace_packets"))]
-        tracing::trace!($target, $($field)*);
+    ($message: expr) => {
+        match $crate::utils::payload_logging() {
+            $crate::utils::PayloadLogging::Off => (),
+            policy => tracing::trace!("{}", $crate::utils::PacketTrace::new(&$message, policy)),
+        }
     };
 }
+
+// region: PayloadLogging Enum
+/// How much of each message [`trace_packet!`] logs.
+///
+/// Messages are logged at trace level, so the log filter must enable it too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadLogging {
+    /// Messages are not logged
+    Off,
+    /// Only the instruction, sender, world and position are logged
+    HeaderOnly,
+    /// Every field is logged, with `data` and `flex` as hex dumps
+    Full,
+}
+
+impl Default for PayloadLogging {
+    /// [`PayloadLogging::Full`] for debug builds with the `trace_packets` feature,
+    /// [`PayloadLogging::Off`] otherwise.
+    fn default() -> Self {
+        if cfg!(all(debug_assertions, feature = "trace_packets")) {
+            Self::Full
+        } else {
+            Self::Off
+        }
+    }
+}
+
+impl FromStr for PayloadLogging {
+    type Err = ParsePayloadLoggingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "header" => Ok(Self::HeaderOnly),
+            "full" => Ok(Self::Full),
+            _ => Err(ParsePayloadLoggingError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: off, header, full")]
+pub struct ParsePayloadLoggingError;
+
+static PAYLOAD_LOGGING: AtomicU8 = AtomicU8::new(u8::MAX);
+
+/// Set the policy used by [`trace_packet!`], can be changed at any time.
+pub fn set_payload_logging(policy: PayloadLogging) {
+    PAYLOAD_LOGGING.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns the policy used by [`trace_packet!`], see [`set_payload_logging`].
+pub fn payload_logging() -> PayloadLogging {
+    match PAYLOAD_LOGGING.load(Ordering::Relaxed) {
+        0 => PayloadLogging::Off,
+        1 => PayloadLogging::HeaderOnly,
+        2 => PayloadLogging::Full,
+        _ => PayloadLogging::default(),
+    }
+}
+// endregion
+
+// region: PacketTrace
+/// Formats a [`Message`] as allowed by a [`PayloadLogging`] policy.
+pub struct PacketTrace<'a> {
+    message: &'a Message,
+    policy: PayloadLogging,
+}
+
+impl<'a> PacketTrace<'a> {
+    pub fn new(message: &'a Message, policy: PayloadLogging) -> Self {
+        Self { message, policy }
+    }
+}
+
+impl Display for PacketTrace<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = self.message;
+        if self.policy != PayloadLogging::Full {
+            write!(
+                f,
+                "{} = {{ sender = \"{}\", world = \"{}\"",
+                message.instruction, message.sender_uuid, message.world_name
+            )?;
+
+            if let Some(position) = &message.position {
+                write!(f, ", position = {}", position)?;
+            }
+
+            return write!(f, " }}");
+        }
+
+        write!(
+            f,
+            "{} = {{ sender = \"{}\", world = \"{}\", replication = {:?}",
+            message.instruction, message.sender_uuid, message.world_name, message.replication
+        )?;
+
+        if let Some(position) = &message.position {
+            write!(f, ", position = {}", position)?;
+        }
+
+        if let Some(parameter) = &message.parameter {
+            write!(f, ", parameter = \"{}\"", parameter)?;
+        }
+
+        if let Some(flex) = &message.flex {
+            write!(f, ", flex = ")?;
+            write_hex(f, flex)?;
+        }
+
+        for record in &message.records {
+            write!(f, ", record = {{ uuid = \"{}\"", record.uuid)?;
+            if let Some(position) = &record.position {
+                write!(f, ", position = {}", position)?;
+            }
+
+            write_payload(f, record.data.as_deref(), record.flex.as_deref())?;
+            write!(f, " }}")?;
+        }
+
+        for entity in &message.entities {
+            write!(
+                f,
+                ", entity = {{ uuid = \"{}\", position = {}",
+                entity.uuid, entity.position
+            )?;
+
+            write_payload(f, entity.data.as_deref(), entity.flex.as_deref())?;
+            write!(f, " }}")?;
+        }
+
+        write!(f, " }}")
+    }
+}
+
+fn write_payload(
+    f: &mut Formatter<'_>,
+    data: Option<&str>,
+    flex: Option<&[u8]>,
+) -> std::fmt::Result {
+    if let Some(data) = data {
+        write!(f, ", data = ")?;
+        write_hex(f, data.as_bytes())?;
+    }
+
+    if let Some(flex) = flex {
+        write!(f, ", flex = ")?;
+        write_hex(f, flex)?;
+    }
+
+    Ok(())
+}
+
+fn write_hex(f: &mut Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    write!(f, "0x")?;
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+
+    Ok(())
+}
+// endregion
+
+// region: Tests
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::structures::{Instruction, Record, Vector3};
+
+    #[test]
+    fn packet_trace() {
+        let message = Message {
+            instruction: Instruction::LocalMessage,
+            sender_uuid: Uuid::nil(),
+            world_name: "world".into(),
+            position: Some(Vector3::new(1.0, 2.0, 3.0)),
+            parameter: Some("secret".into()),
+            flex: Some(Bytes::from_static(&[0x00, 0xff])),
+            records: vec![Record {
+                uuid: Uuid::nil(),
+                position: None,
+                world_name: "world".into(),
+                data: Some("hi".into()),
+                flex: None,
+            }],
+            ..Default::default()
+        };
+
+        let header = PacketTrace::new(&message, PayloadLogging::HeaderOnly).to_string();
+        assert!(header.contains("world = \"world\""));
+        assert!(header.contains("position = "));
+        assert!(!header.contains("secret"));
+        assert!(!header.contains("0x"));
+
+        let full = PacketTrace::new(&message, PayloadLogging::Full).to_string();
+        assert!(full.contains("parameter = \"secret\""));
+        assert!(full.contains("flex = 0x00ff"));
+        assert!(full.contains("data = 0x6869"));
+    }
+}
+// endregion
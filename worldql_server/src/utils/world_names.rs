@@ -1,6 +1,7 @@
 use std::ops::RangeInclusive;
+use std::str::FromStr;
 
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use thiserror::Error;
 
 // region: Constants
@@ -49,9 +50,98 @@ static VALID_START_CHARS: Lazy<Vec<char>> = Lazy::new(|| {
 
 // Max Length
 const MAX_NAME_LENGTH: usize = 63;
+
+static SANITIZE_CONFIG: OnceCell<SanitizeConfig> = OnceCell::new();
+static STRICT_CONFIG: Lazy<SanitizeConfig> = Lazy::new(SanitizeConfig::default);
+// endregion
+
+// region: SanitizeConfig Struct
+/// Rules applied to world names before they are used as database schema names.
+///
+/// Names are used unquoted in SQL, so only character classes PostgreSQL accepts in unquoted
+/// identifiers can be allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizeConfig {
+    /// Maximum length in bytes after replacements
+    ///
+    /// PostgreSQL truncates identifiers longer than 63 bytes, so longer names may collide.
+    pub max_length: usize,
+    pub charset: Charset,
+    /// Lowercase names, so worlds differing only by case share a schema
+    pub case_fold: bool,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            max_length: MAX_NAME_LENGTH,
+            charset: Charset::Ascii,
+            case_fold: false,
+        }
+    }
+}
+
+/// Letters and digits allowed in world names, on top of `_` and the replaced characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// `a-z`, `A-Z` and `0-9`
+    Ascii,
+    /// Any unicode letter or digit
+    Unicode,
+}
+
+impl Charset {
+    fn is_valid_start(&self, char: char) -> bool {
+        match self {
+            Self::Ascii => VALID_START_CHARS.contains(&char),
+            Self::Unicode => char.is_alphabetic(),
+        }
+    }
+
+    fn is_valid(&self, char: char) -> bool {
+        match self {
+            Self::Ascii => CHARSET.contains(&char),
+            Self::Unicode => CHARSET.contains(&char) || char.is_alphanumeric(),
+        }
+    }
+}
+
+impl FromStr for Charset {
+    type Err = ParseCharsetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Self::Ascii),
+            "unicode" => Ok(Self::Unicode),
+            _ => Err(ParseCharsetError),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("must be one of: ascii, unicode")]
+pub struct ParseCharsetError;
 // endregion
 
+/// Sets the rules used by [`sanitize_world_name`], can only be set once.
+///
+/// Returns the config back if it was already set.
+pub fn set_sanitize_config(config: SanitizeConfig) -> Result<(), SanitizeConfig> {
+    SANITIZE_CONFIG.set(config)
+}
+
+/// Sanitize a world name using the rules set by [`set_sanitize_config`], or the strict
+/// defaults if none were set.
+#[inline]
 pub fn sanitize_world_name(world_name: &str) -> Result<String, SanitizeError> {
+    let config = SANITIZE_CONFIG.get().unwrap_or(&STRICT_CONFIG);
+    sanitize_world_name_with(world_name, config)
+}
+
+pub fn sanitize_world_name_with(
+    world_name: &str,
+    config: &SanitizeConfig,
+) -> Result<String, SanitizeError> {
     if world_name == GLOBAL_WORLD {
         return Err(SanitizeError::IsGlobalWorld);
     }
@@ -60,14 +150,14 @@ pub fn sanitize_world_name(world_name: &str) -> Result<String, SanitizeError> {
         return Err(SanitizeError::ZeroLength);
     }
 
-    // Check first character is a-z or A-Z
+    // Check first character is a letter
     let first_char = world_name.chars().next().unwrap();
-    if !VALID_START_CHARS.contains(&first_char) {
+    if !config.charset.is_valid_start(first_char) {
         return Err(SanitizeError::InvalidStart);
     }
 
     // Check for all characters being valid
-    let is_valid_charset = world_name.chars().all(|char| CHARSET.contains(&char));
+    let is_valid_charset = world_name.chars().all(|char| config.charset.is_valid(char));
     if !is_valid_charset {
         return Err(SanitizeError::InvalidChars);
     }
@@ -79,7 +169,12 @@ pub fn sanitize_world_name(world_name: &str) -> Result<String, SanitizeError> {
     let world_name = world_name.replace(COLON.0, COLON.1);
     let world_name = world_name.replace(ASPERAND.0, ASPERAND.1);
 
-    if world_name.len() > MAX_NAME_LENGTH {
+    let world_name = match config.case_fold {
+        true => world_name.to_lowercase(),
+        false => world_name,
+    };
+
+    if world_name.len() > config.max_length {
         return Err(SanitizeError::TooLong);
     }
 
@@ -169,4 +264,38 @@ mod tests {
             SanitizeError::TooLong
         );
     }
+
+    #[test]
+    fn sanitize_with_config() {
+        let config = SanitizeConfig {
+            max_length: 12,
+            charset: Charset::Unicode,
+            case_fold: true,
+        };
+
+        let sanitize = |world_name| sanitize_world_name_with(world_name, &config);
+
+        // Unicode letters are allowed and names are folded to lowercase
+        assert_eq!(sanitize("Wörld"), Ok("wörld".into()));
+        assert_eq!(sanitize("Мир 2"), Ok("мир_2".into()));
+        assert_eq!(sanitize("WORLD"), sanitize("world"));
+
+        // Every error is still reported
+        assert_eq!(sanitize(GLOBAL_WORLD), Err(SanitizeError::IsGlobalWorld));
+        assert_eq!(sanitize(""), Err(SanitizeError::ZeroLength));
+        assert_eq!(sanitize("2мир"), Err(SanitizeError::InvalidStart));
+        assert_eq!(sanitize("мир-2"), Err(SanitizeError::InvalidChars));
+        assert_eq!(sanitize("мир;drop"), Err(SanitizeError::InvalidChars));
+
+        // Length is measured in bytes after replacements
+        assert_eq!(sanitize("aaaa/aaaa"), Ok("aaaa_fs_aaaa".into()));
+        assert_eq!(sanitize("aaaaa/aaaa"), Err(SanitizeError::TooLong));
+        assert_eq!(sanitize("мирмирм"), Err(SanitizeError::TooLong));
+
+        // The strict defaults reject unicode
+        assert_eq!(
+            sanitize_world_name_with("Wörld", &SanitizeConfig::default()),
+            Err(SanitizeError::InvalidChars)
+        );
+    }
 }